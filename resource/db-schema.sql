@@ -1,53 +0,0 @@
--- ノード情報を管理するテーブル
-CREATE TABLE IF NOT EXISTS node_list (
-    id SMALLINT PRIMARY KEY,
-    name VARCHAR(255) NOT NULL,
-    description TEXT
-);
-
--- ファイアウォール設定のテーブル
-CREATE TABLE IF NOT EXISTS firewall_settings
-(
-    id           SERIAL PRIMARY KEY,
-    node_id      SMALLINT,
-    filter_type  VARCHAR(50) NOT NULL CHECK (filter_type IN
-                                             ('SrcIpAddress', 'DstIpAddress', 'SrcPort', 'DstPort', 'EtherType',
-                                              'IpProtocol', 'SrcMacAddress', 'DstMac＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋＋++++++++++++++++++++++++++++++++++++Address',)),
-    filter_value TEXT        NOT NULL,
-    priority     SMALLINT    NOT NULL,
-    policy       VARCHAR(20) NOT NULL CHECK (policy IN ('Whitelist', 'Blacklist'))
-);
-
--- ノードの起動情報を記録するテーブル
-CREATE TABLE IF NOT EXISTS node_activity (
-    id SERIAL PRIMARY KEY,
-    node_id SMALLINT NOT NULL,
-    boot_time TIMESTAMPTZ DEFAULT NOW(),
-    interface_name VARCHAR(255) NOT NULL,
-    mac_address MACADDR NOT NULL,
-    ip_addresses TEXT NOT NULL,
-    FOREIGN KEY (node_id) REFERENCES node_list(id)
-);
-
--- インデックスの作成
-CREATE INDEX IF NOT EXISTS idx_firewall_settings_node_id ON firewall_settings(node_id);
-CREATE INDEX IF NOT EXISTS idx_node_activity_node_id ON node_activity(node_id);
-
--- サンプルデータの挿入
-INSERT INTO node_list (id, name, description)
-VALUES
-    (150, 'Gateway Node', 'Main gateway node for internal network'),
-    (151, 'Firewall Node', 'Dedicated firewall node'),
-    (152, 'Monitor Node', 'Network monitoring node')
-ON CONFLICT (id) DO NOTHING;
-
--- サンプルファイアウォールルール
-INSERT INTO firewall_settings (node_id, filter_type, filter_value, priority, policy)
-VALUES
-    (NULL, 'SrcIpAddress', '192.168.0.1', 100, 'Whitelist'),
-    (NULL, 'DstIpAddress', '192.168.0.1', 100, 'Whitelist'),
-    (150, 'SrcIpAddress', '192.168.0.30', 95, 'Whitelist'),
-    (150, 'DstIpAddress', '192.168.0.30', 95, 'Whitelist'),
-    (151, 'SrcIpAddress', '192.168.0.155', 90, 'Whitelist'),
-    (151, 'DstIpAddress', '192.168.0.155', 90, 'Whitelist')
-ON CONFLICT DO NOTHING;
\ No newline at end of file
@@ -5,5 +5,23 @@ DROP INDEX IF EXISTS idx_packets_recent;
 -- 外部キー制約の為
 DROP TABLE IF EXISTS packet_details CASCADE;
 
+-- ノードごとの読み取り位置
+DROP TABLE IF EXISTS delivery_cursors;
+
+-- ノードごとの受信済みのシーケンス番号
+DROP TABLE IF EXISTS delivery_seq_ranges;
+DROP TABLE IF EXISTS delivery_seqs;
+
+-- 処理済みパケットの記録 (スキーマバージョン3で削除済み)
+DROP TABLE IF EXISTS processed_packets;
+
 -- ハイパーテーブルの削除（packetsテーブルも同時に削除される）
 DROP TABLE IF EXISTS packets CASCADE;
+
+-- ノード・ファイアウォール設定のテーブルを削除
+DROP TABLE IF EXISTS node_activity;
+DROP TABLE IF EXISTS firewall_settings;
+DROP TABLE IF EXISTS node_list;
+
+-- マイグレーションの適用履歴
+DROP TABLE IF EXISTS schema_migrations;
@@ -0,0 +1,34 @@
+-- ノード情報を管理するテーブル
+CREATE TABLE IF NOT EXISTS node_list (
+    id SMALLINT PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    description TEXT
+);
+
+-- ファイアウォール設定のテーブル
+CREATE TABLE IF NOT EXISTS firewall_settings
+(
+    id           SERIAL PRIMARY KEY,
+    node_id      SMALLINT,
+    filter_type  VARCHAR(50) NOT NULL CHECK (filter_type IN
+                                             ('SrcIpAddress', 'DstIpAddress', 'SrcPort', 'DstPort', 'EtherType',
+                                              'IpProtocol', 'SrcMacAddress', 'DstMacAddress')),
+    filter_value TEXT        NOT NULL,
+    priority     SMALLINT    NOT NULL,
+    policy       VARCHAR(20) NOT NULL CHECK (policy IN ('Whitelist', 'Blacklist'))
+);
+
+-- ノードの起動情報を記録するテーブル
+CREATE TABLE IF NOT EXISTS node_activity (
+    id SERIAL PRIMARY KEY,
+    node_id SMALLINT NOT NULL,
+    boot_time TIMESTAMPTZ DEFAULT NOW(),
+    interface_name VARCHAR(255) NOT NULL,
+    mac_address MACADDR NOT NULL,
+    ip_addresses TEXT NOT NULL,
+    FOREIGN KEY (node_id) REFERENCES node_list(id)
+);
+
+-- インデックスの作成
+CREATE INDEX IF NOT EXISTS idx_firewall_settings_node_id ON firewall_settings(node_id);
+CREATE INDEX IF NOT EXISTS idx_node_activity_node_id ON node_activity(node_id);
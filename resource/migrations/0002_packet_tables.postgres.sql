@@ -0,0 +1,28 @@
+-- TimescaleDBがない場合の0002_packet_tables (TimescaleDBの関数・設定を除き、0002_packet_tables.sql と同じテーブルを作成する)
+-- 時間範囲のパーティションへの変換は 0008_packet_partitions で行う
+CREATE TABLE IF NOT EXISTS packets
+(
+    id          BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1),
+    timestamp   TIMESTAMPTZ NOT NULL,
+    node_id     SMALLINT    NOT NULL,
+    src_mac     MACADDR     NOT NULL,
+    dst_mac     MACADDR     NOT NULL,
+    ether_type  INTEGER     NOT NULL,
+    ip_protocol INTEGER     NOT NULL,
+    src_ip      INET        NOT NULL,
+    dst_ip      INET        NOT NULL,
+    src_port    INTEGER     NOT NULL,
+    dst_port    INTEGER     NOT NULL,
+    raw_packet  BYTEA       NOT NULL,
+    CONSTRAINT packets_pkey PRIMARY KEY (id, timestamp)
+);
+
+CREATE TABLE IF NOT EXISTS processed_packets (
+    packet_id BIGINT,
+    node_id SMALLINT,
+    processed_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (packet_id, node_id)
+);
+
+-- 主要な検索パターン用のインデックス
+CREATE INDEX IF NOT EXISTS idx_packets_node_timestamp_included ON packets (node_id, timestamp DESC) INCLUDE (raw_packet);
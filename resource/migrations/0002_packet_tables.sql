@@ -16,7 +16,7 @@ CREATE TABLE IF NOT EXISTS packets
     CONSTRAINT packets_pkey PRIMARY KEY (id, timestamp)
 );
 
-CREATE TABLE processed_packets (
+CREATE TABLE IF NOT EXISTS processed_packets (
     packet_id BIGINT,
     node_id SMALLINT,
     processed_at TIMESTAMPTZ DEFAULT NOW(),
@@ -24,16 +24,16 @@ CREATE TABLE processed_packets (
 );
 
 -- ハイパーテーブルへの変換
-SELECT create_hypertable('packets', 'timestamp', chunk_time_interval => INTERVAL '1 hour');
+SELECT create_hypertable('packets', 'timestamp', chunk_time_interval => INTERVAL '1 hour', if_not_exists => TRUE);
 
 -- 主要な検索パターン用のインデックス
-CREATE INDEX idx_packets_node_timestamp_included ON packets (node_id, timestamp DESC) INCLUDE (raw_packet);
+CREATE INDEX IF NOT EXISTS idx_packets_node_timestamp_included ON packets (node_id, timestamp DESC) INCLUDE (raw_packet);
 
 -- 圧縮設定（オプション）
 ALTER TABLE packets SET (timescaledb.compress, timescaledb.compress_segmentby = 'node_id', timescaledb.compress_orderby = 'timestamp DESC');
 
 -- 自動圧縮ポリシーの設定（オプション）
-SELECT add_compression_policy('packets', INTERVAL '7 days');
+SELECT add_compression_policy('packets', INTERVAL '7 days', if_not_exists => TRUE);
 
 -- 最後にtimestampのみのインデックスを削除
 DROP INDEX IF EXISTS packets_timestamp_idx;
@@ -0,0 +1,10 @@
+-- 読み取りカーソル用に、パケットを書き込んだトランザクションのIDを記録する
+-- コミットの順序はIDの順序と一致しないため、読み取り側は実行中のトランザクションより前のものだけを読み取る
+-- (既存の行は0とし、新しい行はデフォルト値で記録する)
+ALTER TABLE packets ADD COLUMN IF NOT EXISTS tx_id BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE packets ALTER COLUMN tx_id SET DEFAULT (pg_current_xact_id()::text::bigint);
+
+CREATE INDEX IF NOT EXISTS idx_packets_tx_id ON packets (tx_id, id);
+
+-- 読み取り済みのパケットはカーソルで管理するため不要
+DROP TABLE IF EXISTS processed_packets;
@@ -0,0 +1,8 @@
+-- ノードごとの読み取り位置 (再起動後に前回の位置から読み取りを再開する)
+CREATE TABLE IF NOT EXISTS delivery_cursors (
+    node_id    SMALLINT PRIMARY KEY,
+    tx_id      BIGINT      NOT NULL,
+    packet_id  BIGINT      NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (node_id) REFERENCES node_list(id)
+);
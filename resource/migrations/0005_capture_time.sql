@@ -0,0 +1,5 @@
+-- timestampはデータベースが割り当てる保存時刻とし、ノードの時計によるキャプチャ時刻はcaptured_atに分ける
+-- ノードの時計がずれていても、保存時刻による検索・保持期間の判定が狂わないようにする
+-- (既存の行のcaptured_atはNULLのままとし、読み取り時はtimestampで代用する)
+ALTER TABLE packets ADD COLUMN IF NOT EXISTS captured_at TIMESTAMPTZ;
+ALTER TABLE packets ALTER COLUMN timestamp SET DEFAULT clock_timestamp();
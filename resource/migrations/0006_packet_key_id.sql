@@ -0,0 +1,3 @@
+-- raw_packetを暗号化した鍵のID (NULLの場合は平文)
+-- 鍵IDは書き込んだノードのIDとともに暗号文の関連データとして認証する
+ALTER TABLE packets ADD COLUMN IF NOT EXISTS key_id SMALLINT;
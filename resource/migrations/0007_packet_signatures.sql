@@ -0,0 +1,6 @@
+-- パケットの送信元の検証
+-- 各ノードは書き込むパケットに署名し、読み取り側はnode_listに登録された公開鍵 (Ed25519) で署名を検証する
+-- seqは書き込んだノードごとのシーケンス番号で、同じ署名付きの行を複製して書き込む再送 (リプレイ) の検出に使用する
+ALTER TABLE node_list ADD COLUMN IF NOT EXISTS public_key BYTEA;
+ALTER TABLE packets ADD COLUMN IF NOT EXISTS seq BIGINT;
+ALTER TABLE packets ADD COLUMN IF NOT EXISTS signature BYTEA;
@@ -0,0 +1,54 @@
+-- TimescaleDBがない場合、packetsをネイティブの宣言的パーティショニングのテーブルに変換する
+-- 時間範囲ごとのパーティションはノードが起動時と定期的に作成・削除する (範囲外の行はpackets_defaultに入る)
+-- 既存の行はpackets_defaultに移し、保持期間を過ぎた時点で行単位で削除される
+-- (既にパーティションテーブルの場合は何もしない)
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_class WHERE oid = to_regclass('packets') AND relkind = 'r') THEN
+        RETURN;
+    END IF;
+
+    ALTER TABLE packets RENAME TO packets_unpartitioned;
+    ALTER TABLE packets_unpartitioned RENAME CONSTRAINT packets_pkey TO packets_unpartitioned_pkey;
+    ALTER INDEX IF EXISTS idx_packets_node_timestamp_included RENAME TO idx_packets_unpartitioned_node_timestamp;
+    ALTER INDEX IF EXISTS idx_packets_tx_id RENAME TO idx_packets_unpartitioned_tx_id;
+
+    CREATE TABLE packets
+    (
+        id          BIGINT GENERATED BY DEFAULT AS IDENTITY (START WITH 1),
+        timestamp   TIMESTAMPTZ NOT NULL DEFAULT clock_timestamp(),
+        node_id     SMALLINT    NOT NULL,
+        src_mac     MACADDR     NOT NULL,
+        dst_mac     MACADDR     NOT NULL,
+        ether_type  INTEGER     NOT NULL,
+        ip_protocol INTEGER     NOT NULL,
+        src_ip      INET        NOT NULL,
+        dst_ip      INET        NOT NULL,
+        src_port    INTEGER     NOT NULL,
+        dst_port    INTEGER     NOT NULL,
+        raw_packet  BYTEA       NOT NULL,
+        tx_id       BIGINT      NOT NULL DEFAULT (pg_current_xact_id()::text::bigint),
+        captured_at TIMESTAMPTZ,
+        key_id      SMALLINT,
+        seq         BIGINT,
+        signature   BYTEA,
+        CONSTRAINT packets_pkey PRIMARY KEY (id, timestamp)
+    ) PARTITION BY RANGE (timestamp);
+
+    CREATE TABLE packets_default PARTITION OF packets DEFAULT;
+    CREATE INDEX idx_packets_node_timestamp_included ON packets (node_id, timestamp DESC) INCLUDE (raw_packet);
+    CREATE INDEX idx_packets_tx_id ON packets (tx_id, id);
+
+    INSERT INTO packets (
+        id, timestamp, node_id, src_mac, dst_mac, ether_type, ip_protocol, src_ip, dst_ip, src_port, dst_port,
+        raw_packet, tx_id, captured_at, key_id, seq, signature
+    )
+    SELECT
+        id, timestamp, node_id, src_mac, dst_mac, ether_type, ip_protocol, src_ip, dst_ip, src_port, dst_port,
+        raw_packet, tx_id, captured_at, key_id, seq, signature
+    FROM packets_unpartitioned;
+    PERFORM setval(pg_get_serial_sequence('packets', 'id'), COALESCE((SELECT MAX(id) FROM packets), 0) + 1, false);
+
+    DROP TABLE packets_unpartitioned;
+END
+$$;
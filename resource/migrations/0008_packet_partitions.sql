@@ -0,0 +1,2 @@
+-- TimescaleDBの場合、packetsはハイパーテーブルとして時間範囲のチャンクに分割されているため変更しない
+-- (TimescaleDBがない場合は 0008_packet_partitions.postgres.sql を適用する)
@@ -0,0 +1,10 @@
+-- 読み取ったノードが署名を検証した、書き込んだノードごとのシーケンス番号の最大値
+-- 再起動後もリプレイ (受信済みのシーケンス番号の行の複製) を検出するため、読み取り位置とともに保存する
+CREATE TABLE IF NOT EXISTS delivery_seqs (
+    node_id        SMALLINT    NOT NULL,
+    origin_node_id SMALLINT    NOT NULL,
+    highest_seq    BIGINT      NOT NULL,
+    updated_at     TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (node_id, origin_node_id),
+    FOREIGN KEY (node_id) REFERENCES node_list(id)
+);
@@ -0,0 +1,10 @@
+-- 最大値からリプレイの検出範囲内で受信済みのシーケンス番号の区間 (両端を含む)
+-- コミット順が前後して後から届く、最大値より小さい未受信の番号を再起動後も受け付けるために使用する
+CREATE TABLE IF NOT EXISTS delivery_seq_ranges (
+    node_id        SMALLINT NOT NULL,
+    origin_node_id SMALLINT NOT NULL,
+    first_seq      BIGINT   NOT NULL,
+    last_seq       BIGINT   NOT NULL,
+    PRIMARY KEY (node_id, origin_node_id, first_seq),
+    FOREIGN KEY (node_id) REFERENCES node_list(id)
+);
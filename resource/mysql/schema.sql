@@ -0,0 +1,100 @@
+-- MySQL/MariaDBの媒体 (carrier.kind = "mysql") のスキーマ
+-- 時刻はUTC_TIMESTAMP(6) (データベースの時計のUTC) またはUTCに変換した値をDATETIME(6)で保存する
+CREATE TABLE IF NOT EXISTS schema_migrations
+(
+    version    INT         NOT NULL PRIMARY KEY,
+    applied_at DATETIME(6) NOT NULL
+) ENGINE = InnoDB;
+
+CREATE TABLE IF NOT EXISTS node_list
+(
+    id          SMALLINT     NOT NULL PRIMARY KEY,
+    name        VARCHAR(255) NOT NULL,
+    description TEXT,
+    -- パケットの署名を検証する公開鍵 (Ed25519)
+    public_key  VARBINARY(32) NULL
+) ENGINE = InnoDB;
+
+CREATE TABLE IF NOT EXISTS firewall_settings
+(
+    id           INT          NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    node_id      SMALLINT,
+    filter_type  ENUM ('SrcIpAddress', 'DstIpAddress', 'SrcPort', 'DstPort', 'EtherType',
+        'IpProtocol', 'SrcMacAddress', 'DstMacAddress') NOT NULL,
+    filter_value VARCHAR(255) NOT NULL,
+    priority     SMALLINT     NOT NULL,
+    policy       ENUM ('Whitelist', 'Blacklist') NOT NULL,
+    INDEX idx_firewall_settings_node_id (node_id)
+) ENGINE = InnoDB;
+
+CREATE TABLE IF NOT EXISTS node_activity
+(
+    id             BIGINT       NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    node_id        SMALLINT     NOT NULL,
+    boot_time      DATETIME(6)  NOT NULL,
+    interface_name VARCHAR(255) NOT NULL,
+    mac_address    VARCHAR(17)  NOT NULL,
+    ip_addresses   TEXT         NOT NULL,
+    INDEX idx_node_activity_node_id (node_id),
+    FOREIGN KEY (node_id) REFERENCES node_list (id)
+) ENGINE = InnoDB;
+
+-- 書き込みはpacket_writer_lockの行ロックで直列に行うため、idの順序がそのままコミット順になる
+CREATE TABLE IF NOT EXISTS packets
+(
+    id          BIGINT      NOT NULL AUTO_INCREMENT PRIMARY KEY,
+    timestamp   DATETIME(6) NOT NULL,
+    captured_at DATETIME(6) NOT NULL,
+    node_id     SMALLINT    NOT NULL,
+    src_mac     BINARY(6)   NOT NULL,
+    dst_mac     BINARY(6)   NOT NULL,
+    ether_type  INT         NOT NULL,
+    ip_protocol INT         NOT NULL,
+    src_ip      VARCHAR(45) NOT NULL,
+    dst_ip      VARCHAR(45) NOT NULL,
+    src_port    INT         NOT NULL,
+    dst_port    INT         NOT NULL,
+    raw_packet  MEDIUMBLOB  NOT NULL,
+    -- raw_packetを暗号化した鍵のID (NULLの場合は平文)
+    key_id      SMALLINT    NULL,
+    -- 書き込んだノードごとのシーケンス番号と署名 (署名しない場合はNULL)
+    seq         BIGINT      NULL,
+    signature   VARBINARY(64) NULL,
+    INDEX idx_packets_timestamp (timestamp),
+    INDEX idx_packets_node_timestamp (node_id, timestamp)
+) ENGINE = InnoDB;
+
+CREATE TABLE IF NOT EXISTS packet_writer_lock
+(
+    id TINYINT NOT NULL PRIMARY KEY
+) ENGINE = InnoDB;
+
+INSERT IGNORE INTO packet_writer_lock (id) VALUES (1);
+
+CREATE TABLE IF NOT EXISTS delivery_cursors
+(
+    node_id    SMALLINT    NOT NULL PRIMARY KEY,
+    packet_id  BIGINT      NOT NULL,
+    updated_at DATETIME(6) NOT NULL
+) ENGINE = InnoDB;
+
+-- 読み取ったノードが署名を検証した、書き込んだノードごとのシーケンス番号の最大値 (再起動後のリプレイの検出に使用する)
+CREATE TABLE IF NOT EXISTS delivery_seqs
+(
+    node_id        SMALLINT    NOT NULL,
+    origin_node_id SMALLINT    NOT NULL,
+    highest_seq    BIGINT      NOT NULL,
+    updated_at     DATETIME(6) NOT NULL,
+    PRIMARY KEY (node_id, origin_node_id)
+) ENGINE = InnoDB;
+
+-- 最大値からリプレイの検出範囲内で受信済みのシーケンス番号の区間 (両端を含む)
+-- コミット順が前後して後から届く、最大値より小さい未受信の番号を再起動後も受け付けるために使用する
+CREATE TABLE IF NOT EXISTS delivery_seq_ranges
+(
+    node_id        SMALLINT NOT NULL,
+    origin_node_id SMALLINT NOT NULL,
+    first_seq      BIGINT   NOT NULL,
+    last_seq       BIGINT   NOT NULL,
+    PRIMARY KEY (node_id, origin_node_id, first_seq)
+) ENGINE = InnoDB;
@@ -0,0 +1,18 @@
+-- サンプルデータの挿入
+INSERT INTO node_list (id, name, description)
+VALUES
+    (150, 'Gateway Node', 'Main gateway node for internal network'),
+    (151, 'Firewall Node', 'Dedicated firewall node'),
+    (152, 'Monitor Node', 'Network monitoring node')
+ON CONFLICT (id) DO NOTHING;
+
+-- サンプルファイアウォールルール
+INSERT INTO firewall_settings (node_id, filter_type, filter_value, priority, policy)
+VALUES
+    (NULL, 'SrcIpAddress', '192.168.0.1', 100, 'Whitelist'),
+    (NULL, 'DstIpAddress', '192.168.0.1', 100, 'Whitelist'),
+    (150, 'SrcIpAddress', '192.168.0.30', 95, 'Whitelist'),
+    (150, 'DstIpAddress', '192.168.0.30', 95, 'Whitelist'),
+    (151, 'SrcIpAddress', '192.168.0.155', 90, 'Whitelist'),
+    (151, 'DstIpAddress', '192.168.0.155', 90, 'Whitelist')
+ON CONFLICT DO NOTHING;
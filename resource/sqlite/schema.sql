@@ -0,0 +1,83 @@
+-- SQLiteの媒体 (carrier.kind = "sqlite") のスキーマ
+-- 時刻はUNIX時間のマイクロ秒で保存する
+CREATE TABLE IF NOT EXISTS node_list (
+    id          INTEGER PRIMARY KEY,
+    name        TEXT NOT NULL,
+    description TEXT,
+    -- パケットの署名を検証する公開鍵 (Ed25519)
+    public_key  BLOB
+);
+
+CREATE TABLE IF NOT EXISTS firewall_settings
+(
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    node_id      INTEGER,
+    filter_type  TEXT    NOT NULL CHECK (filter_type IN
+                                         ('SrcIpAddress', 'DstIpAddress', 'SrcPort', 'DstPort', 'EtherType',
+                                          'IpProtocol', 'SrcMacAddress', 'DstMacAddress')),
+    filter_value TEXT    NOT NULL,
+    priority     INTEGER NOT NULL,
+    policy       TEXT    NOT NULL CHECK (policy IN ('Whitelist', 'Blacklist'))
+);
+
+CREATE TABLE IF NOT EXISTS node_activity (
+    id             INTEGER PRIMARY KEY AUTOINCREMENT,
+    node_id        INTEGER NOT NULL REFERENCES node_list (id),
+    boot_time      INTEGER NOT NULL,
+    interface_name TEXT    NOT NULL,
+    mac_address    TEXT    NOT NULL,
+    ip_addresses   TEXT    NOT NULL
+);
+
+-- 書き込みは1つずつ直列に行われるため、idの順序がそのままコミット順になる
+CREATE TABLE IF NOT EXISTS packets
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp   INTEGER NOT NULL,
+    captured_at INTEGER NOT NULL,
+    node_id     INTEGER NOT NULL,
+    src_mac     BLOB    NOT NULL,
+    dst_mac     BLOB    NOT NULL,
+    ether_type  INTEGER NOT NULL,
+    ip_protocol INTEGER NOT NULL,
+    src_ip      TEXT    NOT NULL,
+    dst_ip      TEXT    NOT NULL,
+    src_port    INTEGER NOT NULL,
+    dst_port    INTEGER NOT NULL,
+    raw_packet  BLOB    NOT NULL,
+    -- raw_packetを暗号化した鍵のID (NULLの場合は平文)
+    key_id      INTEGER,
+    -- 書き込んだノードごとのシーケンス番号と署名 (署名しない場合はNULL)
+    seq         INTEGER,
+    signature   BLOB
+);
+
+CREATE TABLE IF NOT EXISTS delivery_cursors (
+    node_id    INTEGER PRIMARY KEY,
+    packet_id  INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+);
+
+-- 読み取ったノードが署名を検証した、書き込んだノードごとのシーケンス番号の最大値 (再起動後のリプレイの検出に使用する)
+CREATE TABLE IF NOT EXISTS delivery_seqs (
+    node_id        INTEGER NOT NULL,
+    origin_node_id INTEGER NOT NULL,
+    highest_seq    INTEGER NOT NULL,
+    updated_at     INTEGER NOT NULL,
+    PRIMARY KEY (node_id, origin_node_id)
+);
+
+-- 最大値からリプレイの検出範囲内で受信済みのシーケンス番号の区間 (両端を含む)
+-- コミット順が前後して後から届く、最大値より小さい未受信の番号を再起動後も受け付けるために使用する
+CREATE TABLE IF NOT EXISTS delivery_seq_ranges (
+    node_id        INTEGER NOT NULL,
+    origin_node_id INTEGER NOT NULL,
+    first_seq      INTEGER NOT NULL,
+    last_seq       INTEGER NOT NULL,
+    PRIMARY KEY (node_id, origin_node_id, first_seq)
+);
+
+CREATE INDEX IF NOT EXISTS idx_firewall_settings_node_id ON firewall_settings (node_id);
+CREATE INDEX IF NOT EXISTS idx_node_activity_node_id ON node_activity (node_id);
+CREATE INDEX IF NOT EXISTS idx_packets_timestamp ON packets (timestamp);
+CREATE INDEX IF NOT EXISTS idx_packets_node_timestamp ON packets (node_id, timestamp);
@@ -0,0 +1,107 @@
+use crate::carrier::error::CarrierError;
+use crate::config::{AppConfig, CircuitBreakerConfig, RetentionConfig};
+use crate::crypto::ReceivedSeqs;
+use crate::database::{Database, DatabaseHealth};
+use crate::packet::analysis::{IpFirewall, Policy};
+use crate::packet::repository::{DeliveryCursor, HeldBackPackets, PacketQuery};
+use crate::packet::{FetchedPacket, PacketData, StoredPacket};
+use crate::services::{NodeRecord, PurgeReport};
+use async_trait::async_trait;
+use pnet::datalink::NetworkInterface;
+use std::time::Duration;
+
+/// ノード間でパケットを受け渡す媒体
+/// PostgreSQLを使う `PostgresCarrier`、MySQL/MariaDBを使う `MysqlCarrier`、1台のホスト上のファイルで受け渡す `SqliteCarrier`、
+/// テストや1つのプロセスで複数のノードを動かすためにメモリ上で受け渡す `MemoryCarrier` がある
+#[async_trait]
+pub trait Carrier: Send + Sync {
+    /// ログに出力する媒体の名前
+    fn name(&self) -> &'static str;
+
+    /// 媒体へのアクセスの状態 (サーキットブレーカー)
+    fn health(&self) -> &DatabaseHealth;
+
+    /// 接続プールなどの統計情報を参照するためのデータベース (PostgreSQL以外の媒体ではNone)
+    fn database(&self) -> Option<&Database> {
+        None
+    }
+
+    /// ノードの初期化の最初に、スキーマの確認など媒体の準備を行う
+    async fn prepare(&self, config: &AppConfig) -> Result<(), CarrierError>;
+
+    /// ノードがキャプチャしたパケットをまとめて書き込み、他のノードに通知する
+    async fn publish(&self, node_id: i16, packets: &[PacketData]) -> Result<(), CarrierError>;
+
+    /// 他のノードの書き込みの通知を購読する
+    async fn subscribe(&self) -> Result<Box<dyn CarrierSubscription>, CarrierError>;
+
+    /// カーソルより後に他のノードが書き込んだパケットを書き込み順に読み取り、次に読み取りを始める位置とともに返す
+    async fn fetch(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(Vec<FetchedPacket>, DeliveryCursor), CarrierError>;
+
+    /// 読み取り位置より後に書き込まれたが、まだfetchが返さないパケット (実行中の古いトランザクションを待つ媒体のみ)
+    async fn held_back(&self, _node_id: i16, _cursor: DeliveryCursor) -> Result<Option<HeldBackPackets>, CarrierError> {
+        Ok(None)
+    }
+
+    /// 送信を終えた読み取り位置を記録する (再起動後に続きから読み取るため)
+    async fn acknowledge(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(), CarrierError>;
+
+    /// 記録されている読み取り位置
+    async fn saved_cursor(&self, node_id: i16) -> Result<Option<DeliveryCursor>, CarrierError>;
+
+    /// 署名を検証したパケットのシーケンス番号を、書き込んだノードごとに記録する (再起動後もリプレイを検出するため)
+    /// seqsに含まれる書き込んだノードの記録は、最大値と受信済みの区間ともに置き換える
+    async fn acknowledge_seqs(&self, node_id: i16, seqs: &[ReceivedSeqs]) -> Result<(), CarrierError>;
+
+    /// 記録されている受信済みのシーケンス番号 (書き込んだノードID順、区間は昇順)
+    async fn saved_seqs(&self, node_id: i16) -> Result<Vec<ReceivedSeqs>, CarrierError>;
+
+    /// 現在の読み取り位置 (これまでに書き込まれたパケットは全て読み取り済みとする位置)
+    async fn live_cursor(&self) -> Result<DeliveryCursor, CarrierError>;
+
+    /// 直近max_ageの間に他のノードが書き込んだパケットから読み取る位置
+    async fn cursor_since(&self, node_id: i16, max_age: Duration) -> Result<DeliveryCursor, CarrierError>;
+
+    /// 他のノードが書き込んだ最新のcount個のパケットから読み取る位置
+    async fn cursor_before_latest(&self, node_id: i16, count: i64) -> Result<DeliveryCursor, CarrierError>;
+
+    /// ノードを登録する (既に存在する場合は名前と説明を更新する)
+    async fn register_node(&self, node_id: i16, name: &str, description: Option<&str>) -> Result<(), CarrierError>;
+
+    /// パケットの署名を検証する公開鍵を登録する (Noneの場合は削除する)
+    async fn set_node_key(&self, node_id: i16, public_key: Option<&[u8]>) -> Result<(), CarrierError>;
+
+    /// 登録されているノードをID順に返す
+    async fn list_nodes(&self) -> Result<Vec<NodeRecord>, CarrierError>;
+
+    /// 登録済みのノードであることを確認して起動を記録し、ノードの名前を返す
+    async fn record_boot(&self, node_id: i16, interface: &NetworkInterface) -> Result<String, CarrierError>;
+
+    /// ノードに適用するファイアウォール設定を読み込む
+    async fn load_firewall(&self, node_id: i16, fallback_policy: Policy) -> Result<IpFirewall, CarrierError>;
+
+    /// 保存済みのパケットを新しい順に検索する
+    async fn query_packets(&self, query: &PacketQuery) -> Result<Vec<StoredPacket>, CarrierError>;
+
+    /// 保持期間・サイズを超えたデータを削除する
+    async fn purge(&self, config: &RetentionConfig) -> Result<PurgeReport, CarrierError>;
+}
+
+/// 接続障害を記録しない媒体 (メモリ上・SQLite) 用の、常に要求を通すサーキットブレーカー
+pub(crate) fn unmonitored_health() -> DatabaseHealth {
+    DatabaseHealth::new(&CircuitBreakerConfig {
+        failure_threshold: u32::MAX,
+        initial_backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(1),
+    })
+}
+
+/// 他のノードの書き込みの通知 (書き込んだノードのID)
+#[async_trait]
+pub trait CarrierSubscription: Send {
+    /// 次の通知を待つ (購読が切断された場合はNone)
+    async fn recv(&mut self) -> Option<i16>;
+
+    /// 既に届いている通知を待たずに取り出す
+    fn try_recv(&mut self) -> Option<i16>;
+}
@@ -0,0 +1,24 @@
+use crate::database::DatabaseError;
+use crate::services::ServiceError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CarrierError {
+    #[error("データベースエラー: {0}")]
+    DatabaseError(#[from] DatabaseError),
+
+    #[error("サービスエラー: {0}")]
+    ServiceError(#[from] ServiceError),
+
+    #[error("SQLiteエラー: {0}")]
+    SqliteError(String),
+
+    #[error("MySQLエラー: {0}")]
+    MysqlError(String),
+
+    #[error("媒体のスキーマのバージョン ({0}) がこのバイナリ ({1}) より新しいため使用できません")]
+    SchemaVersionError(i32, i32),
+
+    #[error("管理コマンド用の媒体ではパケットの書き込みと通知の待機はできません")]
+    ManagementOnlyError,
+}
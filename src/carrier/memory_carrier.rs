@@ -0,0 +1,332 @@
+use crate::carrier::carrier::{unmonitored_health, Carrier, CarrierSubscription};
+use crate::carrier::error::CarrierError;
+use crate::config::{AppConfig, RetentionConfig};
+use crate::crypto::ReceivedSeqs;
+use crate::database::DatabaseHealth;
+use crate::packet::analysis::{IpFirewall, Policy};
+use crate::packet::repository::{DeliveryCursor, PacketQuery, PacketRepository};
+use crate::packet::{FetchedPacket, PacketData, StoredPacket};
+use crate::services::{NodeRecord, PurgeReport, ServiceError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use pnet::datalink::NetworkInterface;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
+
+// 購読者が遅れた場合に保持しておく通知の数 (溢れた通知は読み捨てても、次の読み取りでまとめて取得される)
+const NOTIFY_CHANNEL_CAPACITY: usize = 256;
+
+struct MemoryNode {
+    name: String,
+    description: Option<String>,
+    public_key: Option<Vec<u8>>,
+    boot_times: Vec<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+struct MemoryStore {
+    // 書き込み順 (idの昇順)
+    packets: VecDeque<StoredPacket>,
+    last_id: i64,
+    nodes: BTreeMap<i16, MemoryNode>,
+    firewalls: HashMap<i16, IpFirewall>,
+    cursors: HashMap<i16, DeliveryCursor>,
+    // 読み取ったノードごとの、書き込んだノードの受信済みのシーケンス番号
+    seqs: HashMap<i16, BTreeMap<i16, ReceivedSeqs>>,
+}
+
+/// プロセス内のメモリでパケットを受け渡す (データベースなしでのテストや、1つのプロセスで複数のノードを動かす場合に使用する)
+/// クローンしたものは同じパケットとノードを共有するため、各ノードには同じMemoryCarrierのクローンを渡す
+/// 読み取り位置はtx_id・idともに書き込み順の連番とし、プロセスを終了すると全て失われる
+#[derive(Clone)]
+pub struct MemoryCarrier {
+    store: Arc<Mutex<MemoryStore>>,
+    notify: broadcast::Sender<i16>,
+    // メモリ上では障害が起きないため、常に要求を通す
+    health: DatabaseHealth,
+}
+
+impl Default for MemoryCarrier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryCarrier {
+    pub fn new() -> Self {
+        Self {
+            store: Arc::new(Mutex::new(MemoryStore::default())),
+            notify: broadcast::channel(NOTIFY_CHANNEL_CAPACITY).0,
+            health: unmonitored_health(),
+        }
+    }
+
+    /// ノードに適用するファイアウォール設定を登録する (登録がないノードは設定のデフォルトポリシーでルールなしになる)
+    pub fn set_firewall(&self, node_id: i16, firewall: IpFirewall) {
+        self.lock().firewalls.insert(node_id, firewall);
+    }
+
+    /// 保持しているパケット数
+    pub fn len(&self) -> usize {
+        self.lock().packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().packets.is_empty()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryStore> {
+        // パニックしたスレッドが保持していても、データは一貫しているため使い続ける
+        self.store.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn cursor_of(packet: &StoredPacket) -> DeliveryCursor {
+        DeliveryCursor {
+            tx_id: packet.id,
+            packet_id: packet.id,
+        }
+    }
+}
+
+#[async_trait]
+impl Carrier for MemoryCarrier {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn health(&self) -> &DatabaseHealth {
+        &self.health
+    }
+
+    async fn prepare(&self, _config: &AppConfig) -> Result<(), CarrierError> {
+        debug!("メモリ上の媒体を使用するため、スキーマの確認を行いません");
+        Ok(())
+    }
+
+    async fn publish(&self, node_id: i16, packets: &[PacketData]) -> Result<(), CarrierError> {
+        if packets.is_empty() {
+            return Ok(());
+        }
+
+        {
+            let mut store = self.lock();
+            let stored_at = Utc::now();
+            for packet in packets {
+                store.last_id += 1;
+                let id = store.last_id;
+                store.packets.push_back(StoredPacket {
+                    id,
+                    node_id,
+                    stored_at,
+                    data: packet.clone(),
+                });
+            }
+        }
+
+        // 購読者がいない場合は失敗するが、読み取り側は一定間隔の読み取りで補う
+        let _ = self.notify.send(node_id);
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> Result<Box<dyn CarrierSubscription>, CarrierError> {
+        Ok(Box::new(MemorySubscription {
+            receiver: self.notify.subscribe(),
+        }))
+    }
+
+    async fn fetch(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(Vec<FetchedPacket>, DeliveryCursor), CarrierError> {
+        let store = self.lock();
+        let fetched: Vec<&StoredPacket> =
+            store.packets.iter().filter(|p| p.node_id != node_id && Self::cursor_of(p) > cursor).take(PacketRepository::FETCH_LIMIT as usize).collect();
+
+        let next = fetched.last().map(|p| Self::cursor_of(p)).unwrap_or(cursor);
+        Ok((
+            fetched
+                .into_iter()
+                .map(|p| FetchedPacket {
+                    node_id: p.node_id,
+                    captured_at: p.data.captured_at,
+                    raw_packet: p.data.raw_packet.clone(),
+                    key_id: p.data.key_id,
+                    signature: p.data.signature.clone(),
+                })
+                .collect(),
+            next,
+        ))
+    }
+
+    async fn acknowledge(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(), CarrierError> {
+        self.lock().cursors.insert(node_id, cursor);
+        Ok(())
+    }
+
+    async fn saved_cursor(&self, node_id: i16) -> Result<Option<DeliveryCursor>, CarrierError> {
+        Ok(self.lock().cursors.get(&node_id).copied())
+    }
+
+    async fn acknowledge_seqs(&self, node_id: i16, seqs: &[ReceivedSeqs]) -> Result<(), CarrierError> {
+        let mut store = self.lock();
+        let saved = store.seqs.entry(node_id).or_default();
+        for received in seqs {
+            saved.insert(received.node_id, received.clone());
+        }
+        Ok(())
+    }
+
+    async fn saved_seqs(&self, node_id: i16) -> Result<Vec<ReceivedSeqs>, CarrierError> {
+        Ok(self.lock().seqs.get(&node_id).map(|seqs| seqs.values().cloned().collect()).unwrap_or_default())
+    }
+
+    async fn live_cursor(&self) -> Result<DeliveryCursor, CarrierError> {
+        let last_id = self.lock().last_id;
+        Ok(DeliveryCursor {
+            tx_id: last_id,
+            packet_id: last_id,
+        })
+    }
+
+    async fn cursor_since(&self, node_id: i16, max_age: Duration) -> Result<DeliveryCursor, CarrierError> {
+        let cutoff = Utc::now() - max_age;
+        let store = self.lock();
+        let first = store.packets.iter().find(|p| p.node_id != node_id && p.stored_at >= cutoff).map(|p| p.id - 1);
+        let id = first.unwrap_or(store.last_id);
+        Ok(DeliveryCursor { tx_id: id, packet_id: id })
+    }
+
+    async fn cursor_before_latest(&self, node_id: i16, count: i64) -> Result<DeliveryCursor, CarrierError> {
+        let store = self.lock();
+        Ok(store.packets.iter().rev().filter(|p| p.node_id != node_id).nth(count.max(0) as usize).map(Self::cursor_of).unwrap_or_default())
+    }
+
+    async fn register_node(&self, node_id: i16, name: &str, description: Option<&str>) -> Result<(), CarrierError> {
+        let mut store = self.lock();
+        let (public_key, boot_times) = store.nodes.remove(&node_id).map(|node| (node.public_key, node.boot_times)).unwrap_or_default();
+        store.nodes.insert(
+            node_id,
+            MemoryNode {
+                name: name.to_string(),
+                description: description.map(str::to_string),
+                public_key,
+                boot_times,
+            },
+        );
+
+        info!("ノード {} ({}) を登録しました", node_id, name);
+        Ok(())
+    }
+
+    async fn set_node_key(&self, node_id: i16, public_key: Option<&[u8]>) -> Result<(), CarrierError> {
+        let mut store = self.lock();
+        let node = store.nodes.get_mut(&node_id).ok_or(ServiceError::NodeNotFound(node_id))?;
+        node.public_key = public_key.map(<[u8]>::to_vec);
+        Ok(())
+    }
+
+    async fn list_nodes(&self) -> Result<Vec<NodeRecord>, CarrierError> {
+        Ok(self
+            .lock()
+            .nodes
+            .iter()
+            .map(|(id, node)| NodeRecord {
+                id: *id,
+                name: node.name.clone(),
+                description: node.description.clone(),
+                last_boot_time: node.boot_times.iter().max().copied(),
+                public_key: node.public_key.clone(),
+            })
+            .collect())
+    }
+
+    async fn record_boot(&self, node_id: i16, interface: &NetworkInterface) -> Result<String, CarrierError> {
+        let mut store = self.lock();
+        let node = store.nodes.get_mut(&node_id).ok_or(ServiceError::NodeNotFound(node_id))?;
+        node.boot_times.push(Utc::now());
+
+        debug!(
+            "ノードID {} ({}) の起動を記録しました (インターフェース: {}, 説明: {:?})",
+            node_id, node.name, interface.name, node.description
+        );
+        Ok(node.name.clone())
+    }
+
+    async fn load_firewall(&self, node_id: i16, fallback_policy: Policy) -> Result<IpFirewall, CarrierError> {
+        Ok(self.lock().firewalls.get(&node_id).cloned().unwrap_or_else(|| IpFirewall::new(fallback_policy)))
+    }
+
+    async fn query_packets(&self, query: &PacketQuery) -> Result<Vec<StoredPacket>, CarrierError> {
+        let store = self.lock();
+        Ok(store
+            .packets
+            .iter()
+            .rev()
+            .filter(|p| query.node_id.is_none_or(|node_id| p.node_id == node_id))
+            .filter(|p| query.since.is_none_or(|since| p.stored_at >= since))
+            .filter(|p| query.until.is_none_or(|until| p.stored_at < until))
+            .take(query.limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn purge(&self, config: &RetentionConfig) -> Result<PurgeReport, CarrierError> {
+        let now = Utc::now();
+        let mut report = PurgeReport::default();
+        let mut store = self.lock();
+
+        let before = store.packets.len();
+        store.packets.retain(|p| {
+            let age = (now - p.stored_at).to_std().unwrap_or_default();
+            let expired = config.packets_max_age.is_some_and(|max_age| age > max_age);
+            let node_expired = config.node_max_age.get(&p.node_id).is_some_and(|max_age| age > *max_age);
+            !expired && !node_expired
+        });
+        report.deleted_packets = (before - store.packets.len()) as u64;
+
+        if let Some(max_age) = config.node_activity_max_age {
+            // 最終起動時刻が分からなくならないよう、ノードごとの最新の記録は残す
+            for node in store.nodes.values_mut() {
+                let Some(latest) = node.boot_times.pop() else { continue };
+                let before = node.boot_times.len();
+                node.boot_times.retain(|boot_time| (now - *boot_time).to_std().unwrap_or_default() <= max_age);
+                node.boot_times.push(latest);
+                report.deleted_activities += (before - (node.boot_times.len() - 1)) as u64;
+            }
+        }
+
+        if config.packets_max_size.is_some() {
+            debug!("メモリ上の媒体ではサイズによる削除を行いません");
+        }
+        Ok(report)
+    }
+}
+
+struct MemorySubscription {
+    receiver: broadcast::Receiver<i16>,
+}
+
+#[async_trait]
+impl CarrierSubscription for MemorySubscription {
+    async fn recv(&mut self) -> Option<i16> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(node_id) => return Some(node_id),
+                // 溢れた通知は読み捨て、残っている通知を返す
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    fn try_recv(&mut self) -> Option<i16> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(node_id) => return Some(node_id),
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+#[allow(clippy::module_inception, reason = "Carrierトレイトを carrier::Carrier として公開するため")]
+mod carrier;
+mod error;
+mod memory_carrier;
+mod mysql_carrier;
+mod postgres_carrier;
+mod sqlite_carrier;
+
+pub use crate::packet::FetchedPacket;
+pub use carrier::{Carrier, CarrierSubscription};
+pub use error::CarrierError;
+pub use memory_carrier::MemoryCarrier;
+pub use mysql_carrier::MysqlCarrier;
+pub use postgres_carrier::PostgresCarrier;
+pub use sqlite_carrier::SqliteCarrier;
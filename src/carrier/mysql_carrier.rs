@@ -0,0 +1,681 @@
+use crate::carrier::carrier::{Carrier, CarrierSubscription};
+use crate::carrier::error::CarrierError;
+use crate::config::{AppConfig, CarrierConfig, CircuitBreakerConfig, RetentionConfig};
+use crate::crypto::ReceivedSeqs;
+use crate::database::{DatabaseError, DatabaseHealth, HealthPermit};
+use crate::packet::analysis::{IpFirewall, Policy};
+use crate::packet::repository::{DeliveryCursor, PacketQuery, PacketRepository};
+use crate::packet::types::{EtherType, IpProtocol};
+use crate::packet::{FetchedPacket, InetAddr, MacAddr, PacketData, PacketSignature, StoredPacket};
+use crate::services::{DbService, FirewallRuleRow, NodeRecord, PurgeReport, ServiceError};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::{debug, info, warn};
+use mysql_async::prelude::{FromValue, Queryable};
+use mysql_async::{params, Conn, Opts, OptsBuilder, Pool, PoolConstraints, PoolOpts, Row, SslOpts, TxOpts, Value};
+use pnet::datalink::NetworkInterface;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+const SCHEMA: &str = include_str!("../../resource/mysql/schema.sql");
+
+// 既存のスキーマに適用する変更 (変更後のバージョン, SQL)
+// SCHEMAは既に存在するテーブルを変更しないため、列の追加などはここに追加する (テーブルの追加はSCHEMAのみでよい)
+const UPGRADES: &[(i32, &str)] = &[
+    (2, "ALTER TABLE packets ADD COLUMN key_id SMALLINT NULL"),
+    (3, "ALTER TABLE packets ADD COLUMN seq BIGINT NULL, ADD COLUMN signature VARBINARY(64) NULL"),
+    (3, "ALTER TABLE node_list ADD COLUMN public_key VARBINARY(32) NULL"),
+];
+
+// プリペアドステートメントのプレースホルダ数の上限 (65535) を超えないよう、1回のINSERTに含めるパケット数を制限する
+const MAX_ROWS_PER_INSERT: usize = 65535 / 14;
+
+// 1回のINSERTに含めるraw_packetの合計サイズ (サーバーのmax_allowed_packetの既定値16MBを超えないようにする)
+const MAX_BYTES_PER_INSERT: usize = 4 * 1024 * 1024;
+
+// 1回のDELETEで削除する行数 (ロックを長時間保持しないよう分割して削除する)
+const PURGE_BATCH_SIZE: u64 = 10_000;
+
+/// MySQL/MariaDBのpacketsテーブルを経由してパケットを受け渡す
+/// 書き込みはpacket_writer_lockの行ロックで直列に行うため、読み取り位置はtx_id・idともにpacketsのidとする
+/// MySQLには通知の仕組みがないため、他のノードの書き込みはmysql_watch_intervalごとに確認する
+pub struct MysqlCarrier {
+    pool: Pool,
+    opts: Opts,
+    config: CarrierConfig,
+    health: DatabaseHealth,
+}
+
+impl MysqlCarrier {
+    /// このバイナリが作成するスキーマのバージョン (schema_migrations)
+    pub const SCHEMA_VERSION: i32 = 5;
+
+    /// MySQLへ接続し、接続できることを確認する
+    pub async fn connect(config: &CarrierConfig, circuit_breaker: &CircuitBreakerConfig) -> Result<Self, CarrierError> {
+        let carrier = Self::connect_lazy(config, circuit_breaker);
+        let (conn, permit) = carrier.conn().await?;
+        permit.success();
+        drop(conn);
+
+        info!("MySQLに接続できました: address:{}, port:{}", config.mysql_host, config.mysql_port);
+        Ok(carrier)
+    }
+
+    /// 接続を確認せずに作成する (データベースが停止していても成功し、使用する時に接続する)
+    pub fn connect_lazy(config: &CarrierConfig, circuit_breaker: &CircuitBreakerConfig) -> Self {
+        let constraints = PoolConstraints::new(0, config.mysql_pool_max_size).unwrap_or_default();
+        let opts: Opts = OptsBuilder::default()
+            .ip_or_hostname(config.mysql_host.clone())
+            .tcp_port(config.mysql_port)
+            .user(Some(config.mysql_user.clone()))
+            .pass(Some(config.mysql_password.clone()))
+            .db_name(Some(config.mysql_database.clone()))
+            .ssl_opts(config.mysql_tls.then(SslOpts::default))
+            .pool_opts(PoolOpts::default().with_constraints(constraints))
+            .into();
+
+        Self {
+            pool: Pool::new(opts.clone()),
+            opts,
+            config: config.clone(),
+            health: DatabaseHealth::new(circuit_breaker),
+        }
+    }
+
+    /// スキーマを作成し、作成した場合はtrueを返す (テーブルが存在する場合は何もしない)
+    pub async fn migrate(&self) -> Result<bool, CarrierError> {
+        let current = self.schema_version().await?.map(|(version, _)| version);
+        if let Some(version) = current {
+            if version > Self::SCHEMA_VERSION {
+                return Err(CarrierError::SchemaVersionError(version, Self::SCHEMA_VERSION));
+            }
+            if version == Self::SCHEMA_VERSION {
+                debug!("MySQLのスキーマは最新です (バージョン {})", version);
+                return Ok(false);
+            }
+        }
+
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            // DDLは暗黙にコミットされるため、文ごとに実行する (各文は既に存在する場合は何もしない)
+            for statement in SCHEMA.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+                conn.query_drop(statement).await?;
+            }
+            if let Some(version) = current {
+                for (_, sql) in UPGRADES.iter().filter(|(upgraded, _)| *upgraded > version) {
+                    conn.query_drop(*sql).await?;
+                }
+            }
+            conn.exec_drop(
+                "INSERT IGNORE INTO schema_migrations (version, applied_at) VALUES (?, UTC_TIMESTAMP(6))",
+                (Self::SCHEMA_VERSION,),
+            )
+            .await
+        }
+        .await;
+        Self::settle(permit, result)?;
+
+        info!("MySQLのスキーマを作成しました (バージョン {})", Self::SCHEMA_VERSION);
+        Ok(true)
+    }
+
+    /// スキーマが最新であることを確認する
+    pub async fn verify(&self) -> Result<(), CarrierError> {
+        let version = self.schema_version().await?.map(|(version, _)| version).unwrap_or(0);
+        if version > Self::SCHEMA_VERSION {
+            return Err(CarrierError::SchemaVersionError(version, Self::SCHEMA_VERSION));
+        }
+        if version < Self::SCHEMA_VERSION {
+            return Err(DatabaseError::PendingMigrations(version, Self::SCHEMA_VERSION).into());
+        }
+        Ok(())
+    }
+
+    /// 適用済みのスキーマのバージョンと適用時刻 (スキーマがない場合はNone)
+    pub async fn schema_version(&self) -> Result<Option<(i32, DateTime<Utc>)>, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            let exists: Option<i64> =
+                conn.query_first("SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = 'schema_migrations'").await?;
+            if exists.unwrap_or(0) == 0 {
+                return Ok(None);
+            }
+            conn.query_first::<(i32, NaiveDateTime), _>("SELECT version, applied_at FROM schema_migrations ORDER BY version DESC LIMIT 1").await
+        }
+        .await;
+        Ok(Self::settle(permit, result)?.map(|(version, applied_at)| (version, applied_at.and_utc())))
+    }
+
+    /// サーキットブレーカーを確認してから、プールから接続を取得する
+    async fn conn(&self) -> Result<(Conn, HealthPermit), CarrierError> {
+        let permit = self.health.try_acquire().ok_or_else(|| {
+            let retry_in = self.health.stats().retry_in.unwrap_or_default();
+            DatabaseError::CircuitOpenError(retry_in.as_millis() as u64)
+        })?;
+        match self.with_timeout(self.pool.get_conn()).await {
+            Ok(conn) => Ok((conn, permit)),
+            Err(e) => {
+                permit.failure(&e.to_string());
+                Err(e)
+            },
+        }
+    }
+
+    /// 接続の確立をmysql_connection_timeoutで打ち切る (MySQL以外のサーバーに接続した場合など、応答がないと待ち続けるため)
+    async fn with_timeout(&self, connecting: impl Future<Output = Result<Conn, mysql_async::Error>>) -> Result<Conn, CarrierError> {
+        match tokio::time::timeout(self.config.mysql_connection_timeout, connecting).await {
+            Ok(result) => result.map_err(Self::mysql_error),
+            Err(_) => Err(CarrierError::MysqlError(format!(
+                "接続が{}ms以内に確立できませんでした",
+                self.config.mysql_connection_timeout.as_millis()
+            ))),
+        }
+    }
+
+    /// 要求の結果をサーキットブレーカーに記録する
+    /// クエリ自体のエラー (制約違反など) は接続の障害として扱わず、通信に失敗した場合のみ失敗とする
+    fn settle<T>(permit: HealthPermit, result: Result<T, mysql_async::Error>) -> Result<T, CarrierError> {
+        match &result {
+            Err(e @ mysql_async::Error::Io(_)) => permit.failure(&e.to_string()),
+            _ => permit.success(),
+        }
+        result.map_err(Self::mysql_error)
+    }
+
+    fn mysql_error(e: mysql_async::Error) -> CarrierError {
+        CarrierError::MysqlError(e.to_string())
+    }
+
+    fn cursor(id: i64) -> DeliveryCursor {
+        DeliveryCursor { tx_id: id, packet_id: id }
+    }
+
+    fn micros(duration: Duration) -> i64 {
+        duration.as_micros() as i64
+    }
+
+    async fn live_id(conn: &mut Conn) -> Result<i64, mysql_async::Error> {
+        Ok(conn.query_first("SELECT COALESCE(MAX(id), 0) FROM packets").await?.unwrap_or(0))
+    }
+
+    fn column<T: FromValue>(row: &mut Row, name: &str) -> Result<T, CarrierError> {
+        match row.take_opt(name) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(e)) => Err(CarrierError::MysqlError(format!("列 {} の値を変換できません: {}", name, e))),
+            None => Err(CarrierError::MysqlError(format!("列 {} がありません", name))),
+        }
+    }
+
+    fn stored_packet(mut row: Row) -> Result<StoredPacket, CarrierError> {
+        let mac = |bytes: Vec<u8>| MacAddr(bytes.try_into().unwrap_or([0; 6]));
+        let ip = |text: String| InetAddr(text.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)));
+
+        Ok(StoredPacket {
+            id: Self::column(&mut row, "id")?,
+            node_id: Self::column(&mut row, "node_id")?,
+            stored_at: Self::column::<NaiveDateTime>(&mut row, "timestamp")?.and_utc(),
+            data: PacketData {
+                src_mac: mac(Self::column(&mut row, "src_mac")?),
+                dst_mac: mac(Self::column(&mut row, "dst_mac")?),
+                ether_type: EtherType::new(Self::column(&mut row, "ether_type")?),
+                src_ip: ip(Self::column(&mut row, "src_ip")?),
+                dst_ip: ip(Self::column(&mut row, "dst_ip")?),
+                src_port: Self::column(&mut row, "src_port")?,
+                dst_port: Self::column(&mut row, "dst_port")?,
+                ip_protocol: IpProtocol::new(Self::column(&mut row, "ip_protocol")?),
+                captured_at: Self::column::<NaiveDateTime>(&mut row, "captured_at")?.and_utc(),
+                raw_packet: Self::column(&mut row, "raw_packet")?,
+                key_id: Self::column(&mut row, "key_id")?,
+                signature: PacketSignature::from_columns(Self::column(&mut row, "seq")?, Self::column(&mut row, "signature")?),
+            },
+        })
+    }
+
+    /// startから1回のINSERTに含めるパケットの終端 (少なくとも1つは含める)
+    fn chunk_end(packets: &[PacketData], start: usize) -> usize {
+        let mut bytes = 0;
+        let mut end = start;
+        while end < packets.len() && end - start < MAX_ROWS_PER_INSERT {
+            bytes += packets[end].raw_packet.len();
+            if bytes > MAX_BYTES_PER_INSERT && end > start {
+                break;
+            }
+            end += 1;
+        }
+        end
+    }
+
+    async fn insert_chunk(tx: &mut mysql_async::Transaction<'_>, node_id: i16, packets: &[PacketData]) -> Result<(), mysql_async::Error> {
+        let rows = vec!["(UTC_TIMESTAMP(6), ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"; packets.len()].join(", ");
+        let query = format!(
+            "INSERT INTO packets (
+                timestamp, captured_at, node_id, src_mac, dst_mac, ether_type, ip_protocol,
+                src_ip, dst_ip, src_port, dst_port, raw_packet, key_id, seq, signature
+            ) VALUES {}",
+            rows
+        );
+
+        let params: Vec<Value> = packets
+            .iter()
+            .flat_map(|packet| {
+                [
+                    packet.captured_at.naive_utc().into(),
+                    node_id.into(),
+                    packet.src_mac.0.to_vec().into(),
+                    packet.dst_mac.0.to_vec().into(),
+                    packet.ether_type.value().into(),
+                    packet.ip_protocol.value().into(),
+                    packet.src_ip.0.to_string().into(),
+                    packet.dst_ip.0.to_string().into(),
+                    packet.src_port.into(),
+                    packet.dst_port.into(),
+                    packet.raw_packet.clone().into(),
+                    packet.key_id.into(),
+                    PacketSignature::seq_column(&packet.signature).into(),
+                    PacketSignature::signature_column(&packet.signature).into(),
+                ]
+            })
+            .collect();
+        tx.exec_drop(query, params).await
+    }
+
+    /// 条件に一致する行をPURGE_BATCH_SIZEずつ削除し、削除した行数を返す
+    async fn delete_in_batches(conn: &mut Conn, query: &str, params: Vec<Value>) -> Result<u64, mysql_async::Error> {
+        let query = format!("{} LIMIT {}", query, PURGE_BATCH_SIZE);
+        let mut deleted = 0;
+        loop {
+            conn.exec_drop(&query, params.clone()).await?;
+            let affected = conn.affected_rows();
+            deleted += affected;
+            if affected < PURGE_BATCH_SIZE {
+                return Ok(deleted);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Carrier for MysqlCarrier {
+    fn name(&self) -> &'static str {
+        "mysql"
+    }
+
+    fn health(&self) -> &DatabaseHealth {
+        &self.health
+    }
+
+    async fn prepare(&self, config: &AppConfig) -> Result<(), CarrierError> {
+        if config.database.auto_migrate {
+            self.migrate().await?;
+        } else {
+            self.verify().await?;
+        }
+
+        if let Some(threshold) = config.database.clock_skew_threshold {
+            let (mut conn, permit) = self.conn().await?;
+            let sent_at = Utc::now();
+            let db_now: Option<NaiveDateTime> = Self::settle(permit, conn.query_first("SELECT UTC_TIMESTAMP(6)").await)?;
+            let received_at = Utc::now();
+            if let Some(db_now) = db_now {
+                DbService::report_clock_skew(sent_at, db_now.and_utc(), received_at, threshold);
+            }
+        }
+
+        if config.retention.packets_max_size.is_some() {
+            warn!("retention.packets_max_size_mb はMySQLの媒体では使用できません");
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, node_id: i16, packets: &[PacketData]) -> Result<(), CarrierError> {
+        if packets.is_empty() {
+            return Ok(());
+        }
+
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            let mut tx = conn.start_transaction(TxOpts::default()).await?;
+            // 他のノードの書き込みと直列にし、idの順序とコミット順を一致させる (ロックはコミットまで保持される)
+            tx.query_drop("SELECT id FROM packet_writer_lock WHERE id = 1 FOR UPDATE").await?;
+            let mut start = 0;
+            while start < packets.len() {
+                let end = Self::chunk_end(packets, start);
+                Self::insert_chunk(&mut tx, node_id, &packets[start..end]).await?;
+                start = end;
+            }
+            tx.commit().await
+        }
+        .await;
+        Self::settle(permit, result)
+    }
+
+    async fn subscribe(&self) -> Result<Box<dyn CarrierSubscription>, CarrierError> {
+        // プールの接続を占有しないよう、専用の接続で確認する
+        let mut conn = self.with_timeout(Conn::new(self.opts.clone())).await?;
+        let last_id = Self::live_id(&mut conn).await.map_err(Self::mysql_error)?;
+        Ok(Box::new(MysqlSubscription {
+            conn,
+            last_id,
+            pending: VecDeque::new(),
+            interval: self.config.mysql_watch_interval,
+        }))
+    }
+
+    async fn fetch(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(Vec<FetchedPacket>, DeliveryCursor), CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let result = conn
+            .exec::<(i64, i16, NaiveDateTime, Vec<u8>, Option<i16>, Option<i64>, Option<Vec<u8>>), _, _>(
+                "SELECT id, node_id, captured_at, raw_packet, key_id, seq, signature FROM packets WHERE node_id <> ? AND id > ? ORDER BY id LIMIT ?",
+                (node_id, cursor.packet_id, PacketRepository::FETCH_LIMIT),
+            )
+            .await;
+        let rows = Self::settle(permit, result)?;
+
+        let next = rows.last().map(|(id, ..)| Self::cursor(*id)).unwrap_or(cursor);
+        Ok((
+            rows.into_iter()
+                .map(|(_, node_id, captured_at, raw_packet, key_id, seq, signature)| FetchedPacket {
+                    node_id,
+                    captured_at: captured_at.and_utc(),
+                    raw_packet,
+                    key_id,
+                    signature: PacketSignature::from_columns(seq, signature),
+                })
+                .collect(),
+            next,
+        ))
+    }
+
+    async fn acknowledge(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(), CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let query = "
+            INSERT INTO delivery_cursors (node_id, packet_id, updated_at) VALUES (?, ?, UTC_TIMESTAMP(6))
+            ON DUPLICATE KEY UPDATE packet_id = VALUES(packet_id), updated_at = VALUES(updated_at)";
+        let result = conn.exec_drop(query, (node_id, cursor.packet_id)).await;
+        Self::settle(permit, result)
+    }
+
+    async fn saved_cursor(&self, node_id: i16) -> Result<Option<DeliveryCursor>, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let result = conn.exec_first::<i64, _, _>("SELECT packet_id FROM delivery_cursors WHERE node_id = ?", (node_id,)).await;
+        Ok(Self::settle(permit, result)?.map(Self::cursor))
+    }
+
+    async fn acknowledge_seqs(&self, node_id: i16, seqs: &[ReceivedSeqs]) -> Result<(), CarrierError> {
+        if seqs.is_empty() {
+            return Ok(());
+        }
+
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            let mut tx = conn.start_transaction(TxOpts::default()).await?;
+            tx.exec_batch(
+                "INSERT INTO delivery_seqs (node_id, origin_node_id, highest_seq, updated_at) VALUES (?, ?, ?, UTC_TIMESTAMP(6))
+                 ON DUPLICATE KEY UPDATE highest_seq = VALUES(highest_seq), updated_at = VALUES(updated_at)",
+                seqs.iter().map(|received| (node_id, received.node_id, received.highest)),
+            )
+            .await?;
+            tx.exec_batch(
+                "DELETE FROM delivery_seq_ranges WHERE node_id = ? AND origin_node_id = ?",
+                seqs.iter().map(|received| (node_id, received.node_id)),
+            )
+            .await?;
+            tx.exec_batch(
+                "INSERT INTO delivery_seq_ranges (node_id, origin_node_id, first_seq, last_seq) VALUES (?, ?, ?, ?)",
+                ReceivedSeqs::range_rows(seqs).map(|(origin, first, last)| (node_id, origin, first, last)),
+            )
+            .await?;
+            tx.commit().await
+        }
+        .await;
+        Self::settle(permit, result)
+    }
+
+    async fn saved_seqs(&self, node_id: i16) -> Result<Vec<ReceivedSeqs>, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            let highest = conn.exec::<(i16, i64), _, _>("SELECT origin_node_id, highest_seq FROM delivery_seqs WHERE node_id = ?", (node_id,)).await?;
+            let ranges = conn.exec::<(i16, i64, i64), _, _>("SELECT origin_node_id, first_seq, last_seq FROM delivery_seq_ranges WHERE node_id = ?", (node_id,)).await?;
+            Ok(ReceivedSeqs::from_rows(highest, ranges))
+        }
+        .await;
+        Self::settle(permit, result)
+    }
+
+    async fn live_cursor(&self) -> Result<DeliveryCursor, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let result = Self::live_id(&mut conn).await;
+        Ok(Self::cursor(Self::settle(permit, result)?))
+    }
+
+    async fn cursor_since(&self, node_id: i16, max_age: Duration) -> Result<DeliveryCursor, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            let first: Option<Option<i64>> = conn
+                .exec_first(
+                    "SELECT MIN(id) FROM packets WHERE node_id <> ? AND timestamp >= UTC_TIMESTAMP(6) - INTERVAL ? MICROSECOND",
+                    (node_id, Self::micros(max_age)),
+                )
+                .await?;
+            match first.flatten() {
+                Some(id) => Ok(id - 1),
+                None => Self::live_id(&mut conn).await,
+            }
+        }
+        .await;
+        Ok(Self::cursor(Self::settle(permit, result)?))
+    }
+
+    async fn cursor_before_latest(&self, node_id: i16, count: i64) -> Result<DeliveryCursor, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let result = conn.exec_first::<i64, _, _>("SELECT id FROM packets WHERE node_id <> ? ORDER BY id DESC LIMIT 1 OFFSET ?", (node_id, count.max(0))).await;
+        Ok(Self::settle(permit, result)?.map(Self::cursor).unwrap_or_default())
+    }
+
+    async fn register_node(&self, node_id: i16, name: &str, description: Option<&str>) -> Result<(), CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let query = "
+            INSERT INTO node_list (id, name, description) VALUES (?, ?, ?)
+            ON DUPLICATE KEY UPDATE name = VALUES(name), description = VALUES(description)";
+        let result = conn.exec_drop(query, (node_id, name, description)).await;
+        Self::settle(permit, result)?;
+
+        info!("ノード {} ({}) を登録しました", node_id, name);
+        Ok(())
+    }
+
+    async fn set_node_key(&self, node_id: i16, public_key: Option<&[u8]>) -> Result<(), CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            // 同じ値で更新した場合も一致した行数を返すよう、存在を確認してから更新する
+            let exists: Option<i16> = conn.exec_first("SELECT id FROM node_list WHERE id = ?", (node_id,)).await?;
+            if exists.is_some() {
+                conn.exec_drop("UPDATE node_list SET public_key = ? WHERE id = ?", (public_key, node_id)).await?;
+            }
+            Ok(exists.is_some())
+        }
+        .await;
+        if !Self::settle(permit, result)? {
+            return Err(ServiceError::NodeNotFound(node_id).into());
+        }
+        Ok(())
+    }
+
+    async fn list_nodes(&self) -> Result<Vec<NodeRecord>, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let query = "
+            SELECT n.id, n.name, n.description, n.public_key, MAX(a.boot_time) AS last_boot_time
+            FROM node_list n
+            LEFT JOIN node_activity a ON a.node_id = n.id
+            GROUP BY n.id, n.name, n.description, n.public_key
+            ORDER BY n.id";
+        let result = conn.query::<(i16, String, Option<String>, Option<Vec<u8>>, Option<NaiveDateTime>), _>(query).await;
+        Ok(Self::settle(permit, result)?
+            .into_iter()
+            .map(|(id, name, description, public_key, last_boot_time)| NodeRecord {
+                id,
+                name,
+                description,
+                last_boot_time: last_boot_time.map(|t| t.and_utc()),
+                public_key,
+            })
+            .collect())
+    }
+
+    async fn record_boot(&self, node_id: i16, interface: &NetworkInterface) -> Result<String, CarrierError> {
+        let mac_address = interface.mac.map(|mac| mac.to_string()).unwrap_or_else(|| "00:00:00:00:00:00".to_string());
+        let ip_addresses: Vec<String> = interface.ips.iter().map(|ip| ip.to_string()).collect();
+        let ip_addresses = if ip_addresses.is_empty() { "0.0.0.0/0".to_string() } else { ip_addresses.join(",") };
+
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            let name: Option<String> = conn.exec_first("SELECT name FROM node_list WHERE id = ?", (node_id,)).await?;
+            if name.is_some() {
+                let query = "
+                    INSERT INTO node_activity (node_id, boot_time, interface_name, mac_address, ip_addresses)
+                    VALUES (?, UTC_TIMESTAMP(6), ?, ?, ?)";
+                conn.exec_drop(query, (node_id, &interface.name, &mac_address, &ip_addresses)).await?;
+            }
+            Ok(name)
+        }
+        .await;
+        let name = Self::settle(permit, result)?.ok_or(ServiceError::NodeNotFound(node_id))?;
+
+        info!("ノードID {} の起動を記録しました (activity_id: {})", node_id, conn.last_insert_id().unwrap_or_default());
+        info!("インターフェース: {}, MACアドレス: {}, IPアドレス: {}", interface.name, mac_address, ip_addresses);
+        Ok(name)
+    }
+
+    async fn load_firewall(&self, node_id: i16, fallback_policy: Policy) -> Result<IpFirewall, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let query = "
+            SELECT filter_type, filter_value, priority, policy
+            FROM firewall_settings
+            WHERE (node_id = ? OR node_id IS NULL)
+            ORDER BY priority DESC";
+        let result = conn.exec::<(String, String, i16, String), _, _>(query, (node_id,)).await;
+        let rules: Vec<FirewallRuleRow> = Self::settle(permit, result)?
+            .into_iter()
+            .map(|(filter_type, filter_value, priority, policy)| FirewallRuleRow {
+                filter_type,
+                filter_value,
+                priority,
+                policy,
+            })
+            .collect();
+
+        Ok(DbService::build_firewall(node_id, fallback_policy, &rules)?)
+    }
+
+    async fn query_packets(&self, query: &PacketQuery) -> Result<Vec<StoredPacket>, CarrierError> {
+        let (mut conn, permit) = self.conn().await?;
+        let sql = "
+            SELECT id, node_id, timestamp, captured_at, src_mac, dst_mac, ether_type, ip_protocol,
+                   src_ip, dst_ip, src_port, dst_port, raw_packet, key_id, seq, signature
+            FROM packets
+            WHERE (:node_id IS NULL OR node_id = :node_id)
+                AND (:since IS NULL OR timestamp >= :since)
+                AND (:until IS NULL OR timestamp < :until)
+            ORDER BY timestamp DESC, id DESC
+            LIMIT :limit";
+        let params = params! {
+            "node_id" => query.node_id,
+            "since" => query.since.map(|t| t.naive_utc()),
+            "until" => query.until.map(|t| t.naive_utc()),
+            "limit" => query.limit.max(0),
+        };
+        let result = conn.exec::<Row, _, _>(sql, params).await;
+        Self::settle(permit, result)?.into_iter().map(Self::stored_packet).collect()
+    }
+
+    async fn purge(&self, config: &RetentionConfig) -> Result<PurgeReport, CarrierError> {
+        let mut report = PurgeReport::default();
+        let (mut conn, permit) = self.conn().await?;
+        let result = async {
+            if let Some(max_age) = config.packets_max_age {
+                let query = "DELETE FROM packets WHERE timestamp < UTC_TIMESTAMP(6) - INTERVAL ? MICROSECOND ORDER BY id";
+                report.deleted_packets += Self::delete_in_batches(&mut conn, query, vec![Self::micros(max_age).into()]).await?;
+            }
+
+            for (node_id, max_age) in &config.node_max_age {
+                // 全体の保持期間で削除される範囲は対象外
+                if config.packets_max_age.is_some_and(|global| global <= *max_age) {
+                    continue;
+                }
+                let query = "DELETE FROM packets WHERE node_id = ? AND timestamp < UTC_TIMESTAMP(6) - INTERVAL ? MICROSECOND ORDER BY id";
+                report.deleted_packets += Self::delete_in_batches(&mut conn, query, vec![(*node_id).into(), Self::micros(*max_age).into()]).await?;
+            }
+
+            if let Some(max_age) = config.node_activity_max_age {
+                // 最終起動時刻が分からなくならないよう、ノードごとの最新の記録は残す
+                // (MySQLは削除対象のテーブルを直接参照するサブクエリを使えないため、導出テーブルを経由する)
+                let query = "
+                    DELETE FROM node_activity
+                    WHERE boot_time < UTC_TIMESTAMP(6) - INTERVAL ? MICROSECOND
+                        AND id NOT IN (SELECT id FROM (SELECT MAX(id) AS id FROM node_activity GROUP BY node_id) AS latest)";
+                conn.exec_drop(query, (Self::micros(max_age),)).await?;
+                report.deleted_activities += conn.affected_rows();
+            }
+            Ok(())
+        }
+        .await;
+        Self::settle(permit, result)?;
+
+        if config.packets_max_size.is_some() {
+            debug!("MySQLの媒体ではサイズによる削除を行いません");
+        }
+        if report.deleted_packets > 0 || report.deleted_activities > 0 {
+            info!(
+                "保持期間を超えたデータを削除しました (packets: {}行, node_activity: {}行)",
+                report.deleted_packets, report.deleted_activities
+            );
+        }
+        Ok(report)
+    }
+}
+
+/// 他のノードの書き込みをwatch_intervalごとに確認する (MySQLには通知の仕組みがないため)
+struct MysqlSubscription {
+    conn: Conn,
+    // 確認済みの最後のパケットID
+    last_id: i64,
+    // 確認した書き込みのうち、まだ返していないノードID
+    pending: VecDeque<i16>,
+    interval: Duration,
+}
+
+impl MysqlSubscription {
+    async fn check(&mut self) -> Result<(), mysql_async::Error> {
+        let writers: Vec<(i16, i64)> = self.conn.exec("SELECT node_id, MAX(id) FROM packets WHERE id > ? GROUP BY node_id", (self.last_id,)).await?;
+        for (node_id, max_id) in writers {
+            self.last_id = self.last_id.max(max_id);
+            self.pending.push_back(node_id);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CarrierSubscription for MysqlSubscription {
+    async fn recv(&mut self) -> Option<i16> {
+        loop {
+            if let Some(node_id) = self.pending.pop_front() {
+                return Some(node_id);
+            }
+            // 切断された場合はNoneを返し、読み取り側に再接続させる
+            if let Err(e) = self.check().await {
+                debug!("MySQLの書き込みの確認に失敗しました: {}", e);
+                return None;
+            }
+            if self.pending.is_empty() {
+                tokio::time::sleep(self.interval).await;
+            }
+        }
+    }
+
+    fn try_recv(&mut self) -> Option<i16> {
+        self.pending.pop_front()
+    }
+}
@@ -0,0 +1,185 @@
+use crate::carrier::carrier::{Carrier, CarrierSubscription};
+use crate::carrier::error::CarrierError;
+use crate::config::{AppConfig, BatchingConfig, RetentionConfig};
+use crate::crypto::ReceivedSeqs;
+use crate::database::{Database, DatabaseHealth, NotificationListener, PacketPartitions, Schema};
+use crate::packet::analysis::{IpFirewall, Policy};
+use crate::packet::repository::{DeliveryCursor, HeldBackPackets, PacketQuery, PacketRepository};
+use crate::packet::{FetchedPacket, PacketData, StoredPacket};
+use crate::services::{DbService, NodeRecord, PurgeReport, RetentionService};
+use async_trait::async_trait;
+use log::{debug, warn};
+use pnet::datalink::NetworkInterface;
+use std::time::Duration;
+
+/// PostgreSQL (TimescaleDB) のpacketsテーブルを経由してパケットを受け渡す
+pub struct PostgresCarrier {
+    database: Database,
+    // パケットの書き込みと通知の設定 (管理コマンド用に作成した場合はNone)
+    transport: Option<Transport>,
+}
+
+struct Transport {
+    batching: BatchingConfig,
+    // 書き込みを通知するLISTEN/NOTIFYのチャネル
+    channel: String,
+}
+
+impl PostgresCarrier {
+    pub fn new(database: Database, config: &AppConfig) -> Self {
+        Self {
+            database,
+            transport: Some(Transport {
+                batching: config.batching.clone(),
+                channel: config.delivery.channel.clone(),
+            }),
+        }
+    }
+
+    /// ノードの登録や削除などの管理コマンド用 (ノードの設定を読み込まないため、パケットの書き込みと通知の待機はできない)
+    pub fn for_management(database: Database) -> Self {
+        Self { database, transport: None }
+    }
+
+    fn transport(&self) -> Result<&Transport, CarrierError> {
+        self.transport.as_ref().ok_or(CarrierError::ManagementOnlyError)
+    }
+}
+
+#[async_trait]
+impl Carrier for PostgresCarrier {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn health(&self) -> &DatabaseHealth {
+        self.database.health()
+    }
+
+    fn database(&self) -> Option<&Database> {
+        Some(&self.database)
+    }
+
+    async fn prepare(&self, config: &AppConfig) -> Result<(), CarrierError> {
+        let db = &self.database;
+        if config.database.auto_migrate {
+            Schema::migrate(db).await?;
+        } else {
+            Schema::verify(db).await?;
+        }
+
+        // TimescaleDBがない場合は、書き込みを始める前に現在の時刻のパーティションを用意する
+        if PacketPartitions::is_partitioned(db).await? {
+            PacketPartitions::create_upcoming(db, &config.partition).await?;
+        }
+
+        if let Some(threshold) = config.database.clock_skew_threshold {
+            DbService::check_clock_skew(db, threshold).await?;
+        }
+
+        RetentionService::install(db, &config.retention).await?;
+        Ok(())
+    }
+
+    async fn publish(&self, node_id: i16, packets: &[PacketData]) -> Result<(), CarrierError> {
+        let transport = self.transport()?;
+        PacketRepository::bulk_insert(&self.database, node_id, packets, &transport.batching).await?;
+
+        // 通知に失敗しても、読み取り側は一定間隔の読み取りで補うため書き込みは失敗としない
+        if let Err(e) = PacketRepository::notify_inserted(&self.database, &transport.channel, node_id).await {
+            warn!("書き込みの通知に失敗しました: {}", e);
+        }
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> Result<Box<dyn CarrierSubscription>, CarrierError> {
+        Ok(Box::new(self.database.listen(&self.transport()?.channel).await?))
+    }
+
+    async fn fetch(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(Vec<FetchedPacket>, DeliveryCursor), CarrierError> {
+        Ok(PacketRepository::fetch_after(&self.database, node_id, cursor).await?)
+    }
+
+    async fn held_back(&self, node_id: i16, cursor: DeliveryCursor) -> Result<Option<HeldBackPackets>, CarrierError> {
+        Ok(PacketRepository::held_back(&self.database, node_id, cursor).await?)
+    }
+
+    async fn acknowledge(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(), CarrierError> {
+        Ok(PacketRepository::save_cursor(&self.database, node_id, cursor).await?)
+    }
+
+    async fn saved_cursor(&self, node_id: i16) -> Result<Option<DeliveryCursor>, CarrierError> {
+        Ok(PacketRepository::load_cursor(&self.database, node_id).await?)
+    }
+
+    async fn acknowledge_seqs(&self, node_id: i16, seqs: &[ReceivedSeqs]) -> Result<(), CarrierError> {
+        Ok(PacketRepository::save_seqs(&self.database, node_id, seqs).await?)
+    }
+
+    async fn saved_seqs(&self, node_id: i16) -> Result<Vec<ReceivedSeqs>, CarrierError> {
+        Ok(PacketRepository::load_seqs(&self.database, node_id).await?)
+    }
+
+    async fn live_cursor(&self) -> Result<DeliveryCursor, CarrierError> {
+        Ok(PacketRepository::live_cursor(&self.database).await?)
+    }
+
+    async fn cursor_since(&self, node_id: i16, max_age: Duration) -> Result<DeliveryCursor, CarrierError> {
+        Ok(PacketRepository::cursor_since(&self.database, node_id, max_age).await?)
+    }
+
+    async fn cursor_before_latest(&self, node_id: i16, count: i64) -> Result<DeliveryCursor, CarrierError> {
+        Ok(PacketRepository::cursor_before_latest(&self.database, node_id, count).await?)
+    }
+
+    async fn register_node(&self, node_id: i16, name: &str, description: Option<&str>) -> Result<(), CarrierError> {
+        Ok(DbService::register_node(&self.database, node_id, name, description).await?)
+    }
+
+    async fn set_node_key(&self, node_id: i16, public_key: Option<&[u8]>) -> Result<(), CarrierError> {
+        Ok(DbService::set_node_key(&self.database, node_id, public_key).await?)
+    }
+
+    async fn list_nodes(&self) -> Result<Vec<NodeRecord>, CarrierError> {
+        Ok(DbService::list_nodes(&self.database).await?)
+    }
+
+    async fn record_boot(&self, node_id: i16, interface: &NetworkInterface) -> Result<String, CarrierError> {
+        Ok(DbService::validate_and_record_node(&self.database, node_id, interface).await?)
+    }
+
+    async fn load_firewall(&self, node_id: i16, fallback_policy: Policy) -> Result<IpFirewall, CarrierError> {
+        Ok(DbService::load_firewall_settings(&self.database, node_id, fallback_policy).await?)
+    }
+
+    async fn query_packets(&self, query: &PacketQuery) -> Result<Vec<StoredPacket>, CarrierError> {
+        Ok(PacketRepository::query_packets(&self.database, query).await?)
+    }
+
+    async fn purge(&self, config: &RetentionConfig) -> Result<PurgeReport, CarrierError> {
+        Ok(RetentionService::purge(&self.database, config).await?)
+    }
+}
+
+#[async_trait]
+impl CarrierSubscription for NotificationListener {
+    async fn recv(&mut self) -> Option<i16> {
+        loop {
+            let notification = NotificationListener::recv(self).await?;
+            match notification.payload().parse() {
+                Ok(node_id) => return Some(node_id),
+                // 他のアプリケーションが同じチャネルに通知した場合など
+                Err(_) => debug!("ペイロードがノードIDではない通知を無視しました: {}", notification.payload()),
+            }
+        }
+    }
+
+    fn try_recv(&mut self) -> Option<i16> {
+        while let Some(notification) = NotificationListener::try_recv(self) {
+            if let Ok(node_id) = notification.payload().parse() {
+                return Some(node_id);
+            }
+        }
+        None
+    }
+}
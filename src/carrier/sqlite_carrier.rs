@@ -0,0 +1,599 @@
+use crate::carrier::carrier::{unmonitored_health, Carrier, CarrierSubscription};
+use crate::carrier::error::CarrierError;
+use crate::config::{AppConfig, CarrierConfig, RetentionConfig};
+use crate::crypto::ReceivedSeqs;
+use crate::database::DatabaseHealth;
+use crate::packet::analysis::{IpFirewall, Policy};
+use crate::packet::repository::{DeliveryCursor, PacketQuery, PacketRepository};
+use crate::packet::types::{EtherType, IpProtocol};
+use crate::packet::{FetchedPacket, InetAddr, MacAddr, PacketData, PacketSignature, StoredPacket};
+use crate::services::{DbService, FirewallRuleRow, NodeRecord, PurgeReport, ServiceError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use pnet::datalink::NetworkInterface;
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const SCHEMA: &str = include_str!("../../resource/sqlite/schema.sql");
+
+// 既存のデータベースに適用する変更 (変更後のバージョン, SQL)
+// SCHEMAは既に存在するテーブルを変更しないため、列の追加などはここに追加する
+const UPGRADES: &[(i32, &str)] = &[
+    (2, "ALTER TABLE packets ADD COLUMN key_id INTEGER"),
+    (
+        3,
+        "ALTER TABLE packets ADD COLUMN seq INTEGER;
+         ALTER TABLE packets ADD COLUMN signature BLOB;
+         ALTER TABLE node_list ADD COLUMN public_key BLOB;",
+    ),
+];
+
+/// 1台のホスト上のSQLiteのファイルを経由してパケットを受け渡す
+/// 同じファイルを開いた複数のプロセス (ネットワーク名前空間で分けたノードなど) の間で受け渡せる
+/// 書き込みは直列に行われるため、読み取り位置はtx_id・idともにpacketsのidとする
+pub struct SqliteCarrier {
+    connection: Arc<Mutex<Connection>>,
+    config: CarrierConfig,
+    // 接続障害がないため、常に要求を通す
+    health: DatabaseHealth,
+}
+
+impl SqliteCarrier {
+    /// このバイナリが作成するスキーマのバージョン (PRAGMA user_version)
+    pub const SCHEMA_VERSION: i32 = 3;
+
+    /// データベースファイルを開き、スキーマがなければ作成する
+    pub fn open(config: &CarrierConfig) -> Result<Self, CarrierError> {
+        let connection = Self::connect(config)?;
+
+        let version: i32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0)).map_err(Self::sqlite_error)?;
+        if version > Self::SCHEMA_VERSION {
+            return Err(CarrierError::SchemaVersionError(version, Self::SCHEMA_VERSION));
+        }
+        connection.execute_batch(SCHEMA).map_err(Self::sqlite_error)?;
+        if version > 0 {
+            for (_, sql) in UPGRADES.iter().filter(|(upgraded, _)| *upgraded > version) {
+                connection.execute_batch(sql).map_err(Self::sqlite_error)?;
+            }
+        }
+        connection.pragma_update(None, "user_version", Self::SCHEMA_VERSION).map_err(Self::sqlite_error)?;
+
+        info!("SQLiteのデータベースを開きました: {}", config.sqlite_path.display());
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            config: config.clone(),
+            health: unmonitored_health(),
+        })
+    }
+
+    fn connect(config: &CarrierConfig) -> Result<Connection, CarrierError> {
+        let connection = Connection::open(&config.sqlite_path).map_err(Self::sqlite_error)?;
+        connection.busy_timeout(config.sqlite_busy_timeout).map_err(Self::sqlite_error)?;
+        // 他のプロセスの書き込み中も読み取れるようにする
+        connection.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(())).map_err(Self::sqlite_error)?;
+        connection.execute_batch("PRAGMA synchronous = NORMAL; PRAGMA foreign_keys = ON;").map_err(Self::sqlite_error)?;
+        Ok(connection)
+    }
+
+    /// 接続を使う処理をブロッキング用のスレッドで実行する
+    async fn run<T, F>(&self, f: F) -> Result<T, CarrierError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, CarrierError> + Send + 'static,
+    {
+        Self::run_on(Arc::clone(&self.connection), f).await
+    }
+
+    async fn run_on<T, F>(connection: Arc<Mutex<Connection>>, f: F) -> Result<T, CarrierError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, CarrierError> + Send + 'static,
+    {
+        tokio::task::spawn_blocking(move || {
+            // パニックしたスレッドが保持していても、トランザクションはロールバックされているため使い続ける
+            let mut connection = connection.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut connection)
+        })
+        .await
+        .map_err(|e| CarrierError::SqliteError(e.to_string()))?
+    }
+
+    fn sqlite_error(e: rusqlite::Error) -> CarrierError {
+        CarrierError::SqliteError(e.to_string())
+    }
+
+    fn cursor(id: i64) -> DeliveryCursor {
+        DeliveryCursor { tx_id: id, packet_id: id }
+    }
+
+    fn to_micros(time: DateTime<Utc>) -> i64 {
+        time.timestamp_micros()
+    }
+
+    fn from_micros(micros: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp_micros(micros).unwrap_or_default()
+    }
+
+    fn live_id(connection: &Connection) -> Result<i64, CarrierError> {
+        connection.query_row("SELECT COALESCE(MAX(id), 0) FROM packets", [], |row| row.get(0)).map_err(Self::sqlite_error)
+    }
+
+    fn stored_packet(row: &Row) -> rusqlite::Result<StoredPacket> {
+        let mac = |index: &str| -> rusqlite::Result<MacAddr> { Ok(MacAddr(row.get::<_, Vec<u8>>(index)?.try_into().unwrap_or([0; 6]))) };
+        let ip = |index: &str| -> rusqlite::Result<InetAddr> { Ok(InetAddr(row.get::<_, String>(index)?.parse().unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)))) };
+
+        Ok(StoredPacket {
+            id: row.get("id")?,
+            node_id: row.get("node_id")?,
+            stored_at: Self::from_micros(row.get("timestamp")?),
+            data: PacketData {
+                src_mac: mac("src_mac")?,
+                dst_mac: mac("dst_mac")?,
+                ether_type: EtherType::new(row.get("ether_type")?),
+                src_ip: ip("src_ip")?,
+                dst_ip: ip("dst_ip")?,
+                src_port: row.get("src_port")?,
+                dst_port: row.get("dst_port")?,
+                ip_protocol: IpProtocol::new(row.get("ip_protocol")?),
+                captured_at: Self::from_micros(row.get("captured_at")?),
+                raw_packet: row.get("raw_packet")?,
+                key_id: row.get("key_id")?,
+                signature: PacketSignature::from_columns(row.get("seq")?, row.get("signature")?),
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl Carrier for SqliteCarrier {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn health(&self) -> &DatabaseHealth {
+        &self.health
+    }
+
+    async fn prepare(&self, config: &AppConfig) -> Result<(), CarrierError> {
+        if config.retention.packets_max_size.is_some() {
+            warn!("retention.packets_max_size_mb はSQLiteの媒体では使用できません");
+        }
+        debug!("SQLiteのスキーマはデータベースを開いた時に作成済みです (バージョン {})", Self::SCHEMA_VERSION);
+        Ok(())
+    }
+
+    async fn publish(&self, node_id: i16, packets: &[PacketData]) -> Result<(), CarrierError> {
+        if packets.is_empty() {
+            return Ok(());
+        }
+
+        let packets = packets.to_vec();
+        self.run(move |connection| {
+            // 書き込みのロックを最初に取得し、他のノードの書き込みと順番に実行する
+            let tx = connection.transaction_with_behavior(TransactionBehavior::Immediate).map_err(Self::sqlite_error)?;
+            {
+                let mut statement = tx
+                    .prepare_cached(
+                        "INSERT INTO packets (
+                            timestamp, captured_at, node_id, src_mac, dst_mac, ether_type, ip_protocol,
+                            src_ip, dst_ip, src_port, dst_port, raw_packet, key_id, seq, signature
+                        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                    )
+                    .map_err(Self::sqlite_error)?;
+
+                let stored_at = Self::to_micros(Utc::now());
+                for packet in &packets {
+                    statement
+                        .execute(params![
+                            stored_at,
+                            Self::to_micros(packet.captured_at),
+                            node_id,
+                            &packet.src_mac.0[..],
+                            &packet.dst_mac.0[..],
+                            packet.ether_type.value(),
+                            packet.ip_protocol.value(),
+                            packet.src_ip.0.to_string(),
+                            packet.dst_ip.0.to_string(),
+                            packet.src_port,
+                            packet.dst_port,
+                            &packet.raw_packet,
+                            packet.key_id,
+                            PacketSignature::seq_column(&packet.signature),
+                            PacketSignature::signature_column(&packet.signature),
+                        ])
+                        .map_err(Self::sqlite_error)?;
+                }
+            }
+            tx.commit().map_err(Self::sqlite_error)
+        })
+        .await
+    }
+
+    async fn subscribe(&self) -> Result<Box<dyn CarrierSubscription>, CarrierError> {
+        // 読み取り用の接続を書き込みと共有しないよう、専用の接続で確認する
+        let connection = Self::connect(&self.config)?;
+        let last_id = Self::live_id(&connection)?;
+        Ok(Box::new(SqliteSubscription {
+            connection: Arc::new(Mutex::new(connection)),
+            last_id,
+            pending: VecDeque::new(),
+            interval: self.config.sqlite_watch_interval,
+        }))
+    }
+
+    async fn fetch(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(Vec<FetchedPacket>, DeliveryCursor), CarrierError> {
+        self.run(move |connection| {
+            let mut statement = connection
+                .prepare_cached("SELECT id, node_id, captured_at, raw_packet, key_id, seq, signature FROM packets WHERE node_id != ?1 AND id > ?2 ORDER BY id LIMIT ?3")
+                .map_err(Self::sqlite_error)?;
+            let rows = statement
+                .query_map(params![node_id, cursor.packet_id, PacketRepository::FETCH_LIMIT], |row| {
+                    Ok((
+                        row.get::<_, i64>("id")?,
+                        FetchedPacket {
+                            node_id: row.get("node_id")?,
+                            captured_at: Self::from_micros(row.get("captured_at")?),
+                            raw_packet: row.get("raw_packet")?,
+                            key_id: row.get("key_id")?,
+                            signature: PacketSignature::from_columns(row.get("seq")?, row.get("signature")?),
+                        },
+                    ))
+                })
+                .map_err(Self::sqlite_error)?
+                .collect::<Result<Vec<(i64, FetchedPacket)>, _>>()
+                .map_err(Self::sqlite_error)?;
+
+            let next = rows.last().map(|(id, _)| Self::cursor(*id)).unwrap_or(cursor);
+            Ok((rows.into_iter().map(|(_, packet)| packet).collect(), next))
+        })
+        .await
+    }
+
+    async fn acknowledge(&self, node_id: i16, cursor: DeliveryCursor) -> Result<(), CarrierError> {
+        self.run(move |connection| {
+            let query = "
+                INSERT INTO delivery_cursors (node_id, packet_id, updated_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT (node_id) DO UPDATE SET packet_id = excluded.packet_id, updated_at = excluded.updated_at";
+            connection.execute(query, params![node_id, cursor.packet_id, Self::to_micros(Utc::now())]).map_err(Self::sqlite_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn saved_cursor(&self, node_id: i16) -> Result<Option<DeliveryCursor>, CarrierError> {
+        self.run(move |connection| {
+            let id: Option<i64> =
+                connection.query_row("SELECT packet_id FROM delivery_cursors WHERE node_id = ?1", params![node_id], |row| row.get(0)).optional().map_err(Self::sqlite_error)?;
+            Ok(id.map(Self::cursor))
+        })
+        .await
+    }
+
+    async fn acknowledge_seqs(&self, node_id: i16, seqs: &[ReceivedSeqs]) -> Result<(), CarrierError> {
+        if seqs.is_empty() {
+            return Ok(());
+        }
+
+        let seqs = seqs.to_vec();
+        self.run(move |connection| {
+            let tx = connection.transaction().map_err(Self::sqlite_error)?;
+            {
+                let mut upsert = tx
+                    .prepare_cached(
+                        "INSERT INTO delivery_seqs (node_id, origin_node_id, highest_seq, updated_at) VALUES (?1, ?2, ?3, ?4)
+                         ON CONFLICT (node_id, origin_node_id) DO UPDATE SET highest_seq = excluded.highest_seq, updated_at = excluded.updated_at",
+                    )
+                    .map_err(Self::sqlite_error)?;
+                let mut delete = tx.prepare_cached("DELETE FROM delivery_seq_ranges WHERE node_id = ?1 AND origin_node_id = ?2").map_err(Self::sqlite_error)?;
+                let updated_at = Self::to_micros(Utc::now());
+                for received in &seqs {
+                    upsert.execute(params![node_id, received.node_id, received.highest, updated_at]).map_err(Self::sqlite_error)?;
+                    delete.execute(params![node_id, received.node_id]).map_err(Self::sqlite_error)?;
+                }
+                let mut insert =
+                    tx.prepare_cached("INSERT INTO delivery_seq_ranges (node_id, origin_node_id, first_seq, last_seq) VALUES (?1, ?2, ?3, ?4)").map_err(Self::sqlite_error)?;
+                for (origin, first, last) in ReceivedSeqs::range_rows(&seqs) {
+                    insert.execute(params![node_id, origin, first, last]).map_err(Self::sqlite_error)?;
+                }
+            }
+            tx.commit().map_err(Self::sqlite_error)
+        })
+        .await
+    }
+
+    async fn saved_seqs(&self, node_id: i16) -> Result<Vec<ReceivedSeqs>, CarrierError> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare_cached("SELECT origin_node_id, highest_seq FROM delivery_seqs WHERE node_id = ?1").map_err(Self::sqlite_error)?;
+            let highest = statement
+                .query_map(params![node_id], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(Self::sqlite_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Self::sqlite_error)?;
+            let mut statement = connection.prepare_cached("SELECT origin_node_id, first_seq, last_seq FROM delivery_seq_ranges WHERE node_id = ?1").map_err(Self::sqlite_error)?;
+            let ranges = statement
+                .query_map(params![node_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .map_err(Self::sqlite_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Self::sqlite_error)?;
+            Ok(ReceivedSeqs::from_rows(highest, ranges))
+        })
+        .await
+    }
+
+    async fn live_cursor(&self) -> Result<DeliveryCursor, CarrierError> {
+        self.run(|connection| Ok(Self::cursor(Self::live_id(connection)?))).await
+    }
+
+    async fn cursor_since(&self, node_id: i16, max_age: Duration) -> Result<DeliveryCursor, CarrierError> {
+        let since = Self::to_micros(Utc::now()) - max_age.as_micros() as i64;
+        self.run(move |connection| {
+            let first: Option<i64> = connection
+                .query_row("SELECT MIN(id) FROM packets WHERE node_id != ?1 AND timestamp >= ?2", params![node_id, since], |row| {
+                    row.get(0)
+                })
+                .map_err(Self::sqlite_error)?;
+            match first {
+                Some(id) => Ok(Self::cursor(id - 1)),
+                None => Ok(Self::cursor(Self::live_id(connection)?)),
+            }
+        })
+        .await
+    }
+
+    async fn cursor_before_latest(&self, node_id: i16, count: i64) -> Result<DeliveryCursor, CarrierError> {
+        self.run(move |connection| {
+            let id: Option<i64> = connection
+                .query_row(
+                    "SELECT id FROM packets WHERE node_id != ?1 ORDER BY id DESC LIMIT 1 OFFSET ?2",
+                    params![node_id, count],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(Self::sqlite_error)?;
+            Ok(id.map(Self::cursor).unwrap_or_default())
+        })
+        .await
+    }
+
+    async fn register_node(&self, node_id: i16, name: &str, description: Option<&str>) -> Result<(), CarrierError> {
+        let (name, description) = (name.to_string(), description.map(str::to_string));
+        self.run(move |connection| {
+            let query = "
+                INSERT INTO node_list (id, name, description) VALUES (?1, ?2, ?3)
+                ON CONFLICT (id) DO UPDATE SET name = excluded.name, description = excluded.description";
+            connection.execute(query, params![node_id, name, description]).map_err(Self::sqlite_error)?;
+
+            info!("ノード {} ({}) を登録しました", node_id, name);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_node_key(&self, node_id: i16, public_key: Option<&[u8]>) -> Result<(), CarrierError> {
+        let public_key = public_key.map(<[u8]>::to_vec);
+        self.run(move |connection| {
+            let updated = connection.execute("UPDATE node_list SET public_key = ?2 WHERE id = ?1", params![node_id, public_key]).map_err(Self::sqlite_error)?;
+            if updated == 0 {
+                return Err(ServiceError::NodeNotFound(node_id).into());
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn list_nodes(&self) -> Result<Vec<NodeRecord>, CarrierError> {
+        self.run(|connection| {
+            let query = "
+                SELECT n.id, n.name, n.description, n.public_key, MAX(a.boot_time) AS last_boot_time
+                FROM node_list n
+                LEFT JOIN node_activity a ON a.node_id = n.id
+                GROUP BY n.id, n.name, n.description, n.public_key
+                ORDER BY n.id";
+            let mut statement = connection.prepare(query).map_err(Self::sqlite_error)?;
+            let nodes = statement
+                .query_map([], |row| {
+                    Ok(NodeRecord {
+                        id: row.get("id")?,
+                        name: row.get("name")?,
+                        description: row.get("description")?,
+                        last_boot_time: row.get::<_, Option<i64>>("last_boot_time")?.map(Self::from_micros),
+                        public_key: row.get("public_key")?,
+                    })
+                })
+                .map_err(Self::sqlite_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Self::sqlite_error)?;
+            Ok(nodes)
+        })
+        .await
+    }
+
+    async fn record_boot(&self, node_id: i16, interface: &NetworkInterface) -> Result<String, CarrierError> {
+        let interface_name = interface.name.clone();
+        let mac_address = interface.mac.map(|mac| mac.to_string()).unwrap_or_else(|| "00:00:00:00:00:00".to_string());
+        let ip_addresses: Vec<String> = interface.ips.iter().map(|ip| ip.to_string()).collect();
+        let ip_addresses = if ip_addresses.is_empty() { "0.0.0.0/0".to_string() } else { ip_addresses.join(",") };
+
+        self.run(move |connection| {
+            let name: Option<String> =
+                connection.query_row("SELECT name FROM node_list WHERE id = ?1", params![node_id], |row| row.get(0)).optional().map_err(Self::sqlite_error)?;
+            let name = name.ok_or(ServiceError::NodeNotFound(node_id))?;
+
+            let query = "INSERT INTO node_activity (node_id, boot_time, interface_name, mac_address, ip_addresses) VALUES (?1, ?2, ?3, ?4, ?5)";
+            connection.execute(query, params![node_id, Self::to_micros(Utc::now()), interface_name, mac_address, ip_addresses]).map_err(Self::sqlite_error)?;
+
+            info!("ノードID {} の起動を記録しました (activity_id: {})", node_id, connection.last_insert_rowid());
+            info!("インターフェース: {}, MACアドレス: {}, IPアドレス: {}", interface_name, mac_address, ip_addresses);
+            Ok(name)
+        })
+        .await
+    }
+
+    async fn load_firewall(&self, node_id: i16, fallback_policy: Policy) -> Result<IpFirewall, CarrierError> {
+        let rules = self
+            .run(move |connection| {
+                let query = "
+                    SELECT filter_type, filter_value, priority, policy
+                    FROM firewall_settings
+                    WHERE (node_id = ?1 OR node_id IS NULL)
+                    ORDER BY priority DESC";
+                let mut statement = connection.prepare(query).map_err(Self::sqlite_error)?;
+                let rules = statement
+                    .query_map(params![node_id], |row| {
+                        Ok(FirewallRuleRow {
+                            filter_type: row.get("filter_type")?,
+                            filter_value: row.get("filter_value")?,
+                            priority: row.get("priority")?,
+                            policy: row.get("policy")?,
+                        })
+                    })
+                    .map_err(Self::sqlite_error)?
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(Self::sqlite_error)?;
+                Ok(rules)
+            })
+            .await?;
+
+        Ok(DbService::build_firewall(node_id, fallback_policy, &rules)?)
+    }
+
+    async fn query_packets(&self, query: &PacketQuery) -> Result<Vec<StoredPacket>, CarrierError> {
+        let query = query.clone();
+        self.run(move |connection| {
+            let sql = "
+                SELECT id, node_id, timestamp, captured_at, src_mac, dst_mac, ether_type, ip_protocol,
+                       src_ip, dst_ip, src_port, dst_port, raw_packet, key_id, seq, signature
+                FROM packets
+                WHERE (?1 IS NULL OR node_id = ?1)
+                    AND (?2 IS NULL OR timestamp >= ?2)
+                    AND (?3 IS NULL OR timestamp < ?3)
+                ORDER BY timestamp DESC, id DESC
+                LIMIT ?4";
+            let mut statement = connection.prepare(sql).map_err(Self::sqlite_error)?;
+            let packets = statement
+                .query_map(
+                    params![
+                        query.node_id,
+                        query.since.map(Self::to_micros),
+                        query.until.map(Self::to_micros),
+                        query.limit
+                    ],
+                    Self::stored_packet,
+                )
+                .map_err(Self::sqlite_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Self::sqlite_error)?;
+            Ok(packets)
+        })
+        .await
+    }
+
+    async fn purge(&self, config: &RetentionConfig) -> Result<PurgeReport, CarrierError> {
+        let config = config.clone();
+        let report = self
+            .run(move |connection| {
+                let now = Self::to_micros(Utc::now());
+                let mut report = PurgeReport::default();
+
+                if let Some(max_age) = config.packets_max_age {
+                    let deleted = connection.execute("DELETE FROM packets WHERE timestamp < ?1", params![now - max_age.as_micros() as i64]).map_err(Self::sqlite_error)?;
+                    report.deleted_packets += deleted as u64;
+                }
+
+                for (node_id, max_age) in &config.node_max_age {
+                    // 全体の保持期間で削除される範囲は対象外
+                    if config.packets_max_age.is_some_and(|global| global <= *max_age) {
+                        continue;
+                    }
+                    let deleted = connection
+                        .execute(
+                            "DELETE FROM packets WHERE node_id = ?1 AND timestamp < ?2",
+                            params![node_id, now - max_age.as_micros() as i64],
+                        )
+                        .map_err(Self::sqlite_error)?;
+                    report.deleted_packets += deleted as u64;
+                }
+
+                if let Some(max_age) = config.node_activity_max_age {
+                    // 最終起動時刻が分からなくならないよう、ノードごとの最新の記録は残す
+                    let query = "
+                        DELETE FROM node_activity
+                        WHERE boot_time < ?1
+                            AND id NOT IN (SELECT MAX(id) FROM node_activity GROUP BY node_id)";
+                    report.deleted_activities += connection.execute(query, params![now - max_age.as_micros() as i64]).map_err(Self::sqlite_error)? as u64;
+                }
+
+                if config.packets_max_size.is_some() {
+                    debug!("SQLiteの媒体ではサイズによる削除を行いません");
+                }
+                Ok(report)
+            })
+            .await?;
+
+        if report.deleted_packets > 0 || report.deleted_activities > 0 {
+            info!(
+                "保持期間を超えたデータを削除しました (packets: {}行, node_activity: {}行)",
+                report.deleted_packets, report.deleted_activities
+            );
+        }
+        Ok(report)
+    }
+}
+
+/// 他のノードの書き込みをwatch_intervalごとに確認する (SQLiteには通知の仕組みがないため)
+struct SqliteSubscription {
+    connection: Arc<Mutex<Connection>>,
+    // 確認済みの最後のパケットID
+    last_id: i64,
+    // 確認した書き込みのうち、まだ返していないノードID
+    pending: VecDeque<i16>,
+    interval: Duration,
+}
+
+impl SqliteSubscription {
+    async fn check(&mut self) -> Result<(), CarrierError> {
+        let last_id = self.last_id;
+        let writers = SqliteCarrier::run_on(Arc::clone(&self.connection), move |connection| {
+            let mut statement = connection.prepare_cached("SELECT node_id, MAX(id) FROM packets WHERE id > ?1 GROUP BY node_id").map_err(SqliteCarrier::sqlite_error)?;
+            let writers = statement
+                .query_map(params![last_id], |row| Ok((row.get::<_, i16>(0)?, row.get::<_, i64>(1)?)))
+                .map_err(SqliteCarrier::sqlite_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(SqliteCarrier::sqlite_error)?;
+            Ok(writers)
+        })
+        .await?;
+
+        for (node_id, max_id) in writers {
+            self.last_id = self.last_id.max(max_id);
+            self.pending.push_back(node_id);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CarrierSubscription for SqliteSubscription {
+    async fn recv(&mut self) -> Option<i16> {
+        loop {
+            if let Some(node_id) = self.pending.pop_front() {
+                return Some(node_id);
+            }
+            if let Err(e) = self.check().await {
+                debug!("SQLiteの書き込みの確認に失敗しました: {}", e);
+                return None;
+            }
+            if self.pending.is_empty() {
+                tokio::time::sleep(self.interval).await;
+            }
+        }
+    }
+
+    fn try_recv(&mut self) -> Option<i16> {
+        self.pending.pop_front()
+    }
+}
@@ -0,0 +1,250 @@
+use crate::cli::{Cli, Command, NodeCommand, SchemaCommand};
+use log::{error, info};
+use pnet::datalink;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use stegrdb::carrier::{Carrier, MysqlCarrier, PostgresCarrier, SqliteCarrier};
+use stegrdb::config::{AppConfig, CarrierConfig, CarrierKind, CircuitBreakerConfig, ConfigOverrides, DatabaseConfig, RetentionConfig, SigningConfig};
+use stegrdb::crypto::PacketSigner;
+use stegrdb::database::{latest_version, Database, Schema};
+use stegrdb::error::InitProcessError;
+use stegrdb::idps_log;
+use stegrdb::interface::select_interface;
+use stegrdb::logger::setup_logger::{setup_console_logger, setup_logger};
+use stegrdb::services::NodeRecord;
+use stegrdb::TunnelNode;
+
+pub async fn execute(cli: Cli) -> Result<(), InitProcessError> {
+    let overrides = cli.overrides();
+
+    match cli.command {
+        None | Some(Command::Run(_)) => run(&overrides).await,
+        Some(Command::Interfaces) => {
+            list_interfaces();
+            Ok(())
+        },
+        Some(Command::Node { command }) => {
+            setup_console_logger();
+            match command {
+                // 署名鍵の作成はデータベースに接続せずに行う
+                NodeCommand::Keygen { output } => generate_signing_key(&overrides, output),
+                NodeCommand::Register { id, name, description } => {
+                    open_carrier(&overrides).await?.register_node(id, &name, description.as_deref()).await.map_err(|e| InitProcessError::CommandError(e.to_string()))
+                },
+                NodeCommand::List => print_nodes(open_carrier(&overrides).await?.list_nodes().await.map_err(|e| InitProcessError::CommandError(e.to_string()))?),
+                NodeCommand::SetKey { id, public_key } => {
+                    open_carrier(&overrides).await?.set_node_key(id, Some(&public_key)).await.map_err(|e| InitProcessError::CommandError(e.to_string()))
+                },
+            }
+        },
+        Some(Command::Schema { command }) => {
+            setup_console_logger();
+            let carrier = CarrierConfig::load(&overrides).map_err(|e| InitProcessError::ConfigurationError(e.to_string()))?;
+            match carrier.kind {
+                // SQLiteのスキーマはデータベースを開いた時に作成される
+                CarrierKind::Sqlite => {
+                    return Err(InitProcessError::CommandError(
+                        "schema コマンドはSQLiteの媒体 (carrier.kind = sqlite) では使用できません".to_string(),
+                    ));
+                },
+                CarrierKind::Mysql => return mysql_schema(command, &connect_mysql(&carrier, &overrides).await?).await,
+                CarrierKind::Postgres => {},
+            }
+            match command {
+                SchemaCommand::Migrate => {
+                    let db = connect_database(&overrides).await?;
+                    Schema::migrate(&db).await.map(|_| ()).map_err(|e| InitProcessError::CommandError(e.to_string()))
+                },
+                SchemaCommand::Status => {
+                    let db = connect_database(&overrides).await?;
+                    migration_status(&db).await
+                },
+                SchemaCommand::Seed => {
+                    let db = connect_database(&overrides).await?;
+                    Schema::seed(&db).await.map_err(|e| InitProcessError::CommandError(e.to_string()))
+                },
+                SchemaCommand::Drop { yes } => {
+                    if !yes && !confirm("全てのテーブルを削除します。よろしいですか?")? {
+                        info!("削除を中止しました");
+                        return Ok(());
+                    }
+                    let db = connect_database(&overrides).await?;
+                    Schema::drop(&db).await.map_err(|e| InitProcessError::CommandError(e.to_string()))
+                },
+            }
+        },
+        Some(Command::Purge) => {
+            setup_console_logger();
+            let retention = RetentionConfig::load(&overrides).map_err(|e| InitProcessError::ConfigurationError(e.to_string()))?;
+            let report = open_carrier(&overrides).await?.purge(&retention).await.map_err(|e| InitProcessError::CommandError(e.to_string()))?;
+            println!(
+                "削除しました: packets {}チャンク/{}行, node_activity {}行",
+                report.dropped_chunks, report.deleted_packets, report.deleted_activities
+            );
+            Ok(())
+        },
+    }
+}
+
+async fn run(overrides: &ConfigOverrides) -> Result<(), InitProcessError> {
+    let config: AppConfig = AppConfig::load(overrides).map_err(|e| InitProcessError::ConfigurationError(e.to_string()))?;
+
+    // ロガーのセットアップ
+    setup_logger(config.logger_config.clone()).map_err(|e| InitProcessError::LoggerError(e.to_string()))?;
+
+    info!("loggerが正常にセットアップされました");
+    idps_log!("idps logの表示が有効になっています");
+
+    info!("Node IDは{}に指定されています", config.node_id);
+
+    let interface = select_interface(&config.network).map_err(|e| InitProcessError::InterfaceSelectionError(e.to_string()))?;
+
+    let mac_str = match &interface.mac {
+        Some(mac) => mac.to_string(),
+        None => "不明".to_string(),
+    };
+
+    let ip_addresses: Vec<String> = interface.ips.iter().map(|ip| ip.to_string()).collect();
+    let ip_str = if ip_addresses.is_empty() { "不明".to_string() } else { ip_addresses.join(", ") };
+
+    info!("デバイスの選択に成功しました: {}", interface.name);
+    info!("選択されたインターフェース情報: 名前={}, MACアドレス={}, IPアドレス={}", interface.name, mac_str, ip_str);
+
+    // データベース接続・ノード検証・ファイアウォール初期化
+    let node = TunnelNode::connect(config, interface).await.map_err(|e| {
+        error!("ノードの初期化に失敗しました: {}", e);
+        InitProcessError::DatabaseConnectionError(e.to_string())
+    })?;
+
+    let shutdown = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("シグナルの待機に失敗しました: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    node.run_until(shutdown).await.map_err(|e| InitProcessError::TaskExecutionProcessError(e.to_string()))
+}
+
+fn list_interfaces() {
+    let interfaces = datalink::interfaces();
+    if interfaces.is_empty() {
+        println!("利用可能なネットワークインターフェースがありません");
+        return;
+    }
+
+    println!("{:<16} {:<6} {:<18} IPアドレス", "名前", "状態", "MACアドレス");
+    for interface in interfaces {
+        let mac = interface.mac.map(|mac| mac.to_string()).unwrap_or_else(|| "-".to_string());
+        let ips: Vec<String> = interface.ips.iter().map(|ip| ip.to_string()).collect();
+        println!(
+            "{:<16} {:<6} {:<18} {}",
+            interface.name,
+            if interface.is_up() { "up" } else { "down" },
+            mac,
+            if ips.is_empty() { "-".to_string() } else { ips.join(", ") }
+        );
+    }
+}
+
+fn print_nodes(nodes: Vec<NodeRecord>) -> Result<(), InitProcessError> {
+    if nodes.is_empty() {
+        println!("登録されているノードはありません");
+        return Ok(());
+    }
+
+    println!("{:<6} {:<24} {:<26} {:<8} 説明", "ID", "名前", "最終起動時刻", "公開鍵");
+    for node in nodes {
+        println!(
+            "{:<6} {:<24} {:<26} {:<8} {}",
+            node.id,
+            node.name,
+            node.last_boot_time.map(|t| t.format("%Y-%m-%d %H:%M:%S%z").to_string()).unwrap_or_else(|| "-".to_string()),
+            node.public_key.map(|key| hex::encode(&key[..key.len().min(4)])).unwrap_or_else(|| "-".to_string()),
+            node.description.unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// 署名鍵を作成し、node_list に登録する公開鍵を表示する
+fn generate_signing_key(overrides: &ConfigOverrides, output: Option<PathBuf>) -> Result<(), InitProcessError> {
+    let path = match output {
+        Some(path) => path,
+        None => SigningConfig::load(overrides).map_err(|e| InitProcessError::ConfigurationError(e.to_string()))?.key_file,
+    };
+    let public_key = PacketSigner::generate(&path).map_err(|e| InitProcessError::CommandError(e.to_string()))?;
+
+    println!("署名鍵を作成しました: {}", path.display());
+    println!("公開鍵: {}", hex::encode(public_key));
+    println!("stegrdb node set-key <ノードID> {} で登録してください", hex::encode(public_key));
+    Ok(())
+}
+
+async fn migration_status(db: &Database) -> Result<(), InitProcessError> {
+    let statuses = Schema::status(db).await.map_err(|e| InitProcessError::CommandError(e.to_string()))?;
+
+    println!("このバイナリのスキーマバージョン: {}", latest_version());
+    println!("{:<8} {:<24} 適用時刻", "Version", "名前");
+    for status in statuses {
+        let applied_at = match status.applied_at {
+            Some(t) => t.format("%Y-%m-%d %H:%M:%S%z").to_string(),
+            None => "未適用".to_string(),
+        };
+        println!("{:<8} {:<24} {}", status.version, status.name, applied_at);
+    }
+    Ok(())
+}
+
+async fn connect_database(overrides: &ConfigOverrides) -> Result<Database, InitProcessError> {
+    let config = DatabaseConfig::load(overrides).map_err(|e| InitProcessError::ConfigurationError(e.to_string()))?;
+    let db = Database::connect(&config).await.map_err(|e| InitProcessError::DatabaseConnectionError(e.to_string()))?;
+    info!("データベースに接続できました: address:{}, port:{}", config.host, config.port);
+    Ok(db)
+}
+
+/// carrier.kindの媒体に管理コマンド用に接続する
+async fn open_carrier(overrides: &ConfigOverrides) -> Result<Box<dyn Carrier>, InitProcessError> {
+    let config = CarrierConfig::load(overrides).map_err(|e| InitProcessError::ConfigurationError(e.to_string()))?;
+    match config.kind {
+        CarrierKind::Sqlite => {
+            let carrier = SqliteCarrier::open(&config).map_err(|e| InitProcessError::DatabaseConnectionError(e.to_string()))?;
+            Ok(Box::new(carrier))
+        },
+        CarrierKind::Mysql => Ok(Box::new(connect_mysql(&config, overrides).await?)),
+        CarrierKind::Postgres => Ok(Box::new(PostgresCarrier::for_management(connect_database(overrides).await?))),
+    }
+}
+
+async fn connect_mysql(config: &CarrierConfig, overrides: &ConfigOverrides) -> Result<MysqlCarrier, InitProcessError> {
+    let circuit_breaker = CircuitBreakerConfig::load(overrides).map_err(|e| InitProcessError::ConfigurationError(e.to_string()))?;
+    MysqlCarrier::connect(config, &circuit_breaker).await.map_err(|e| InitProcessError::DatabaseConnectionError(e.to_string()))
+}
+
+/// MySQLの媒体のスキーマの作成と状況の表示 (seed・dropはPostgreSQLのみ)
+async fn mysql_schema(command: SchemaCommand, carrier: &MysqlCarrier) -> Result<(), InitProcessError> {
+    match command {
+        SchemaCommand::Migrate => carrier.migrate().await.map(|_| ()).map_err(|e| InitProcessError::CommandError(e.to_string())),
+        SchemaCommand::Status => {
+            let version = carrier.schema_version().await.map_err(|e| InitProcessError::CommandError(e.to_string()))?;
+            println!("このバイナリのスキーマバージョン: {}", MysqlCarrier::SCHEMA_VERSION);
+            match version {
+                Some((version, applied_at)) => println!("適用済みのバージョン: {} ({})", version, applied_at.format("%Y-%m-%d %H:%M:%S%z")),
+                None => println!("スキーマは作成されていません"),
+            }
+            Ok(())
+        },
+        SchemaCommand::Seed | SchemaCommand::Drop { .. } => Err(InitProcessError::CommandError(
+            "schema seed / drop はPostgreSQLの媒体 (carrier.kind = postgres) でのみ使用できます".to_string(),
+        )),
+    }
+}
+
+fn confirm(message: &str) -> Result<bool, InitProcessError> {
+    print!("{} [y/N]: ", message);
+    io::stdout().flush().map_err(|e| InitProcessError::CommandError(e.to_string()))?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).map_err(|e| InitProcessError::CommandError(e.to_string()))?;
+    Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,146 @@
+mod commands;
+
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use stegrdb::config::ConfigOverrides;
+
+pub use commands::execute;
+
+#[derive(Debug, Parser)]
+#[command(name = "stegrdb", version, about = "RDBを経由して通信を行うApplication Tunnel Client")]
+pub struct Cli {
+    /// 設定ファイルのパス (未指定の場合は STEGRDB_CONFIG, ./stegrdb.toml の順に探索)
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// 設定値の上書き (例: --set database.host=10.0.0.5)
+    #[arg(short, long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+    pub set: Vec<(String, String)>,
+
+    /// 省略した場合は run として動作します
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// トンネルノードを起動する
+    Run(RunArgs),
+
+    /// 利用可能なネットワークインターフェースを表示する
+    Interfaces,
+
+    /// node_list の管理
+    Node {
+        #[command(subcommand)]
+        command: NodeCommand,
+    },
+
+    /// スキーマのマイグレーション管理
+    Schema {
+        #[command(subcommand)]
+        command: SchemaCommand,
+    },
+
+    /// [retention] の保持期間・サイズを超えたデータを削除する
+    Purge,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct RunArgs {
+    /// 使用するインターフェース名 (network.interface を上書き)
+    #[arg(short, long)]
+    pub interface: Option<String>,
+
+    /// ノードID (node_id を上書き)
+    #[arg(short, long)]
+    pub node_id: Option<i16>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum NodeCommand {
+    /// ノードを登録する (既に存在する場合は更新)
+    Register {
+        /// ノードID (0~32767)
+        #[arg(value_parser = clap::value_parser!(i16).range(0..))]
+        id: i16,
+
+        /// ノード名
+        name: String,
+
+        /// ノードの説明
+        #[arg(short, long)]
+        description: Option<String>,
+    },
+
+    /// 登録されているノードを表示する
+    List,
+
+    /// このノードの署名鍵を作成し、node_list に登録する公開鍵を表示する
+    Keygen {
+        /// 署名鍵の保存先 (省略時は signing.key_file。既に存在する場合は上書きしない)
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// ノードの公開鍵 (`node keygen` で表示された16進表記) を登録する
+    SetKey {
+        /// ノードID (0~32767)
+        #[arg(value_parser = clap::value_parser!(i16).range(0..))]
+        id: i16,
+
+        /// 公開鍵 (32バイトの16進表記)
+        #[arg(value_parser = parse_public_key)]
+        public_key: [u8; 32],
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SchemaCommand {
+    /// 未適用のマイグレーションを適用する
+    #[command(alias = "init")]
+    Migrate,
+
+    /// マイグレーションの適用状況を表示する
+    Status,
+
+    /// サンプルのノードとファイアウォールルールを登録する
+    Seed,
+
+    /// 全てのテーブルを削除する
+    Drop {
+        /// 確認なしで削除する
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+impl Cli {
+    pub fn overrides(&self) -> ConfigOverrides {
+        let mut overrides = ConfigOverrides {
+            config_path: self.config.clone(),
+            values: self.set.clone(),
+        };
+
+        if let Some(Command::Run(args)) = &self.command {
+            if let Some(interface) = &args.interface {
+                overrides.set("network.interface", interface);
+            }
+            if let Some(node_id) = args.node_id {
+                overrides.set("node_id", node_id);
+            }
+        }
+
+        overrides
+    }
+}
+
+fn parse_key_value(pair: &str) -> Result<(String, String), String> {
+    pair.split_once('=').map(|(key, value)| (key.trim().to_string(), value.to_string())).ok_or_else(|| format!("key=value の形式ではありません: {}", pair))
+}
+
+fn parse_public_key(value: &str) -> Result<[u8; 32], String> {
+    let mut public_key = [0u8; 32];
+    hex::decode_to_slice(value.trim(), &mut public_key).map_err(|e| format!("公開鍵は32バイトの16進表記で指定してください: {}", e))?;
+    Ok(public_key)
+}
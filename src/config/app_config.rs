@@ -1,5 +1,23 @@
 use crate::config::error::ConfigError;
-use dotenv::dotenv;
+use crate::config::source::{ConfigSource, CONFIG_PATH_ENV, DEFAULT_CONFIG_PATH};
+use crate::packet::analysis::Policy;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// 時間単位の設定値の上限 (1000年)
+const MAX_HOURS: u64 = 1000 * 365 * 24;
+
+const LOG_MODES: &[&str] = &["all", "file", "console", "none"];
+const PATH_STYLES: &[&str] = &["file_path", "module_path", "none"];
+const SSL_MODES: &[&str] = &["disable", "prefer", "require", "verify-full"];
+const INSERT_METHODS: &[&str] = &["auto", "copy", "unnest"];
+const OVERFLOW_POLICIES: &[&str] = &["drop_newest", "drop_oldest", "block"];
+const DELIVERY_MODES: &[&str] = &["notify", "poll"];
+const CATCH_UP_POLICIES: &[&str] = &["resume", "live", "replay"];
+const CARRIER_KINDS: &[&str] = &["postgres", "sqlite", "mysql"];
+/// "encrypt" は "redact" の以前の名前 (既存の設定ファイルのために受け付ける)
+const METADATA_MODES: &[&str] = &["redact", "plain", "encrypt"];
 
 #[derive(Debug, Clone)]
 pub struct DatabaseConfig {
@@ -8,10 +26,70 @@ pub struct DatabaseConfig {
     pub user: String,
     pub password: String,
     pub database: String,
+    /// 起動時に未適用のマイグレーションを適用する (falseの場合はバージョンの確認のみ)
+    pub auto_migrate: bool,
+    /// 起動時にデータベースへ接続できない場合も終了せず、接続できるまで再試行する
+    pub degraded_start: bool,
+    /// ノードの時計とデータベースの時計のずれがこれを超えた場合に起動時に警告する (Noneの場合は確認しない)
+    pub clock_skew_threshold: Option<Duration>,
+    pub tls: TlsConfig,
+    pub pool: PoolConfig,
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_size: u32,
+    /// 常に確保しておくアイドル接続の数 (max_size以下)
+    pub min_idle: u32,
+    /// 接続の取得を待つ最大時間
+    pub connection_timeout: Duration,
+    /// Noneの場合はアイドル接続を切断しない
+    pub idle_timeout: Option<Duration>,
+    /// Noneの場合は接続を作り直さない
+    pub max_lifetime: Option<Duration>,
+    /// プールの統計情報をログに出力する間隔 (Noneの場合は出力しない)
+    pub stats_interval: Option<Duration>,
+}
+
+/// データベースの障害を検知して接続の試行を止めるサーキットブレーカーの設定
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// 連続してこの回数だけ接続に失敗した場合に遮断する
+    pub failure_threshold: u32,
+    /// 遮断してから最初の再試行までの待機時間 (再試行に失敗するたびに2倍にする)
+    pub initial_backoff: Duration,
+    /// 再試行までの待機時間の上限
+    pub max_backoff: Duration,
+}
+
+/// libpqの `sslmode` に相当する接続時の暗号化の要否
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SslMode {
+    /// 暗号化しない
+    Disable,
+    /// サーバーが対応していれば暗号化する (証明書は検証しない)
+    Prefer,
+    /// 暗号化を必須とする (CA証明書が指定された場合のみ証明書チェーンを検証する)
+    Require,
+    /// 暗号化を必須とし、証明書チェーンとホスト名を検証する
+    VerifyFull,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub mode: SslMode,
+    /// 追加で信頼するCA証明書 (PEM、複数可)
+    pub root_cert: Option<PathBuf>,
+    /// クライアント証明書認証に使用する証明書とPKCS#8形式の秘密鍵 (PEM)
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
 pub struct NetworkConfig {
+    /// 使用するインターフェース名 (指定がない場合はDocker Modeまたは対話的に選択)
+    pub interface: Option<String>,
     pub docker_mode: bool,
     pub docker_interface_name: String,
 }
@@ -25,44 +103,678 @@ pub struct LoggerConfig {
     pub idps_path_style: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct FirewallConfig {
+    /// データベースにポリシーが登録されていない場合に使用するポリシー
+    pub default_policy: Policy,
+}
+
+/// バッファが上限に達した場合の動作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// 新しく届いたパケットを破棄する
+    DropNewest,
+    /// バッファ内の最も古いパケットを破棄して空きを作る
+    DropOldest,
+    /// 空きができるまでキャプチャを止める (カーネル側で破棄される)
+    Block,
+}
+
+/// キャプチャタスクとライタータスクの間のパケットバッファの上限
+#[derive(Debug, Clone)]
+pub struct BufferConfig {
+    pub max_packets: usize,
+    /// raw_packetとパケット1つあたりの管理領域の合計
+    pub max_bytes: usize,
+    pub overflow_policy: OverflowPolicy,
+}
+
+/// データベースに書き込めなかったパケットを退避するスプールの設定
+#[derive(Debug, Clone)]
+pub struct SpoolConfig {
+    pub enabled: bool,
+    pub directory: PathBuf,
+    /// スプールのファイルの合計サイズの上限 (超えた分のパケットは破棄する)
+    pub max_bytes: u64,
+    /// 再送に失敗した場合に次の再送を試みるまでの間隔
+    pub retry_interval: Duration,
+}
+
+/// パケットの一括挿入に使用する方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertMethod {
+    /// copy_threshold以上のパケット数の場合はCOPY、それ以外はunnestを使用する
+    Auto,
+    /// 常に `COPY ... FROM STDIN BINARY` を使用する
+    Copy,
+    /// 常にチャンクごとの `INSERT ... SELECT unnest(...)` を使用する
+    Unnest,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchingConfig {
+    /// バッファ内のパケットがこの時間を超えて滞留しないようにフラッシュする
+    pub flush_interval: Duration,
+    /// unnestによる挿入で1回のINSERTに含めるパケット数
+    pub chunk_size: usize,
+    /// 1回のフラッシュで書き込むパケット数の範囲 (負荷に応じてこの範囲で増減する)
+    pub min_batch_size: usize,
+    pub max_batch_size: usize,
+    /// 同時に実行するフラッシュの数 (それぞれ別の接続を使用する)
+    pub max_in_flight: usize,
+    pub max_retries: u64,
+    pub insert_method: InsertMethod,
+    pub copy_threshold: usize,
+}
+
+/// 他のノードが書き込んだパケットを読み取るタイミング
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryMode {
+    /// 書き込み時のNOTIFYを専用の接続でLISTENし、通知を受けた時に読み取る (poll_intervalごとの読み取りも併用する)
+    Notify,
+    /// poll_intervalごとに読み取る
+    Poll,
+}
+
+/// 起動時に、停止中に他のノードが書き込んだパケットをどこから読み取るか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatchUpPolicy {
+    /// 前回の読み取り位置から全て読み取る (保存された位置がない場合はliveと同じ)
+    Resume,
+    /// 停止中のパケットは読み取らず、起動後に書き込まれたパケットから読み取る
+    Live,
+    /// 前回の読み取り位置から読み取るが、catch_up_max_age / catch_up_max_packets より古いパケットは読み飛ばす
+    Replay,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeliveryConfig {
+    pub mode: DeliveryMode,
+    /// NOTIFY/LISTENに使用するチャネル名
+    pub channel: String,
+    /// 読み取りの間隔 (notifyの場合は通知を取りこぼした場合の補助)
+    pub poll_interval: Duration,
+    pub catch_up: CatchUpPolicy,
+    /// replayで読み取るパケットの最大の経過時間 (Noneの場合は制限しない)
+    pub catch_up_max_age: Option<Duration>,
+    /// replayで読み取る最大のパケット数 (Noneの場合は制限しない)
+    pub catch_up_max_packets: Option<i64>,
+}
+
+/// 古いデータを削除する保持期間・サイズの設定 (Noneの場合は制限しない)
+#[derive(Debug, Clone)]
+pub struct RetentionConfig {
+    /// packetsを保持する期間 (TimescaleDBの場合は保持ポリシーとして登録する)
+    pub packets_max_age: Option<Duration>,
+    /// packetsの最大サイズ (バイト、超えた場合は古いチャンクから削除する)
+    pub packets_max_size: Option<u64>,
+    /// ノードごとのpacketsの保持期間 (packets_max_ageより短い場合のみ意味を持つ)
+    pub node_max_age: BTreeMap<i16, Duration>,
+    /// node_activity (起動記録) を保持する期間
+    pub node_activity_max_age: Option<Duration>,
+    /// ノードの起動中に削除を実行する間隔 (Noneの場合は起動時と `stegrdb purge` でのみ削除する)
+    pub purge_interval: Option<Duration>,
+}
+
+/// TimescaleDBがない場合に使用するpacketsのパーティションの設定
+#[derive(Debug, Clone)]
+pub struct PartitionConfig {
+    /// 1つのパーティションに含める時間の範囲
+    pub interval: Duration,
+    /// 現在のパーティションに加えて事前に作成しておくパーティションの数
+    pub premake: u32,
+    /// パーティションの作成・削除を行う間隔
+    pub maintenance_interval: Duration,
+}
+
+/// ノード間でパケットを受け渡す媒体の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarrierKind {
+    /// PostgreSQL (TimescaleDB)
+    Postgres,
+    /// 1台のホスト上のSQLiteのファイル (ネットワーク名前空間で分けた複数のノードや検証用)
+    Sqlite,
+    /// MySQL/MariaDB (PostgreSQLを用意できないネットワーク用)
+    Mysql,
+}
+
+#[derive(Debug, Clone)]
+pub struct CarrierConfig {
+    pub kind: CarrierKind,
+    /// SQLiteのデータベースファイル (同じホストのノード間で共有する)
+    pub sqlite_path: PathBuf,
+    /// 他のノードが書き込み中の場合に待機する時間の上限
+    pub sqlite_busy_timeout: Duration,
+    /// 他のノードの書き込みを確認する間隔 (delivery.mode = notify の場合)
+    pub sqlite_watch_interval: Duration,
+    pub mysql_host: String,
+    pub mysql_port: u16,
+    pub mysql_user: String,
+    pub mysql_password: String,
+    pub mysql_database: String,
+    /// TLSで接続する (サーバー証明書はシステムの証明書ストアで検証する)
+    pub mysql_tls: bool,
+    /// 接続プールの最大接続数
+    pub mysql_pool_max_size: usize,
+    /// 接続の取得を待つ最大時間
+    pub mysql_connection_timeout: Duration,
+    /// 他のノードの書き込みを確認する間隔 (MySQLには通知の仕組みがないため)
+    pub mysql_watch_interval: Duration,
+}
+
+/// raw_packetを暗号化した場合のpacketsのヘッダーの列 (src_ip, dst_macなど) の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataMode {
+    /// 列には0を書き込み、ヘッダーは暗号化したフレームからのみ復元できるようにする (列の値自体は暗号化しない)
+    Redact,
+    /// 列には平文のまま書き込む (データベース上で宛先などを集計する場合)
+    Plain,
+}
+
+/// raw_packetの認証付き暗号化の設定
+#[derive(Debug, Clone)]
+pub struct EncryptionConfig {
+    pub enabled: bool,
+    /// 鍵ファイル (同じトンネルグループのノードで同じ内容のファイルを使用する)
+    pub key_file: PathBuf,
+    pub metadata: MetadataMode,
+    /// 暗号化されていないパケットも送信する (暗号化を導入する間のみ使用する)
+    pub accept_plaintext: bool,
+}
+
+/// パケットの送信元の署名 (Ed25519) の設定
+#[derive(Debug, Clone)]
+pub struct SigningConfig {
+    pub enabled: bool,
+    /// このノードの署名鍵 (公開鍵は node_list に登録する)
+    pub key_file: PathBuf,
+    /// 使用したシーケンス番号を保存するファイル (再起動後や時計を戻した後も、前回より大きい番号から始めるため)
+    pub seq_file: PathBuf,
+    /// 署名されていないパケットも送信する (署名を導入する間のみ使用する)
+    pub accept_unsigned: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     pub node_id: i16,
     pub database: DatabaseConfig,
     pub network: NetworkConfig,
     pub logger_config: LoggerConfig,
+    pub firewall: FirewallConfig,
+    pub buffer: BufferConfig,
+    pub spool: SpoolConfig,
+    pub batching: BatchingConfig,
+    pub delivery: DeliveryConfig,
+    pub retention: RetentionConfig,
+    pub partition: PartitionConfig,
+    pub carrier: CarrierConfig,
+    pub encryption: EncryptionConfig,
+    pub signing: SigningConfig,
 }
 
-impl AppConfig {
-    pub fn new() -> Result<Self, ConfigError> {
-        dotenv().map_err(|e| ConfigError::EnvFileReadError(e.to_string()))?;
+/// 設定の読み込み元の指定
+/// 優先順位は 設定ファイル < 環境変数 (.env含む) < overrides
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub config_path: Option<PathBuf>,
+    pub values: Vec<(String, String)>,
+}
+
+impl ConfigOverrides {
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        self.values.push((key.to_string(), value.to_string()));
+    }
+}
+
+impl DatabaseConfig {
+    /// データベースの設定のみを読み込む (ノードを起動しない管理コマンド用)
+    pub fn load(overrides: &ConfigOverrides) -> Result<Self, ConfigError> {
+        Self::from_source(&AppConfig::source(overrides)?, true)
+    }
+
+    /// connection_requiredがfalseの場合 (PostgreSQL以外の媒体を使う場合) は接続先を省略できる
+    fn from_source(source: &ConfigSource, connection_required: bool) -> Result<Self, ConfigError> {
+        let connection = |key: &'static str| -> Result<String, ConfigError> {
+            if connection_required {
+                source.required(key)
+            } else {
+                source.with_default(key, String::new())
+            }
+        };
+        // 0を指定した場合は確認しない
+        let clock_skew_warn_ms: u64 = source.with_default("database.clock_skew_warn_ms", 1000)?;
+        let database = DatabaseConfig {
+            host: connection("database.host")?,
+            port: source.with_default("database.port", 5432)?,
+            user: connection("database.user")?,
+            password: connection("database.password")?,
+            database: connection("database.database")?,
+            auto_migrate: AppConfig::parse_bool(source, "database.auto_migrate")?.unwrap_or(true),
+            degraded_start: AppConfig::parse_bool(source, "database.degraded_start")?.unwrap_or(true),
+            clock_skew_threshold: (clock_skew_warn_ms > 0).then(|| Duration::from_millis(clock_skew_warn_ms)),
+            tls: TlsConfig::from_source(source)?,
+            pool: PoolConfig::from_source(source)?,
+            circuit_breaker: CircuitBreakerConfig::from_source(source)?,
+        };
+        if connection_required && database.host.is_empty() {
+            return Err(source.invalid("database.host", "空文字は指定できません"));
+        }
+        Ok(database)
+    }
+}
+
+impl CarrierConfig {
+    /// 媒体の設定のみを読み込む (ノードを起動しない管理コマンド用)
+    pub fn load(overrides: &ConfigOverrides) -> Result<Self, ConfigError> {
+        Self::from_source(&AppConfig::source(overrides)?)
+    }
 
-        let get_env_var =
-            |var_name: &str| -> Result<String, ConfigError> { dotenv::var(var_name).map_err(|e| ConfigError::EnvVarError(format!("{}: {}", var_name, e.to_string()))) };
+    fn from_source(source: &ConfigSource) -> Result<Self, ConfigError> {
+        let kind = match AppConfig::one_of(source, "carrier.kind", CARRIER_KINDS, "postgres")?.as_str() {
+            "sqlite" => CarrierKind::Sqlite,
+            "mysql" => CarrierKind::Mysql,
+            _ => CarrierKind::Postgres,
+        };
+        // MySQLを使う場合のみ接続先を必須とする
+        let connection = |key: &'static str| -> Result<String, ConfigError> {
+            if kind == CarrierKind::Mysql {
+                source.required(key)
+            } else {
+                source.with_default(key, String::new())
+            }
+        };
+        let config = CarrierConfig {
+            kind,
+            sqlite_path: source.with_default("carrier.sqlite_path", PathBuf::from("./stegrdb.sqlite3"))?,
+            sqlite_busy_timeout: Duration::from_millis(AppConfig::positive(source, "carrier.sqlite_busy_timeout_ms", 5000)?),
+            sqlite_watch_interval: Duration::from_millis(AppConfig::positive(source, "carrier.sqlite_watch_interval_ms", 10)?),
+            mysql_host: connection("carrier.mysql_host")?,
+            mysql_port: source.with_default("carrier.mysql_port", 3306)?,
+            mysql_user: connection("carrier.mysql_user")?,
+            mysql_password: source.with_default("carrier.mysql_password", String::new())?,
+            mysql_database: connection("carrier.mysql_database")?,
+            mysql_tls: AppConfig::parse_bool(source, "carrier.mysql_tls")?.unwrap_or(false),
+            mysql_pool_max_size: AppConfig::positive(source, "carrier.mysql_pool_max_size", 10)? as usize,
+            mysql_connection_timeout: Duration::from_millis(AppConfig::positive(source, "carrier.mysql_connection_timeout_ms", 10_000)?),
+            mysql_watch_interval: Duration::from_millis(AppConfig::positive(source, "carrier.mysql_watch_interval_ms", 100)?),
+        };
+        if kind == CarrierKind::Mysql && config.mysql_host.is_empty() {
+            return Err(source.invalid("carrier.mysql_host", "空文字は指定できません"));
+        }
+        Ok(config)
+    }
+}
+
+impl RetentionConfig {
+    /// 保持期間の設定のみを読み込む (ノードを起動しない管理コマンド用)
+    pub fn load(overrides: &ConfigOverrides) -> Result<Self, ConfigError> {
+        Self::from_source(&AppConfig::source(overrides)?)
+    }
+
+    fn from_source(source: &ConfigSource) -> Result<Self, ConfigError> {
+        // 0を指定した場合は制限しない
+        let optional_hours = |key: &'static str, default: u64| -> Result<Option<Duration>, ConfigError> {
+            let hours: u64 = source.with_default(key, default)?;
+            if hours == 0 {
+                return Ok(None);
+            }
+            AppConfig::hours(source, key, hours).map(Some)
+        };
+        let max_size_mb: u64 = source.with_default("retention.packets_max_size_mb", 0)?;
+        // データベースのサイズ (bigint) と比較するため、i64で表せるバイト数までとする
+        let packets_max_size = match max_size_mb {
+            0 => None,
+            mb => Some(mb.checked_mul(1024 * 1024).filter(|bytes| *bytes <= i64::MAX as u64).ok_or_else(|| {
+                source.invalid(
+                    "retention.packets_max_size_mb",
+                    format!("{}以下の値を指定してください: {}", i64::MAX as u64 / (1024 * 1024), mb),
+                )
+            })?),
+        };
+        let purge_interval_secs: u64 = source.with_default("retention.purge_interval_secs", 3600)?;
+
+        // "150=24, 151=72" の形式 (ノードID=保持する時間)
+        let mut node_max_age = BTreeMap::new();
+        let node_max_age_hours: String = source.with_default("retention.node_max_age_hours", String::new())?;
+        for entry in node_max_age_hours.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let parsed = entry.split_once('=').and_then(|(node_id, hours)| Some((node_id.trim().parse::<i16>().ok()?, hours.trim().parse::<u64>().ok()?)));
+            match parsed {
+                Some((node_id, hours)) if node_id >= 0 && hours > 0 => {
+                    node_max_age.insert(node_id, AppConfig::hours(source, "retention.node_max_age_hours", hours)?);
+                },
+                _ => return Err(source.invalid("retention.node_max_age_hours", format!("ノードID=時間 (1以上) の形式で指定してください: {}", entry))),
+            }
+        }
+
+        Ok(RetentionConfig {
+            packets_max_age: optional_hours("retention.packets_max_age_hours", 168)?,
+            packets_max_size,
+            node_max_age,
+            node_activity_max_age: optional_hours("retention.node_activity_max_age_hours", 90 * 24)?,
+            purge_interval: (purge_interval_secs > 0).then(|| Duration::from_secs(purge_interval_secs)),
+        })
+    }
+}
+
+impl PoolConfig {
+    fn from_source(source: &ConfigSource) -> Result<Self, ConfigError> {
+        let max_size = u32::try_from(AppConfig::positive(source, "database.pool.max_size", 10)?)
+            .map_err(|_| source.invalid("database.pool.max_size", format!("{}以下の値を指定してください", u32::MAX)))?;
+        let min_idle = source.with_default("database.pool.min_idle", 2.min(max_size))?;
+        if min_idle > max_size {
+            return Err(source.invalid("database.pool.min_idle", format!("database.pool.max_size ({}) 以下の値を指定してください", max_size)));
+        }
+
+        // 0を指定した場合は無効にする
+        let optional_secs = |key: &'static str, default: u64| -> Result<Option<Duration>, ConfigError> {
+            let secs: u64 = source.with_default(key, default)?;
+            Ok((secs > 0).then(|| Duration::from_secs(secs)))
+        };
+
+        Ok(PoolConfig {
+            max_size,
+            min_idle,
+            connection_timeout: Duration::from_millis(AppConfig::positive(source, "database.pool.connection_timeout_ms", 10_000)?),
+            idle_timeout: optional_secs("database.pool.idle_timeout_secs", 60)?,
+            max_lifetime: optional_secs("database.pool.max_lifetime_secs", 1800)?,
+            stats_interval: optional_secs("database.pool.stats_interval_secs", 0)?,
+        })
+    }
+}
+
+impl SigningConfig {
+    /// 署名の設定のみを読み込む (署名鍵を作成する管理コマンド用)
+    pub fn load(overrides: &ConfigOverrides) -> Result<Self, ConfigError> {
+        Self::from_source(&AppConfig::source(overrides)?)
+    }
 
+    fn from_source(source: &ConfigSource) -> Result<Self, ConfigError> {
         Ok(Self {
-            node_id: {
-                let value = get_env_var("NODE_ID")?.parse::<u16>().map_err(|e| ConfigError::EnvVarParseError(format!("NODE_ID: {}", e.to_string())))?;
-                i16::try_from(value).map_err(|_| ConfigError::EnvVarParseError("NODE_ID: value exceeds i16::MAX".to_string()))?
+            enabled: AppConfig::parse_bool(source, "signing.enabled")?.unwrap_or(false),
+            key_file: source.with_default("signing.key_file", PathBuf::from("./stegrdb.signing.key"))?,
+            seq_file: source.with_default("signing.seq_file", PathBuf::from("./stegrdb.signing.seq"))?,
+            accept_unsigned: AppConfig::parse_bool(source, "signing.accept_unsigned")?.unwrap_or(false),
+        })
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// サーキットブレーカーの設定のみを読み込む (PostgreSQL以外の媒体に接続する管理コマンド用)
+    pub fn load(overrides: &ConfigOverrides) -> Result<Self, ConfigError> {
+        Self::from_source(&AppConfig::source(overrides)?)
+    }
+
+    fn from_source(source: &ConfigSource) -> Result<Self, ConfigError> {
+        let initial_backoff = Duration::from_millis(AppConfig::positive(source, "database.circuit_breaker.initial_backoff_ms", 1000)?);
+        let max_backoff = Duration::from_millis(AppConfig::positive(source, "database.circuit_breaker.max_backoff_ms", 60_000)?);
+        if max_backoff < initial_backoff {
+            return Err(source.invalid(
+                "database.circuit_breaker.max_backoff_ms",
+                format!("database.circuit_breaker.initial_backoff_ms ({}) 以上の値を指定してください", initial_backoff.as_millis()),
+            ));
+        }
+
+        Ok(CircuitBreakerConfig {
+            failure_threshold: u32::try_from(AppConfig::positive(source, "database.circuit_breaker.failure_threshold", 5)?)
+                .map_err(|_| source.invalid("database.circuit_breaker.failure_threshold", format!("{}以下の値を指定してください", u32::MAX)))?,
+            initial_backoff,
+            max_backoff,
+        })
+    }
+}
+
+impl TlsConfig {
+    fn from_source(source: &ConfigSource) -> Result<Self, ConfigError> {
+        // CA証明書が指定されている場合は、指定がなければ証明書とホスト名を検証する
+        let root_cert: Option<PathBuf> = source.optional("database.sslrootcert")?;
+        let default_mode = if root_cert.is_some() { "verify-full" } else { "prefer" };
+        let mode = match AppConfig::one_of(source, "database.sslmode", SSL_MODES, default_mode)?.as_str() {
+            "disable" => SslMode::Disable,
+            "require" => SslMode::Require,
+            "verify-full" => SslMode::VerifyFull,
+            _ => SslMode::Prefer,
+        };
+
+        let tls = TlsConfig {
+            mode,
+            root_cert,
+            client_cert: source.optional("database.sslcert")?,
+            client_key: source.optional("database.sslkey")?,
+        };
+
+        match (&tls.client_cert, &tls.client_key) {
+            (Some(_), None) => return Err(source.invalid("database.sslcert", "database.sslkey も指定してください")),
+            (None, Some(_)) => return Err(source.invalid("database.sslkey", "database.sslcert も指定してください")),
+            _ => {},
+        }
+        for (key, path) in [
+            ("database.sslrootcert", &tls.root_cert),
+            ("database.sslcert", &tls.client_cert),
+            ("database.sslkey", &tls.client_key),
+        ] {
+            if let Some(path) = path {
+                if !path.is_file() {
+                    return Err(source.invalid(key, format!("ファイルが存在しません: {}", path.display())));
+                }
+            }
+        }
+
+        Ok(tls)
+    }
+}
+
+impl AppConfig {
+    pub fn load(overrides: &ConfigOverrides) -> Result<Self, ConfigError> {
+        Self::from_source(&Self::source(overrides)?)
+    }
+
+    fn source(overrides: &ConfigOverrides) -> Result<ConfigSource, ConfigError> {
+        // .envは存在しなくても良い (実際の環境変数のみで動作させる場合)
+        dotenv::dotenv().ok();
+
+        let mut source = ConfigSource::new();
+
+        if let Some(path) = Self::resolve_config_path(overrides) {
+            source.merge_file(&path)?;
+        }
+        source.merge_env();
+        source.merge_overrides(&overrides.values)?;
+
+        Ok(source)
+    }
+
+    fn resolve_config_path(overrides: &ConfigOverrides) -> Option<PathBuf> {
+        // 明示的に指定されたファイルは存在しなければ読み込み時にエラーとなる
+        if let Some(path) = &overrides.config_path {
+            return Some(path.clone());
+        }
+        if let Ok(path) = std::env::var(CONFIG_PATH_ENV) {
+            return Some(PathBuf::from(path));
+        }
+
+        // デフォルトのパスは存在する場合のみ読み込む
+        let default_path = Path::new(DEFAULT_CONFIG_PATH);
+        default_path.exists().then(|| default_path.to_path_buf())
+    }
+
+    fn from_source(source: &ConfigSource) -> Result<Self, ConfigError> {
+        let node_id = {
+            let value: i16 = source.required("node_id")?;
+            if value < 0 {
+                return Err(source.invalid("node_id", "0~32767の範囲で指定してください"));
+            }
+            value
+        };
+
+        let carrier = CarrierConfig::from_source(source)?;
+        let database = DatabaseConfig::from_source(source, carrier.kind == CarrierKind::Postgres)?;
+
+        let network = NetworkConfig {
+            interface: source.optional("network.interface")?,
+            docker_mode: Self::parse_bool(source, "network.docker_mode")?.unwrap_or(false),
+            docker_interface_name: source.with_default("network.docker_interface_name", "eth0".to_string())?,
+        };
+
+        let logger_config = LoggerConfig {
+            normal_logger_file: source.with_default("logger.normal_logger_file", "./logs/system.log".to_string())?,
+            idps_logger_file: source.with_default("logger.idps_logger_file", "./logs/idps.log".to_string())?,
+            idps_log_mode: Self::one_of(source, "logger.idps_log_mode", LOG_MODES, "all")?,
+            normal_path_style: Self::one_of(source, "logger.normal_path_style", PATH_STYLES, "file_path")?,
+            idps_path_style: Self::one_of(source, "logger.idps_path_style", PATH_STYLES, "file_path")?,
+        };
+
+        let firewall = FirewallConfig {
+            default_policy: match Self::one_of(source, "firewall.default_policy", &["whitelist", "blacklist"], "whitelist")?.as_str() {
+                "blacklist" => Policy::Blacklist,
+                _ => Policy::Whitelist,
             },
-            database: DatabaseConfig {
-                host: get_env_var("TIMESCALE_DB_HOST")?,
-                port: get_env_var("TIMESCALE_DB_PORT")?.parse::<u16>().map_err(|e| ConfigError::EnvVarParseError(format!("TIMESCALE_DB_PORT: {}", e.to_string())))?,
-                user: get_env_var("TIMESCALE_DB_USER")?,
-                password: get_env_var("TIMESCALE_DB_PASSWORD")?,
-                database: get_env_var("TIMESCALE_DB_DATABASE")?,
+        };
+
+        let buffer = BufferConfig {
+            max_packets: Self::positive(source, "buffer.max_packets", 100_000)? as usize,
+            max_bytes: Self::positive(source, "buffer.max_bytes", 256 * 1024 * 1024)? as usize,
+            overflow_policy: match Self::one_of(source, "buffer.overflow_policy", OVERFLOW_POLICIES, "drop_oldest")?.as_str() {
+                "drop_newest" => OverflowPolicy::DropNewest,
+                "block" => OverflowPolicy::Block,
+                _ => OverflowPolicy::DropOldest,
             },
-            network: NetworkConfig {
-                docker_mode: dotenv::var("DOCKER_MODE").map(|v| v.to_lowercase() == "true").unwrap_or(false),
-                docker_interface_name: get_env_var("DOCKER_INTERFACE_NAME")?,
+        };
+
+        let spool = SpoolConfig {
+            enabled: Self::parse_bool(source, "spool.enabled")?.unwrap_or(false),
+            directory: source.with_default("spool.directory", PathBuf::from("./spool"))?,
+            max_bytes: Self::positive(source, "spool.max_bytes", 1024 * 1024 * 1024)?,
+            retry_interval: Duration::from_millis(Self::positive(source, "spool.retry_interval_ms", 5000)?),
+        };
+
+        let batching = BatchingConfig {
+            flush_interval: Duration::from_millis(Self::positive(source, "batching.flush_interval_ms", 10)?),
+            chunk_size: Self::positive(source, "batching.chunk_size", 50)? as usize,
+            min_batch_size: Self::positive(source, "batching.min_batch_size", 50)? as usize,
+            max_batch_size: Self::positive(source, "batching.max_batch_size", 5000)? as usize,
+            // 読み取りタスク用の接続を残すため、デフォルトはプールの最大接続数未満にする
+            max_in_flight: Self::positive(source, "batching.max_in_flight", 4.min(database.pool.max_size.saturating_sub(1)).max(1) as u64)? as usize,
+            max_retries: source.with_default("batching.max_retries", 3)?,
+            insert_method: match Self::one_of(source, "batching.insert_method", INSERT_METHODS, "auto")?.as_str() {
+                "copy" => InsertMethod::Copy,
+                "unnest" => InsertMethod::Unnest,
+                _ => InsertMethod::Auto,
+            },
+            copy_threshold: Self::positive(source, "batching.copy_threshold", 200)? as usize,
+        };
+
+        let delivery = {
+            let mode = match Self::one_of(source, "delivery.mode", DELIVERY_MODES, "notify")?.as_str() {
+                "poll" => DeliveryMode::Poll,
+                _ => DeliveryMode::Notify,
+            };
+            let channel: String = source.with_default("delivery.channel", "stegrdb_packets".to_string())?;
+            // LISTENでは識別子として埋め込むため、引用符が不要な名前に限定する
+            if channel.is_empty() || channel.len() > 63 || !channel.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+                return Err(source.invalid("delivery.channel", "英小文字・数字・_ からなる63文字以内の名前を指定してください"));
+            }
+            let default_interval = match mode {
+                DeliveryMode::Notify => 1000,
+                DeliveryMode::Poll => 10,
+            };
+            let catch_up = match Self::one_of(source, "delivery.catch_up", CATCH_UP_POLICIES, "replay")?.as_str() {
+                "resume" => CatchUpPolicy::Resume,
+                "live" => CatchUpPolicy::Live,
+                _ => CatchUpPolicy::Replay,
+            };
+            // 0を指定した場合は制限しない
+            let max_age_secs: u64 = source.with_default("delivery.catch_up_max_age_secs", 4)?;
+            let max_packets: i64 = source.with_default("delivery.catch_up_max_packets", 0)?;
+            if max_packets < 0 {
+                return Err(source.invalid("delivery.catch_up_max_packets", "0以上の値を指定してください"));
+            }
+            DeliveryConfig {
+                mode,
+                channel,
+                poll_interval: Duration::from_millis(Self::positive(source, "delivery.poll_interval_ms", default_interval)?),
+                catch_up,
+                catch_up_max_age: (max_age_secs > 0).then(|| Duration::from_secs(max_age_secs)),
+                catch_up_max_packets: (max_packets > 0).then_some(max_packets),
+            }
+        };
+
+        if batching.min_batch_size > batching.max_batch_size {
+            return Err(source.invalid(
+                "batching.min_batch_size",
+                format!("batching.max_batch_size ({}) 以下の値を指定してください", batching.max_batch_size),
+            ));
+        }
+        if batching.max_in_flight > database.pool.max_size as usize {
+            return Err(source.invalid(
+                "batching.max_in_flight",
+                format!("database.pool.max_size ({}) 以下の値を指定してください", database.pool.max_size),
+            ));
+        }
+
+        Ok(Self {
+            node_id,
+            database,
+            network,
+            logger_config,
+            firewall,
+            buffer,
+            spool,
+            batching,
+            delivery,
+            retention: RetentionConfig::from_source(source)?,
+            partition: PartitionConfig {
+                interval: Self::hours(source, "partition.interval_hours", Self::positive(source, "partition.interval_hours", 24)?)?,
+                premake: source.with_default("partition.premake", 3)?,
+                maintenance_interval: Duration::from_secs(Self::positive(source, "partition.maintenance_interval_secs", 3600)?),
             },
-            logger_config: LoggerConfig {
-                normal_logger_file: get_env_var("NORMAL_LOGGER_FILE")?,
-                idps_logger_file: get_env_var("IDPS_LOGGER_FILE")?,
-                idps_log_mode: get_env_var("IDPS_LOG_MODE")?,
-                normal_path_style: get_env_var("NORMAL_PATH_STYLE")?,
-                idps_path_style: get_env_var("IDPS_PATH_STYLE")?,
+            carrier,
+            encryption: EncryptionConfig {
+                enabled: Self::parse_bool(source, "encryption.enabled")?.unwrap_or(false),
+                key_file: source.with_default("encryption.key_file", PathBuf::from("./stegrdb.keys"))?,
+                metadata: match Self::one_of(source, "encryption.metadata", METADATA_MODES, "redact")?.as_str() {
+                    "plain" => MetadataMode::Plain,
+                    _ => MetadataMode::Redact,
+                },
+                accept_plaintext: Self::parse_bool(source, "encryption.accept_plaintext")?.unwrap_or(false),
             },
+            signing: SigningConfig::from_source(source)?,
         })
     }
+
+    fn parse_bool(source: &ConfigSource, key: &'static str) -> Result<Option<bool>, ConfigError> {
+        match source.optional::<String>(key)? {
+            Some(value) => match value.to_lowercase().as_str() {
+                "true" | "1" | "yes" => Ok(Some(true)),
+                "false" | "0" | "no" => Ok(Some(false)),
+                _ => Err(source.invalid(key, format!("真偽値ではありません: {}", value))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn one_of(source: &ConfigSource, key: &'static str, allowed: &[&str], default: &str) -> Result<String, ConfigError> {
+        let value = source.with_default(key, default.to_string())?.to_lowercase();
+        if allowed.contains(&value.as_str()) {
+            Ok(value)
+        } else {
+            Err(source.invalid(key, format!("{} のいずれかを指定してください: {}", allowed.join(", "), value)))
+        }
+    }
+
+    /// 時間単位の設定値をDurationに変換する (秒への変換やデータベースの期間型で桁あふれしないよう、MAX_HOURSを超える値はエラーにする)
+    fn hours(source: &ConfigSource, key: &'static str, hours: u64) -> Result<Duration, ConfigError> {
+        hours
+            .checked_mul(3600)
+            .filter(|_| hours <= MAX_HOURS)
+            .map(Duration::from_secs)
+            .ok_or_else(|| source.invalid(key, format!("{}以下の値を指定してください: {}", MAX_HOURS, hours)))
+    }
+
+    fn positive(source: &ConfigSource, key: &'static str, default: u64) -> Result<u64, ConfigError> {
+        let value = source.with_default(key, default)?;
+        if value == 0 {
+            return Err(source.invalid(key, "1以上の値を指定してください"));
+        }
+        Ok(value)
+    }
 }
@@ -1,13 +1,20 @@
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names, reason = "エラー型のバリアントは `~Error` で統一している")]
 #[derive(Error, Debug)]
 pub enum ConfigError {
-    #[error("環境変数ファイルの読み込みに失敗しました: {0}")]
-    EnvFileReadError(String),
+    #[error("設定ファイルの読み込みに失敗しました: {0}")]
+    FileReadError(String),
 
-    #[error("環境変数の取得に失敗しました: {0}")]
-    EnvVarError(String),
+    #[error("設定ファイルの解析に失敗しました: {0}")]
+    FileParseError(String),
 
-    #[error("環境変数の解析に失敗しました: {0}")]
-    EnvVarParseError(String),
+    #[error("不明な設定キーです: {0}")]
+    UnknownKeyError(String),
+
+    #[error("必須の設定値がありません: {0}")]
+    MissingValueError(String),
+
+    #[error("設定値が不正です: {0}: {1}")]
+    InvalidValueError(String, String),
 }
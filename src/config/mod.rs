@@ -1,5 +1,30 @@
 mod app_config;
 mod error;
+mod source;
 
 pub use app_config::AppConfig;
+pub use app_config::BatchingConfig;
+pub use app_config::BufferConfig;
+pub use app_config::CarrierConfig;
+pub use app_config::CarrierKind;
+pub use app_config::CatchUpPolicy;
+pub use app_config::CircuitBreakerConfig;
+pub use app_config::ConfigOverrides;
+pub use app_config::DatabaseConfig;
+pub use app_config::DeliveryConfig;
+pub use app_config::DeliveryMode;
+pub use app_config::EncryptionConfig;
+pub use app_config::FirewallConfig;
+pub use app_config::InsertMethod;
 pub use app_config::LoggerConfig;
+pub use app_config::MetadataMode;
+pub use app_config::NetworkConfig;
+pub use app_config::OverflowPolicy;
+pub use app_config::PartitionConfig;
+pub use app_config::PoolConfig;
+pub use app_config::RetentionConfig;
+pub use app_config::SigningConfig;
+pub use app_config::SpoolConfig;
+pub use app_config::SslMode;
+pub use app_config::TlsConfig;
+pub use error::ConfigError;
@@ -0,0 +1,223 @@
+use crate::config::error::ConfigError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// 設定ファイルを明示しない場合に参照する環境変数とデフォルトのパス
+pub const CONFIG_PATH_ENV: &str = "STEGRDB_CONFIG";
+pub const DEFAULT_CONFIG_PATH: &str = "./stegrdb.toml";
+
+/// 設定キーと、それに対応する環境変数名の対応表
+/// 設定ファイルに存在しないキーが書かれていた場合はエラーとして扱う
+pub const KNOWN_KEYS: &[(&str, &str)] = &[
+    ("node_id", "NODE_ID"),
+    ("database.host", "TIMESCALE_DB_HOST"),
+    ("database.port", "TIMESCALE_DB_PORT"),
+    ("database.user", "TIMESCALE_DB_USER"),
+    ("database.password", "TIMESCALE_DB_PASSWORD"),
+    ("database.database", "TIMESCALE_DB_DATABASE"),
+    ("database.auto_migrate", "DATABASE_AUTO_MIGRATE"),
+    ("database.degraded_start", "DATABASE_DEGRADED_START"),
+    ("database.clock_skew_warn_ms", "DATABASE_CLOCK_SKEW_WARN_MS"),
+    ("database.sslmode", "TIMESCALE_DB_SSLMODE"),
+    ("database.sslrootcert", "TIMESCALE_DB_SSLROOTCERT"),
+    ("database.sslcert", "TIMESCALE_DB_SSLCERT"),
+    ("database.sslkey", "TIMESCALE_DB_SSLKEY"),
+    ("database.pool.max_size", "DATABASE_POOL_MAX_SIZE"),
+    ("database.pool.min_idle", "DATABASE_POOL_MIN_IDLE"),
+    ("database.pool.connection_timeout_ms", "DATABASE_POOL_CONNECTION_TIMEOUT_MS"),
+    ("database.pool.idle_timeout_secs", "DATABASE_POOL_IDLE_TIMEOUT_SECS"),
+    ("database.pool.max_lifetime_secs", "DATABASE_POOL_MAX_LIFETIME_SECS"),
+    ("database.pool.stats_interval_secs", "DATABASE_POOL_STATS_INTERVAL_SECS"),
+    ("database.circuit_breaker.failure_threshold", "DATABASE_CIRCUIT_BREAKER_FAILURE_THRESHOLD"),
+    ("database.circuit_breaker.initial_backoff_ms", "DATABASE_CIRCUIT_BREAKER_INITIAL_BACKOFF_MS"),
+    ("database.circuit_breaker.max_backoff_ms", "DATABASE_CIRCUIT_BREAKER_MAX_BACKOFF_MS"),
+    ("network.interface", "INTERFACE_NAME"),
+    ("network.docker_mode", "DOCKER_MODE"),
+    ("network.docker_interface_name", "DOCKER_INTERFACE_NAME"),
+    ("logger.normal_logger_file", "NORMAL_LOGGER_FILE"),
+    ("logger.idps_logger_file", "IDPS_LOGGER_FILE"),
+    ("logger.idps_log_mode", "IDPS_LOG_MODE"),
+    ("logger.normal_path_style", "NORMAL_PATH_STYLE"),
+    ("logger.idps_path_style", "IDPS_PATH_STYLE"),
+    ("firewall.default_policy", "FIREWALL_DEFAULT_POLICY"),
+    ("buffer.max_packets", "BUFFER_MAX_PACKETS"),
+    ("buffer.max_bytes", "BUFFER_MAX_BYTES"),
+    ("buffer.overflow_policy", "BUFFER_OVERFLOW_POLICY"),
+    ("spool.enabled", "SPOOL_ENABLED"),
+    ("spool.directory", "SPOOL_DIRECTORY"),
+    ("spool.max_bytes", "SPOOL_MAX_BYTES"),
+    ("spool.retry_interval_ms", "SPOOL_RETRY_INTERVAL_MS"),
+    ("delivery.mode", "DELIVERY_MODE"),
+    ("delivery.channel", "DELIVERY_CHANNEL"),
+    ("delivery.poll_interval_ms", "DELIVERY_POLL_INTERVAL_MS"),
+    ("delivery.catch_up", "DELIVERY_CATCH_UP"),
+    ("delivery.catch_up_max_age_secs", "DELIVERY_CATCH_UP_MAX_AGE_SECS"),
+    ("delivery.catch_up_max_packets", "DELIVERY_CATCH_UP_MAX_PACKETS"),
+    ("batching.flush_interval_ms", "BATCH_FLUSH_INTERVAL_MS"),
+    ("batching.chunk_size", "BATCH_CHUNK_SIZE"),
+    ("batching.min_batch_size", "BATCH_MIN_BATCH_SIZE"),
+    ("batching.max_batch_size", "BATCH_MAX_BATCH_SIZE"),
+    ("batching.max_in_flight", "BATCH_MAX_IN_FLIGHT"),
+    ("batching.max_retries", "BATCH_MAX_RETRIES"),
+    ("batching.insert_method", "BATCH_INSERT_METHOD"),
+    ("batching.copy_threshold", "BATCH_COPY_THRESHOLD"),
+    ("retention.packets_max_age_hours", "RETENTION_PACKETS_MAX_AGE_HOURS"),
+    ("retention.packets_max_size_mb", "RETENTION_PACKETS_MAX_SIZE_MB"),
+    ("retention.node_max_age_hours", "RETENTION_NODE_MAX_AGE_HOURS"),
+    ("retention.node_activity_max_age_hours", "RETENTION_NODE_ACTIVITY_MAX_AGE_HOURS"),
+    ("retention.purge_interval_secs", "RETENTION_PURGE_INTERVAL_SECS"),
+    ("partition.interval_hours", "PARTITION_INTERVAL_HOURS"),
+    ("partition.premake", "PARTITION_PREMAKE"),
+    ("partition.maintenance_interval_secs", "PARTITION_MAINTENANCE_INTERVAL_SECS"),
+    ("carrier.kind", "CARRIER_KIND"),
+    ("carrier.sqlite_path", "CARRIER_SQLITE_PATH"),
+    ("carrier.sqlite_busy_timeout_ms", "CARRIER_SQLITE_BUSY_TIMEOUT_MS"),
+    ("carrier.sqlite_watch_interval_ms", "CARRIER_SQLITE_WATCH_INTERVAL_MS"),
+    ("carrier.mysql_host", "CARRIER_MYSQL_HOST"),
+    ("carrier.mysql_port", "CARRIER_MYSQL_PORT"),
+    ("carrier.mysql_user", "CARRIER_MYSQL_USER"),
+    ("carrier.mysql_password", "CARRIER_MYSQL_PASSWORD"),
+    ("carrier.mysql_database", "CARRIER_MYSQL_DATABASE"),
+    ("carrier.mysql_tls", "CARRIER_MYSQL_TLS"),
+    ("carrier.mysql_pool_max_size", "CARRIER_MYSQL_POOL_MAX_SIZE"),
+    ("carrier.mysql_connection_timeout_ms", "CARRIER_MYSQL_CONNECTION_TIMEOUT_MS"),
+    ("carrier.mysql_watch_interval_ms", "CARRIER_MYSQL_WATCH_INTERVAL_MS"),
+    ("encryption.enabled", "ENCRYPTION_ENABLED"),
+    ("encryption.key_file", "ENCRYPTION_KEY_FILE"),
+    ("encryption.metadata", "ENCRYPTION_METADATA"),
+    ("encryption.accept_plaintext", "ENCRYPTION_ACCEPT_PLAINTEXT"),
+    ("signing.enabled", "SIGNING_ENABLED"),
+    ("signing.key_file", "SIGNING_KEY_FILE"),
+    ("signing.seq_file", "SIGNING_SEQ_FILE"),
+    ("signing.accept_unsigned", "SIGNING_ACCEPT_UNSIGNED"),
+];
+
+/// 値がどの層から来たかを表す (エラーメッセージ用)
+#[derive(Debug, Clone)]
+pub enum Origin {
+    File(PathBuf),
+    Env(&'static str),
+    Override,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::File(path) => write!(f, "設定ファイル {}", path.display()),
+            Origin::Env(name) => write!(f, "環境変数 {}", name),
+            Origin::Override => write!(f, "コマンドライン引数"),
+        }
+    }
+}
+
+/// 設定ファイル < 環境変数 < コマンドライン引数 の順に上書きされるキー/値の集合
+#[derive(Debug, Default)]
+pub struct ConfigSource {
+    values: BTreeMap<&'static str, (String, Origin)>,
+}
+
+impl ConfigSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// TOMLファイルを読み込んで値を追加する
+    pub fn merge_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let content = std::fs::read_to_string(path).map_err(|e| ConfigError::FileReadError(format!("{}: {}", path.display(), e)))?;
+        let table: toml::Table = content.parse().map_err(|e| ConfigError::FileParseError(format!("{}: {}", path.display(), e)))?;
+
+        let mut flattened = Vec::new();
+        flatten_table("", &table, &mut flattened)?;
+
+        for (key, value) in flattened {
+            let known_key = Self::known_key(&key).ok_or_else(|| ConfigError::UnknownKeyError(key.clone()))?;
+            self.values.insert(known_key, (value, Origin::File(path.to_path_buf())));
+        }
+
+        Ok(())
+    }
+
+    /// 既知の環境変数を読み込んで値を追加する
+    pub fn merge_env(&mut self) {
+        for (key, env_name) in KNOWN_KEYS {
+            if let Ok(value) = std::env::var(env_name) {
+                self.values.insert(key, (value, Origin::Env(env_name)));
+            }
+        }
+    }
+
+    /// `key=value` 形式の上書き指定を追加する
+    pub fn merge_overrides(&mut self, overrides: &[(String, String)]) -> Result<(), ConfigError> {
+        for (key, value) in overrides {
+            let known_key = Self::known_key(key).ok_or_else(|| ConfigError::UnknownKeyError(key.clone()))?;
+            self.values.insert(known_key, (value.clone(), Origin::Override));
+        }
+        Ok(())
+    }
+
+    pub fn required<T>(&self, key: &'static str) -> Result<T, ConfigError>
+    where
+        T: std::str::FromStr,
+        T::Err: fmt::Display,
+    {
+        self.optional(key)?.ok_or_else(|| ConfigError::MissingValueError(Self::describe_key(key)))
+    }
+
+    pub fn optional<T>(&self, key: &'static str) -> Result<Option<T>, ConfigError>
+    where
+        T: std::str::FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.values.get(key) {
+            Some((value, origin)) => value.trim().parse::<T>().map(Some).map_err(|e| ConfigError::InvalidValueError(format!("{} ({})", key, origin), e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    pub fn with_default<T>(&self, key: &'static str, default: T) -> Result<T, ConfigError>
+    where
+        T: std::str::FromStr,
+        T::Err: fmt::Display,
+    {
+        Ok(self.optional(key)?.unwrap_or(default))
+    }
+
+    /// 値の検証に失敗した場合に、キーと値の出所を含むエラーを作成する
+    pub fn invalid(&self, key: &'static str, reason: impl Into<String>) -> ConfigError {
+        let label = match self.values.get(key) {
+            Some((_, origin)) => format!("{} ({})", key, origin),
+            None => key.to_string(),
+        };
+        ConfigError::InvalidValueError(label, reason.into())
+    }
+
+    fn known_key(key: &str) -> Option<&'static str> {
+        KNOWN_KEYS.iter().find(|(known, _)| *known == key).map(|(known, _)| *known)
+    }
+
+    fn describe_key(key: &'static str) -> String {
+        match KNOWN_KEYS.iter().find(|(known, _)| *known == key) {
+            Some((_, env_name)) => format!("{} (環境変数 {})", key, env_name),
+            None => key.to_string(),
+        }
+    }
+}
+
+/// ネストしたTOMLテーブルを `section.key` 形式のキーに展開する
+fn flatten_table(prefix: &str, table: &toml::Table, out: &mut Vec<(String, String)>) -> Result<(), ConfigError> {
+    for (name, value) in table {
+        let key = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        match value {
+            toml::Value::Table(inner) => flatten_table(&key, inner, out)?,
+            toml::Value::String(s) => out.push((key, s.clone())),
+            toml::Value::Integer(i) => out.push((key, i.to_string())),
+            toml::Value::Float(f) => out.push((key, f.to_string())),
+            toml::Value::Boolean(b) => out.push((key, b.to_string())),
+            toml::Value::Datetime(_) | toml::Value::Array(_) => {
+                return Err(ConfigError::InvalidValueError(key, "配列・日時の値には対応していません".to_string()));
+            },
+        }
+    }
+    Ok(())
+}
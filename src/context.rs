@@ -0,0 +1,53 @@
+use crate::carrier::Carrier;
+use crate::config::AppConfig;
+use crate::crypto::{PacketCipher, PacketSigner};
+use crate::packet::reader::DeliveryTracker;
+use crate::packet::spool::PacketSpool;
+use crate::packet::writer::PacketBuffer;
+use crate::packet::PacketData;
+use crate::services::FirewallService;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+// キャプチャ通知の購読者が遅れた場合に保持しておくパケット数
+const CAPTURE_CHANNEL_CAPACITY: usize = 1024;
+
+/// 1つのトンネルインスタンスが共有する実行時の状態
+/// mainで一度だけ構築し、各タスクにはArcで渡す
+pub struct AppContext {
+    pub config: AppConfig,
+    /// 他のノードとパケットを受け渡す媒体
+    pub carrier: Arc<dyn Carrier>,
+    pub firewall: FirewallService,
+    pub buffer: PacketBuffer,
+    /// データベースに書き込めなかったパケットの退避先 (無効の場合はNone)
+    pub spool: Option<PacketSpool>,
+    /// raw_packetの暗号化・復号 (暗号化が無効の場合はNone)
+    pub cipher: Option<PacketCipher>,
+    /// 書き込むパケットへの署名 (署名が無効の場合はNone)
+    pub signer: Option<PacketSigner>,
+    /// 他のノードから読み取ったパケットの送信状況
+    pub delivery: DeliveryTracker,
+    /// ファイアウォールを通過してバッファに積まれたパケットの通知
+    pub captured: broadcast::Sender<PacketData>,
+}
+
+impl AppContext {
+    pub fn new(config: AppConfig, carrier: Arc<dyn Carrier>, spool: Option<PacketSpool>, cipher: Option<PacketCipher>, signer: Option<PacketSigner>) -> Arc<Self> {
+        Arc::new(Self {
+            buffer: PacketBuffer::new(&config.buffer),
+            spool,
+            cipher,
+            signer,
+            config,
+            carrier,
+            firewall: FirewallService::new(),
+            delivery: DeliveryTracker::new(),
+            captured: broadcast::channel(CAPTURE_CHANNEL_CAPACITY).0,
+        })
+    }
+
+    pub fn node_id(&self) -> i16 {
+        self.config.node_id
+    }
+}
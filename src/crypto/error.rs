@@ -0,0 +1,46 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CryptoError {
+    #[error("鍵ファイルの読み込みに失敗しました: {0}")]
+    KeyFileError(String),
+
+    #[error("鍵ファイルの形式が不正です: {0}")]
+    KeyFormatError(String),
+
+    #[error("鍵ID {0} が鍵ファイルに登録されていません")]
+    UnknownKeyError(i16),
+
+    #[error("暗号化されていないパケットは受け付けません (encryption.accept_plaintext)")]
+    PlaintextRejectedError,
+
+    #[error("パケットの復号に失敗しました (鍵ID {0}): 改ざんされたか、鍵が異なります")]
+    DecryptionError(i16),
+
+    #[error("パケットの暗号化に失敗しました: {0}")]
+    EncryptionError(String),
+
+    #[error("署名鍵ファイルを作成できません: {0}")]
+    KeyGenerationError(String),
+
+    #[error("シーケンス番号のファイルを読み書きできません: {0}")]
+    SeqFileError(String),
+
+    #[error("署名されていないパケットは受け付けません (signing.accept_unsigned)")]
+    UnsignedPacketError,
+
+    #[error("ノード {0} の公開鍵が node_list に登録されていません")]
+    UnknownNodeKeyError(i16),
+
+    #[error("ノード {0} の公開鍵が不正です")]
+    InvalidPublicKeyError(i16),
+
+    #[error("ノード {0} の署名の検証に失敗しました: 改ざんされたか、ノード {0} 以外が書き込んだパケットです")]
+    SignatureError(i16),
+
+    #[error("ノード {0} のシーケンス番号 {1} は既に受信済みか古すぎるため、再送 (リプレイ) されたパケットとして扱います")]
+    ReplayError(i16, i64),
+
+    #[error("node_list に登録されたノード {0} の公開鍵が署名鍵 ({1}) と一致しません。`stegrdb node set-key` で登録してください")]
+    NodeKeyMismatchError(i16, String),
+}
@@ -0,0 +1,106 @@
+use crate::crypto::error::CryptoError;
+use log::warn;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// XChaCha20-Poly1305の鍵の長さ (バイト)
+pub const KEY_LENGTH: usize = 32;
+
+/// トンネルグループで共有する暗号鍵の一覧
+///
+/// 鍵ファイルはTOMLで、`active` に書き込みに使う鍵のIDを、`[keys]` に鍵ID (0~32767) と
+/// 32バイトの鍵の16進表記を記述する。古い鍵は過去に書き込まれたパケットを読むために残しておく。
+///
+/// ```toml
+/// active = 2
+///
+/// [keys]
+/// 1 = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff"
+/// 2 = "ffeeddccbbaa99887766554433221100ffeeddccbbaa99887766554433221100"
+/// ```
+#[derive(Clone)]
+pub struct KeyRing {
+    active: i16,
+    keys: BTreeMap<i16, [u8; KEY_LENGTH]>,
+}
+
+impl KeyRing {
+    pub fn load(path: &Path) -> Result<Self, CryptoError> {
+        let content = std::fs::read_to_string(path).map_err(|e| CryptoError::KeyFileError(format!("{}: {}", path.display(), e)))?;
+        Self::warn_if_readable(path);
+        Self::parse(&content).map_err(|e| match e {
+            CryptoError::KeyFormatError(message) => CryptoError::KeyFormatError(format!("{}: {}", path.display(), message)),
+            e => e,
+        })
+    }
+
+    pub fn parse(content: &str) -> Result<Self, CryptoError> {
+        let table: toml::Table = content.parse().map_err(|e: toml::de::Error| CryptoError::KeyFormatError(e.to_string()))?;
+
+        let active = match table.get("active") {
+            Some(toml::Value::Integer(id)) => Self::key_id(&id.to_string())?,
+            Some(_) => return Err(CryptoError::KeyFormatError("active には鍵IDを整数で指定してください".to_string())),
+            None => return Err(CryptoError::KeyFormatError("active が指定されていません".to_string())),
+        };
+
+        let Some(toml::Value::Table(entries)) = table.get("keys") else {
+            return Err(CryptoError::KeyFormatError("[keys] が指定されていません".to_string()));
+        };
+
+        let mut keys = BTreeMap::new();
+        for (id, value) in entries {
+            let id = Self::key_id(id)?;
+            let toml::Value::String(encoded) = value else {
+                return Err(CryptoError::KeyFormatError(format!("鍵ID {} の鍵は16進表記の文字列で指定してください", id)));
+            };
+            let mut key = [0u8; KEY_LENGTH];
+            hex::decode_to_slice(encoded.trim(), &mut key)
+                .map_err(|e| CryptoError::KeyFormatError(format!("鍵ID {} の鍵は{}バイトの16進表記で指定してください: {}", id, KEY_LENGTH, e)))?;
+            keys.insert(id, key);
+        }
+
+        if !keys.contains_key(&active) {
+            return Err(CryptoError::UnknownKeyError(active));
+        }
+        Ok(Self { active, keys })
+    }
+
+    /// 書き込みに使用する鍵のID
+    pub fn active_id(&self) -> i16 {
+        self.active
+    }
+
+    pub fn get(&self, id: i16) -> Option<&[u8; KEY_LENGTH]> {
+        self.keys.get(&id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = i16> + '_ {
+        self.keys.keys().copied()
+    }
+
+    fn key_id(value: &str) -> Result<i16, CryptoError> {
+        match value.parse::<i16>() {
+            Ok(id) if id >= 0 => Ok(id),
+            _ => Err(CryptoError::KeyFormatError(format!("鍵IDは0~{}の整数で指定してください: {}", i16::MAX, value))),
+        }
+    }
+
+    // 鍵ファイルを他のユーザーが読める場合は警告する
+    #[cfg(unix)]
+    fn warn_if_readable(path: &Path) {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            let mode = metadata.permissions().mode();
+            if mode & 0o077 != 0 {
+                warn!(
+                    "鍵ファイル {} のパーミッション ({:o}) は所有者以外も読み取れます。chmod 600 を推奨します",
+                    path.display(),
+                    mode & 0o777
+                );
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn warn_if_readable(_path: &Path) {}
+}
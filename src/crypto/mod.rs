@@ -0,0 +1,11 @@
+mod error;
+mod key_ring;
+mod packet_cipher;
+mod packet_signer;
+mod packet_verifier;
+
+pub use error::CryptoError;
+pub use key_ring::KeyRing;
+pub use packet_cipher::PacketCipher;
+pub use packet_signer::PacketSigner;
+pub use packet_verifier::{PacketVerifier, ReceivedSeqs};
@@ -0,0 +1,117 @@
+use crate::config::{EncryptionConfig, MetadataMode};
+use crate::crypto::error::CryptoError;
+use crate::crypto::key_ring::KeyRing;
+use crate::packet::types::{EtherType, InetAddr, IpProtocol, MacAddr};
+use crate::packet::PacketData;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// raw_packetの認証付き暗号化 (XChaCha20-Poly1305)
+///
+/// 暗号化したraw_packetは `nonce (24バイト) || 暗号文 || タグ (16バイト)` の形式で保存する。
+/// 書き込んだノードのIDと鍵IDを関連データとして認証するため、行のnode_idやkey_idを書き換えると復号に失敗する。
+pub struct PacketCipher {
+    active: i16,
+    ciphers: BTreeMap<i16, XChaCha20Poly1305>,
+    metadata: MetadataMode,
+    accept_plaintext: bool,
+}
+
+impl PacketCipher {
+    pub const NONCE_LENGTH: usize = 24;
+    pub const TAG_LENGTH: usize = 16;
+
+    /// encryption.enabledの場合は鍵ファイルを読み込む (無効の場合はNone)
+    pub fn load(config: &EncryptionConfig) -> Result<Option<Self>, CryptoError> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let key_ring = KeyRing::load(&config.key_file)?;
+        Ok(Some(Self::new(&key_ring, config.metadata, config.accept_plaintext)))
+    }
+
+    pub fn new(key_ring: &KeyRing, metadata: MetadataMode, accept_plaintext: bool) -> Self {
+        let ciphers = key_ring.ids().filter_map(|id| key_ring.get(id).map(|key| (id, XChaCha20Poly1305::new(key.into())))).collect();
+        Self {
+            active: key_ring.active_id(),
+            ciphers,
+            metadata,
+            accept_plaintext,
+        }
+    }
+
+    /// 書き込みに使用する鍵のID
+    pub fn active_id(&self) -> i16 {
+        self.active
+    }
+
+    /// パケットのraw_packetを書き込み用の鍵で暗号化する
+    /// encryption.metadata = "redact" の場合、ヘッダーの列には0を書き込む
+    pub fn seal(&self, node_id: i16, packet: &PacketData) -> Result<PacketData, CryptoError> {
+        let cipher = self.ciphers.get(&self.active).ok_or(CryptoError::UnknownKeyError(self.active))?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = Self::associated_data(node_id, self.active);
+        let ciphertext = cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &packet.raw_packet,
+                    aad: &aad,
+                },
+            )
+            .map_err(|e| CryptoError::EncryptionError(e.to_string()))?;
+
+        let mut raw_packet = Vec::with_capacity(Self::NONCE_LENGTH + ciphertext.len());
+        raw_packet.extend_from_slice(&nonce);
+        raw_packet.extend_from_slice(&ciphertext);
+
+        let mut sealed = match self.metadata {
+            MetadataMode::Plain => packet.clone(),
+            MetadataMode::Redact => PacketData {
+                src_mac: MacAddr([0; 6]),
+                dst_mac: MacAddr([0; 6]),
+                ether_type: EtherType::from(0u16),
+                src_ip: InetAddr(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                dst_ip: InetAddr(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                src_port: 0,
+                dst_port: 0,
+                ip_protocol: IpProtocol::from(0u8),
+                captured_at: packet.captured_at,
+                raw_packet: Vec::new(),
+                key_id: None,
+                signature: None,
+            },
+        };
+        sealed.raw_packet = raw_packet;
+        sealed.key_id = Some(self.active);
+        Ok(sealed)
+    }
+
+    /// 読み取ったraw_packetを復号する (key_idがNoneの場合は平文として扱う)
+    pub fn open(&self, node_id: i16, key_id: Option<i16>, raw_packet: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let Some(key_id) = key_id else {
+            return if self.accept_plaintext {
+                Ok(raw_packet.to_vec())
+            } else {
+                Err(CryptoError::PlaintextRejectedError)
+            };
+        };
+        let cipher = self.ciphers.get(&key_id).ok_or(CryptoError::UnknownKeyError(key_id))?;
+        if raw_packet.len() < Self::NONCE_LENGTH + Self::TAG_LENGTH {
+            return Err(CryptoError::DecryptionError(key_id));
+        }
+
+        let (nonce, ciphertext) = raw_packet.split_at(Self::NONCE_LENGTH);
+        let aad = Self::associated_data(node_id, key_id);
+        cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad }).map_err(|_| CryptoError::DecryptionError(key_id))
+    }
+
+    fn associated_data(node_id: i16, key_id: i16) -> [u8; 4] {
+        let mut aad = [0u8; 4];
+        aad[..2].copy_from_slice(&node_id.to_be_bytes());
+        aad[2..].copy_from_slice(&key_id.to_be_bytes());
+        aad
+    }
+}
@@ -0,0 +1,158 @@
+use crate::config::SigningConfig;
+use crate::crypto::error::CryptoError;
+use crate::packet::{PacketData, PacketSignature};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signer, SigningKey, SECRET_KEY_LENGTH};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+// 署名する内容の先頭に付ける識別子 (他の用途の署名と区別する)
+const SIGNATURE_CONTEXT: &[u8] = b"stegrdb-packet-v1\0";
+
+// シーケンス番号を先に使用済みとして保存しておく数 (使い切るたびにseq_fileを書き換える)
+const SEQ_RESERVATION: i64 = 1 << 20;
+
+/// seq_fileに保存したシーケンス番号の上限 (この値より小さい番号は使用済みの可能性がある)
+struct SeqReservation {
+    path: PathBuf,
+    limit: i64,
+}
+
+impl SeqReservation {
+    /// 前回までに保存した上限を読み込む (ファイルがない場合は0)
+    fn load(path: &Path) -> Result<i64, CryptoError> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => content.trim().parse().map_err(|e| CryptoError::SeqFileError(format!("{}: シーケンス番号は整数で記録してください: {}", path.display(), e))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(CryptoError::SeqFileError(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    /// seqを使用する前に、seqより大きい上限を保存しておく (保存できない場合は使用しない)
+    fn reserve(&mut self, seq: i64) -> Result<(), CryptoError> {
+        if seq < self.limit {
+            return Ok(());
+        }
+        let limit = seq.saturating_add(SEQ_RESERVATION);
+
+        // 書き込み途中で停止しても前回の上限が残るよう、一時ファイルに書き込んでから置き換える
+        let temporary = self.path.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&temporary)?;
+            writeln!(file, "{}", limit)?;
+            file.sync_all()?;
+            std::fs::rename(&temporary, &self.path)
+        };
+        write().map_err(|e| CryptoError::SeqFileError(format!("{}: {}", self.path.display(), e)))?;
+        self.limit = limit;
+        Ok(())
+    }
+}
+
+/// 書き込むパケットにこのノードの署名鍵 (Ed25519) で署名する
+///
+/// 署名の対象は書き込んだノードのID・シーケンス番号・キャプチャ時刻・鍵ID・保存するraw_packet (暗号化した場合は暗号文) で、
+/// 読み取り側は node_list に登録された公開鍵で検証する。
+pub struct PacketSigner {
+    key: SigningKey,
+    next_seq: AtomicI64,
+    // Noneの場合はシーケンス番号を保存しない
+    reservation: Option<Mutex<SeqReservation>>,
+}
+
+impl PacketSigner {
+    /// signing.enabledの場合は署名鍵を読み込む (無効の場合はNone)
+    pub fn load(config: &SigningConfig) -> Result<Option<Self>, CryptoError> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let path = &config.key_file;
+        let content = std::fs::read_to_string(path).map_err(|e| CryptoError::KeyFileError(format!("{}: {}", path.display(), e)))?;
+        let mut seed = [0u8; SECRET_KEY_LENGTH];
+        hex::decode_to_slice(content.trim(), &mut seed)
+            .map_err(|e| CryptoError::KeyFormatError(format!("{}: 署名鍵は{}バイトの16進表記で指定してください: {}", path.display(), SECRET_KEY_LENGTH, e)))?;
+
+        // 時計を戻した場合も前回より大きい番号から始まるよう、保存した上限と起動時の時刻 (マイクロ秒) の大きい方から始める
+        let saved = SeqReservation::load(&config.seq_file)?;
+        let start = saved.max(Utc::now().timestamp_micros());
+        let mut reservation = SeqReservation {
+            path: config.seq_file.clone(),
+            limit: saved,
+        };
+        reservation.reserve(start)?;
+
+        Ok(Some(Self {
+            key: SigningKey::from_bytes(&seed),
+            next_seq: AtomicI64::new(start),
+            reservation: Some(Mutex::new(reservation)),
+        }))
+    }
+
+    /// シーケンス番号を保存しない署名を作成する (起動時の時刻から始めるため、時計を戻すと前回より小さい番号を使用する)
+    pub fn new(key: SigningKey) -> Self {
+        Self {
+            key,
+            next_seq: AtomicI64::new(Utc::now().timestamp_micros()),
+            reservation: None,
+        }
+    }
+
+    /// 新しい署名鍵を作成してファイルに保存し、公開鍵を返す (既にファイルがある場合は上書きしない)
+    pub fn generate(path: &Path) -> Result<[u8; 32], CryptoError> {
+        let mut seed = [0u8; SECRET_KEY_LENGTH];
+        OsRng.fill_bytes(&mut seed);
+        let key = SigningKey::from_bytes(&seed);
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path).map_err(|e| CryptoError::KeyGenerationError(format!("{}: {}", path.display(), e)))?;
+        writeln!(file, "{}", hex::encode(seed)).map_err(|e| CryptoError::KeyGenerationError(format!("{}: {}", path.display(), e)))?;
+
+        Ok(key.verifying_key().to_bytes())
+    }
+
+    /// node_list に登録する公開鍵
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    /// シーケンス番号を割り当てて署名する
+    pub fn sign(&self, node_id: i16, mut packet: PacketData) -> Result<PacketData, CryptoError> {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        if let Some(reservation) = &self.reservation {
+            // パニックしたスレッドが保持していても、保存済みの上限は正しいため使い続ける
+            reservation.lock().unwrap_or_else(|e| e.into_inner()).reserve(seq)?;
+        }
+        let message = signed_message(node_id, seq, packet.captured_at, packet.key_id, &packet.raw_packet);
+        packet.signature = Some(Box::new(PacketSignature {
+            seq,
+            signature: self.key.sign(&message).to_bytes(),
+        }));
+        Ok(packet)
+    }
+}
+
+/// 署名の対象となるバイト列
+pub(crate) fn signed_message(node_id: i16, seq: i64, captured_at: DateTime<Utc>, key_id: Option<i16>, raw_packet: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(SIGNATURE_CONTEXT.len() + 21 + raw_packet.len());
+    message.extend_from_slice(SIGNATURE_CONTEXT);
+    message.extend_from_slice(&node_id.to_be_bytes());
+    message.extend_from_slice(&seq.to_be_bytes());
+    // データベースに保存される精度 (マイクロ秒) で署名する
+    message.extend_from_slice(&captured_at.timestamp_micros().to_be_bytes());
+    match key_id {
+        Some(key_id) => {
+            message.push(1);
+            message.extend_from_slice(&key_id.to_be_bytes());
+        },
+        None => message.extend_from_slice(&[0, 0, 0]),
+    }
+    message.extend_from_slice(raw_packet);
+    message
+}
@@ -0,0 +1,163 @@
+use crate::crypto::error::CryptoError;
+use crate::crypto::packet_signer::signed_message;
+use crate::packet::FetchedPacket;
+use crate::services::NodeRecord;
+use ed25519_dalek::{Signature, VerifyingKey};
+use std::collections::{BTreeSet, HashMap};
+
+/// 書き込んだノードごとの受信済みのシーケンス番号
+/// 同時に書き込んだバッチのコミット順が前後するため、最大値からREPLAY_WINDOWの範囲は順不同で受け付ける
+#[derive(Clone, Default)]
+struct ReplayWindow {
+    highest: Option<i64>,
+    seen: BTreeSet<i64>,
+}
+
+/// 媒体に記録する、書き込んだノードごとの受信済みのシーケンス番号
+/// 最大値と、最大値からREPLAY_WINDOWの範囲内で受信済みの番号を連続する区間にまとめて保持する
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReceivedSeqs {
+    /// 書き込んだノードのID
+    pub node_id: i16,
+    /// 受信済みのシーケンス番号の最大値
+    pub highest: i64,
+    /// 受信済みの番号の区間 (両端を含む、昇順)
+    pub ranges: Vec<(i64, i64)>,
+}
+
+impl ReceivedSeqs {
+    /// 媒体から読み出した最大値 (書き込んだノードID, 最大値) と区間 (書き込んだノードID, 先頭, 末尾) をノードごとにまとめる
+    pub fn from_rows(highest: Vec<(i16, i64)>, ranges: Vec<(i16, i64, i64)>) -> Vec<Self> {
+        let mut seqs: Vec<Self> = highest
+            .into_iter()
+            .map(|(node_id, highest)| Self {
+                node_id,
+                highest,
+                ranges: Vec::new(),
+            })
+            .collect();
+        seqs.sort_unstable_by_key(|received| received.node_id);
+        for (node_id, first, last) in ranges {
+            if let Ok(index) = seqs.binary_search_by_key(&node_id, |received| received.node_id) {
+                seqs[index].ranges.push((first, last));
+            }
+        }
+        for received in &mut seqs {
+            received.ranges.sort_unstable();
+        }
+        seqs
+    }
+
+    /// 媒体に書き込む区間の行 (書き込んだノードID, 先頭, 末尾)
+    pub fn range_rows(seqs: &[Self]) -> impl Iterator<Item = (i16, i64, i64)> + '_ {
+        seqs.iter().flat_map(|received| received.ranges.iter().map(move |(first, last)| (received.node_id, *first, *last)))
+    }
+}
+
+/// 他のノードが書き込んだパケットの署名とシーケンス番号を検証する
+/// 公開鍵は node_list から読み込む。受信済みのシーケンス番号は範囲内の全てを保持し、
+/// 再起動後は媒体に記録した範囲 (`restore_seqs`) から復元する
+#[derive(Clone)]
+pub struct PacketVerifier {
+    keys: HashMap<i16, VerifyingKey>,
+    windows: HashMap<i16, ReplayWindow>,
+    accept_unsigned: bool,
+}
+
+impl PacketVerifier {
+    /// 受信済みのシーケンス番号を保持する範囲
+    pub const REPLAY_WINDOW: i64 = 1 << 16;
+
+    pub fn new(accept_unsigned: bool) -> Self {
+        Self {
+            keys: HashMap::new(),
+            windows: HashMap::new(),
+            accept_unsigned,
+        }
+    }
+
+    /// node_list に登録された公開鍵を読み込み直す (不正な公開鍵のノードはNoneの場合と同じく検証に失敗する)
+    pub fn update_keys(&mut self, nodes: &[NodeRecord]) -> Vec<CryptoError> {
+        let mut errors = Vec::new();
+        self.keys.clear();
+        for node in nodes {
+            let Some(public_key) = &node.public_key else { continue };
+            match <[u8; 32]>::try_from(public_key.as_slice()).ok().and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok()) {
+                Some(key) => {
+                    self.keys.insert(node.id, key);
+                },
+                None => errors.push(CryptoError::InvalidPublicKeyError(node.id)),
+            }
+        }
+        errors
+    }
+
+    pub fn has_key(&self, node_id: i16) -> bool {
+        self.keys.contains_key(&node_id)
+    }
+
+    /// 前回の起動までに記録した受信済みのシーケンス番号を復元する
+    /// 記録した時点でコミットされていなかった、最大値より小さい未受信の番号も範囲内であれば受け付ける
+    pub fn restore_seqs(&mut self, seqs: &[ReceivedSeqs]) {
+        for received in seqs {
+            let window = self.windows.entry(received.node_id).or_default();
+            let highest = window.highest.map_or(received.highest, |highest| highest.max(received.highest));
+            window.highest = Some(highest);
+            window.seen.insert(received.highest);
+            for (first, last) in &received.ranges {
+                window.seen.extend((*first).max(highest - Self::REPLAY_WINDOW + 1)..=(*last).min(highest));
+            }
+            window.seen = window.seen.split_off(&(highest - Self::REPLAY_WINDOW + 1));
+        }
+    }
+
+    /// 書き込んだノードごとの受信済みのシーケンス番号 (ノードID順)
+    pub fn received_seqs(&self) -> Vec<ReceivedSeqs> {
+        let mut seqs: Vec<ReceivedSeqs> = self
+            .windows
+            .iter()
+            .filter_map(|(node_id, window)| {
+                let highest = window.highest?;
+                let mut ranges: Vec<(i64, i64)> = Vec::new();
+                for seq in &window.seen {
+                    match ranges.last_mut() {
+                        Some((_, last)) if *last + 1 == *seq => *last = *seq,
+                        _ => ranges.push((*seq, *seq)),
+                    }
+                }
+                Some(ReceivedSeqs {
+                    node_id: *node_id,
+                    highest,
+                    ranges,
+                })
+            })
+            .collect();
+        seqs.sort_unstable_by_key(|received| received.node_id);
+        seqs
+    }
+
+    /// 署名を検証し、未受信のシーケンス番号であれば受信済みとして記録する
+    pub fn verify(&mut self, packet: &FetchedPacket) -> Result<(), CryptoError> {
+        let Some(signed) = &packet.signature else {
+            return if self.accept_unsigned { Ok(()) } else { Err(CryptoError::UnsignedPacketError) };
+        };
+        let seq = signed.seq;
+        let key = self.keys.get(&packet.node_id).ok_or(CryptoError::UnknownNodeKeyError(packet.node_id))?;
+        let signature = Signature::from_bytes(&signed.signature);
+        let message = signed_message(packet.node_id, seq, packet.captured_at, packet.key_id, &packet.raw_packet);
+        key.verify_strict(&message, &signature).map_err(|_| CryptoError::SignatureError(packet.node_id))?;
+
+        // 署名が正しいパケットだけで受信済みの範囲を進める
+        let window = self.windows.entry(packet.node_id).or_default();
+        if let Some(highest) = window.highest {
+            if seq <= highest - Self::REPLAY_WINDOW || window.seen.contains(&seq) {
+                return Err(CryptoError::ReplayError(packet.node_id, seq));
+            }
+        }
+        window.seen.insert(seq);
+        let highest = window.highest.map_or(seq, |highest| highest.max(seq));
+        window.highest = Some(highest);
+        window.seen = window.seen.split_off(&(highest - Self::REPLAY_WINDOW + 1));
+        Ok(())
+    }
+}
@@ -1,8 +1,14 @@
+use crate::config::DatabaseConfig;
 use crate::database::error::DatabaseError;
-use crate::database::pool::DatabasePool;
+use crate::database::health::{DatabaseHealth, HealthPermit};
+use crate::database::listener::NotificationListener;
+use crate::database::pool::{DatabasePool, PoolStats};
+use crate::database::statement_cache::{CachedTransaction, CachingConnectionManager, StatementCacheStats};
 use async_trait::async_trait;
-use std::collections::HashMap;
-use tokio_postgres::{Row, Statement};
+use bb8::PooledConnection;
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::{Client, Row};
 
 #[async_trait]
 pub trait ExecuteQuery {
@@ -11,74 +17,177 @@ pub trait ExecuteQuery {
 }
 
 pub struct Database {
-    prepared_statements: HashMap<String, Statement>,
+    pool: DatabasePool,
+    health: DatabaseHealth,
 }
 
 impl Database {
-    pub async fn connect(host: &str, port: u16, user: &str, password: &str, database: &str) -> Result<(), DatabaseError> {
-        DatabasePool::initialize(host, port, user, password, database).await
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self, DatabaseError> {
+        let pool = DatabasePool::initialize(config).await?;
+        Ok(Self {
+            pool,
+            health: DatabaseHealth::new(&config.circuit_breaker),
+        })
     }
 
-    pub fn get_database() -> &'static Self {
-        // DbPoolの存在を確認
-        let _ = DatabasePool::get_pool();
-        // Databaseはステートレスなので、staticなインスタンスを返す
-        static DATABASE: std::sync::OnceLock<Database> = std::sync::OnceLock::new();
-        DATABASE.get_or_init(|| Database {
-            prepared_statements: HashMap::new(),
+    /// 接続を確認せずに作成する (データベースが停止していても成功し、使用する時に接続する)
+    pub fn connect_lazy(config: &DatabaseConfig) -> Result<Self, DatabaseError> {
+        let pool = DatabasePool::initialize_lazy(config)?;
+        Ok(Self {
+            pool,
+            health: DatabaseHealth::new(&config.circuit_breaker),
         })
     }
 
+    /// データベースの接続状態 (サーキットブレーカー)
+    pub fn health(&self) -> &DatabaseHealth {
+        &self.health
+    }
+
+    /// チャネルをLISTENする専用の接続を確立する
+    pub async fn listen(&self, channel: &str) -> Result<NotificationListener, DatabaseError> {
+        let permit = self.acquire()?;
+        match self.pool.listen(channel).await {
+            Ok(listener) => {
+                permit.success();
+                Ok(listener)
+            },
+            Err(e @ DatabaseError::ConnectionError(_)) => {
+                permit.failure(&e.to_string());
+                Err(e)
+            },
+            Err(e) => {
+                permit.success();
+                Err(e)
+            },
+        }
+    }
+
+    /// 接続プールの使用状況
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// プリペアドステートメントキャッシュの累計ヒット数・ミス数
+    pub fn statement_cache_stats(&self) -> StatementCacheStats {
+        self.pool.statement_cache_stats()
+    }
+
+    /// 複数のSQL文をまとめて実行する (パラメータは使用できない)
+    pub async fn batch_execute(&self, sql: &str) -> Result<(), DatabaseError> {
+        let (client, permit) = self.get().await?;
+        let result = client.batch_execute(sql).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()));
+        Self::settle(permit, &client, result)
+    }
+
+    /// `COPY ... FROM STDIN BINARY` で行をまとめて書き込み、書き込んだ行数を返す
+    /// 途中で失敗した場合はCOPY全体が取り消される
+    pub async fn copy_in_binary(&self, sql: &str, types: &[Type], rows: &[Vec<&(dyn ToSql + Sync)>]) -> Result<u64, DatabaseError> {
+        let (client, permit) = self.get().await?;
+        let result = async {
+            let sink = client.copy_in(sql).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
+
+            let mut writer = std::pin::pin!(BinaryCopyInWriter::new(sink, types));
+            for row in rows {
+                writer.as_mut().write(row).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
+            }
+            writer.finish().await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))
+        }
+        .await;
+        Self::settle(permit, &client, result)
+    }
+
     pub async fn transaction<F, T>(&self, f: F) -> Result<T, DatabaseError>
     where
-        F: for<'a> FnOnce(&'a mut tokio_postgres::Transaction<'_>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, DatabaseError>> + Send + 'a>>,
+        F: for<'a> FnOnce(&'a mut CachedTransaction<'_>) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, DatabaseError>> + Send + 'a>>,
     {
-        let pool = DatabasePool::get_pool()?;
-        let mut client = pool.inner().get().await.map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
-        let mut tx = client.transaction().await.map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
-
-        match f(&mut tx).await {
-            Ok(result) => {
-                tx.commit().await.map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
-                Ok(result)
-            },
+        self.transaction_with(&(), |tx, _| f(tx)).await
+    }
+
+    /// transactionと同じく1つのトランザクションで実行し、借用したデータをfに渡す (所有権のあるデータにコピーせずに書き込むため)
+    pub async fn transaction_with<C, F, T>(&self, data: &C, f: F) -> Result<T, DatabaseError>
+    where
+        C: ?Sized + Sync,
+        F: for<'a> FnOnce(&'a mut CachedTransaction<'_>, &'a C) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<T, DatabaseError>> + Send + 'a>>,
+    {
+        let (mut client, permit) = self.get().await?;
+        let result = async {
+            let mut tx = client.transaction().await.map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+
+            match f(&mut tx, data).await {
+                Ok(result) => {
+                    tx.commit().await.map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+                    Ok(result)
+                },
+                Err(e) => {
+                    tx.rollback().await.map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+                    Err(e)
+                },
+            }
+        }
+        .await;
+        Self::settle(permit, &client, result)
+    }
+
+    /// サーキットブレーカーを確認してから、プールから接続を取得する
+    async fn get(&self) -> Result<(PooledConnection<'_, CachingConnectionManager>, HealthPermit), DatabaseError> {
+        let permit = self.acquire()?;
+        match self.pool.inner().get().await {
+            Ok(client) => Ok((client, permit)),
             Err(e) => {
-                tx.rollback().await.map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
-                Err(e)
+                permit.failure(&e.to_string());
+                Err(DatabaseError::ConnectionError(e.to_string()))
             },
         }
     }
+
+    fn acquire(&self) -> Result<HealthPermit, DatabaseError> {
+        self.health.try_acquire().ok_or_else(|| {
+            let retry_in = self.health.stats().retry_in.unwrap_or_default();
+            DatabaseError::CircuitOpenError(retry_in.as_millis() as u64)
+        })
+    }
+
+    /// 要求の結果をサーキットブレーカーに記録する
+    /// クエリ自体のエラー (制約違反など) は接続の障害として扱わず、接続が切断された場合のみ失敗とする
+    fn settle<T>(permit: HealthPermit, client: &Client, result: Result<T, DatabaseError>) -> Result<T, DatabaseError> {
+        match &result {
+            Err(e) if client.is_closed() => permit.failure(&e.to_string()),
+            _ => permit.success(),
+        }
+        result
+    }
 }
 
 #[async_trait]
 impl ExecuteQuery for Database {
     async fn execute(&self, query: &str, params: &[&(dyn tokio_postgres::types::ToSql + Sync)]) -> Result<u64, DatabaseError> {
-        let pool = DatabasePool::get_pool().map_err(|e| DatabaseError::PoolRetrievalError(e.to_string()))?;
-        let client = pool.inner().get().await.map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
-
-        // プリペアドステートメントのキャッシュを試みる
-        let stmt = if let Some(stmt) = self.prepared_statements.get(query) {
-            stmt.clone()
-        } else {
-            client.prepare(query).await.map_err(|e| DatabaseError::QueryPreparationError(e.to_string()))?
-        };
-
-        let result = client.execute(&stmt, params).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
-        Ok(result)
+        let (mut client, permit) = self.get().await?;
+
+        // 接続ごとにキャッシュされたプリペアドステートメントを使用する
+        let result = async {
+            let stmt = client.prepare_cached(query).await.map_err(|e| DatabaseError::QueryPreparationError(e.to_string()))?;
+            client.execute(&stmt, params).await.map_err(|e| {
+                client.evict(query);
+                DatabaseError::QueryExecutionError(e.to_string())
+            })
+        }
+        .await;
+        Self::settle(permit, &client, result)
     }
 
     async fn query(&self, query: &str, params: &[&(dyn tokio_postgres::types::ToSql + Sync)]) -> Result<Vec<Row>, DatabaseError> {
-        let pool = DatabasePool::get_pool().map_err(|e| DatabaseError::PoolRetrievalError(e.to_string()))?;
-        let client = pool.inner().get().await.map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
-
-        // プリペアドステートメントのキャッシュを試みる
-        let stmt = if let Some(stmt) = self.prepared_statements.get(query) {
-            stmt.clone()
-        } else {
-            client.prepare(query).await.map_err(|e| DatabaseError::QueryPreparationError(e.to_string()))?
-        };
-
-        let rows = client.query(&stmt, params).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
-        Ok(rows)
+        let (mut client, permit) = self.get().await?;
+
+        // 接続ごとにキャッシュされたプリペアドステートメントを使用する
+        let result = async {
+            let stmt = client.prepare_cached(query).await.map_err(|e| DatabaseError::QueryPreparationError(e.to_string()))?;
+            client.query(&stmt, params).await.map_err(|e| {
+                client.evict(query);
+                DatabaseError::QueryExecutionError(e.to_string())
+            })
+        }
+        .await;
+        Self::settle(permit, &client, result)
     }
 }
@@ -1,4 +1,3 @@
-use log::error;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -12,24 +11,30 @@ pub enum DatabaseError {
     #[error("初期プロセスでデータベースの接続に失敗しました: {0}")]
     InitFailedConnectDatabase(String),
 
-    #[error("データベースプールの初期化に失敗しました")]
-    InitializationError,
-
-    #[error("データベースプールが初期化されていません")]
-    PoolNotInitialized,
+    #[error("TLSの設定に失敗しました: {0}")]
+    TlsError(String),
 
     #[error("データベース接続エラー: {0}")]
     ConnectionError(String),
 
+    #[error("データベースへの接続の失敗が続いているため、アクセスを停止しています (再試行まで{0}ms)")]
+    CircuitOpenError(u64),
+
     #[error("クエリの実行に失敗しました: {0}")]
     QueryExecutionError(String),
 
     #[error("クエリの準備に失敗しました: {0}")]
     QueryPreparationError(String),
 
-    #[error("データベースプールの取得に失敗しました: {0}")]
-    PoolRetrievalError(String),
-
     #[error("トランザクション処理に失敗しました: {0}")]
     TransactionError(String),
+
+    #[error("マイグレーションの適用に失敗しました: {0}")]
+    MigrationError(String),
+
+    #[error("データベースのスキーマ (バージョン {0}) はこのバイナリ (バージョン {1}) より新しいため起動できません")]
+    SchemaVersionTooNew(i32, i32),
+
+    #[error("未適用のマイグレーションがあります (現在 {0}, 最新 {1})。`stegrdb schema migrate` を実行してください")]
+    PendingMigrations(i32, i32),
 }
@@ -0,0 +1,257 @@
+use crate::config::CircuitBreakerConfig;
+use log::{info, warn};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::Notify;
+use tokio::time::{Duration, Instant};
+
+/// サーキットブレーカーの状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// 正常 (全ての要求を通す)
+    Closed,
+    /// 遮断中 (再試行の時刻まで要求を通さない)
+    Open,
+    /// 再試行中 (1つの要求だけを試しに通し、その結果で開閉を決める)
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+/// データベースの接続状態とサーキットブレーカーの累計
+#[derive(Debug, Clone)]
+pub struct HealthStats {
+    pub state: CircuitState,
+    /// 連続して接続に失敗した回数
+    pub consecutive_failures: u32,
+    /// 遮断した回数の累計
+    pub trips: u64,
+    /// 次に再試行するまでの残り時間 (遮断中のみ)
+    pub retry_in: Option<Duration>,
+    pub last_error: Option<String>,
+}
+
+struct HealthState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+    // HalfOpenで試行中の要求がある
+    probing: bool,
+    trips: u64,
+    last_error: Option<String>,
+}
+
+/// データベースの接続障害を検知するサーキットブレーカー
+/// 接続の失敗が続いた場合は一定時間データベースへの要求を止め、1つの要求で復旧を確認してから再開する
+#[derive(Clone)]
+pub struct DatabaseHealth {
+    inner: Arc<Mutex<HealthState>>,
+    config: CircuitBreakerConfig,
+    // 状態が変わった時に待機中のタスクを起こす
+    changed: Arc<Notify>,
+}
+
+impl DatabaseHealth {
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(HealthState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                backoff: config.initial_backoff,
+                retry_at: None,
+                probing: false,
+                trips: 0,
+                last_error: None,
+            })),
+            config: config.clone(),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// 要求を通してよい場合はPermitを返す
+    /// 遮断中に再試行の時刻を過ぎていれば、この要求を復旧確認のための試行として通す
+    pub fn try_acquire(&self) -> Option<HealthPermit> {
+        let mut state = self.lock();
+        let probe = match state.state {
+            CircuitState::Closed => false,
+            CircuitState::Open if state.retry_at.is_some_and(|at| Instant::now() < at) => return None,
+            CircuitState::Open => {
+                info!("データベースへの再接続を試みます (連続失敗: {}回)", state.consecutive_failures);
+                state.state = CircuitState::HalfOpen;
+                true
+            },
+            CircuitState::HalfOpen if state.probing => return None,
+            CircuitState::HalfOpen => true,
+        };
+        if probe {
+            state.probing = true;
+        }
+
+        Some(HealthPermit {
+            health: self.clone(),
+            probe,
+            settled: false,
+        })
+    }
+
+    /// 要求を通せる状態か (遮断中でも再試行の時刻を過ぎていればtrue)
+    pub fn is_available(&self) -> bool {
+        let state = self.lock();
+        match state.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => state.retry_at.is_none_or(|at| Instant::now() >= at),
+            CircuitState::HalfOpen => !state.probing,
+        }
+    }
+
+    /// 直前の要求が成功しているか (接続の失敗が1回でも続いている間はfalse)
+    pub fn is_healthy(&self) -> bool {
+        let state = self.lock();
+        state.state == CircuitState::Closed && state.consecutive_failures == 0
+    }
+
+    /// 要求を通せる状態になるまで待機する
+    pub async fn wait_until_available(&self) {
+        loop {
+            // 確認と通知の間に状態の変化を見逃さないよう、確認の前に通知を受け取れる状態にする
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let retry_at = {
+                let state = self.lock();
+                match state.state {
+                    CircuitState::Closed => return,
+                    CircuitState::HalfOpen if !state.probing => return,
+                    CircuitState::HalfOpen => None,
+                    CircuitState::Open => state.retry_at,
+                }
+            };
+
+            match retry_at {
+                Some(at) if Instant::now() >= at => return,
+                Some(at) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep_until(at) => {},
+                        _ = &mut changed => {},
+                    }
+                },
+                None => changed.await,
+            }
+        }
+    }
+
+    pub fn stats(&self) -> HealthStats {
+        let state = self.lock();
+        HealthStats {
+            state: state.state,
+            consecutive_failures: state.consecutive_failures,
+            trips: state.trips,
+            retry_in: state.retry_at.filter(|_| state.state == CircuitState::Open).map(|at| at.saturating_duration_since(Instant::now())),
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    /// 接続に失敗したことを記録する (Permitを経由しない起動時の接続失敗など)
+    pub fn record_failure(&self, error: &str) {
+        let mut state = self.lock();
+        state.consecutive_failures += 1;
+        state.last_error = Some(error.to_string());
+
+        match state.state {
+            CircuitState::HalfOpen => {
+                state.backoff = (state.backoff * 2).min(self.config.max_backoff);
+                warn!("データベースへの再接続に失敗しました。{}ms後に再試行します: {}", state.backoff.as_millis(), error);
+                self.open(&mut state);
+            },
+            CircuitState::Closed if state.consecutive_failures >= self.config.failure_threshold => {
+                state.backoff = self.config.initial_backoff;
+                state.trips += 1;
+                warn!(
+                    "データベースへの接続に{}回連続で失敗したため、{}ms間アクセスを停止します: {}",
+                    state.consecutive_failures,
+                    state.backoff.as_millis(),
+                    error
+                );
+                self.open(&mut state);
+            },
+            _ => {},
+        }
+    }
+
+    fn record_success(&self) {
+        let mut state = self.lock();
+        if state.state == CircuitState::Closed && state.consecutive_failures == 0 {
+            return;
+        }
+
+        if state.state != CircuitState::Closed {
+            info!("データベースへの接続が回復しました (連続失敗: {}回)", state.consecutive_failures);
+        }
+        state.state = CircuitState::Closed;
+        state.consecutive_failures = 0;
+        state.backoff = self.config.initial_backoff;
+        state.retry_at = None;
+        state.probing = false;
+        state.last_error = None;
+        self.changed.notify_waiters();
+    }
+
+    /// 試行の結果が記録されないまま終わった場合に、次の要求で再び試行できるようにする
+    fn release_probe(&self) {
+        let mut state = self.lock();
+        if state.state == CircuitState::HalfOpen {
+            state.probing = false;
+            self.changed.notify_waiters();
+        }
+    }
+
+    fn open(&self, state: &mut HealthState) {
+        state.state = CircuitState::Open;
+        state.retry_at = Some(Instant::now() + state.backoff);
+        state.probing = false;
+        self.changed.notify_waiters();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HealthState> {
+        // 状態の更新中にpanicすることはないため、poisonの場合もそのまま使用する
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// サーキットブレーカーを通過した1回の要求
+/// 結果を記録せずに破棄された場合は、成功・失敗のどちらとしても扱わない
+pub struct HealthPermit {
+    health: DatabaseHealth,
+    probe: bool,
+    settled: bool,
+}
+
+impl HealthPermit {
+    pub fn success(mut self) {
+        self.settled = true;
+        self.health.record_success();
+    }
+
+    pub fn failure(mut self, error: &str) {
+        self.settled = true;
+        self.health.record_failure(error);
+    }
+}
+
+impl Drop for HealthPermit {
+    fn drop(&mut self) {
+        if self.probe && !self.settled {
+            self.health.release_probe();
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::database::error::DatabaseError;
+use log::debug;
+use postgres_native_tls::MakeTlsConnector;
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, Client, Notification};
+
+/// LISTENを実行した専用の接続 (プールの接続とは別に確立する)
+/// 接続が切断された場合は `recv` がNoneを返すため、作り直す必要がある
+pub struct NotificationListener {
+    // 破棄すると接続が閉じられる
+    _client: Client,
+    notifications: mpsc::UnboundedReceiver<Notification>,
+}
+
+impl NotificationListener {
+    pub(crate) async fn connect(config: &tokio_postgres::Config, connector: MakeTlsConnector, channel: &str) -> Result<Self, DatabaseError> {
+        let (client, mut connection) = config.connect(connector).await.map_err(|e| DatabaseError::ConnectionError(e.to_string()))?;
+
+        // 通知はConnectionをポーリングしないと受け取れないため、専用のタスクで転送する
+        let (tx, notifications) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                match std::future::poll_fn(|cx| connection.poll_message(cx)).await {
+                    Some(Ok(AsyncMessage::Notification(notification))) => {
+                        if tx.send(notification).is_err() {
+                            break;
+                        }
+                    },
+                    Some(Ok(_)) => {},
+                    Some(Err(e)) => {
+                        debug!("通知用の接続が切断されました: {}", e);
+                        break;
+                    },
+                    None => break,
+                }
+            }
+        });
+
+        client.batch_execute(&format!("LISTEN {}", channel)).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
+
+        Ok(Self { _client: client, notifications })
+    }
+
+    /// 次の通知を待つ (接続が切断された場合はNone)
+    pub async fn recv(&mut self) -> Option<Notification> {
+        self.notifications.recv().await
+    }
+
+    /// 既に届いている通知を待たずに取り出す
+    pub fn try_recv(&mut self) -> Option<Notification> {
+        self.notifications.try_recv().ok()
+    }
+}
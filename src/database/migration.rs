@@ -0,0 +1,87 @@
+/// バイナリに埋め込まれたスキーマのマイグレーション
+/// 追加する場合は resource/migrations/ にファイルを作成し、末尾に追記してください (既存のものは変更しないこと)
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+    /// TimescaleDBがない場合に代わりに適用するSQL (Noneの場合はどちらでもsqlを適用する)
+    pub postgres_sql: Option<&'static str>,
+}
+
+impl Migration {
+    /// TimescaleDBの有無に応じて適用するSQL
+    pub fn sql_for(&self, timescale: bool) -> &'static str {
+        match self.postgres_sql {
+            Some(sql) if !timescale => sql,
+            _ => self.sql,
+        }
+    }
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "node_tables",
+        sql: include_str!("../../resource/migrations/0001_node_tables.sql"),
+        postgres_sql: None,
+    },
+    Migration {
+        version: 2,
+        name: "packet_tables",
+        sql: include_str!("../../resource/migrations/0002_packet_tables.sql"),
+        postgres_sql: Some(include_str!("../../resource/migrations/0002_packet_tables.postgres.sql")),
+    },
+    Migration {
+        version: 3,
+        name: "delivery_cursor",
+        sql: include_str!("../../resource/migrations/0003_delivery_cursor.sql"),
+        postgres_sql: None,
+    },
+    Migration {
+        version: 4,
+        name: "delivery_cursors",
+        sql: include_str!("../../resource/migrations/0004_delivery_cursors.sql"),
+        postgres_sql: None,
+    },
+    Migration {
+        version: 5,
+        name: "capture_time",
+        sql: include_str!("../../resource/migrations/0005_capture_time.sql"),
+        postgres_sql: None,
+    },
+    Migration {
+        version: 6,
+        name: "packet_key_id",
+        sql: include_str!("../../resource/migrations/0006_packet_key_id.sql"),
+        postgres_sql: None,
+    },
+    Migration {
+        version: 7,
+        name: "packet_signatures",
+        sql: include_str!("../../resource/migrations/0007_packet_signatures.sql"),
+        postgres_sql: None,
+    },
+    Migration {
+        version: 8,
+        name: "packet_partitions",
+        sql: include_str!("../../resource/migrations/0008_packet_partitions.sql"),
+        postgres_sql: Some(include_str!("../../resource/migrations/0008_packet_partitions.postgres.sql")),
+    },
+    Migration {
+        version: 9,
+        name: "delivery_seqs",
+        sql: include_str!("../../resource/migrations/0009_delivery_seqs.sql"),
+        postgres_sql: None,
+    },
+    Migration {
+        version: 10,
+        name: "delivery_seq_ranges",
+        sql: include_str!("../../resource/migrations/0010_delivery_seq_ranges.sql"),
+        postgres_sql: None,
+    },
+];
+
+/// このバイナリが扱えるスキーマのバージョン
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map(|migration| migration.version).unwrap_or(0)
+}
@@ -1,8 +1,20 @@
 mod client;
 mod error;
+mod health;
+mod listener;
+mod migration;
+mod partition;
 mod pool;
+mod schema;
+mod statement_cache;
+mod tls;
 
-pub use client::Database;
+pub use client::{Database, ExecuteQuery};
 pub use error::DatabaseError;
-
-pub(crate) use client::ExecuteQuery;
+pub use health::{CircuitState, DatabaseHealth, HealthPermit, HealthStats};
+pub use listener::NotificationListener;
+pub use migration::{latest_version, Migration, MIGRATIONS};
+pub use partition::{PacketPartitions, Partition};
+pub use pool::PoolStats;
+pub use schema::{MigrationStatus, Schema};
+pub use statement_cache::{CachedTransaction, StatementCacheStats};
@@ -0,0 +1,167 @@
+use crate::config::PartitionConfig;
+use crate::database::error::DatabaseError;
+use crate::database::{Database, ExecuteQuery};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{debug, info, warn};
+
+/// packetsの時間範囲のパーティション
+#[derive(Debug, Clone)]
+pub struct Partition {
+    pub name: String,
+    pub range_start: DateTime<Utc>,
+    pub range_end: DateTime<Utc>,
+    /// インデックスを含むサイズ (バイト)
+    pub size: i64,
+}
+
+/// TimescaleDBがない場合のpacketsのパーティション (ネイティブの宣言的パーティショニング) の管理
+/// どのパーティションにも含まれない時刻の行はpackets_defaultに入るため、書き込みより先にパーティションを作成しておく
+pub struct PacketPartitions;
+
+impl PacketPartitions {
+    pub const DEFAULT_PARTITION: &'static str = "packets_default";
+
+    /// packetsがパーティションテーブルか (TimescaleDBのハイパーテーブルの場合はfalse)
+    pub async fn is_partitioned(db: &Database) -> Result<bool, DatabaseError> {
+        let rows = db
+            .query(
+                "SELECT EXISTS (SELECT 1 FROM pg_partitioned_table WHERE partrelid = to_regclass('packets')) AS partitioned",
+                &[],
+            )
+            .await?;
+        Ok(rows[0].get("partitioned"))
+    }
+
+    /// 時間範囲のパーティションを古い順に返す (デフォルトのパーティションは含まない)
+    pub async fn list(db: &Database) -> Result<Vec<Partition>, DatabaseError> {
+        let query = r"
+            SELECT c.relname::TEXT AS name, b.m[1]::TIMESTAMPTZ AS range_start, b.m[2]::TIMESTAMPTZ AS range_end, pg_total_relation_size(c.oid) AS size
+            FROM pg_inherits i
+            JOIN pg_class c ON c.oid = i.inhrelid
+            CROSS JOIN LATERAL (SELECT regexp_match(pg_get_expr(c.relpartbound, c.oid), 'FROM \(''([^'']+)''\) TO \(''([^'']+)''\)') AS m) b
+            WHERE i.inhparent = 'packets'::regclass AND b.m IS NOT NULL
+            ORDER BY range_start";
+        let rows = db.query(query, &[]).await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| Partition {
+                name: row.get("name"),
+                range_start: row.get("range_start"),
+                range_end: row.get("range_end"),
+                size: row.get("size"),
+            })
+            .collect())
+    }
+
+    /// 現在の時刻を含むパーティションと、その後のpremake個のパーティションを作成し、作成した数を返す
+    /// 既存のパーティションと範囲が重なる場合は作成しない (intervalを変更した場合も既存のパーティションはそのまま使用する)
+    pub async fn create_upcoming(db: &Database, config: &PartitionConfig) -> Result<usize, DatabaseError> {
+        let interval = TimeDelta::from_std(config.interval).map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
+        let interval_secs = interval.num_seconds();
+        let now = Self::database_now(db).await?;
+        let existing = Self::list(db).await?;
+
+        // ノードによって境界がずれないよう、UNIX時間でintervalの倍数に揃える
+        let first = DateTime::from_timestamp(now.timestamp().div_euclid(interval_secs) * interval_secs, 0).unwrap_or(now);
+
+        let mut created = 0;
+        for i in 0..=config.premake as i32 {
+            let start = first + interval * i;
+            let end = start + interval;
+            if existing.iter().any(|p| p.range_start < end && start < p.range_end) {
+                continue;
+            }
+
+            let name = format!("packets_p{}", start.format("%Y%m%d_%H%M"));
+            let sql = format!(
+                "CREATE TABLE IF NOT EXISTS {} PARTITION OF packets FOR VALUES FROM ('{}') TO ('{}')",
+                name,
+                start.to_rfc3339(),
+                end.to_rfc3339()
+            );
+            match db.batch_execute(&sql).await {
+                Ok(()) => {
+                    info!("パーティションを作成しました: {} ({} ~ {})", name, start, end);
+                    created += 1;
+                },
+                // 範囲内の行が既にpackets_defaultにある場合など (その範囲の行はpackets_defaultに入り続ける)
+                Err(e) => warn!("パーティション {} の作成に失敗しました: {}", name, e),
+            }
+        }
+
+        debug!("パーティションを確認しました (作成: {}, 既存: {})", created, existing.len());
+        Ok(created)
+    }
+
+    /// 範囲の終わりが現在からmax_ageより前のパーティションを削除する
+    pub async fn drop_expired(db: &Database, max_age: TimeDelta) -> Result<Vec<Partition>, DatabaseError> {
+        let cutoff = Self::database_now(db).await? - max_age;
+        let expired: Vec<Partition> = Self::list(db).await?.into_iter().filter(|p| p.range_end <= cutoff).collect();
+
+        for partition in &expired {
+            Self::drop_partition(db, partition).await?;
+            info!(
+                "保持期間を過ぎたパーティションを削除しました: {} ({} ~ {})",
+                partition.name, partition.range_start, partition.range_end
+            );
+        }
+        Ok(expired)
+    }
+
+    /// パーティションの合計サイズが上限以下になるまで古いものから削除する (現在の時刻を含むパーティション以降は削除しない)
+    /// packets_defaultのサイズは合計に含めない
+    pub async fn drop_oldest_over(db: &Database, max_size: u64) -> Result<Vec<Partition>, DatabaseError> {
+        let now = Self::database_now(db).await?;
+        let partitions = Self::list(db).await?;
+        let mut total = partitions.iter().map(|p| p.size).sum::<i64>();
+
+        // packets_defaultはパーティションごと削除できず、保持期間による行単位の削除の対象のため合計に含めない
+        // (含めると、packets_defaultが上限を超えた時に全ての過去のパーティションを削除してしまう)
+        let query = format!(
+            "SELECT pg_total_relation_size(to_regclass($1)) AS size, EXISTS (SELECT 1 FROM {}) AS has_rows",
+            Self::DEFAULT_PARTITION
+        );
+        let rows = db.query(&query, &[&Self::DEFAULT_PARTITION]).await?;
+        if rows[0].get::<_, bool>("has_rows") {
+            warn!(
+                "{} に時間範囲のパーティションに入らなかった行があります ({}バイト)。サイズの上限の対象外のため、packets_max_age_hours で削除されます",
+                Self::DEFAULT_PARTITION,
+                rows[0].get::<_, i64>("size")
+            );
+        }
+
+        let mut dropped = Vec::new();
+        for partition in partitions.into_iter().filter(|p| p.range_end <= now) {
+            if total as u64 <= max_size {
+                break;
+            }
+            Self::drop_partition(db, &partition).await?;
+            info!(
+                "packetsのサイズ ({}バイト) が上限 ({}バイト) を超えたため、パーティションを削除しました: {}",
+                total, max_size, partition.name
+            );
+            total -= partition.size;
+            dropped.push(partition);
+        }
+
+        if total as u64 > max_size {
+            warn!(
+                "packetsのサイズ ({}バイト) が上限 ({}バイト) を超えていますが、削除できる過去のパーティションがありません",
+                total, max_size
+            );
+        }
+        Ok(dropped)
+    }
+
+    async fn drop_partition(db: &Database, partition: &Partition) -> Result<(), DatabaseError> {
+        // 同時に削除した他のノードと競合しても失敗しないようにする
+        db.batch_execute(&format!("DROP TABLE IF EXISTS \"{}\"", partition.name.replace('"', "\"\""))).await
+    }
+
+    /// ノード間で判定がずれないよう、データベースの時刻を基準にする
+    async fn database_now(db: &Database) -> Result<DateTime<Utc>, DatabaseError> {
+        let rows = db.query("SELECT NOW() AS now", &[]).await?;
+        Ok(rows[0].get("now"))
+    }
+}
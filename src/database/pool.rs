@@ -1,39 +1,90 @@
+use crate::config::{DatabaseConfig, PoolConfig};
 use crate::database::error::DatabaseError;
-use bb8::Pool;
+use crate::database::listener::NotificationListener;
+use crate::database::statement_cache::{CachingConnectionManager, StatementCacheCounters, StatementCacheStats};
+use crate::database::tls;
+use bb8::{Builder, Pool};
 use bb8_postgres::PostgresConnectionManager;
-use std::sync::OnceLock;
+use postgres_native_tls::MakeTlsConnector;
+use std::fmt;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio_postgres::NoTls;
 
-pub(crate) static DATABASE_POOL: OnceLock<DatabasePool> = OnceLock::new();
+/// 接続プールの現在の状態と累計の統計情報
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolStats {
+    pub connections: u32,
+    pub in_use: u32,
+    pub idle: u32,
+    /// 待機せずに接続を取得できた回数
+    pub gets_direct: u64,
+    /// 接続の取得で待機が発生した回数
+    pub gets_waited: u64,
+    /// 接続の取得がタイムアウトした回数
+    pub gets_timed_out: u64,
+    /// 接続の取得で待機した時間の合計
+    pub wait_time: Duration,
+    pub connections_created: u64,
+    pub connections_closed: u64,
+}
+
+impl PoolStats {
+    /// 待機が発生した取得1回あたりの平均待機時間
+    pub fn average_wait(&self) -> Duration {
+        if self.gets_waited == 0 {
+            Duration::ZERO
+        } else {
+            self.wait_time / self.gets_waited as u32
+        }
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "接続数={} (使用中={}, アイドル={}), 取得={} (待機={}, 平均待機={}ms, タイムアウト={}), 作成={}, 切断={}",
+            self.connections,
+            self.in_use,
+            self.idle,
+            self.gets_direct + self.gets_waited + self.gets_timed_out,
+            self.gets_waited,
+            self.average_wait().as_millis(),
+            self.gets_timed_out,
+            self.connections_created,
+            self.connections_closed
+        )
+    }
+}
 
-#[derive(Debug)]
 pub struct DatabasePool {
-    pool: Pool<PostgresConnectionManager<NoTls>>,
+    pool: Pool<CachingConnectionManager>,
+    statement_counters: Arc<StatementCacheCounters>,
+    // プール外の専用接続 (LISTENなど) を確立するための設定
+    config: tokio_postgres::Config,
+    connector: MakeTlsConnector,
 }
 
 impl DatabasePool {
-    pub async fn new(connection_string: &str) -> Result<Self, DatabaseError> {
-        let manager = PostgresConnectionManager::new_from_stringlike(connection_string, NoTls).map_err(DatabaseError::ConnectionManagerError)?;
-        let pool = Pool::builder()
-            .max_size(30)
-            .min_idle(Some(10))
-            .connection_timeout(Duration::from_secs(10))
-            .idle_timeout(Some(Duration::from_secs(60)))
-            .max_lifetime(Some(Duration::from_secs(1800)))
-            .build(manager)
-            .await
-            .map_err(|e| DatabaseError::CreatePoolError(e.to_string()))?;
-
-        Ok(Self { pool })
-    }
-
-    pub async fn initialize(host: &str, port: u16, user: &str, password: &str, database: &str) -> Result<(), DatabaseError> {
-        let connection_string = format!("postgres://{}:{}@{}:{}/{}", user, password, host, port, database);
-        let pool = Self::new(&connection_string).await?;
-
-        // 接続テスト
-        let (client, connection) = tokio_postgres::connect(&connection_string, NoTls).await.map_err(|e| {
+    pub async fn new(config: tokio_postgres::Config, connector: MakeTlsConnector, pool_config: &PoolConfig) -> Result<Self, DatabaseError> {
+        let statement_counters = Arc::new(StatementCacheCounters::default());
+        let manager = CachingConnectionManager::new(PostgresConnectionManager::new(config.clone(), connector.clone()), statement_counters.clone());
+        let pool = Self::builder(pool_config).build(manager).await.map_err(|e| DatabaseError::CreatePoolError(e.to_string()))?;
+
+        Ok(Self {
+            pool,
+            statement_counters,
+            config,
+            connector,
+        })
+    }
+
+    pub async fn initialize(config: &DatabaseConfig) -> Result<Self, DatabaseError> {
+        let pg_config = Self::pg_config(config);
+        let connector = tls::make_connector(&config.tls)?;
+
+        // 接続テスト (TLSのハンドシェイクや証明書の検証に失敗した場合もここで検出する)
+        let (client, connection) = pg_config.connect(connector.clone()).await.map_err(|e| {
             eprintln!("接続エラー: {:?}", e);
             DatabaseError::InitFailedConnectDatabase(e.to_string())
         })?;
@@ -46,15 +97,69 @@ impl DatabasePool {
 
         drop(client);
 
-        DATABASE_POOL.set(pool).map_err(|_| DatabaseError::InitializationError)?;
-        Ok(())
+        Self::new(pg_config, connector, &config.pool).await
     }
 
-    pub fn get_pool() -> Result<&'static DatabasePool, DatabaseError> {
-        DATABASE_POOL.get().ok_or(DatabaseError::PoolNotInitialized)
+    /// 接続を確認せずにプールを作成する (接続は最初に使用する時に確立する)
+    pub fn initialize_lazy(config: &DatabaseConfig) -> Result<Self, DatabaseError> {
+        let pg_config = Self::pg_config(config);
+        let connector = tls::make_connector(&config.tls)?;
+        let statement_counters = Arc::new(StatementCacheCounters::default());
+        let manager = CachingConnectionManager::new(PostgresConnectionManager::new(pg_config.clone(), connector.clone()), statement_counters.clone());
+        let pool = Self::builder(&config.pool).build_unchecked(manager);
+
+        Ok(Self {
+            pool,
+            statement_counters,
+            config: pg_config,
+            connector,
+        })
+    }
+
+    fn pg_config(config: &DatabaseConfig) -> tokio_postgres::Config {
+        let mut pg_config = tokio_postgres::Config::new();
+        pg_config.host(&config.host).port(config.port).user(&config.user).password(&config.password).dbname(&config.database).ssl_mode(tls::ssl_mode(config.tls.mode));
+        pg_config
     }
 
-    pub fn inner(&self) -> &Pool<PostgresConnectionManager<NoTls>> {
+    fn builder(pool_config: &PoolConfig) -> Builder<CachingConnectionManager> {
+        Pool::builder()
+            .max_size(pool_config.max_size)
+            .min_idle(Some(pool_config.min_idle))
+            .connection_timeout(pool_config.connection_timeout)
+            .idle_timeout(pool_config.idle_timeout)
+            .max_lifetime(pool_config.max_lifetime)
+    }
+
+    /// プールを経由せずに、LISTENを実行した専用の接続を確立する
+    pub async fn listen(&self, channel: &str) -> Result<NotificationListener, DatabaseError> {
+        NotificationListener::connect(&self.config, self.connector.clone(), channel).await
+    }
+
+    pub fn inner(&self) -> &Pool<CachingConnectionManager> {
         &self.pool
     }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.pool.state();
+        let statistics = state.statistics;
+        PoolStats {
+            connections: state.connections,
+            in_use: state.connections.saturating_sub(state.idle_connections),
+            idle: state.idle_connections,
+            gets_direct: statistics.get_direct,
+            gets_waited: statistics.get_waited,
+            gets_timed_out: statistics.get_timed_out,
+            wait_time: statistics.get_wait_time,
+            connections_created: statistics.connections_created,
+            connections_closed: statistics.connections_closed_broken
+                + statistics.connections_closed_invalid
+                + statistics.connections_closed_max_lifetime
+                + statistics.connections_closed_idle_timeout,
+        }
+    }
+
+    pub fn statement_cache_stats(&self) -> StatementCacheStats {
+        self.statement_counters.snapshot()
+    }
 }
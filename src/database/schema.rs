@@ -0,0 +1,157 @@
+use crate::database::error::DatabaseError;
+use crate::database::migration::{latest_version, MIGRATIONS};
+use crate::database::{Database, ExecuteQuery};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+
+const SAMPLE_DATA: &str = include_str!("../../resource/sample-data.sql");
+const DROP_TABLES: &str = include_str!("../../resource/delete-table.sql");
+
+// 複数ノードが同時に起動した場合にマイグレーションを直列化するためのアドバイザリロックのキー
+const MIGRATION_LOCK_KEY: i64 = 0x5354_4547_5244_4201;
+
+const CREATE_MIGRATIONS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version    INTEGER PRIMARY KEY,
+        name       TEXT        NOT NULL,
+        applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+    )";
+
+const TIMESCALE_INSTALLED: &str = "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'timescaledb') AS installed";
+
+/// マイグレーションの適用状況
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    pub version: i32,
+    pub name: String,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+/// スキーマの作成・更新・削除
+pub struct Schema;
+
+impl Schema {
+    /// 未適用のマイグレーションを順番に適用し、適用したマイグレーションの数を返す
+    pub async fn migrate(db: &Database) -> Result<usize, DatabaseError> {
+        let applied = db
+            .transaction(|tx| {
+                Box::pin(async move {
+                    // 他のノードが適用中の場合は完了するまで待機する
+                    tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_KEY]).await.map_err(Self::query_error)?;
+                    tx.batch_execute(CREATE_MIGRATIONS_TABLE).await.map_err(Self::query_error)?;
+
+                    let rows = tx.query("SELECT version FROM schema_migrations", &[]).await.map_err(Self::query_error)?;
+                    let applied_versions: Vec<i32> = rows.iter().map(|row| row.get("version")).collect();
+                    Self::ensure_supported(applied_versions.iter().copied().max().unwrap_or(0))?;
+
+                    let rows = tx.query(TIMESCALE_INSTALLED, &[]).await.map_err(Self::query_error)?;
+                    let timescale: bool = rows[0].get("installed");
+
+                    let mut applied = 0;
+                    for migration in MIGRATIONS.iter().filter(|m| !applied_versions.contains(&m.version)) {
+                        info!(
+                            "マイグレーションを適用しています: {:04}_{} ({})",
+                            migration.version,
+                            migration.name,
+                            if timescale { "TimescaleDB" } else { "PostgreSQL" }
+                        );
+                        tx.batch_execute(migration.sql_for(timescale))
+                            .await
+                            .map_err(|e| DatabaseError::MigrationError(format!("{:04}_{}: {}", migration.version, migration.name, e)))?;
+                        tx.execute("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)", &[&migration.version, &migration.name]).await.map_err(Self::query_error)?;
+                        applied += 1;
+                    }
+
+                    Ok(applied)
+                })
+            })
+            .await?;
+
+        if applied == 0 {
+            info!("スキーマは最新です (バージョン {})", latest_version());
+        } else {
+            info!("{} 個のマイグレーションを適用しました (バージョン {})", applied, latest_version());
+        }
+        Ok(applied)
+    }
+
+    /// マイグレーションを適用せずに、スキーマのバージョンがこのバイナリと一致するかを確認する
+    pub async fn verify(db: &Database) -> Result<(), DatabaseError> {
+        let current = Self::current_version(db).await?;
+        Self::ensure_supported(current)?;
+
+        if current < latest_version() {
+            return Err(DatabaseError::PendingMigrations(current, latest_version()));
+        }
+        Ok(())
+    }
+
+    pub async fn status(db: &Database) -> Result<Vec<MigrationStatus>, DatabaseError> {
+        db.batch_execute(CREATE_MIGRATIONS_TABLE).await?;
+        let rows = db.query("SELECT version, name, applied_at FROM schema_migrations ORDER BY version", &[]).await?;
+
+        let mut statuses: Vec<MigrationStatus> = MIGRATIONS
+            .iter()
+            .map(|migration| MigrationStatus {
+                version: migration.version,
+                name: migration.name.to_string(),
+                applied_at: rows.iter().find(|row| row.get::<_, i32>("version") == migration.version).map(|row| row.get("applied_at")),
+            })
+            .collect();
+
+        // バイナリが知らないマイグレーション (新しいバージョンで適用されたもの)
+        for row in rows.iter().filter(|row| row.get::<_, i32>("version") > latest_version()) {
+            statuses.push(MigrationStatus {
+                version: row.get("version"),
+                name: row.get("name"),
+                applied_at: row.get("applied_at"),
+            });
+        }
+
+        Ok(statuses)
+    }
+
+    /// サンプルのノードとファイアウォールルールを登録する
+    pub async fn seed(db: &Database) -> Result<(), DatabaseError> {
+        db.batch_execute(SAMPLE_DATA).await?;
+        info!("サンプルデータを登録しました");
+        Ok(())
+    }
+
+    /// マイグレーションで作成した全てのテーブルを削除する
+    pub async fn drop(db: &Database) -> Result<(), DatabaseError> {
+        warn!("全てのテーブルを削除しています");
+        db.batch_execute(DROP_TABLES).await?;
+
+        info!("スキーマの削除が完了しました");
+        Ok(())
+    }
+
+    /// TimescaleDBの拡張機能が有効か (無効の場合はネイティブのパーティショニングを使用する)
+    pub async fn timescale_installed(db: &Database) -> Result<bool, DatabaseError> {
+        let rows = db.query(TIMESCALE_INSTALLED, &[]).await?;
+        Ok(rows[0].get("installed"))
+    }
+
+    async fn current_version(db: &Database) -> Result<i32, DatabaseError> {
+        // schema_migrationsが存在しない場合は未初期化とみなす
+        let rows = db.query("SELECT to_regclass('schema_migrations') IS NOT NULL AS exists", &[]).await?;
+        if !rows.first().map(|row| row.get::<_, bool>("exists")).unwrap_or(false) {
+            return Ok(0);
+        }
+
+        let rows = db.query("SELECT COALESCE(MAX(version), 0) AS version FROM schema_migrations", &[]).await?;
+        Ok(rows.first().map(|row| row.get("version")).unwrap_or(0))
+    }
+
+    fn query_error(e: tokio_postgres::Error) -> DatabaseError {
+        DatabaseError::QueryExecutionError(e.to_string())
+    }
+
+    fn ensure_supported(current: i32) -> Result<(), DatabaseError> {
+        if current > latest_version() {
+            return Err(DatabaseError::SchemaVersionTooNew(current, latest_version()));
+        }
+        Ok(())
+    }
+}
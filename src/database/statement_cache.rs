@@ -0,0 +1,182 @@
+use bb8_postgres::PostgresConnectionManager;
+use postgres_native_tls::MakeTlsConnector;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Error, GenericClient, Row, Statement, Transaction};
+
+// 1接続あたりにキャッシュするステートメントの上限 (超えた分は毎回prepareする)
+const MAX_CACHED_STATEMENTS: usize = 128;
+
+/// プリペアドステートメントキャッシュのヒット数・ミス数 (全接続の合計)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatementCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StatementCacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl StatementCacheCounters {
+    pub fn snapshot(&self) -> StatementCacheStats {
+        StatementCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 接続ごとのプリペアドステートメントのキャッシュ (SQL文字列がキー)
+#[derive(Debug)]
+pub(crate) struct StatementCache {
+    statements: HashMap<String, Statement>,
+    counters: Arc<StatementCacheCounters>,
+}
+
+impl StatementCache {
+    fn new(counters: Arc<StatementCacheCounters>) -> Self {
+        Self {
+            statements: HashMap::new(),
+            counters,
+        }
+    }
+
+    async fn prepare<C: GenericClient>(&mut self, client: &C, sql: &str) -> Result<Statement, Error> {
+        if let Some(stmt) = self.statements.get(sql) {
+            self.counters.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(stmt.clone());
+        }
+
+        self.counters.misses.fetch_add(1, Ordering::Relaxed);
+        let stmt = client.prepare(sql).await?;
+        if self.statements.len() < MAX_CACHED_STATEMENTS {
+            self.statements.insert(sql.to_string(), stmt.clone());
+        }
+        Ok(stmt)
+    }
+
+    /// 実行に失敗したステートメントは、スキーマの変更などで無効になっている可能性があるため破棄する
+    fn evict(&mut self, sql: &str) {
+        self.statements.remove(sql);
+    }
+
+    async fn execute<C: GenericClient>(&mut self, client: &C, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        let stmt = self.prepare(client, sql).await?;
+        client.execute(&stmt, params).await.inspect_err(|_| self.evict(sql))
+    }
+
+    async fn query<C: GenericClient>(&mut self, client: &C, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        let stmt = self.prepare(client, sql).await?;
+        client.query(&stmt, params).await.inspect_err(|_| self.evict(sql))
+    }
+}
+
+/// ステートメントキャッシュを持つプール内の接続
+pub struct CachedClient {
+    client: Client,
+    cache: StatementCache,
+}
+
+impl CachedClient {
+    /// キャッシュ済みのステートメントを返す (なければprepareしてキャッシュする)
+    pub async fn prepare_cached(&mut self, sql: &str) -> Result<Statement, Error> {
+        self.cache.prepare(&self.client, sql).await
+    }
+
+    pub fn evict(&mut self, sql: &str) {
+        self.cache.evict(sql);
+    }
+
+    /// トランザクションを開始する (トランザクション内でも接続のキャッシュを使用する)
+    pub async fn transaction(&mut self) -> Result<CachedTransaction<'_>, Error> {
+        let tx = self.client.transaction().await?;
+        Ok(CachedTransaction { tx, cache: &mut self.cache })
+    }
+}
+
+impl Deref for CachedClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.client
+    }
+}
+
+impl DerefMut for CachedClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        &mut self.client
+    }
+}
+
+/// `execute`・`query` でキャッシュ済みのステートメントを使用するトランザクション
+/// それ以外の操作は `Transaction` に委譲する
+pub struct CachedTransaction<'a> {
+    tx: Transaction<'a>,
+    cache: &'a mut StatementCache,
+}
+
+impl CachedTransaction<'_> {
+    pub async fn execute(&mut self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<u64, Error> {
+        self.cache.execute(&self.tx, sql, params).await
+    }
+
+    pub async fn query(&mut self, sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>, Error> {
+        self.cache.query(&self.tx, sql, params).await
+    }
+
+    pub async fn commit(self) -> Result<(), Error> {
+        self.tx.commit().await
+    }
+
+    pub async fn rollback(self) -> Result<(), Error> {
+        self.tx.rollback().await
+    }
+}
+
+impl<'a> Deref for CachedTransaction<'a> {
+    type Target = Transaction<'a>;
+
+    fn deref(&self) -> &Transaction<'a> {
+        &self.tx
+    }
+}
+
+/// 接続ごとにステートメントキャッシュを持たせるための接続マネージャ
+#[derive(Debug)]
+pub struct CachingConnectionManager {
+    inner: PostgresConnectionManager<MakeTlsConnector>,
+    counters: Arc<StatementCacheCounters>,
+}
+
+impl CachingConnectionManager {
+    pub(crate) fn new(inner: PostgresConnectionManager<MakeTlsConnector>, counters: Arc<StatementCacheCounters>) -> Self {
+        Self { inner, counters }
+    }
+}
+
+impl bb8::ManageConnection for CachingConnectionManager {
+    type Connection = CachedClient;
+    type Error = Error;
+
+    async fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let client = self.inner.connect().await?;
+        Ok(CachedClient {
+            client,
+            cache: StatementCache::new(self.counters.clone()),
+        })
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        self.inner.is_valid(&mut conn.client).await
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        self.inner.has_broken(&mut conn.client)
+    }
+}
@@ -0,0 +1,89 @@
+use crate::config::{SslMode, TlsConfig};
+use crate::database::error::DatabaseError;
+use log::warn;
+use native_tls::{Certificate, Identity, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use std::path::Path;
+
+const PEM_CERT_END: &str = "-----END CERTIFICATE-----";
+
+/// 設定からTLSコネクタを作成する
+/// `disable` の場合も同じ型のコネクタを返し、接続設定側で暗号化を無効にする
+pub fn make_connector(config: &TlsConfig) -> Result<MakeTlsConnector, DatabaseError> {
+    warn_if_unverified(config);
+    let mut builder = TlsConnector::builder();
+
+    match config.mode {
+        SslMode::Disable | SslMode::Prefer => {
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        },
+        SslMode::Require => {
+            // libpqと同様に、CA証明書が指定された場合のみ証明書チェーンを検証する
+            if config.root_cert.is_none() {
+                builder.danger_accept_invalid_certs(true);
+            }
+            builder.danger_accept_invalid_hostnames(true);
+        },
+        SslMode::VerifyFull => {},
+    }
+
+    if let Some(path) = &config.root_cert {
+        for cert in read_certificates(path)? {
+            builder.add_root_certificate(cert);
+        }
+    }
+
+    if let (Some(cert_path), Some(key_path)) = (&config.client_cert, &config.client_key) {
+        let cert = read_file(cert_path)?;
+        let key = read_file(key_path)?;
+        let identity = Identity::from_pkcs8(&cert, &key).map_err(|e| DatabaseError::TlsError(format!("クライアント証明書の読み込みに失敗しました: {}", e)))?;
+        builder.identity(identity);
+    }
+
+    let connector = builder.build().map_err(|e| DatabaseError::TlsError(e.to_string()))?;
+    Ok(MakeTlsConnector::new(connector))
+}
+
+/// サーバー証明書を検証しない設定の場合は警告する (データベースとの通信を盗聴・改ざんされる可能性がある)
+fn warn_if_unverified(config: &TlsConfig) {
+    let (mode, reason) = match (config.mode, &config.root_cert) {
+        (SslMode::VerifyFull, _) => return,
+        (SslMode::Disable, _) => ("disable", "暗号化しません"),
+        (SslMode::Prefer, _) => ("prefer", "サーバーが対応していない場合は暗号化せず、サーバー証明書とホスト名も検証しません"),
+        (SslMode::Require, None) => ("require", "サーバー証明書とホスト名を検証しません"),
+        (SslMode::Require, Some(_)) => ("require", "サーバー証明書のホスト名を検証しません"),
+    };
+    warn!(
+        "database.sslmode = \"{}\" のため、データベースへの接続は{}。なりすましを防ぐには database.sslrootcert と database.sslmode = \"verify-full\" を指定してください",
+        mode, reason
+    );
+}
+
+pub fn ssl_mode(mode: SslMode) -> tokio_postgres::config::SslMode {
+    match mode {
+        SslMode::Disable => tokio_postgres::config::SslMode::Disable,
+        SslMode::Prefer => tokio_postgres::config::SslMode::Prefer,
+        SslMode::Require | SslMode::VerifyFull => tokio_postgres::config::SslMode::Require,
+    }
+}
+
+/// CAバンドル (複数の証明書を連結したPEM) を読み込む
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>, DatabaseError> {
+    let content = String::from_utf8(read_file(path)?).map_err(|e| DatabaseError::TlsError(format!("{}: {}", path.display(), e)))?;
+
+    let certs = content
+        .split_inclusive(PEM_CERT_END)
+        .filter(|block| block.contains(PEM_CERT_END))
+        .map(|block| Certificate::from_pem(block.trim().as_bytes()).map_err(|e| DatabaseError::TlsError(format!("{}: {}", path.display(), e))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if certs.is_empty() {
+        return Err(DatabaseError::TlsError(format!("{}: 証明書が含まれていません", path.display())));
+    }
+    Ok(certs)
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, DatabaseError> {
+    std::fs::read(path).map_err(|e| DatabaseError::TlsError(format!("{}: {}", path.display(), e)))
+}
@@ -16,4 +16,7 @@ pub enum InitProcessError {
 
     #[error("タスクの実行処理に失敗しました: {0}")]
     TaskExecutionProcessError(String),
+
+    #[error("コマンドの実行に失敗しました: {0}")]
+    CommandError(String),
 }
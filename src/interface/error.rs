@@ -6,6 +6,9 @@ pub enum InterfaceError {
     #[error("利用可能なネットワークインターフェースがありません")]
     NoAvailableNetworkInterfaceError,
 
+    #[error("指定されたインターフェースが見つかりません: {0}")]
+    InterfaceNotFound(String),
+
     #[error("指定されたDocker使用時のインターフェースが見つかりません: {0}")]
     DockerInterfaceNotFound(String),
 
@@ -1,4 +1,5 @@
 mod error;
 mod select_interface;
 
+pub use error::InterfaceError;
 pub use select_interface::select_interface;
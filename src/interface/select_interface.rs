@@ -1,17 +1,25 @@
+use crate::config::NetworkConfig;
 use crate::interface::error::InterfaceError;
 use log::info;
 use pnet::datalink::{self, NetworkInterface};
 use std::io::{self, Write};
 
-pub fn select_interface(docker_mode: bool, docker_interface_name: &str) -> Result<NetworkInterface, InterfaceError> {
+pub fn select_interface(network: &NetworkConfig) -> Result<NetworkInterface, InterfaceError> {
     let interfaces = datalink::interfaces();
 
     if interfaces.is_empty() {
         return Err(InterfaceError::NoAvailableNetworkInterfaceError);
     }
 
+    // インターフェイス名が指定されている場合はそれを使用
+    if let Some(name) = &network.interface {
+        info!("{}インターフェイスが指定されています", name);
+        return interfaces.iter().find(|interface| &interface.name == name).cloned().ok_or_else(|| InterfaceError::InterfaceNotFound(name.clone()));
+    }
+
     // Dockerモードの場合はインターフェイスの自動選択
-    if docker_mode {
+    let docker_interface_name = network.docker_interface_name.as_str();
+    if network.docker_mode {
         info!("Docker Modeが有効な為、{}インターフェイスで自動実行されます。", docker_interface_name);
         return if let Some(interface) = interfaces.iter().find(|interface| interface.name == docker_interface_name) {
             Ok(interface.clone())
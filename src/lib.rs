@@ -0,0 +1,25 @@
+//! RDBを経由してL2フレームをトンネルするノードのライブラリ
+//!
+//! `TunnelNode` でノードの起動・停止、キャプチャパケットの購読、保存済みパケットの検索、
+//! ファイアウォールルールの管理を行います。解析用途では `packet` 以下の型を直接利用できます。
+
+pub mod carrier;
+pub mod config;
+pub mod context;
+pub mod crypto;
+pub mod database;
+pub mod error;
+pub mod interface;
+pub mod logger;
+pub mod node;
+pub mod packet;
+pub mod services;
+mod tasks;
+
+pub use carrier::{Carrier, MemoryCarrier, MysqlCarrier, PostgresCarrier, SqliteCarrier};
+pub use config::{AppConfig, ConfigOverrides};
+pub use context::AppContext;
+pub use node::{NodeError, TunnelNode};
+pub use packet::analysis::{AnalyzeResult, Filter, FirewallPacket, IpFirewall, PacketAnalyzer, Policy};
+pub use packet::repository::PacketQuery;
+pub use packet::{PacketData, StoredPacket};
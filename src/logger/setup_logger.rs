@@ -14,7 +14,7 @@ pub fn setup_logger(logger_config: LoggerConfig) -> Result<(), Box<dyn std::erro
     };
 
     // IDPSロガーの設定
-    idps_logger::set_idps_settings(log_mode, &format!("../../{}", logger_config.idps_logger_file), &*logger_config.idps_path_style).expect("IDPSロガーの設定に失敗しました");
+    idps_logger::set_idps_settings(log_mode, &format!("../../{}", logger_config.idps_logger_file), &logger_config.idps_path_style).expect("IDPSロガーの設定に失敗しました");
 
     Builder::new()
         .filter_level(LevelFilter::Info)
@@ -39,3 +39,12 @@ pub fn setup_logger(logger_config: LoggerConfig) -> Result<(), Box<dyn std::erro
 
     Ok(())
 }
+
+/// 管理コマンド用にコンソールのみへ出力するロガーを設定する
+pub fn setup_console_logger() {
+    Builder::new()
+        .filter_level(LevelFilter::Info)
+        .format(|buf, record| writeln!(buf, "{} [{}] {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"), record.level(), record.args()))
+        .target(Target::Stderr)
+        .init();
+}
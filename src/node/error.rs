@@ -0,0 +1,33 @@
+use crate::carrier::CarrierError;
+use crate::crypto::CryptoError;
+use crate::database::DatabaseError;
+use crate::packet::spool::SpoolError;
+use crate::services::ServiceError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum NodeError {
+    #[error("データベースエラー: {0}")]
+    DatabaseError(#[from] DatabaseError),
+
+    #[error("媒体のエラー: {0}")]
+    CarrierError(#[from] CarrierError),
+
+    #[error("サービスエラー: {0}")]
+    ServiceError(#[from] ServiceError),
+
+    #[error("スプールエラー: {0}")]
+    SpoolError(#[from] SpoolError),
+
+    #[error("暗号化の設定エラー: {0}")]
+    CryptoError(#[from] CryptoError),
+
+    #[error("ノードは既に起動しています")]
+    AlreadyRunningError,
+
+    #[error("ノードは起動していません")]
+    NotRunningError,
+
+    #[error("タスクの実行処理に失敗しました: {0}")]
+    TaskExecutionError(String),
+}
@@ -0,0 +1,5 @@
+mod error;
+mod tunnel_node;
+
+pub use error::NodeError;
+pub use tunnel_node::TunnelNode;
@@ -0,0 +1,329 @@
+use crate::carrier::{Carrier, MysqlCarrier, PostgresCarrier, SqliteCarrier};
+use crate::config::{AppConfig, CarrierKind};
+use crate::context::AppContext;
+use crate::crypto::{CryptoError, PacketCipher, PacketSigner};
+use crate::database::{Database, HealthStats, PoolStats, StatementCacheStats};
+use crate::node::error::NodeError;
+use crate::packet::analysis::PacketAnalyzer;
+use crate::packet::reader::DeliveryStats;
+use crate::packet::repository::PacketQuery;
+use crate::packet::spool::{PacketSpool, SpoolStats};
+use crate::packet::writer::BufferStats;
+use crate::packet::{PacketData, StoredPacket};
+use crate::services::FirewallService;
+use crate::tasks::TaskScheduler;
+use log::{info, warn};
+use pnet::datalink::NetworkInterface;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+struct RunningTasks {
+    shutdown_tx: broadcast::Sender<()>,
+    handle: JoinHandle<Result<(), String>>,
+}
+
+/// 1つのインターフェースをデータベース (Carrier) 経由でトンネルするノード
+pub struct TunnelNode {
+    context: Arc<AppContext>,
+    interface: NetworkInterface,
+    // 縮退モードで起動した場合、データベースに接続して初期化が完了するまでfalse
+    initialized: Arc<AtomicBool>,
+    running: Option<RunningTasks>,
+}
+
+impl TunnelNode {
+    /// carrier.kindの媒体 (データベース) へ接続し、ノードの検証とファイアウォールの初期化を行う
+    /// database.degraded_startが有効な場合、データベースに接続できなくても終了せず、起動後に接続できるまで初期化を再試行する
+    pub async fn connect(config: AppConfig, interface: NetworkInterface) -> Result<Self, NodeError> {
+        match config.carrier.kind {
+            CarrierKind::Sqlite => {
+                let carrier = Arc::new(SqliteCarrier::open(&config.carrier)?);
+                return Self::assemble(config, carrier, interface, true).await;
+            },
+            CarrierKind::Mysql => {
+                let (carrier, connected) = match MysqlCarrier::connect(&config.carrier, &config.database.circuit_breaker).await {
+                    Ok(carrier) => (carrier, true),
+                    Err(e) if config.database.degraded_start => {
+                        warn!("MySQLに接続できないため、接続できるまで待機する縮退モードで起動します: {}", e);
+                        let carrier = MysqlCarrier::connect_lazy(&config.carrier, &config.database.circuit_breaker);
+                        carrier.health().record_failure(&e.to_string());
+                        (carrier, false)
+                    },
+                    Err(e) => return Err(e.into()),
+                };
+                return Self::assemble(config, Arc::new(carrier), interface, connected).await;
+            },
+            CarrierKind::Postgres => {},
+        }
+
+        let (database, connected) = match Database::connect(&config.database).await {
+            Ok(database) => {
+                info!("データベースに接続できました: address:{}, port:{}", config.database.host, config.database.port);
+                (database, true)
+            },
+            Err(e) if config.database.degraded_start => {
+                warn!("データベースに接続できないため、接続できるまで待機する縮退モードで起動します: {}", e);
+                let database = Database::connect_lazy(&config.database)?;
+                database.health().record_failure(&e.to_string());
+                (database, false)
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        let carrier = Arc::new(PostgresCarrier::new(database, &config));
+        Self::assemble(config, carrier, interface, connected).await
+    }
+
+    /// 指定した媒体でパケットを受け渡すノードを作成し、ノードの検証とファイアウォールの初期化を行う
+    /// 同じMemoryCarrierのクローンを渡すと、データベースなしで1つのプロセス内の複数のノード間でパケットを受け渡せる
+    pub async fn with_carrier(config: AppConfig, carrier: Arc<dyn Carrier>, interface: NetworkInterface) -> Result<Self, NodeError> {
+        Self::assemble(config, carrier, interface, true).await
+    }
+
+    async fn assemble(config: AppConfig, carrier: Arc<dyn Carrier>, interface: NetworkInterface, connected: bool) -> Result<Self, NodeError> {
+        let spool = if config.spool.enabled { Some(PacketSpool::open(&config.spool)?) } else { None };
+        let cipher = PacketCipher::load(&config.encryption)?;
+        if let Some(cipher) = &cipher {
+            info!("raw_packetの暗号化が有効です (書き込みに使用する鍵ID: {})", cipher.active_id());
+        }
+        let signer = PacketSigner::load(&config.signing)?;
+        if let Some(signer) = &signer {
+            info!("パケットへの署名が有効です (公開鍵: {})", hex::encode(signer.public_key()));
+        }
+        let context = AppContext::new(config, carrier, spool, cipher, signer);
+
+        let node = Self::from_context(context, interface);
+        if connected {
+            Self::initialize(&node.context, &node.interface).await?;
+        } else {
+            node.initialized.store(false, Ordering::Release);
+        }
+        Ok(node)
+    }
+
+    /// 構築済みのコンテキストからノードを作成する (検証や初期化は行わない)
+    pub fn from_context(context: Arc<AppContext>, interface: NetworkInterface) -> Self {
+        Self {
+            context,
+            interface,
+            initialized: Arc::new(AtomicBool::new(true)),
+            running: None,
+        }
+    }
+
+    /// スキーマの確認、ノードの検証と起動記録、ファイアウォールの初期化を行う
+    async fn initialize(context: &AppContext, interface: &NetworkInterface) -> Result<(), NodeError> {
+        let carrier = context.carrier.as_ref();
+        carrier.prepare(&context.config).await?;
+
+        let node_name = carrier.record_boot(context.node_id(), interface).await?;
+        info!("ノード {} ({}) の検証と起動記録が完了しました", context.node_id(), node_name);
+
+        // 署名したパケットが他のノードで破棄されないよう、公開鍵が登録されていることを確認する
+        if let Some(signer) = &context.signer {
+            let public_key = signer.public_key();
+            let registered = carrier.list_nodes().await?.into_iter().find(|node| node.id == context.node_id()).and_then(|node| node.public_key);
+            if registered.as_deref() != Some(&public_key[..]) {
+                return Err(CryptoError::NodeKeyMismatchError(context.node_id(), hex::encode(public_key)).into());
+            }
+        }
+
+        context.firewall.initialize(carrier, context.node_id(), &context.config.firewall).await?;
+        Ok(())
+    }
+
+    /// データベースに接続できるまで初期化を再試行する (縮退モードで起動した場合)
+    /// 再試行の間隔はサーキットブレーカーのバックオフに従う
+    async fn initialize_with_retry(context: &AppContext, interface: &NetworkInterface) -> Result<(), NodeError> {
+        let health = context.carrier.health();
+        loop {
+            health.wait_until_available().await;
+            match Self::initialize(context, interface).await {
+                Ok(()) => {
+                    info!("データベースに接続できたため、ノード {} の処理を開始します", context.node_id());
+                    return Ok(());
+                },
+                // データベースに接続できている場合の失敗 (ノードが未登録など) は再試行しても解決しない
+                Err(e) if health.is_healthy() => return Err(e),
+                Err(e) => warn!("データベースに接続できないため、ノードの初期化を待機しています: {}", e),
+            }
+        }
+    }
+
+    pub fn context(&self) -> &Arc<AppContext> {
+        &self.context
+    }
+
+    pub fn node_id(&self) -> i16 {
+        self.context.node_id()
+    }
+
+    pub fn interface(&self) -> &NetworkInterface {
+        &self.interface
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.as_ref().is_some_and(|running| !running.handle.is_finished())
+    }
+
+    /// パケットバッファの使用状況と、上限による破棄の累計
+    pub async fn buffer_stats(&self) -> BufferStats {
+        self.context.buffer.stats().await
+    }
+
+    /// 他のノードから読み取ったパケットの送信状況 (読み取ったのに送信できなかったパケット数を含む)
+    pub fn delivery_stats(&self) -> DeliveryStats {
+        self.context.delivery.stats()
+    }
+
+    /// スプールの使用状況 (スプールが無効の場合はNone)
+    pub fn spool_stats(&self) -> Option<SpoolStats> {
+        self.context.spool.as_ref().map(|spool| spool.stats())
+    }
+
+    /// データベースの接続状態 (サーキットブレーカー)
+    pub fn health_stats(&self) -> HealthStats {
+        self.context.carrier.health().stats()
+    }
+
+    /// データベースへの接続と初期化が完了しているか (縮退モードで起動し、まだ接続できていない間はfalse)
+    pub fn is_initialized(&self) -> bool {
+        self.initialized.load(Ordering::Acquire)
+    }
+
+    /// 接続プールの使用状況 (データベースを使わない媒体の場合はNone)
+    pub fn pool_stats(&self) -> Option<PoolStats> {
+        self.context.carrier.database().map(Database::pool_stats)
+    }
+
+    /// プリペアドステートメントキャッシュの累計ヒット数・ミス数 (データベースを使わない媒体の場合はNone)
+    pub fn statement_cache_stats(&self) -> Option<StatementCacheStats> {
+        self.context.carrier.database().map(Database::statement_cache_stats)
+    }
+
+    /// キャプチャ・書き込み・読み取りのタスクをバックグラウンドで起動する
+    pub fn start(&mut self) -> Result<(), NodeError> {
+        if self.is_running() {
+            return Err(NodeError::AlreadyRunningError);
+        }
+
+        let scheduler = TaskScheduler::new(Arc::clone(&self.context), self.interface.clone());
+        let shutdown_tx = scheduler.shutdown_sender();
+
+        let context = Arc::clone(&self.context);
+        let interface = self.interface.clone();
+        let initialized = Arc::clone(&self.initialized);
+        let mut shutdown_rx = shutdown_tx.subscribe();
+        let handle = tokio::spawn(async move {
+            // 縮退モードで起動した場合は、初期化が完了するまでタスクを起動しない
+            if !initialized.load(Ordering::Acquire) {
+                tokio::select! {
+                    result = Self::initialize_with_retry(&context, &interface) => result.map_err(|e| e.to_string())?,
+                    _ = shutdown_rx.recv() => return Ok(()),
+                }
+                initialized.store(true, Ordering::Release);
+            }
+            scheduler.run().await.map_err(|e| e.to_string())
+        });
+
+        info!("ノード {} を起動しました", self.node_id());
+        self.running = Some(RunningTasks { shutdown_tx, handle });
+        Ok(())
+    }
+
+    /// 起動中のタスクを停止し、終了を待つ (バッファに残っているパケットは書き込んでから停止する)
+    pub async fn stop(&mut self) -> Result<(), NodeError> {
+        let running = self.running.take().ok_or(NodeError::NotRunningError)?;
+
+        // 既にタスクが終了している場合は受信側が存在しない
+        let _ = running.shutdown_tx.send(());
+        let result = Self::join(running.handle).await;
+
+        match self.statement_cache_stats() {
+            Some(stats) => info!(
+                "ノード {} を停止しました (ステートメントキャッシュ: hit={}, miss={})",
+                self.node_id(),
+                stats.hits,
+                stats.misses
+            ),
+            None => info!("ノード {} を停止しました", self.node_id()),
+        }
+        result
+    }
+
+    /// タスクを起動し、いずれかのタスクが終了するか、shutdownが完了するまで実行する
+    pub async fn run_until<F>(mut self, shutdown: F) -> Result<(), NodeError>
+    where
+        F: Future<Output = ()>,
+    {
+        self.start()?;
+        let running = self.running.as_mut().ok_or(NodeError::NotRunningError)?;
+
+        tokio::select! {
+            result = &mut running.handle => {
+                self.running = None;
+                match result {
+                    Ok(result) => result.map_err(NodeError::TaskExecutionError),
+                    Err(e) => Err(NodeError::TaskExecutionError(e.to_string())),
+                }
+            }
+            _ = shutdown => {
+                info!("停止要求を受け付けました");
+                self.stop().await
+            }
+        }
+    }
+
+    /// ファイアウォールを通過したキャプチャパケットを購読する
+    /// 受信が遅れた場合は古いパケットから失われる (RecvError::Lagged)
+    pub fn subscribe(&self) -> broadcast::Receiver<PacketData> {
+        self.context.captured.subscribe()
+    }
+
+    /// データベース (媒体) に保存されたパケットを検索する
+    /// 暗号化が有効な場合はraw_packetを復号し、暗号化して保存したヘッダーの値を復元する (復号できない行はそのまま返す)
+    pub async fn query_packets(&self, query: &PacketQuery) -> Result<Vec<StoredPacket>, NodeError> {
+        let packets = self.context.carrier.query_packets(query).await?;
+        let Some(cipher) = &self.context.cipher else { return Ok(packets) };
+
+        let mut opened = Vec::with_capacity(packets.len());
+        for mut packet in packets {
+            if let Some(key_id) = packet.data.key_id {
+                match cipher.open(packet.node_id, Some(key_id), &packet.data.raw_packet) {
+                    Ok(raw_packet) => {
+                        packet.data = match PacketAnalyzer::parse_frame(&raw_packet, packet.data.captured_at).await {
+                            Some(data) => data,
+                            None => PacketData {
+                                raw_packet,
+                                key_id: None,
+                                ..packet.data
+                            },
+                        };
+                    },
+                    Err(e) => warn!("パケット {} を復号できませんでした: {}", packet.id, e),
+                }
+            }
+            opened.push(packet);
+        }
+        Ok(opened)
+    }
+
+    pub fn firewall(&self) -> &FirewallService {
+        &self.context.firewall
+    }
+
+    /// データベース (媒体) からファイアウォール設定を読み込み直す
+    pub async fn reload_firewall(&self) -> Result<(), NodeError> {
+        Ok(self.context.firewall.initialize(self.context.carrier.as_ref(), self.node_id(), &self.context.config.firewall).await?)
+    }
+
+    async fn join(handle: JoinHandle<Result<(), String>>) -> Result<(), NodeError> {
+        match handle.await {
+            Ok(result) => result.map_err(NodeError::TaskExecutionError),
+            Err(e) => Err(NodeError::TaskExecutionError(e.to_string())),
+        }
+    }
+}
@@ -5,17 +5,8 @@ use crate::packet::analysis::ip::parse_ip_packet;
 use crate::packet::types::EtherType;
 use crate::packet::{InetAddr, PacketData};
 use crate::services::FirewallService;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use log::trace;
-use std::net::IpAddr;
-
-#[derive(Clone, Copy)]
-pub struct IpHeader {
-    pub version: u8,
-    pub protocol: u8,
-    pub src_ip: IpAddr,
-    pub dst_ip: IpAddr,
-}
 
 pub enum AnalyzeResult {
     Accept(PacketData),
@@ -25,7 +16,7 @@ pub enum AnalyzeResult {
 pub struct PacketAnalyzer {}
 
 impl PacketAnalyzer {
-    pub async fn analyze_packet(ethernet_frame: &[u8]) -> AnalyzeResult {
+    pub async fn analyze_packet(ethernet_frame: &[u8], firewall: &FirewallService) -> AnalyzeResult {
         // 基本的な長さチェック
         if ethernet_frame.len() < 14 + 20 {
             idps_log!("パケットが短すぎます: パケット長={}、期待値={}", ethernet_frame.len(), 14 + 20);
@@ -56,7 +47,7 @@ impl PacketAnalyzer {
             dst_port,
         );
 
-        if !FirewallService::check_packet(&firewall_packet).await {
+        if !firewall.check_packet(&firewall_packet).await {
             /*idps_log!(
                 "パケットがファイアウォールルールによってブロックされました: {}:{} -> {}:{}",
                 src_ip,
@@ -92,8 +83,35 @@ impl PacketAnalyzer {
             src_port: src_port as i32,
             dst_port: dst_port as i32,
             ip_protocol,
-            timestamp: Utc::now(),
+            captured_at: Utc::now(),
+            raw_packet: ethernet_frame.to_vec(),
+            key_id: None,
+            signature: None,
+        })
+    }
+
+    /// 保存済みのフレームからヘッダーの値を復元する (ファイアウォールは適用しない)
+    /// ヘッダーを暗号化して保存したパケットの検索結果に使用する。解析できない場合はNone
+    pub async fn parse_frame(ethernet_frame: &[u8], captured_at: DateTime<Utc>) -> Option<PacketData> {
+        if ethernet_frame.len() < 14 + 20 {
+            return None;
+        }
+        let ethernet_header = parse_ethernet_header(ethernet_frame).ok()?;
+        let (src_ip, dst_ip, ip_protocol, src_port, dst_port, _) = parse_ip_packet(ethernet_frame, ethernet_header.ether_type).await.ok()?;
+
+        Some(PacketData {
+            src_mac: ethernet_header.src_mac,
+            dst_mac: ethernet_header.dst_mac,
+            ether_type: ethernet_header.ether_type,
+            src_ip: InetAddr(src_ip),
+            dst_ip: InetAddr(dst_ip),
+            src_port: src_port as i32,
+            dst_port: dst_port as i32,
+            ip_protocol,
+            captured_at,
             raw_packet: ethernet_frame.to_vec(),
+            key_id: None,
+            signature: None,
         })
     }
 }
@@ -1,8 +1,7 @@
 use crate::packet::MacAddr;
 use std::net::IpAddr;
 
-#[allow(dead_code)]
-#[derive(Debug, Eq, Hash, PartialEq)]
+#[derive(Debug, Clone, Eq, Hash, PartialEq)]
 pub enum Filter {
     // L2 Filters
     SrcMacAddress(MacAddr),
@@ -18,3 +17,30 @@ pub enum Filter {
     SrcPort(u16),
     DstPort(u16),
 }
+
+impl Filter {
+    /// firewall_settings.filter_type に保存する名前
+    pub fn filter_type(&self) -> &'static str {
+        match self {
+            Filter::SrcMacAddress(_) => "SrcMacAddress",
+            Filter::DstMacAddress(_) => "DstMacAddress",
+            Filter::EtherType(_) => "EtherType",
+            Filter::SrcIpAddress(_) => "SrcIpAddress",
+            Filter::DstIpAddress(_) => "DstIpAddress",
+            Filter::IpProtocol(_) => "IpProtocol",
+            Filter::SrcPort(_) => "SrcPort",
+            Filter::DstPort(_) => "DstPort",
+        }
+    }
+
+    /// firewall_settings.filter_value に保存する値
+    pub fn filter_value(&self) -> String {
+        match self {
+            Filter::SrcMacAddress(mac) | Filter::DstMacAddress(mac) => mac.to_string(),
+            Filter::EtherType(ether_type) => format!("0x{:04x}", ether_type),
+            Filter::SrcIpAddress(ip) | Filter::DstIpAddress(ip) => ip.to_string(),
+            Filter::IpProtocol(protocol) => protocol.to_string(),
+            Filter::SrcPort(port) | Filter::DstPort(port) => port.to_string(),
+        }
+    }
+}
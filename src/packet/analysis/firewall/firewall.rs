@@ -1,7 +1,7 @@
 use super::{Filter, FirewallPacket, Policy};
 use std::collections::HashMap;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IpFirewall {
     rules: HashMap<Filter, u8>,
     policy: Policy,
@@ -16,6 +16,15 @@ impl IpFirewall {
         self.rules.insert(filter, priority);
     }
 
+    /// ルールを削除し、設定されていた優先度を返す
+    pub fn remove_rule(&mut self, filter: &Filter) -> Option<u8> {
+        self.rules.remove(filter)
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = (&Filter, u8)> {
+        self.rules.iter().map(|(filter, priority)| (filter, *priority))
+    }
+
     pub fn check(&self, packet: &FirewallPacket) -> bool {
         let mut block = false;
         let mut allow = false;
@@ -1,4 +1,5 @@
 mod filter;
+#[allow(clippy::module_inception, reason = "IpFirewallを firewall モジュールの中心の型として公開するため")]
 mod firewall;
 mod packet;
 mod policy;
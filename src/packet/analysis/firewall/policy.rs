@@ -1,6 +1,15 @@
-#[allow(dead_code)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Policy {
     Whitelist,
     Blacklist,
 }
+
+impl Policy {
+    /// firewall_settings.policy に保存する名前
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Policy::Whitelist => "Whitelist",
+            Policy::Blacklist => "Blacklist",
+        }
+    }
+}
@@ -2,17 +2,13 @@ use crate::idps_log;
 use crate::packet::analysis::transport::parse_transport_header;
 use crate::packet::analysis::AnalyzeResult;
 use crate::packet::types::{EtherType, IpProtocol};
-use log::info;
-use rtnetlink::IpVersion;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 #[derive(Debug)]
 pub struct IpHeader {
-    pub version: IpVersion,
     pub ip_protocol: IpProtocol,
     pub src_ip: IpAddr,
     pub dst_ip: IpAddr,
-    pub header_length: usize,
 }
 
 pub async fn parse_ip_packet(ethernet_frame: &[u8], ether_type: EtherType) -> Result<(IpAddr, IpAddr, IpProtocol, u16, u16, u8), AnalyzeResult> {
@@ -33,13 +29,10 @@ pub async fn parse_ip_packet(ethernet_frame: &[u8], ether_type: EtherType) -> Re
                 dst_ip = ip_header.dst_ip;
                 ip_protocol = ip_header.ip_protocol;
 
-                match parse_transport_header(ip_data, src_ip, dst_ip) {
-                    Ok(transport_header) => {
-                        src_port = transport_header.src_port;
-                        dst_port = transport_header.dst_port;
-                        flags = transport_header.flags;
-                    },
-                    Err(_) => {},
+                if let Ok(transport_header) = parse_transport_header(ip_data, src_ip, dst_ip) {
+                    src_port = transport_header.src_port;
+                    dst_port = transport_header.dst_port;
+                    flags = transport_header.flags;
                 }
             },
             Err(_e) => {
@@ -84,11 +77,9 @@ async fn parse_ip_header(data: &[u8]) -> Result<Option<IpHeader>, AnalyzeResult>
             //info!("IPv4パケット: src_ip={}, dst_ip={}, protocol={:?}", src_ip, dst_ip, ip_protocol);
 
             Ok(Some(IpHeader {
-                version: IpVersion::V4,
                 ip_protocol,
                 src_ip: IpAddr::V4(src_ip),
                 dst_ip: IpAddr::V4(dst_ip),
-                header_length: ihl,
             }))
         },
         6 => {
@@ -121,11 +112,9 @@ async fn parse_ip_header(data: &[u8]) -> Result<Option<IpHeader>, AnalyzeResult>
             );
 
             Ok(Some(IpHeader {
-                version: IpVersion::V6,
                 ip_protocol,
                 src_ip: IpAddr::V6(src_ip),
                 dst_ip: IpAddr::V6(dst_ip),
-                header_length: 40,
             }))
         },
         _ => {
@@ -2,7 +2,8 @@ pub mod analysis;
 pub mod monitor;
 pub mod reader;
 pub mod repository;
+pub mod spool;
 pub mod types;
 pub mod writer;
 
-pub use types::{InetAddr, MacAddr, PacketData};
+pub use types::{FetchedPacket, InetAddr, MacAddr, PacketData, PacketSignature, StoredPacket};
@@ -1,9 +1,12 @@
+use crate::context::AppContext;
 use crate::packet::monitor::error::MonitorError;
 use crate::packet::writer::PacketWriter;
 use log::{error, info, trace};
-use nix::sys::socket::{self, AddressFamily, SockFlag, SockType, SockaddrLike, SockaddrStorage};
+use nix::sys::socket::{self, sockopt, AddressFamily, SockFlag, SockType, SockaddrLike, SockaddrStorage};
+use nix::sys::time::TimeVal;
 use pnet::datalink::{self, Channel::Ethernet, Config, NetworkInterface};
 use std::os::fd::AsRawFd;
+use std::sync::Arc;
 use std::time::Duration;
 
 const READ_BUFFER_SIZE: usize = 65536;
@@ -14,9 +17,12 @@ const PACKET_OUTGOING: u8 = 4;
 pub struct NetworkMonitor;
 
 impl NetworkMonitor {
-    pub async fn start(interface: NetworkInterface) -> Result<(), MonitorError> {
+    pub async fn start(context: Arc<AppContext>, interface: NetworkInterface) -> Result<(), MonitorError> {
         let sock_fd = socket::socket(AddressFamily::Packet, SockType::Raw, SockFlag::empty(), None).map_err(|e| MonitorError::NetworkError(e.to_string()))?;
 
+        // 受信待ちで停止通知を取りこぼさないように、受信にタイムアウトを設定する
+        socket::setsockopt(&sock_fd, sockopt::ReceiveTimeout, &TimeVal::new(READ_TIMEOUT.as_secs() as i64, 0)).map_err(|e| MonitorError::NetworkError(e.to_string()))?;
+
         let config = Config {
             write_buffer_size: WRITE_BUFFER_SIZE,
             read_buffer_size: READ_BUFFER_SIZE,
@@ -39,7 +45,7 @@ impl NetworkMonitor {
         let mut buf = vec![0u8; 65536];
 
         info!("インターフェース {} でパケット受信を開始", interface.name);
-        let writer = PacketWriter::default();
+        let writer = PacketWriter::new(context);
 
         loop {
             match socket::recvfrom::<SockaddrStorage>(sock_fd.as_raw_fd(), &mut buf) {
@@ -65,7 +71,10 @@ impl NetworkMonitor {
                     }
                 },
                 Ok((_, None)) => continue,
-                Err(nix::errno::Errno::EAGAIN) => continue,
+                Err(nix::errno::Errno::EAGAIN) => {
+                    tokio::task::yield_now().await;
+                    continue;
+                },
                 Err(e) => {
                     error!("パケット読み取りエラー: {}", e);
                     break;
@@ -0,0 +1,73 @@
+use crate::packet::repository::{DeliveryCursor, HeldBackPackets};
+use std::sync::{Arc, Mutex};
+
+/// 他のノードから読み取ったパケットの送信状況の累計
+/// 読み取ったのに送信できなかったパケット (トンネル内での損失) はfetched - sentで確認できる
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeliveryStats {
+    pub fetched: u64,
+    pub sent: u64,
+    /// MTUを超えるため送信しなかったパケット数
+    pub oversized: u64,
+    /// インターフェースへの送信に失敗したパケット数
+    pub send_failed: u64,
+    /// 復号できなかったため破棄したパケット数 (改ざん・鍵の不一致・暗号化されていないパケット)
+    pub rejected: u64,
+    /// 署名を検証できなかったため破棄したパケット数 (署名の不一致・未署名・公開鍵が未登録)
+    pub forged: u64,
+    /// 受信済みのシーケンス番号のため破棄したパケット数 (同じ行の複製による再送)
+    pub replayed: u64,
+    /// 現在の読み取り位置 (読み取りを開始する前はNone)
+    pub cursor: Option<DeliveryCursor>,
+    /// 実行中の古いトランザクションのために読み取れていないパケット (最後に確認した時点で、ない場合はNone)
+    pub held_back: Option<HeldBackPackets>,
+}
+
+impl DeliveryStats {
+    pub fn lost(&self) -> u64 {
+        self.oversized + self.send_failed + self.rejected + self.forged + self.replayed
+    }
+}
+
+/// 読み取りタスクと統計情報の参照元で共有する送信状況
+#[derive(Clone, Default)]
+pub struct DeliveryTracker {
+    stats: Arc<Mutex<DeliveryStats>>,
+}
+
+impl DeliveryTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> DeliveryStats {
+        *self.lock()
+    }
+
+    pub(crate) fn set_cursor(&self, cursor: DeliveryCursor) {
+        self.lock().cursor = Some(cursor);
+    }
+
+    pub(crate) fn set_held_back(&self, held_back: Option<HeldBackPackets>) {
+        self.lock().held_back = held_back;
+    }
+
+    pub(crate) fn record(&self, fetched: usize, sent: usize, oversized: usize, send_failed: usize, rejected: usize) {
+        let mut stats = self.lock();
+        stats.fetched += fetched as u64;
+        stats.sent += sent as u64;
+        stats.oversized += oversized as u64;
+        stats.send_failed += send_failed as u64;
+        stats.rejected += rejected as u64;
+    }
+
+    pub(crate) fn record_unverified(&self, forged: usize, replayed: usize) {
+        let mut stats = self.lock();
+        stats.forged += forged as u64;
+        stats.replayed += replayed as u64;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DeliveryStats> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -1,5 +1,7 @@
+mod delivery_stats;
 mod error;
 mod packet_reader;
 mod packet_sender;
 
+pub use delivery_stats::{DeliveryStats, DeliveryTracker};
 pub use packet_reader::PacketReader;
@@ -1,35 +1,103 @@
-use crate::config::AppConfig;
+use crate::carrier::{CarrierError, CarrierSubscription, FetchedPacket};
+use crate::config::{CatchUpPolicy, DeliveryMode};
+use crate::context::AppContext;
+use crate::crypto::{CryptoError, PacketVerifier, ReceivedSeqs};
 use crate::packet::reader::error::PacketReaderError;
-use crate::packet::reader::packet_sender::PacketSender;
-use crate::packet::repository::PacketRepository;
+use crate::packet::reader::packet_sender::{PacketSender, SendSummary};
+use crate::packet::repository::{DeliveryCursor, PacketRepository};
 use chrono::{DateTime, Utc};
-use log::{error, info};
+use log::{debug, error, info, warn};
 use pnet::datalink::NetworkInterface;
+use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::time::Instant;
+
+// 通知用の接続に失敗した場合に、次に接続を試みるまでの間隔
+const LISTEN_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+// 署名を検証できなかった場合に、node_list の公開鍵を読み込み直す最小の間隔 (公開鍵が未登録のノードはノードごとに1度だけ待たずに読み込み直す)
+const KEY_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+// 実行中の古いトランザクションのために読み取れないパケットがある状態が、この回数の読み取りの間続いた場合に警告する
+const HELD_BACK_WARN_FETCHES: u32 = 10;
 
 #[derive(Clone)]
 pub struct PacketReader {
-    last_timestamp: Option<DateTime<Utc>>,
-    is_first_fetch: bool,
+    // 最初の読み取りで決定する
+    cursor: Option<DeliveryCursor>,
+    // 署名が有効な場合の検証 (公開鍵は最初の読み取りで読み込む)
+    verifier: Option<PacketVerifier>,
+    keys_loaded_at: Option<Instant>,
+    // 公開鍵が未登録だったため、KEY_RELOAD_INTERVALを待たずに読み込み直したノード (次の読み込みまで同じノードでは待つ)
+    reloaded_for: HashSet<i16>,
+    // 媒体に記録済みの、書き込んだノードごとのシーケンス番号の最大値
+    acknowledged_seqs: Vec<ReceivedSeqs>,
+    // 同じパケットが読み取れないまま残っていた読み取りの回数と、そのパケットの書き込み時刻
+    held_back_fetches: u32,
+    held_back_since: Option<DateTime<Utc>>,
+}
+
+impl Default for PacketReader {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PacketReader {
     pub fn new() -> Self {
         Self {
-            last_timestamp: None,
-            is_first_fetch: true,
+            cursor: None,
+            verifier: None,
+            keys_loaded_at: None,
+            reloaded_for: HashSet::new(),
+            acknowledged_seqs: Vec::new(),
+            held_back_fetches: 0,
+            held_back_since: None,
         }
     }
 
-    pub async fn start(interface: NetworkInterface) -> Result<(), PacketReaderError> {
-        let config: AppConfig = AppConfig::new().map_err(|e| PacketReaderError::ConfigurationError(e.to_string()))?;
-
+    pub async fn start(context: Arc<AppContext>, interface: NetworkInterface) -> Result<(), PacketReaderError> {
         let mut reader = Self::new();
+        if context.config.signing.enabled {
+            reader.verifier = Some(PacketVerifier::new(context.config.signing.accept_unsigned));
+        }
+        let health = context.carrier.health();
+        let delivery = &context.config.delivery;
+        let mut listener: Option<Box<dyn CarrierSubscription>> = None;
+        let mut next_listen = Instant::now();
 
         loop {
-            match reader.fetch_and_send_packets(&interface, config.node_id).await {
+            // データベースへのアクセスが停止している間は、復旧するまで読み取りを止める
+            health.wait_until_available().await;
+
+            // 読み取り中に書き込まれたパケットの通知を取りこぼさないよう、読み取りの前にLISTENしておく
+            if delivery.mode == DeliveryMode::Notify && listener.is_none() && Instant::now() >= next_listen {
+                match context.carrier.subscribe().await {
+                    Ok(new_listener) => {
+                        info!("{} の書き込みの通知の待機を開始しました (チャネル: {})", context.carrier.name(), delivery.channel);
+                        listener = Some(new_listener);
+                    },
+                    Err(e) => {
+                        warn!("通知用の接続に失敗しました。{}ms間隔の読み取りで継続します: {}", delivery.poll_interval.as_millis(), e);
+                        next_listen = Instant::now() + LISTEN_RETRY_INTERVAL;
+                    },
+                }
+            }
+
+            match reader.fetch_and_send_packets(&context, &interface).await {
+                // 上限まで取得した場合は、待たずに続きを読み取る
+                Ok(fetched) if fetched as i64 >= PacketRepository::FETCH_LIMIT => {},
                 Ok(_) => {
-                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    reader.check_held_back(&context).await;
+                    if !Self::wait_for_packets(&mut listener, context.node_id(), delivery.poll_interval).await {
+                        warn!("通知用の接続が切断されました。再接続します");
+                        listener = None;
+                    }
+                },
+                // 接続の障害はサーキットブレーカーが記録し、遮断した場合は上で待機する
+                Err(e) if !health.is_healthy() => {
+                    debug!("データベースに接続できないため、パケットの読み取りを待機しています: {}", e);
                 },
                 Err(e) => {
                     error!("パケット処理中にエラーが発生しました: {:?}", e);
@@ -39,36 +107,276 @@ impl PacketReader {
         }
     }
 
-    async fn fetch_and_send_packets(&mut self, interface: &NetworkInterface, node_id: i16) -> Result<(), PacketReaderError> {
-        match PacketRepository::get_filtered_packets(node_id, self.is_first_fetch, self.last_timestamp.as_ref()).await {
-            Ok(packets) => {
-                if !packets.is_empty() {
-                    info!(
-                        "パケットを取得しました: {} 個 (開始時刻: {}, 終了時刻: {})",
-                        packets.len(),
-                        packets.first().map(|(t, _)| t).unwrap(),
-                        packets.last().map(|(t, _)| t).unwrap()
-                    );
-
-                    // 最後のタイムスタンプを更新
-                    self.last_timestamp = packets.last().map(|(t, _)| *t);
-
-                    // パケットを送信
-                    if let Err(e) = PacketSender::send_packets(interface, packets).await {
-                        error!("パケットの送信に失敗しました: {:?}", e);
-                    }
-                }
+    /// 読み取り位置より後にコミット済みのパケットが、実行中の古いトランザクションのために読み取れない状態が続いていないか確認する
+    /// 読み取り位置を進めると取りこぼすため待つしかなく、HELD_BACK_WARN_FETCHES 回の読み取りごとに警告する
+    async fn check_held_back(&mut self, context: &AppContext) {
+        let Some(cursor) = self.cursor else {
+            return;
+        };
 
-                if self.is_first_fetch {
-                    self.is_first_fetch = false;
-                }
+        let held_back = match context.carrier.held_back(context.node_id(), cursor).await {
+            Ok(held_back) => held_back,
+            Err(e) => {
+                debug!("読み取れないパケットの確認に失敗しました: {}", e);
+                return;
+            },
+        };
+        context.delivery.set_held_back(held_back);
+
+        // 書き込みが続いている間は常にいくつかのパケットが待っているため、同じパケットが残り続けている場合だけ数える
+        let oldest = held_back.map(|held_back| held_back.oldest);
+        if oldest.is_some() && oldest == self.held_back_since {
+            self.held_back_fetches += 1;
+        } else {
+            if self.held_back_fetches >= HELD_BACK_WARN_FETCHES {
+                info!(
+                    "実行中の古いトランザクションが終了し、パケットの読み取りを再開しました ({} 回の読み取りの間停止していました)",
+                    self.held_back_fetches
+                );
+            }
+            self.held_back_fetches = u32::from(oldest.is_some());
+            self.held_back_since = oldest;
+        }
+
+        if let Some(held_back) = held_back.filter(|_| self.held_back_fetches.is_multiple_of(HELD_BACK_WARN_FETCHES)) {
+            warn!(
+                "実行中の古いトランザクションのため、{} 回の読み取りの間パケットを読み取れていません: {} 個以上 (最も古い書き込み時刻: {}, 読み取り位置: {})。長時間実行中のトランザクションを終了してください",
+                self.held_back_fetches, held_back.count, held_back.oldest, cursor
+            );
+        }
+    }
+
+    /// 他のノードからの書き込みの通知を受けるか、poll_intervalが経過するまで待機する
+    /// 通知用の接続が切断された場合はfalseを返す
+    async fn wait_for_packets(listener: &mut Option<Box<dyn CarrierSubscription>>, node_id: i16, poll_interval: Duration) -> bool {
+        let Some(listener) = listener.as_mut() else {
+            tokio::time::sleep(poll_interval).await;
+            return true;
+        };
+
+        let timeout = tokio::time::sleep(poll_interval);
+        tokio::pin!(timeout);
 
-                Ok(())
+        loop {
+            tokio::select! {
+                _ = &mut timeout => return true,
+                notification = listener.recv() => match notification {
+                    // 自ノードが書き込んだパケットは読み取りの対象外
+                    Some(publisher) if publisher == node_id => {},
+                    Some(_) => {
+                        // まとめて読み取るため、既に届いている通知は読み捨てる
+                        while listener.try_recv().is_some() {}
+                        return true;
+                    },
+                    None => return false,
+                },
+            }
+        }
+    }
+
+    /// 他のノードが書き込んだパケットを読み取って送信し、読み取ったパケット数を返す
+    async fn fetch_and_send_packets(&mut self, context: &AppContext, interface: &NetworkInterface) -> Result<usize, PacketReaderError> {
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => {
+                // 再起動前に受信したパケットの再送を検出できるよう、記録したシーケンス番号を復元する
+                if let Some(verifier) = self.verifier.as_mut() {
+                    let seqs = context.carrier.saved_seqs(context.node_id()).await.map_err(|e| PacketReaderError::DatabaseError(e.to_string()))?;
+                    verifier.restore_seqs(&seqs);
+                    self.acknowledged_seqs = verifier.received_seqs();
+                }
+                let cursor = Self::start_cursor(context).await.map_err(|e| PacketReaderError::DatabaseError(e.to_string()))?;
+                self.cursor = Some(cursor);
+                context.delivery.set_cursor(cursor);
+                cursor
             },
+        };
+
+        let (packets, next) = context.carrier.fetch(context.node_id(), cursor).await.map_err(|e| PacketReaderError::DatabaseError(e.to_string()))?;
+        let fetched = packets.len();
+        if packets.is_empty() {
+            return Ok(0);
+        }
+
+        info!(
+            "パケットを取得しました: {} 個 (最初のキャプチャ時刻: {}, 最後のキャプチャ時刻: {})",
+            packets.len(),
+            packets.first().map(|p| p.captured_at).unwrap(),
+            packets.last().map(|p| p.captured_at).unwrap()
+        );
+
+        // 公開鍵を読み込めない場合は、読み取り位置を進めずに再試行する
+        let packets = self.verify_packets(context, packets).await?;
+
+        // 送信の成否に関わらず読み取り位置を進める (同じパケットを重複して送信しないため)
+        self.cursor = Some(next);
+        context.delivery.set_cursor(next);
+
+        // 復号できないパケット (改ざん・鍵の不一致・平文) は送信せずに破棄する
+        let verified = packets.len();
+        let packets = Self::open_packets(context, packets);
+        let rejected = verified - packets.len();
+
+        // パケットを送信
+        let summary = match PacketSender::send_packets(interface, packets).await {
+            Ok(summary) => summary,
             Err(e) => {
-                error!("パケットの取得に失敗しました: {:?}", e);
-                Err(PacketReaderError::DatabaseError(e.to_string()))
+                error!("パケットの送信に失敗しました: {:?}", e);
+                SendSummary {
+                    failed: verified - rejected,
+                    ..SendSummary::default()
+                }
             },
+        };
+        context.delivery.record(fetched, summary.sent, summary.oversized, summary.failed, rejected);
+
+        // シーケンス番号は読み取り位置より先に記録する (間で停止した場合、このバッチは再起動後にリプレイとして破棄される)
+        self.acknowledge_seqs(context).await;
+
+        // 再起動後に再開できるよう保存する (保存前に停止した場合は、このバッチを再起動後にもう一度送信する)
+        if let Err(e) = context.carrier.acknowledge(context.node_id(), next).await {
+            warn!("読み取り位置の保存に失敗しました ({}): {}", next, e);
+        }
+
+        Ok(fetched)
+    }
+
+    /// 署名が有効な場合は書き込んだノードの署名とシーケンス番号を検証し、偽造・再送されたパケットを除く
+    async fn verify_packets(&mut self, context: &AppContext, packets: Vec<FetchedPacket>) -> Result<Vec<FetchedPacket>, PacketReaderError> {
+        let Some(verifier) = self.verifier.as_mut() else { return Ok(packets) };
+        if self.keys_loaded_at.is_none() {
+            Self::load_keys(context, verifier).await?;
+            self.keys_loaded_at = Some(Instant::now());
+        }
+
+        let mut results: Vec<Result<(), CryptoError>> = packets.iter().map(|packet| verifier.verify(packet)).collect();
+
+        // ノードの追加や公開鍵の変更を反映するため、公開鍵の問題で検証できなかった場合は読み込み直して再検証する
+        let key_error = |result: &Result<(), CryptoError>| matches!(result, Err(CryptoError::UnknownNodeKeyError(_) | CryptoError::SignatureError(_)));
+        // 新しく登録されたノードのパケットを偽造として破棄しないよう、未登録のノードだけが原因の場合は間隔を待たずに1度読み込み直す
+        let unknown: HashSet<i16> = results
+            .iter()
+            .filter_map(|result| match result {
+                Err(CryptoError::UnknownNodeKeyError(node_id)) => Some(*node_id),
+                _ => None,
+            })
+            .collect();
+        let only_unknown = results.iter().all(|result| matches!(result, Ok(()) | Err(CryptoError::UnknownNodeKeyError(_))));
+        let interval_elapsed = self.keys_loaded_at.is_some_and(|loaded_at| loaded_at.elapsed() >= KEY_RELOAD_INTERVAL);
+        let new_node = only_unknown && !unknown.is_subset(&self.reloaded_for);
+        if results.iter().any(key_error) && (interval_elapsed || new_node) {
+            Self::load_keys(context, verifier).await?;
+            self.keys_loaded_at = Some(Instant::now());
+            if interval_elapsed {
+                self.reloaded_for.clear();
+            }
+            self.reloaded_for.extend(unknown);
+            for (packet, result) in packets.iter().zip(results.iter_mut()) {
+                if key_error(result) {
+                    *result = verifier.verify(packet);
+                }
+            }
+        }
+
+        let (mut forged, mut replayed) = (0, 0);
+        let mut verified = Vec::with_capacity(packets.len());
+        for (packet, result) in packets.into_iter().zip(results) {
+            match result {
+                Ok(()) => verified.push(packet),
+                Err(e) => {
+                    if matches!(e, CryptoError::ReplayError(..)) {
+                        replayed += 1;
+                    } else {
+                        forged += 1;
+                    }
+                    error!("ノード {} のパケット (キャプチャ時刻: {}) を破棄しました: {}", packet.node_id, packet.captured_at, e);
+                },
+            }
+        }
+        if forged + replayed > 0 {
+            context.delivery.record_unverified(forged, replayed);
+        }
+        Ok(verified)
+    }
+
+    /// 署名が有効な場合は、受信済みのシーケンス番号が変わったノードの分を媒体に記録する
+    async fn acknowledge_seqs(&mut self, context: &AppContext) {
+        let Some(verifier) = &self.verifier else { return };
+        let seqs = verifier.received_seqs();
+        let changed: Vec<ReceivedSeqs> = seqs.iter().filter(|received| !self.acknowledged_seqs.contains(received)).cloned().collect();
+        if changed.is_empty() {
+            return;
+        }
+
+        match context.carrier.acknowledge_seqs(context.node_id(), &changed).await {
+            Ok(()) => self.acknowledged_seqs = seqs,
+            Err(e) => warn!("受信済みのシーケンス番号の保存に失敗しました: {}", e),
+        }
+    }
+
+    async fn load_keys(context: &AppContext, verifier: &mut PacketVerifier) -> Result<(), PacketReaderError> {
+        let nodes = context.carrier.list_nodes().await.map_err(|e| PacketReaderError::DatabaseError(e.to_string()))?;
+        for e in verifier.update_keys(&nodes) {
+            warn!("{}", e);
+        }
+        Ok(())
+    }
+
+    /// 暗号化が有効な場合はraw_packetを復号し、復号できなかったパケットを除く
+    fn open_packets(context: &AppContext, packets: Vec<FetchedPacket>) -> Vec<FetchedPacket> {
+        let Some(cipher) = &context.cipher else { return packets };
+
+        packets
+            .into_iter()
+            .filter_map(|packet| match cipher.open(packet.node_id, packet.key_id, &packet.raw_packet) {
+                Ok(raw_packet) => Some(FetchedPacket {
+                    raw_packet,
+                    key_id: None,
+                    ..packet
+                }),
+                Err(e) => {
+                    warn!("ノード {} のパケット (キャプチャ時刻: {}) を破棄しました: {}", packet.node_id, packet.captured_at, e);
+                    None
+                },
+            })
+            .collect()
+    }
+
+    /// delivery.catch_upに従って、起動時の読み取り位置を決定する
+    async fn start_cursor(context: &AppContext) -> Result<DeliveryCursor, CarrierError> {
+        let carrier = &context.carrier;
+        let node_id = context.node_id();
+        let delivery = &context.config.delivery;
+
+        let live = carrier.live_cursor().await?;
+        let saved = match delivery.catch_up {
+            CatchUpPolicy::Live => None,
+            CatchUpPolicy::Resume | CatchUpPolicy::Replay => carrier.saved_cursor(node_id).await?,
+        };
+
+        let cursor = match (delivery.catch_up, saved) {
+            (CatchUpPolicy::Live, _) | (CatchUpPolicy::Resume, None) => live,
+            (CatchUpPolicy::Resume, Some(saved)) => saved,
+            // 上限がない場合に、保存された位置がなければ全てのパケットを読み取ってしまうため現在の位置から始める
+            (CatchUpPolicy::Replay, None) if delivery.catch_up_max_age.is_none() && delivery.catch_up_max_packets.is_none() => live,
+            (CatchUpPolicy::Replay, saved) => {
+                // 保存された位置と上限のうち、最も新しい位置から読み取る
+                let mut cursor = saved.unwrap_or_default();
+                if let Some(max_age) = delivery.catch_up_max_age {
+                    cursor = cursor.max(carrier.cursor_since(node_id, max_age).await?);
+                }
+                if let Some(max_packets) = delivery.catch_up_max_packets {
+                    cursor = cursor.max(carrier.cursor_before_latest(node_id, max_packets).await?);
+                }
+                cursor
+            },
+        };
+
+        match saved {
+            Some(saved) if saved == cursor => info!("前回の読み取り位置からパケットの読み取りを再開します ({})", cursor),
+            Some(saved) => info!("パケットの読み取りを開始します ({}、前回の読み取り位置: {})", cursor, saved),
+            None => info!("パケットの読み取りを開始します ({})", cursor),
         }
+        Ok(cursor)
     }
 }
@@ -1,20 +1,30 @@
+use crate::carrier::FetchedPacket;
 use crate::packet::reader::error::PacketReaderError;
-use chrono::{DateTime, Utc};
 use log::{error, info};
 use pnet::datalink::Channel::Ethernet;
 use pnet::datalink::{self, NetworkInterface};
 use std::time::Duration;
 use tokio::time::sleep;
 
+/// 1回の送信の結果
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SendSummary {
+    pub sent: usize,
+    pub oversized: usize,
+    pub failed: usize,
+}
+
 pub struct PacketSender;
 
 impl PacketSender {
     const MAX_PACKET_SIZE: usize = 1500;
 
-    pub async fn send_packets(interface: &NetworkInterface, packets: Vec<(DateTime<Utc>, Vec<u8>)>) -> Result<(), PacketReaderError> {
+    /// 他のノードから読み取ったパケットを順番に送信する (raw_packetは復号済みであること)
+    pub async fn send_packets(interface: &NetworkInterface, packets: Vec<FetchedPacket>) -> Result<SendSummary, PacketReaderError> {
+        let mut summary = SendSummary::default();
         if packets.is_empty() {
             info!("送信するパケットがありません");
-            return Ok(());
+            return Ok(summary);
         }
 
         let mut tx = match datalink::channel(interface, Default::default()) {
@@ -24,43 +34,59 @@ impl PacketSender {
         };
 
         info!("パケット送信を開始します: {} パケット", packets.len());
-        let mut last_packet_time = packets[0].0;
+        let mut last_packet = (packets[0].node_id, packets[0].captured_at);
 
-        for (i, (timestamp, raw_packet)) in packets.iter().enumerate() {
+        for (
+            i,
+            FetchedPacket {
+                node_id, captured_at, raw_packet, ..
+            },
+        ) in packets.iter().enumerate()
+        {
             // 前のパケットとの時間差を計算して待機
-            let time_diff = *timestamp - last_packet_time;
+            // キャプチャ時刻はノードごとの時計によるため、同じノードのパケットの間でだけ比較する
+            let (last_node_id, last_captured_at) = last_packet;
+            let time_diff = if *node_id == last_node_id {
+                *captured_at - last_captured_at
+            } else {
+                chrono::Duration::zero()
+            };
             if time_diff.num_microseconds().unwrap_or(0) > 0 {
                 sleep(Duration::from_micros(time_diff.num_microseconds().unwrap_or(0) as u64)).await;
             }
 
             if raw_packet.len() > Self::MAX_PACKET_SIZE {
                 error!("パケットサイズが制限を超えています: {} bytes (最大: {} bytes)", raw_packet.len(), Self::MAX_PACKET_SIZE);
+                summary.oversized += 1;
                 continue;
             }
 
             match tx.send_to(raw_packet, None) {
                 Some(Ok(_)) => {
+                    summary.sent += 1;
                     info!(
-                        "{index}> 送信したパケット: {packet_size}bytes, timestamp = {timestamp}",
+                        "{index}> 送信したパケット: {packet_size}bytes, node_id = {node_id}, captured_at = {captured_at}",
                         index = i + 1,
                         packet_size = raw_packet.len(),
-                        timestamp = timestamp.format("%Y-%m-%d %H:%M:%S.%f").to_string()
+                        captured_at = captured_at.format("%Y-%m-%d %H:%M:%S.%f").to_string()
                     );
                 },
                 Some(Err(e)) => {
                     error!("パケット送信エラー: {}", e);
+                    summary.failed += 1;
                     continue;
                 },
                 None => {
                     error!("パケット送信エラー: 宛先が指定されていません");
+                    summary.failed += 1;
                     continue;
                 },
             }
 
-            last_packet_time = *timestamp;
+            last_packet = (*node_id, *captured_at);
         }
 
         info!("パケット送信が完了しました");
-        Ok(())
+        Ok(summary)
     }
 }
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+/// 他のノードが書き込んだパケットの読み取り位置
+/// パケットを書き込んだトランザクションのIDとパケットIDの組で順序付ける (コミット済みの行だけを進めるため取りこぼさない)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DeliveryCursor {
+    pub tx_id: i64,
+    pub packet_id: i64,
+}
+
+impl fmt::Display for DeliveryCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tx_id={}, id={}", self.tx_id, self.packet_id)
+    }
+}
+
+/// 読み取り位置より後にコミット済みだが、実行中の古いトランザクションより後に書き込まれたため、まだ読み取れないパケット
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeldBackPackets {
+    /// 読み取れないパケット数 (PacketRepository::FETCH_LIMIT 個までしか数えない)
+    pub count: i64,
+    /// 読み取れないパケットのうち最も古い書き込み時刻
+    pub oldest: DateTime<Utc>,
+}
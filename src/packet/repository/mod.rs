@@ -1,3 +1,7 @@
+mod delivery_cursor;
+mod packet_query;
 mod packet_repository;
 
-pub(crate) use packet_repository::PacketRepository;
+pub use delivery_cursor::{DeliveryCursor, HeldBackPackets};
+pub use packet_query::PacketQuery;
+pub use packet_repository::PacketRepository;
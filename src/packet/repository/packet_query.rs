@@ -0,0 +1,32 @@
+use chrono::{DateTime, Utc};
+
+/// 保存済みパケットの検索条件
+#[derive(Debug, Clone)]
+pub struct PacketQuery {
+    /// 送信元ノードで絞り込む (Noneの場合は全ノード)
+    pub node_id: Option<i16>,
+    /// この時刻以降のパケット
+    pub since: Option<DateTime<Utc>>,
+    /// この時刻より前のパケット
+    pub until: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+impl PacketQuery {
+    pub const DEFAULT_LIMIT: i64 = 1000;
+
+    pub fn new() -> Self {
+        Self {
+            node_id: None,
+            since: None,
+            until: None,
+            limit: Self::DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl Default for PacketQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,18 +1,44 @@
-use crate::database::{Database, DatabaseError, ExecuteQuery};
-use crate::packet::types::PacketData;
+use crate::config::{BatchingConfig, InsertMethod};
+use crate::crypto::ReceivedSeqs;
+use crate::database::{CachedTransaction, Database, DatabaseError, ExecuteQuery};
+use crate::packet::repository::{DeliveryCursor, HeldBackPackets, PacketQuery};
+use crate::packet::types::{EtherType, FetchedPacket, IpProtocol, PacketData, PacketSignature, StoredPacket};
 use crate::packet::{InetAddr, MacAddr};
 use chrono::{DateTime, Utc};
 use log::{debug, info, warn};
 use std::time::{Duration, Instant};
-use tokio_postgres::types::ToSql;
+use tokio_postgres::types::{ToSql, Type};
+
+const COPY_QUERY: &str = "
+    COPY packets (
+        node_id, captured_at, src_mac, dst_mac, ether_type, ip_protocol,
+        src_ip, dst_ip, src_port, dst_port, raw_packet, key_id, seq, signature
+    ) FROM STDIN BINARY";
+
+const COPY_TYPES: &[Type] = &[
+    Type::INT2,
+    Type::TIMESTAMPTZ,
+    Type::MACADDR,
+    Type::MACADDR,
+    Type::INT4,
+    Type::INT4,
+    Type::INET,
+    Type::INET,
+    Type::INT4,
+    Type::INT4,
+    Type::BYTEA,
+    Type::INT2,
+    Type::INT8,
+    Type::BYTEA,
+];
 
 pub struct PacketRepository;
 
 impl PacketRepository {
-    const CHUNK_SIZE: usize = 50;
-    const MAX_RETRIES: u64 = 3;
+    /// 1回の読み取りで取得するパケット数の上限
+    pub const FETCH_LIMIT: i64 = 1000;
 
-    pub async fn bulk_insert(node_id: i16, packets: Vec<PacketData>) -> Result<(), DatabaseError> {
+    pub async fn bulk_insert(db: &Database, node_id: i16, packets: &[PacketData], batching: &BatchingConfig) -> Result<(), DatabaseError> {
         if packets.is_empty() {
             return Ok(());
         }
@@ -20,158 +46,398 @@ impl PacketRepository {
         debug!("バルク挿入開始: パケット数={}, node_id={}", packets.len(), node_id);
 
         let start_time = Instant::now();
-        let packets = std::sync::Arc::new(packets);
-
-        for (chunk_index, chunk) in packets.chunks(Self::CHUNK_SIZE).enumerate() {
-            debug!("チャンク処理開始: インデックス={}, サイズ={}", chunk_index, chunk.len());
-            let mut retries = 0;
-            let chunk_data = chunk.to_vec();
-
-            loop {
-                let chunk_clone = chunk_data.clone();
-                match Self::insert_chunk(node_id, chunk_clone).await {
-                    Ok(_) => {
-                        debug!("チャンク{}の挿入成功", chunk_index);
-                        break;
-                    },
-                    Err(e) if retries < Self::MAX_RETRIES => {
-                        warn!("チャンク{}の挿入に失敗（リトライ {}/{}）: {:?}", chunk_index, retries + 1, Self::MAX_RETRIES, e);
-                        retries += 1;
-                        tokio::time::sleep(Duration::from_millis(100 * retries)).await;
-                    },
-                    Err(e) => {
-                        warn!("チャンク{}の挿入が最終的に失敗: {:?}", chunk_index, e);
-                        return Err(e);
-                    },
-                }
+
+        let use_copy = match batching.insert_method {
+            InsertMethod::Copy => true,
+            InsertMethod::Unnest => false,
+            InsertMethod::Auto => packets.len() >= batching.copy_threshold,
+        };
+        if use_copy {
+            match Self::copy_insert(db, node_id, packets).await {
+                Ok(_) => {
+                    Self::log_throughput("COPY", packets.len(), start_time.elapsed());
+                    return Ok(());
+                },
+                // COPYは失敗した場合に全体が取り消されるため、unnestでそのまま再試行できる
+                Err(e) => warn!("COPYによる挿入に失敗したため、unnestによる挿入で再試行します: {:?}", e),
+            }
+        }
+
+        // 全てのチャンクを1つのトランザクションで書き込む
+        // (途中のチャンクで失敗した場合に前のチャンクだけがコミットされ、スプールからの再送で重複しないようにする)
+        let mut retries = 0;
+        loop {
+            match Self::insert_chunks(db, node_id, packets, batching.chunk_size).await {
+                Ok(_) => break,
+                Err(e) if retries < batching.max_retries => {
+                    warn!("unnestによる挿入に失敗（リトライ {}/{}）: {:?}", retries + 1, batching.max_retries, e);
+                    retries += 1;
+                    tokio::time::sleep(Duration::from_millis(100 * retries)).await;
+                },
+                Err(e) => {
+                    warn!("unnestによる挿入が最終的に失敗: {:?}", e);
+                    return Err(e);
+                },
             }
         }
 
-        let elapsed = start_time.elapsed();
+        Self::log_throughput("unnest", packets.len(), start_time.elapsed());
+        Ok(())
+    }
+
+    /// `COPY ... FROM STDIN BINARY` で全パケットを1回で書き込む
+    async fn copy_insert(db: &Database, node_id: i16, packets: &[PacketData]) -> Result<(), DatabaseError> {
+        let ether_types: Vec<i32> = packets.iter().map(|p| p.ether_type.as_i32()).collect();
+        let ip_protocols: Vec<i32> = packets.iter().map(|p| p.ip_protocol.as_i32()).collect();
+        let seqs: Vec<Option<i64>> = packets.iter().map(|p| PacketSignature::seq_column(&p.signature)).collect();
+        let signatures: Vec<Option<&[u8]>> = packets.iter().map(|p| PacketSignature::signature_column(&p.signature)).collect();
+
+        let rows: Vec<Vec<&(dyn ToSql + Sync)>> = packets
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let row: Vec<&(dyn ToSql + Sync)> = vec![
+                    &node_id,
+                    &p.captured_at,
+                    &p.src_mac,
+                    &p.dst_mac,
+                    &ether_types[i],
+                    &ip_protocols[i],
+                    &p.src_ip,
+                    &p.dst_ip,
+                    &p.src_port,
+                    &p.dst_port,
+                    &p.raw_packet,
+                    &p.key_id,
+                    &seqs[i],
+                    &signatures[i],
+                ];
+                row
+            })
+            .collect();
+
+        let inserted = db.copy_in_binary(COPY_QUERY, COPY_TYPES, &rows).await?;
+        if inserted as usize != packets.len() {
+            warn!("期待された挿入数と実際の挿入数が一致しません: expected={}, actual={}", packets.len(), inserted);
+            return Err(DatabaseError::QueryExecutionError("Inserted row count mismatch".to_string()));
+        }
+        Ok(())
+    }
+
+    fn log_throughput(method: &str, count: usize, elapsed: Duration) {
         info!(
-            "{}個のパケットを{}秒で一括挿入しました ({}ms/packet)",
-            packets.len(),
+            "{}個のパケットを{}秒で一括挿入しました ({}, {}ms/packet)",
+            count,
             elapsed.as_secs_f64(),
-            elapsed.as_millis() as f64 / packets.len() as f64
+            method,
+            elapsed.as_millis() as f64 / count as f64
         );
+    }
 
-        Ok(())
+    /// パケットをchunk_size個ずつのINSERTに分けて書き込み、全てのチャンクをまとめてコミットする
+    async fn insert_chunks(db: &Database, node_id: i16, packets: &[PacketData], chunk_size: usize) -> Result<(), DatabaseError> {
+        db.transaction_with(packets, |tx, packets| {
+            Box::pin(async move {
+                for (chunk_index, chunk) in packets.chunks(chunk_size).enumerate() {
+                    debug!("チャンク処理開始: インデックス={}, サイズ={}", chunk_index, chunk.len());
+                    Self::insert_chunk(tx, node_id, chunk).await?;
+                }
+                Ok(())
+            })
+        })
+        .await
     }
 
-    async fn insert_chunk(node_id: i16, packets: Vec<PacketData>) -> Result<(), DatabaseError> {
-        let db = Database::get_database();
+    async fn insert_chunk(tx: &mut CachedTransaction<'_>, node_id: i16, packets: &[PacketData]) -> Result<(), DatabaseError> {
         let start_time = Instant::now();
 
+        let insert_query = "
+            INSERT INTO packets (
+                node_id, captured_at, src_mac, dst_mac, ether_type, ip_protocol,
+                src_ip, dst_ip, src_port, dst_port, raw_packet, key_id, seq, signature
+            )
+            SELECT *
+            FROM (
+                SELECT
+                    unnest($1::SMALLINT[]) as node_id,
+                    unnest($2::TIMESTAMPTZ[]) as captured_at,
+                    unnest($3::macaddr[]) as src_mac,
+                    unnest($4::macaddr[]) as dst_mac,
+                    unnest($5::INTEGER[]) as ether_type,
+                    unnest($6::INTEGER[]) as ip_protocol,
+                    unnest($7::inet[]) as src_ip,
+                    unnest($8::inet[]) as dst_ip,
+                    unnest($9::INTEGER[]) as src_port,
+                    unnest($10::INTEGER[]) as dst_port,
+                    unnest($11::BYTEA[]) as raw_packet,
+                    unnest($12::SMALLINT[]) as key_id,
+                    unnest($13::BIGINT[]) as seq,
+                    unnest($14::BYTEA[]) as signature
+            ) t";
+
+        let node_ids: Vec<i16> = vec![node_id; packets.len()];
+        let captured_ats: Vec<DateTime<Utc>> = packets.iter().map(|p| p.captured_at).collect();
+        let src_macs: Vec<&MacAddr> = packets.iter().map(|p| &p.src_mac).collect();
+        let dst_macs: Vec<&MacAddr> = packets.iter().map(|p| &p.dst_mac).collect();
+        let ether_types: Vec<i32> = packets.iter().map(|p| p.ether_type.as_i32()).collect();
+        let ip_protocols: Vec<i32> = packets.iter().map(|p| p.ip_protocol.as_i32()).collect();
+        let src_ips: Vec<&InetAddr> = packets.iter().map(|p| &p.src_ip).collect();
+        let dst_ips: Vec<&InetAddr> = packets.iter().map(|p| &p.dst_ip).collect();
+        let src_ports: Vec<i32> = packets.iter().map(|p| p.src_port).collect();
+        let dst_ports: Vec<i32> = packets.iter().map(|p| p.dst_port).collect();
+        let raw_packets: Vec<&[u8]> = packets.iter().map(|p| p.raw_packet.as_slice()).collect();
+        let key_ids: Vec<Option<i16>> = packets.iter().map(|p| p.key_id).collect();
+        let seqs: Vec<Option<i64>> = packets.iter().map(|p| PacketSignature::seq_column(&p.signature)).collect();
+        let signatures: Vec<Option<&[u8]>> = packets.iter().map(|p| PacketSignature::signature_column(&p.signature)).collect();
+
+        debug!("データ挿入開始: パケット数={}, 最初のキャプチャ時刻={:?}", packets.len(), captured_ats.first());
+
+        let result = tx
+            .execute(
+                insert_query,
+                &[
+                    &node_ids,
+                    &captured_ats,
+                    &src_macs,
+                    &dst_macs,
+                    &ether_types,
+                    &ip_protocols,
+                    &src_ips,
+                    &dst_ips,
+                    &src_ports,
+                    &dst_ports,
+                    &raw_packets,
+                    &key_ids,
+                    &seqs,
+                    &signatures,
+                ],
+            )
+            .await
+            .map_err(|e| {
+                warn!("データ挿入中にエラーが発生: {:?}", e);
+                DatabaseError::QueryExecutionError(e.to_string())
+            })?;
+
+        debug!("データ挿入完了: 挿入数={}, 実行時間={}ms", result, start_time.elapsed().as_millis());
+
+        if result as usize != packets.len() {
+            warn!("期待された挿入数と実際の挿入数が一致しません: expected={}, actual={}", packets.len(), result);
+            return Err(DatabaseError::QueryExecutionError("Inserted row count mismatch".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// 現在の読み取り位置 (実行中のトランザクションより前にコミットされたパケットは全て読み取り済みとする位置)
+    pub async fn live_cursor(db: &Database) -> Result<DeliveryCursor, DatabaseError> {
+        let rows = db.query("SELECT pg_snapshot_xmin(pg_current_snapshot())::text::bigint AS tx_id", &[]).await?;
+        let tx_id = rows.first().map(|row| row.get("tx_id")).unwrap_or_default();
+        Ok(DeliveryCursor { tx_id, packet_id: 0 })
+    }
+
+    /// 直近max_ageの間に他のノードが書き込んだパケットから読み取る位置 (該当するパケットがない場合は現在の位置)
+    pub async fn cursor_since(db: &Database, node_id: i16, max_age: Duration) -> Result<DeliveryCursor, DatabaseError> {
+        let query = "
+            SELECT LEAST(
+                (SELECT MIN(tx_id) FROM packets WHERE node_id != $1 AND timestamp >= NOW() - make_interval(secs => $2)),
+                pg_snapshot_xmin(pg_current_snapshot())::text::bigint
+            ) AS tx_id";
+
+        let rows = db.query(query, &[&node_id, &max_age.as_secs_f64()]).await?;
+        let tx_id = rows.first().map(|row| row.get("tx_id")).unwrap_or_default();
+        Ok(DeliveryCursor { tx_id, packet_id: 0 })
+    }
+
+    /// 他のノードが書き込んだ最新のcount個のパケットから読み取る位置 (count個に満たない場合は先頭)
+    pub async fn cursor_before_latest(db: &Database, node_id: i16, count: i64) -> Result<DeliveryCursor, DatabaseError> {
+        let query = "
+            SELECT tx_id, id
+            FROM packets
+            WHERE node_id != $1
+                AND tx_id < pg_snapshot_xmin(pg_current_snapshot())::text::bigint
+            ORDER BY tx_id DESC, id DESC
+            OFFSET $2
+            LIMIT 1";
+
+        let rows = db.query(query, &[&node_id, &count]).await?;
+        Ok(rows
+            .first()
+            .map(|row| DeliveryCursor {
+                tx_id: row.get("tx_id"),
+                packet_id: row.get("id"),
+            })
+            .unwrap_or_default())
+    }
+
+    /// 保存されている読み取り位置
+    pub async fn load_cursor(db: &Database, node_id: i16) -> Result<Option<DeliveryCursor>, DatabaseError> {
+        let rows = db.query("SELECT tx_id, packet_id FROM delivery_cursors WHERE node_id = $1", &[&node_id]).await?;
+        Ok(rows.first().map(|row| DeliveryCursor {
+            tx_id: row.get("tx_id"),
+            packet_id: row.get("packet_id"),
+        }))
+    }
+
+    pub async fn save_cursor(db: &Database, node_id: i16, cursor: DeliveryCursor) -> Result<(), DatabaseError> {
+        let query = "
+            INSERT INTO delivery_cursors (node_id, tx_id, packet_id, updated_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (node_id) DO UPDATE
+            SET tx_id = EXCLUDED.tx_id, packet_id = EXCLUDED.packet_id, updated_at = EXCLUDED.updated_at";
+
+        db.execute(query, &[&node_id, &cursor.tx_id, &cursor.packet_id]).await?;
+        Ok(())
+    }
+
+    /// 保存されている、書き込んだノードごとの受信済みのシーケンス番号
+    pub async fn load_seqs(db: &Database, node_id: i16) -> Result<Vec<ReceivedSeqs>, DatabaseError> {
+        let rows = db.query("SELECT origin_node_id, highest_seq FROM delivery_seqs WHERE node_id = $1", &[&node_id]).await?;
+        let highest = rows.iter().map(|row| (row.get("origin_node_id"), row.get("highest_seq"))).collect();
+        let rows = db.query("SELECT origin_node_id, first_seq, last_seq FROM delivery_seq_ranges WHERE node_id = $1", &[&node_id]).await?;
+        let ranges = rows.iter().map(|row| (row.get("origin_node_id"), row.get("first_seq"), row.get("last_seq"))).collect();
+        Ok(ReceivedSeqs::from_rows(highest, ranges))
+    }
+
+    /// 書き込んだノードごとの受信済みのシーケンス番号を、保存済みの最大値と区間と置き換えて保存する
+    pub async fn save_seqs(db: &Database, node_id: i16, seqs: &[ReceivedSeqs]) -> Result<(), DatabaseError> {
+        if seqs.is_empty() {
+            return Ok(());
+        }
+
+        let origins: Vec<i16> = seqs.iter().map(|received| received.node_id).collect();
+        let highest: Vec<i64> = seqs.iter().map(|received| received.highest).collect();
+        let (range_origins, (firsts, lasts)): (Vec<i16>, (Vec<i64>, Vec<i64>)) = ReceivedSeqs::range_rows(seqs).map(|(origin, first, last)| (origin, (first, last))).unzip();
         db.transaction(|tx| {
             Box::pin(async move {
-                let insert_query = "
-                    INSERT INTO packets (
-                        node_id, timestamp, src_mac, dst_mac, ether_type, ip_protocol,
-                        src_ip, dst_ip, src_port, dst_port, raw_packet
-                    )
-                    SELECT *
-                    FROM (
-                        SELECT
-                            unnest($1::SMALLINT[]) as node_id,
-                            unnest($2::TIMESTAMPTZ[]) as timestamp,
-                            unnest($3::macaddr[]) as src_mac,
-                            unnest($4::macaddr[]) as dst_mac,
-                            unnest($5::INTEGER[]) as ether_type,
-                            unnest($6::INTEGER[]) as ip_protocol,
-                            unnest($7::inet[]) as src_ip,
-                            unnest($8::inet[]) as dst_ip,
-                            unnest($9::INTEGER[]) as src_port,
-                            unnest($10::INTEGER[]) as dst_port,
-                            unnest($11::BYTEA[]) as raw_packet
-                    ) t";
-
-                let node_ids: Vec<i16> = vec![node_id; packets.len()];
-                let timestamps: Vec<DateTime<Utc>> = packets.iter().map(|p| p.timestamp).collect();
-                let src_macs: Vec<MacAddr> = packets.iter().map(|p| p.src_mac.clone()).collect();
-                let dst_macs: Vec<MacAddr> = packets.iter().map(|p| p.dst_mac.clone()).collect();
-                let ether_types: Vec<i32> = packets.iter().map(|p| p.ether_type.as_i32()).collect();
-                let ip_protocols: Vec<i32> = packets.iter().map(|p| p.ip_protocol.as_i32()).collect();
-                let src_ips: Vec<InetAddr> = packets.iter().map(|p| p.src_ip.clone()).collect();
-                let dst_ips: Vec<InetAddr> = packets.iter().map(|p| p.dst_ip.clone()).collect();
-                let src_ports: Vec<i32> = packets.iter().map(|p| p.src_port).collect();
-                let dst_ports: Vec<i32> = packets.iter().map(|p| p.dst_port).collect();
-                let raw_packets: Vec<Vec<u8>> = packets.iter().map(|p| p.raw_packet.clone()).collect();
-
-                debug!("データ挿入開始: パケット数={}, 最初のタイムスタンプ={:?}", packets.len(), timestamps.first());
-
-                let result = tx
-                    .execute(
-                        insert_query,
-                        &[
-                            &node_ids,
-                            &timestamps,
-                            &src_macs,
-                            &dst_macs,
-                            &ether_types,
-                            &ip_protocols,
-                            &src_ips,
-                            &dst_ips,
-                            &src_ports,
-                            &dst_ports,
-                            &raw_packets,
-                        ],
-                    )
-                    .await
-                    .map_err(|e| {
-                        warn!("データ挿入中にエラーが発生: {:?}", e);
-                        DatabaseError::QueryExecutionError(e.to_string())
-                    })?;
-
-                debug!("データ挿入完了: 挿入数={}, 実行時間={}ms", result, start_time.elapsed().as_millis());
-
-                if result as usize != packets.len() {
-                    warn!("期待された挿入数と実際の挿入数が一致しません: expected={}, actual={}", packets.len(), result);
-                    return Err(DatabaseError::QueryExecutionError("Inserted row count mismatch".to_string()));
-                }
+                let query = "
+                    INSERT INTO delivery_seqs (node_id, origin_node_id, highest_seq, updated_at)
+                    SELECT $1, origin_node_id, highest_seq, NOW() FROM UNNEST($2::smallint[], $3::bigint[]) AS s(origin_node_id, highest_seq)
+                    ON CONFLICT (node_id, origin_node_id) DO UPDATE
+                    SET highest_seq = EXCLUDED.highest_seq, updated_at = EXCLUDED.updated_at";
+                tx.execute(query, &[&node_id, &origins, &highest]).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
+
+                let query = "DELETE FROM delivery_seq_ranges WHERE node_id = $1 AND origin_node_id = ANY($2::smallint[])";
+                tx.execute(query, &[&node_id, &origins]).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
 
+                let query = "
+                    INSERT INTO delivery_seq_ranges (node_id, origin_node_id, first_seq, last_seq)
+                    SELECT $1, * FROM UNNEST($2::smallint[], $3::bigint[], $4::bigint[])";
+                tx.execute(query, &[&node_id, &range_origins, &firsts, &lasts]).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
                 Ok(())
             })
         })
         .await
     }
 
-    pub async fn get_filtered_packets(node_id: i16, is_first: bool, last_timestamp: Option<&DateTime<Utc>>) -> Result<Vec<(DateTime<Utc>, Vec<u8>)>, DatabaseError> {
-        let db = Database::get_database();
-        let query = if is_first {
-            "SELECT timestamp, raw_packet FROM packets
-            WHERE node_id != $1 AND timestamp >= NOW() - INTERVAL '4 seconds'
-            ORDER BY timestamp ASC LIMIT 1000"
-        } else {
-            "SELECT p.id, p.timestamp, p.raw_packet
-            FROM packets p
-            LEFT JOIN processed_packets pp ON p.id = pp.packet_id AND pp.node_id = $1
-            WHERE p.node_id != $1
-                AND p.timestamp > $2
-                AND pp.packet_id IS NULL
-            ORDER BY p.timestamp ASC
-            LIMIT 1000"
-        };
+    /// カーソルより後に他のノードが書き込んだパケットを、コミット順に読み取る
+    /// 実行中のトランザクションより前にコミットされた行だけを返すため、後からコミットされた行を読み飛ばすことはない
+    /// 読み取ったパケットと、次に読み取りを始める位置を返す
+    ///
+    /// 実行中の最も古いトランザクション (xmin) が終わるまでは、それより後にコミットされた行も返さない。
+    /// packetsに書き込まないトランザクションでも同じデータベースで長時間実行されていると、その間は読み取りが止まる
+    /// (held_back で読み取れないパケットを確認できる)
+    pub async fn fetch_after(db: &Database, node_id: i16, cursor: DeliveryCursor) -> Result<(Vec<FetchedPacket>, DeliveryCursor), DatabaseError> {
+        let query = "
+            SELECT id, tx_id, node_id, COALESCE(captured_at, timestamp) AS captured_at, raw_packet, key_id, seq, signature
+            FROM packets
+            WHERE node_id != $1
+                AND (tx_id, id) > ($2, $3)
+                AND tx_id < pg_snapshot_xmin(pg_current_snapshot())::text::bigint
+            ORDER BY tx_id, id
+            LIMIT $4";
 
-        let fallback_time = Utc::now() - chrono::Duration::seconds(5);
-        let params: Vec<&(dyn ToSql + Sync)> = if is_first {
-            vec![&node_id]
-        } else {
-            vec![&node_id, last_timestamp.unwrap_or(&fallback_time)]
-        };
+        let rows = db.query(query, &[&node_id, &cursor.tx_id, &cursor.packet_id, &Self::FETCH_LIMIT]).await?;
 
-        let rows = db.query(query, &params).await?;
+        let next = rows
+            .last()
+            .map(|row| DeliveryCursor {
+                tx_id: row.get("tx_id"),
+                packet_id: row.get("id"),
+            })
+            .unwrap_or(cursor);
 
-        // パケットIDを記録
-        if !is_first && !rows.is_empty() {
-            let insert_query = "
-                INSERT INTO processed_packets (packet_id, node_id)
-                VALUES (unnest($1::bigint[]), $2)
-            ";
-            let packet_ids: Vec<i64> = rows.iter().map(|row| row.get("id")).collect();
-            db.execute(insert_query, &[&packet_ids, &node_id]).await?;
-        }
+        Ok((
+            rows.into_iter()
+                .map(|row| FetchedPacket {
+                    node_id: row.get("node_id"),
+                    captured_at: row.get("captured_at"),
+                    raw_packet: row.get("raw_packet"),
+                    key_id: row.get("key_id"),
+                    signature: PacketSignature::from_columns(row.get("seq"), row.get("signature")),
+                })
+                .collect(),
+            next,
+        ))
+    }
 
-        // タイムスタンプとパケットデータのタプルを返す
-        Ok(rows.into_iter().map(|row| (row.get("timestamp"), row.get("raw_packet"))).collect())
+    /// カーソルより後にコミット済みだが、実行中の古いトランザクションがあるため fetch_after が返さないパケット
+    /// FETCH_LIMIT 個まで数え、該当するパケットがない場合はNoneを返す
+    pub async fn held_back(db: &Database, node_id: i16, cursor: DeliveryCursor) -> Result<Option<HeldBackPackets>, DatabaseError> {
+        let query = "
+            SELECT COUNT(*) AS count, MIN(timestamp) AS oldest
+            FROM (
+                SELECT timestamp
+                FROM packets
+                WHERE node_id != $1
+                    AND (tx_id, id) > ($2, $3)
+                    AND tx_id >= pg_snapshot_xmin(pg_current_snapshot())::text::bigint
+                ORDER BY tx_id, id
+                LIMIT $4
+            ) AS held_back";
+
+        let rows = db.query(query, &[&node_id, &cursor.tx_id, &cursor.packet_id, &Self::FETCH_LIMIT]).await?;
+        Ok(rows.first().and_then(|row| {
+            let oldest: Option<DateTime<Utc>> = row.get("oldest");
+            oldest.map(|oldest| HeldBackPackets { count: row.get("count"), oldest })
+        }))
+    }
+
+    /// パケットを書き込んだことを他のノードの読み取りタスクに通知する (ペイロードは書き込んだノードのID)
+    /// NOTIFYはトランザクションのコミット時に配信されるため、書き込みが完了してから呼び出す
+    pub async fn notify_inserted(db: &Database, channel: &str, node_id: i16) -> Result<(), DatabaseError> {
+        db.execute("SELECT pg_notify($1, $2)", &[&channel, &node_id.to_string()]).await?;
+        Ok(())
+    }
+
+    /// 保存済みのパケットを新しい順に検索する
+    pub async fn query_packets(db: &Database, query: &PacketQuery) -> Result<Vec<StoredPacket>, DatabaseError> {
+        let sql = "
+            SELECT id, node_id, timestamp, COALESCE(captured_at, timestamp) AS captured_at, src_mac, dst_mac, ether_type, ip_protocol,
+                   src_ip, dst_ip, src_port, dst_port, raw_packet, key_id, seq, signature
+            FROM packets
+            WHERE ($1::SMALLINT IS NULL OR node_id = $1)
+                AND ($2::TIMESTAMPTZ IS NULL OR timestamp >= $2)
+                AND ($3::TIMESTAMPTZ IS NULL OR timestamp < $3)
+            ORDER BY timestamp DESC, id DESC
+            LIMIT $4";
+
+        let rows = db.query(sql, &[&query.node_id, &query.since, &query.until, &query.limit]).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let ether_type: i32 = row.get("ether_type");
+                let ip_protocol: i32 = row.get("ip_protocol");
+                StoredPacket {
+                    id: row.get("id"),
+                    node_id: row.get("node_id"),
+                    stored_at: row.get("timestamp"),
+                    data: PacketData {
+                        src_mac: row.get("src_mac"),
+                        dst_mac: row.get("dst_mac"),
+                        ether_type: EtherType::new(ether_type as u16),
+                        src_ip: row.get("src_ip"),
+                        dst_ip: row.get("dst_ip"),
+                        src_port: row.get("src_port"),
+                        dst_port: row.get("dst_port"),
+                        ip_protocol: IpProtocol::new(ip_protocol as u8),
+                        captured_at: row.get("captured_at"),
+                        raw_packet: row.get("raw_packet"),
+                        key_id: row.get("key_id"),
+                        signature: PacketSignature::from_columns(row.get("seq"), row.get("signature")),
+                    },
+                }
+            })
+            .collect())
     }
 }
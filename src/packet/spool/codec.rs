@@ -0,0 +1,167 @@
+use crate::packet::spool::error::SpoolError;
+use crate::packet::types::{EtherType, IpProtocol};
+use crate::packet::{InetAddr, MacAddr, PacketData};
+use bytes::{Buf, BufMut};
+use chrono::DateTime;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// フレームのヘッダー (ペイロード長 u32 + CRC32 u32)
+pub const FRAME_HEADER_LEN: usize = 8;
+
+/// フレームのペイロード長の上限 (ファイル形式の定数で、設定に依存しない)
+/// 超えるヘッダーは破損として扱い、1回のフラッシュ分がこれを超える場合は複数のフレームに分けて書き込む
+pub const MAX_PAYLOAD_LEN: usize = 16 * 1024 * 1024;
+
+// ペイロードの先頭 (node_id i16 + パケット数 u32)
+const BATCH_HEADER_LEN: usize = 6;
+
+/// 1回のフラッシュ分のパケットを、ペイロードがMAX_PAYLOAD_LEN以下のフレームに分けてエンコードする
+/// フレーム: [ペイロード長][CRC32][node_id i16][パケット数 u32][パケット...]
+pub fn encode_batch(node_id: i16, packets: &[PacketData]) -> Result<Vec<Vec<u8>>, SpoolError> {
+    let mut frames = Vec::new();
+    let mut start = 0;
+    while start < packets.len() {
+        let mut end = start;
+        let mut len = BATCH_HEADER_LEN;
+        while end < packets.len() && len + packet_len(&packets[end]) <= MAX_PAYLOAD_LEN {
+            len += packet_len(&packets[end]);
+            end += 1;
+        }
+        if end == start {
+            return Err(SpoolError::FrameTooLargeError(packet_len(&packets[start]), MAX_PAYLOAD_LEN - BATCH_HEADER_LEN));
+        }
+        frames.push(encode_frame(node_id, &packets[start..end], len));
+        start = end;
+    }
+    Ok(frames)
+}
+
+fn encode_frame(node_id: i16, packets: &[PacketData], payload_len: usize) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(payload_len);
+    payload.put_i16_le(node_id);
+    payload.put_u32_le(packets.len() as u32);
+
+    for packet in packets {
+        payload.put_i64_le(packet.captured_at.timestamp_micros());
+        payload.put_slice(&packet.src_mac.0);
+        payload.put_slice(&packet.dst_mac.0);
+        payload.put_u16_le(packet.ether_type.value());
+        payload.put_u8(packet.ip_protocol.value());
+        put_ip(&mut payload, &packet.src_ip.0);
+        put_ip(&mut payload, &packet.dst_ip.0);
+        payload.put_i32_le(packet.src_port);
+        payload.put_i32_le(packet.dst_port);
+        payload.put_u32_le(packet.raw_packet.len() as u32);
+        payload.put_slice(&packet.raw_packet);
+    }
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    frame.put_u32_le(payload.len() as u32);
+    frame.put_u32_le(crc32fast::hash(&payload));
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// 1パケットをエンコードした長さ
+fn packet_len(packet: &PacketData) -> usize {
+    8 + 6 + 6 + 2 + 1 + ip_len(&packet.src_ip.0) + ip_len(&packet.dst_ip.0) + 4 + 4 + 4 + packet.raw_packet.len()
+}
+
+fn ip_len(ip: &IpAddr) -> usize {
+    match ip {
+        IpAddr::V4(_) => 1 + 4,
+        IpAddr::V6(_) => 1 + 16,
+    }
+}
+
+/// フレームのヘッダーからペイロード長とCRC32を取り出す
+pub fn decode_header(header: &[u8; FRAME_HEADER_LEN]) -> (usize, u32) {
+    let mut buf = &header[..];
+    (buf.get_u32_le() as usize, buf.get_u32_le())
+}
+
+pub fn verify_payload(payload: &[u8], crc: u32) -> bool {
+    crc32fast::hash(payload) == crc
+}
+
+pub fn decode_batch(mut payload: &[u8]) -> Result<(i16, Vec<PacketData>), SpoolError> {
+    let node_id = get(&mut payload, 2, |b| b.get_i16_le())?;
+    let count = get(&mut payload, 4, |b| b.get_u32_le())? as usize;
+
+    let mut packets = Vec::with_capacity(count.min(65536));
+    for _ in 0..count {
+        let micros = get(&mut payload, 8, |b| b.get_i64_le())?;
+        let captured_at = DateTime::from_timestamp_micros(micros).ok_or_else(|| SpoolError::CorruptedError(format!("不正なタイムスタンプ: {}", micros)))?;
+        let src_mac = MacAddr(get(&mut payload, 6, get_mac)?);
+        let dst_mac = MacAddr(get(&mut payload, 6, get_mac)?);
+        let ether_type = EtherType::new(get(&mut payload, 2, |b| b.get_u16_le())?);
+        let ip_protocol = IpProtocol::new(get(&mut payload, 1, |b| b.get_u8())?);
+        let src_ip = InetAddr(get_ip(&mut payload)?);
+        let dst_ip = InetAddr(get_ip(&mut payload)?);
+        let src_port = get(&mut payload, 4, |b| b.get_i32_le())?;
+        let dst_port = get(&mut payload, 4, |b| b.get_i32_le())?;
+        let raw_len = get(&mut payload, 4, |b| b.get_u32_le())? as usize;
+        let raw_packet = get(&mut payload, raw_len, |b| b.copy_to_bytes(raw_len).to_vec())?;
+
+        packets.push(PacketData {
+            src_mac,
+            dst_mac,
+            ether_type,
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
+            ip_protocol,
+            captured_at,
+            raw_packet,
+            // スプールには暗号化・署名する前のパケットを退避する
+            key_id: None,
+            signature: None,
+        });
+    }
+
+    Ok((node_id, packets))
+}
+
+fn put_ip(buf: &mut Vec<u8>, ip: &IpAddr) {
+    match ip {
+        IpAddr::V4(addr) => {
+            buf.put_u8(4);
+            buf.put_slice(&addr.octets());
+        },
+        IpAddr::V6(addr) => {
+            buf.put_u8(6);
+            buf.put_slice(&addr.octets());
+        },
+    }
+}
+
+fn get_ip(buf: &mut &[u8]) -> Result<IpAddr, SpoolError> {
+    match get(buf, 1, |b| b.get_u8())? {
+        4 => {
+            let mut octets = [0u8; 4];
+            get(buf, 4, |b| b.copy_to_slice(&mut octets))?;
+            Ok(IpAddr::V4(Ipv4Addr::from(octets)))
+        },
+        6 => {
+            let mut octets = [0u8; 16];
+            get(buf, 16, |b| b.copy_to_slice(&mut octets))?;
+            Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+        },
+        tag => Err(SpoolError::CorruptedError(format!("不正なIPアドレスの種別: {}", tag))),
+    }
+}
+
+fn get_mac(buf: &mut &[u8]) -> [u8; 6] {
+    let mut mac = [0u8; 6];
+    buf.copy_to_slice(&mut mac);
+    mac
+}
+
+/// 残りのバイト数を確認してから読み取る (bytesのget_*は不足時にpanicするため)
+fn get<T>(buf: &mut &[u8], len: usize, read: impl FnOnce(&mut &[u8]) -> T) -> Result<T, SpoolError> {
+    if buf.remaining() < len {
+        return Err(SpoolError::CorruptedError("フレームが途中で終わっています".to_string()));
+    }
+    Ok(read(buf))
+}
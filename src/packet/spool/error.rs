@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SpoolError {
+    #[error("スプールのファイル操作に失敗しました: {0}")]
+    IoError(String),
+
+    #[error("スプールのデータが破損しています: {0}")]
+    CorruptedError(String),
+
+    #[error("パケットがスプールのフレームの上限を超えています: {0}バイト > {1}バイト")]
+    FrameTooLargeError(usize, usize),
+
+    #[error("スプールの容量の上限 ({0}バイト) に達しました")]
+    CapacityExceededError(u64),
+
+    #[error("スプールの処理に失敗しました: {0}")]
+    TaskError(String),
+}
+
+impl From<std::io::Error> for SpoolError {
+    fn from(e: std::io::Error) -> Self {
+        SpoolError::IoError(e.to_string())
+    }
+}
@@ -0,0 +1,6 @@
+mod codec;
+mod error;
+mod packet_spool;
+
+pub use error::SpoolError;
+pub use packet_spool::{PacketSpool, ReplayGuard, SpoolBatch, SpoolStats};
@@ -0,0 +1,397 @@
+use crate::config::SpoolConfig;
+use crate::packet::spool::codec::{self, FRAME_HEADER_LEN, MAX_PAYLOAD_LEN};
+use crate::packet::spool::error::SpoolError;
+use crate::packet::PacketData;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OwnedMutexGuard;
+
+// 1つのセグメントファイルの上限 (超えた場合は次のセグメントに書き込む)
+const SEGMENT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_SUFFIX: &str = ".spool";
+// 再送済みの位置 (セグメント番号とオフセット) を記録するファイル
+const OFFSET_FILE: &str = "replay.offset";
+
+/// スプールの使用状況
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpoolStats {
+    /// まだデータベースに再送していないバイト数
+    pub pending_bytes: u64,
+    /// スプールに退避したバッチの累計
+    pub spooled_batches: u64,
+    /// データベースに再送したバッチの累計
+    pub replayed_batches: u64,
+    /// 容量の上限により破棄したバッチの累計
+    pub dropped_batches: u64,
+}
+
+/// スプールから読み出した1回のフラッシュ分のパケット
+pub struct SpoolBatch {
+    pub node_id: i16,
+    pub packets: Vec<PacketData>,
+    position: (u64, u64),
+}
+
+struct SpoolState {
+    directory: PathBuf,
+    /// セグメント番号 -> ファイルサイズ
+    segments: BTreeMap<u64, u64>,
+    /// 追記中のセグメント
+    active: Option<(u64, File)>,
+    /// ここより前は再送済み (セグメント番号, オフセット)
+    ack: (u64, u64),
+    total_bytes: u64,
+}
+
+#[derive(Default)]
+struct SpoolCounters {
+    pending_bytes: AtomicU64,
+    spooled_batches: AtomicU64,
+    replayed_batches: AtomicU64,
+    dropped_batches: AtomicU64,
+}
+
+/// データベースに書き込めなかったパケットを退避する追記専用のファイル
+/// 退避した順番で再送し、再送済みの位置はファイルに記録するため再起動後も続きから再送する
+#[derive(Clone)]
+pub struct PacketSpool {
+    state: Arc<Mutex<SpoolState>>,
+    counters: Arc<SpoolCounters>,
+    max_bytes: u64,
+    retry_interval: Duration,
+    replaying: Arc<AtomicBool>,
+    next_replay: Arc<Mutex<Instant>>,
+    /// 再送待ちの確認とスプールへの追記、再送済みの記録を直列にするロック (スプールを経由しないパケットが再送待ちのパケットを追い越さないようにする)
+    order: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// 再送中であることを表すガード (dropで再送中の状態を解除する)
+pub struct ReplayGuard {
+    replaying: Arc<AtomicBool>,
+}
+
+impl Drop for ReplayGuard {
+    fn drop(&mut self) {
+        self.replaying.store(false, Ordering::Release);
+    }
+}
+
+impl PacketSpool {
+    /// スプールのディレクトリを開き、書き込み途中で終わっているフレームを切り詰める
+    pub fn open(config: &SpoolConfig) -> Result<Self, SpoolError> {
+        fs::create_dir_all(&config.directory)?;
+
+        let ack = Self::read_offset(&config.directory)?;
+        let mut segments = BTreeMap::new();
+        for entry in fs::read_dir(&config.directory)? {
+            let path = entry?.path();
+            let Some(seq) = Self::segment_seq(&path) else { continue };
+
+            if seq < ack.0 {
+                // 再送済みのセグメントが削除される前に終了していた場合
+                fs::remove_file(&path)?;
+                continue;
+            }
+            segments.insert(seq, Self::recover_segment(&path)?);
+        }
+
+        let total_bytes = segments.values().sum();
+        let mut state = SpoolState {
+            directory: config.directory.clone(),
+            segments,
+            active: None,
+            ack,
+            total_bytes,
+        };
+        if let Some(size) = state.segments.get(&ack.0) {
+            state.ack.1 = ack.1.min(*size);
+        }
+
+        let spool = Self {
+            state: Arc::new(Mutex::new(state)),
+            counters: Arc::new(SpoolCounters::default()),
+            max_bytes: config.max_bytes,
+            retry_interval: config.retry_interval,
+            replaying: Arc::new(AtomicBool::new(false)),
+            next_replay: Arc::new(Mutex::new(Instant::now())),
+            order: Arc::new(tokio::sync::Mutex::new(())),
+        };
+        let pending = spool.with_state(|state| Ok(spool.update_pending(state)))?;
+        if pending > 0 {
+            info!("未送信のスプールがあります: {}バイト ({})", pending, config.directory.display());
+        }
+        Ok(spool)
+    }
+
+    /// 再送していないパケットが残っているか
+    pub fn has_pending(&self) -> bool {
+        self.counters.pending_bytes.load(Ordering::Acquire) > 0
+    }
+
+    pub fn stats(&self) -> SpoolStats {
+        SpoolStats {
+            pending_bytes: self.counters.pending_bytes.load(Ordering::Relaxed),
+            spooled_batches: self.counters.spooled_batches.load(Ordering::Relaxed),
+            replayed_batches: self.counters.replayed_batches.load(Ordering::Relaxed),
+            dropped_batches: self.counters.dropped_batches.load(Ordering::Relaxed),
+        }
+    }
+
+    /// パケットをスプールの末尾に追記する (ディスクへの同期まで行う)
+    /// フレームの上限を超える場合は複数のフレームに分けて追記し、再送時はフレームごとに書き込む
+    pub async fn append(&self, node_id: i16, packets: &[PacketData]) -> Result<(), SpoolError> {
+        let frames = codec::encode_batch(node_id, packets)?;
+        let frames_len: u64 = frames.iter().map(|frame| frame.len() as u64).sum();
+        let spool = self.clone();
+
+        tokio::task::spawn_blocking(move || {
+            spool.with_state(|state| {
+                if state.total_bytes + frames_len > spool.max_bytes {
+                    spool.counters.dropped_batches.fetch_add(frames.len() as u64, Ordering::Relaxed);
+                    return Err(SpoolError::CapacityExceededError(spool.max_bytes));
+                }
+
+                for frame in &frames {
+                    let (seq, file) = Self::active_segment(state)?;
+                    file.write_all(frame)?;
+                    file.sync_data()?;
+                    *state.segments.entry(seq).or_insert(0) += frame.len() as u64;
+                    state.total_bytes += frame.len() as u64;
+                }
+                spool.counters.spooled_batches.fetch_add(frames.len() as u64, Ordering::Relaxed);
+                spool.update_pending(state);
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| SpoolError::TaskError(e.to_string()))?
+    }
+
+    /// 再送していない最も古いバッチを読み出す (再送済みのセグメントは削除する)
+    pub async fn next_batch(&self) -> Result<Option<SpoolBatch>, SpoolError> {
+        let spool = self.clone();
+        tokio::task::spawn_blocking(move || spool.with_state(|state| spool.read_next(state))).await.map_err(|e| SpoolError::TaskError(e.to_string()))?
+    }
+
+    /// バッチの再送が完了したことを記録する
+    pub async fn ack(&self, batch: &SpoolBatch) -> Result<(), SpoolError> {
+        let position = batch.position;
+        let spool = self.clone();
+        tokio::task::spawn_blocking(move || {
+            spool.with_state(|state| {
+                state.ack = position;
+                Self::write_offset(&state.directory, position)?;
+                spool.counters.replayed_batches.fetch_add(1, Ordering::Relaxed);
+                spool.update_pending(state);
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| SpoolError::TaskError(e.to_string()))?
+    }
+
+    /// 書き込みの順序を保つロックを取得する
+    /// 再送待ちがあるかの確認からスプールへの追記までと、再送済みの記録の間だけ保持する (データベースへの書き込み中は保持しない)
+    pub async fn lock_order(&self) -> OwnedMutexGuard<()> {
+        Arc::clone(&self.order).lock_owned().await
+    }
+
+    /// 再送を開始できる場合はガードを返す (再送中、または前回の失敗から間隔が空いていない場合はNone)
+    pub fn try_begin_replay(&self) -> Option<ReplayGuard> {
+        if !self.has_pending() {
+            return None;
+        }
+        if Instant::now() < *self.next_replay.lock().ok()? {
+            return None;
+        }
+        if self.replaying.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some(ReplayGuard {
+            replaying: Arc::clone(&self.replaying),
+        })
+    }
+
+    /// 再送に失敗したため、retry_intervalの間は再送を試みない
+    pub fn replay_failed(&self) {
+        if let Ok(mut next_replay) = self.next_replay.lock() {
+            *next_replay = Instant::now() + self.retry_interval;
+        }
+    }
+
+    fn read_next(&self, state: &mut SpoolState) -> Result<Option<SpoolBatch>, SpoolError> {
+        loop {
+            let Some((&seq, &size)) = state.segments.range(state.ack.0..).next() else {
+                return Ok(None);
+            };
+            if seq > state.ack.0 {
+                state.ack = (seq, 0);
+            }
+
+            if state.ack.1 >= size {
+                // 再送済みのセグメントを削除する (追記中の場合は以降の追記を次のセグメントに切り替える)
+                if state.active.as_ref().is_some_and(|(active, _)| *active == seq) {
+                    state.active = None;
+                }
+                fs::remove_file(Self::segment_path(&state.directory, seq))?;
+                state.segments.remove(&seq);
+                state.total_bytes -= size;
+                state.ack = (seq + 1, 0);
+                Self::write_offset(&state.directory, state.ack)?;
+                self.update_pending(state);
+                continue;
+            }
+
+            let path = Self::segment_path(&state.directory, seq);
+            match Self::read_frame(&path, state.ack.1) {
+                Ok((Some(payload), end)) => match codec::decode_batch(&payload) {
+                    Ok((node_id, packets)) => {
+                        return Ok(Some(SpoolBatch {
+                            node_id,
+                            packets,
+                            position: (seq, end),
+                        }))
+                    },
+                    Err(e) => {
+                        warn!("スプールのフレームを読み飛ばします ({} offset={}): {}", path.display(), state.ack.1, e);
+                        state.ack = (seq, end);
+                    },
+                },
+                Ok((None, end)) => {
+                    warn!("CRCが一致しないスプールのフレームを読み飛ばします ({} offset={})", path.display(), state.ack.1);
+                    state.ack = (seq, end);
+                },
+                Err(e) => {
+                    // 起動時の検査後に破損した場合は、セグメントの残りを読み飛ばす
+                    warn!("スプールのセグメントの残りを読み飛ばします ({} offset={}): {}", path.display(), state.ack.1, e);
+                    state.ack = (seq, size);
+                },
+            }
+            Self::write_offset(&state.directory, state.ack)?;
+            self.update_pending(state);
+        }
+    }
+
+    fn active_segment(state: &mut SpoolState) -> Result<(u64, &mut File), SpoolError> {
+        let needs_new = match &state.active {
+            Some((seq, _)) => state.segments.get(seq).copied().unwrap_or(0) >= SEGMENT_MAX_BYTES,
+            None => true,
+        };
+
+        if needs_new {
+            // 再送済みの位置と重ならないよう、記録済みの番号より後の番号を使用する
+            let seq = state.segments.keys().next_back().map_or(0, |seq| seq + 1).max(state.ack.0 + 1);
+            let file = OpenOptions::new().create_new(true).append(true).open(Self::segment_path(&state.directory, seq))?;
+            // 新しいファイルのディレクトリエントリを永続化する
+            File::open(&state.directory)?.sync_all()?;
+            state.segments.insert(seq, 0);
+            state.active = Some((seq, file));
+        }
+
+        let (seq, file) = state.active.as_mut().ok_or_else(|| SpoolError::IoError("追記先のセグメントがありません".to_string()))?;
+        Ok((*seq, file))
+    }
+
+    /// オフセットのフレームを読み出し、ペイロードと次のフレームの位置を返す
+    /// CRCが一致しない場合はペイロードをNoneとして返す (長さは読めるため、次のフレームから読み続けられる)
+    fn read_frame(path: &Path, offset: u64) -> Result<(Option<Vec<u8>>, u64), SpoolError> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        file.seek(SeekFrom::Start(offset))?;
+
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        file.read_exact(&mut header)?;
+        let (len, crc) = codec::decode_header(&header);
+
+        // 破損したヘッダーの長さで確保しないよう、確保する前に上限とファイルの残りのサイズを確認する
+        if len > MAX_PAYLOAD_LEN {
+            return Err(SpoolError::CorruptedError(format!(
+                "フレームの長さが上限を超えています: {}バイト > {}バイト",
+                len, MAX_PAYLOAD_LEN
+            )));
+        }
+        if offset + (FRAME_HEADER_LEN + len) as u64 > size {
+            return Err(SpoolError::CorruptedError("フレームが途中で終わっています".to_string()));
+        }
+
+        let mut payload = vec![0u8; len];
+        file.read_exact(&mut payload)?;
+        let end = offset + (FRAME_HEADER_LEN + len) as u64;
+        Ok((codec::verify_payload(&payload, crc).then_some(payload), end))
+    }
+
+    /// セグメントを先頭から検査し、書き込み途中で終了したフレーム以降を切り詰めてサイズを返す
+    /// CRCが一致しないフレームは切り詰めずに残し、再送時に読み飛ばす (後続の正常なフレームを失わないようにする)
+    fn recover_segment(path: &Path) -> Result<u64, SpoolError> {
+        let size = fs::metadata(path)?.len();
+        let mut offset = 0;
+        while offset < size {
+            match Self::read_frame(path, offset) {
+                Ok((Some(_), end)) => offset = end,
+                Ok((None, end)) => {
+                    warn!("スプールにCRCが一致しないフレームがあります ({} offset={})", path.display(), offset);
+                    offset = end;
+                },
+                Err(e) => {
+                    warn!("スプールの不完全なフレームを切り詰めます ({} offset={}, size={}): {}", path.display(), offset, size, e);
+                    let file = OpenOptions::new().write(true).open(path)?;
+                    file.set_len(offset)?;
+                    file.sync_all()?;
+                    break;
+                },
+            }
+        }
+        Ok(offset)
+    }
+
+    fn update_pending(&self, state: &SpoolState) -> u64 {
+        let replayed = if state.segments.contains_key(&state.ack.0) { state.ack.1 } else { 0 };
+        let pending = state.total_bytes.saturating_sub(replayed);
+        self.counters.pending_bytes.store(pending, Ordering::Release);
+        pending
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut SpoolState) -> Result<T, SpoolError>) -> Result<T, SpoolError> {
+        let mut state = self.state.lock().map_err(|e| SpoolError::TaskError(e.to_string()))?;
+        f(&mut state)
+    }
+
+    fn read_offset(directory: &Path) -> Result<(u64, u64), SpoolError> {
+        let path = directory.join(OFFSET_FILE);
+        if !path.exists() {
+            return Ok((0, 0));
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let mut values = content.split_whitespace().map(|v| v.parse::<u64>());
+        match (values.next(), values.next()) {
+            (Some(Ok(seq)), Some(Ok(offset))) => Ok((seq, offset)),
+            _ => Err(SpoolError::CorruptedError(format!("{}: {}", path.display(), content.trim()))),
+        }
+    }
+
+    /// 一時ファイルに書き込んでから置き換えることで、途中で終了しても壊れないようにする
+    fn write_offset(directory: &Path, (seq, offset): (u64, u64)) -> Result<(), SpoolError> {
+        let tmp = directory.join(format!("{}.tmp", OFFSET_FILE));
+        let mut file = File::create(&tmp)?;
+        writeln!(file, "{} {}", seq, offset)?;
+        file.sync_all()?;
+        fs::rename(&tmp, directory.join(OFFSET_FILE))?;
+        Ok(())
+    }
+
+    fn segment_path(directory: &Path, seq: u64) -> PathBuf {
+        directory.join(format!("{}{:016}{}", SEGMENT_PREFIX, seq, SEGMENT_SUFFIX))
+    }
+
+    fn segment_seq(path: &Path) -> Option<u64> {
+        path.file_name()?.to_str()?.strip_prefix(SEGMENT_PREFIX)?.strip_suffix(SEGMENT_SUFFIX)?.parse().ok()
+    }
+}
@@ -1,7 +1,7 @@
 use bytes::BytesMut;
-use postgres_types::{IsNull, ToSql, Type};
+use postgres_types::{FromSql, IsNull, ToSql, Type};
 use std::error::Error;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 #[derive(Debug, Clone)]
 pub struct InetAddr(pub IpAddr);
@@ -35,3 +35,26 @@ impl ToSql for InetAddr {
         self.to_sql(ty, out)
     }
 }
+
+impl<'a> FromSql<'a> for InetAddr {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        // family, bits, is_cidr, length, address の順
+        if raw.len() < 4 || raw.len() != 4 + raw[3] as usize {
+            return Err("Invalid inet length".into());
+        }
+        let address = &raw[4..];
+        match (raw[0], address.len()) {
+            (2, 4) => Ok(InetAddr(IpAddr::V4(Ipv4Addr::new(address[0], address[1], address[2], address[3])))),
+            (3, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(address);
+                Ok(InetAddr(IpAddr::V6(Ipv6Addr::from(octets))))
+            },
+            _ => Err("Invalid inet family".into()),
+        }
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        ty.name() == "inet"
+    }
+}
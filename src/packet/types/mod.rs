@@ -5,5 +5,5 @@ mod protocol;
 
 pub use inet_addr::InetAddr;
 pub use mac_addr::MacAddr;
-pub use packet::PacketData;
+pub use packet::{FetchedPacket, PacketData, PacketSignature, StoredPacket};
 pub use protocol::{EtherType, IpProtocol};
@@ -1,7 +1,6 @@
 use super::{InetAddr, MacAddr};
 use crate::packet::types::protocol::{EtherType, IpProtocol};
 use chrono::{DateTime, Utc};
-use std::net::IpAddr;
 
 #[derive(Debug, Clone)]
 pub struct PacketData {
@@ -13,20 +12,62 @@ pub struct PacketData {
     pub src_port: i32,
     pub dst_port: i32,
     pub ip_protocol: IpProtocol,
-    pub timestamp: DateTime<Utc>,
+    /// キャプチャしたノードの時計による時刻 (ノード間の順序の比較には使用しない)
+    pub captured_at: DateTime<Utc>,
     pub raw_packet: Vec<u8>,
+    /// raw_packetを暗号化した鍵のID (Noneの場合は平文)
+    pub key_id: Option<i16>,
+    /// 書き込んだノードの署名 (署名しない場合はNone)
+    pub signature: Option<Box<PacketSignature>>,
 }
 
-#[derive(Clone, Debug)]
-pub struct Packet {
-    pub src_mac: MacAddr,
-    pub dst_mac: MacAddr,
-    pub ether_type: i32,
-    pub src_ip: IpAddr,
-    pub dst_ip: IpAddr,
-    pub src_port: Option<i32>,
-    pub dst_port: Option<i32>,
-    pub ip_protocol: i32,
-    pub timestamp: DateTime<Utc>,
+/// データベースに保存されたパケット
+#[derive(Debug, Clone)]
+pub struct StoredPacket {
+    pub id: i64,
+    pub node_id: i16,
+    /// データベースが割り当てた保存時刻
+    pub stored_at: DateTime<Utc>,
+    pub data: PacketData,
+}
+
+/// 他のノードから読み取ったパケット
+#[derive(Debug, Clone)]
+pub struct FetchedPacket {
+    /// 書き込んだノードのID
+    pub node_id: i16,
+    pub captured_at: DateTime<Utc>,
     pub raw_packet: Vec<u8>,
+    /// raw_packetを暗号化した鍵のID (Noneの場合は平文)
+    pub key_id: Option<i16>,
+    pub signature: Option<Box<PacketSignature>>,
+}
+
+/// 書き込んだノードによるパケットの署名
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketSignature {
+    /// 書き込んだノードごとのシーケンス番号
+    pub seq: i64,
+    /// Ed25519の署名
+    pub signature: [u8; 64],
+}
+
+impl PacketSignature {
+    /// 保存された列から復元する (どちらかの列がない・署名の長さが異なる場合は署名なしとして扱う)
+    pub fn from_columns(seq: Option<i64>, signature: Option<Vec<u8>>) -> Option<Box<Self>> {
+        Some(Box::new(Self {
+            seq: seq?,
+            signature: signature?.try_into().ok()?,
+        }))
+    }
+
+    /// seq列に保存する値
+    pub fn seq_column(signature: &Option<Box<Self>>) -> Option<i64> {
+        signature.as_ref().map(|signature| signature.seq)
+    }
+
+    /// signature列に保存する値
+    pub fn signature_column(signature: &Option<Box<Self>>) -> Option<&[u8]> {
+        signature.as_ref().map(|signature| signature.signature.as_slice())
+    }
 }
@@ -6,7 +6,6 @@ use std::error::Error;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct EtherType(u16);
 
-#[allow(dead_code)]
 impl EtherType {
     // 既知のEtherType定数
     pub const IP_V4: EtherType = EtherType(0x0800);
@@ -0,0 +1,34 @@
+use log::debug;
+
+/// 負荷に応じて1回のフラッシュで書き込むパケット数を調整する
+/// 目標値いっぱいまで溜まっていた場合は倍にし、目標値の1/4未満しか溜まっていなかった場合は半分にする
+#[derive(Debug, Clone)]
+pub struct AdaptiveBatchSize {
+    current: usize,
+    min: usize,
+    max: usize,
+}
+
+impl AdaptiveBatchSize {
+    pub fn new(min: usize, max: usize) -> Self {
+        Self { current: min, min, max }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// フラッシュしたパケット数を記録し、次回の目標値を更新する
+    pub fn record(&mut self, flushed: usize) {
+        let previous = self.current;
+        if flushed >= self.current {
+            self.current = self.current.saturating_mul(2).min(self.max);
+        } else if flushed < self.current / 4 {
+            self.current = (self.current / 2).max(self.min);
+        }
+
+        if self.current != previous {
+            debug!("バッチサイズを変更しました: {} -> {}", previous, self.current);
+        }
+    }
+}
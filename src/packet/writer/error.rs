@@ -1,10 +1,17 @@
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names, reason = "エラー型のバリアントは `~Error` で統一している")]
 #[derive(Error, Debug)]
 pub enum WriterError {
     #[error("パケットバッファのフラッシュに失敗しました: {0}")]
     PacketBufferFlushError(String),
 
-    #[error("設定エラー: {0}")]
-    ConfigurationError(String),
+    #[error("スプールへの退避に失敗しました: {0}")]
+    SpoolError(String),
+
+    #[error("パケットの暗号化に失敗しました: {0}")]
+    EncryptionError(String),
+
+    #[error("パケットへの署名に失敗しました: {0}")]
+    SigningError(String),
 }
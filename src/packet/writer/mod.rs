@@ -1,6 +1,8 @@
+mod batch_size;
 mod error;
 mod packet_buffer;
 mod packet_writer;
 
-pub use packet_buffer::PacketBuffer;
+pub use batch_size::AdaptiveBatchSize;
+pub use packet_buffer::{BufferStats, PacketBuffer};
 pub use packet_writer::PacketWriter;
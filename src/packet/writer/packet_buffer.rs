@@ -1,39 +1,235 @@
+use crate::config::{BufferConfig, OverflowPolicy};
 use crate::packet::PacketData;
-use lazy_static::lazy_static;
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
+use tokio::time::{Duration, Instant};
 
-lazy_static! {
-    static ref PACKET_BUFFER: Arc<Mutex<Vec<PacketData>>> = Arc::new(Mutex::new(Vec::new()));
+// パケット破棄の警告を出力する最小間隔
+const DROP_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// バッファの使用状況と、上限による破棄・待機の累計
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BufferStats {
+    pub len: usize,
+    pub bytes: usize,
+    /// パケット数の上限により破棄したパケット数
+    pub dropped_packet_limit: u64,
+    /// バイト数の上限により破棄したパケット数
+    pub dropped_byte_limit: u64,
+    /// 1つでバイト数の上限を超えるため破棄したパケット数
+    pub dropped_oversized: u64,
+    /// block ポリシーでキャプチャを停止した回数
+    pub blocked: u64,
+}
+
+impl BufferStats {
+    pub fn dropped(&self) -> u64 {
+        self.dropped_packet_limit + self.dropped_byte_limit + self.dropped_oversized
+    }
+}
+
+#[derive(Clone, Copy)]
+enum DropReason {
+    PacketLimit,
+    ByteLimit,
+    Oversized,
 }
 
-pub struct PacketBuffer;
+struct BufferState {
+    queue: VecDeque<PacketData>,
+    bytes: usize,
+    last_drop_log: Option<Instant>,
+}
+
+#[derive(Default)]
+struct BufferCounters {
+    dropped_packet_limit: AtomicU64,
+    dropped_byte_limit: AtomicU64,
+    dropped_oversized: AtomicU64,
+    blocked: AtomicU64,
+}
+
+/// キャプチャタスクとライタータスクの間で共有される、上限付きのパケットバッファ
+#[derive(Clone)]
+pub struct PacketBuffer {
+    inner: Arc<Mutex<BufferState>>,
+    config: BufferConfig,
+    counters: Arc<BufferCounters>,
+    // パケット数がflush_thresholdに達した時にライターを起こす
+    notify: Arc<Notify>,
+    flush_threshold: Arc<AtomicUsize>,
+    // block ポリシーで待機しているキャプチャを、取り出し時に起こす
+    space: Arc<Notify>,
+}
 
-#[allow(dead_code)]
 impl PacketBuffer {
+    pub fn new(config: &BufferConfig) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(BufferState {
+                queue: VecDeque::new(),
+                bytes: 0,
+                last_drop_log: None,
+            })),
+            config: config.clone(),
+            counters: Arc::new(BufferCounters::default()),
+            notify: Arc::new(Notify::new()),
+            flush_threshold: Arc::new(AtomicUsize::new(usize::MAX)),
+            space: Arc::new(Notify::new()),
+        }
+    }
+
+    /// パケットを追加する
+    /// 上限に達している場合はoverflow_policyに従って破棄するか、空きができるまで待機する
     pub async fn push(&self, packet: PacketData) {
-        PACKET_BUFFER.lock().await.push(packet);
+        let size = Self::packet_size(&packet);
+        if size > self.config.max_bytes {
+            let mut state = self.inner.lock().await;
+            self.record_drop(&mut state, DropReason::Oversized, 1);
+            return;
+        }
+
+        let mut blocked = false;
+        let len = loop {
+            let space = self.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+
+            let mut state = self.inner.lock().await;
+            match self.overflow_reason(&state, size) {
+                None => {
+                    state.bytes += size;
+                    state.queue.push_back(packet);
+                    break state.queue.len();
+                },
+                Some(reason) => match self.config.overflow_policy {
+                    OverflowPolicy::DropNewest => {
+                        self.record_drop(&mut state, reason, 1);
+                        return;
+                    },
+                    OverflowPolicy::DropOldest => {
+                        let mut reason = Some(reason);
+                        while let Some(current) = reason {
+                            let Some(oldest) = state.queue.pop_front() else { break };
+                            state.bytes -= Self::packet_size(&oldest);
+                            self.record_drop(&mut state, current, 1);
+                            reason = self.overflow_reason(&state, size);
+                        }
+                        state.bytes += size;
+                        state.queue.push_back(packet);
+                        break state.queue.len();
+                    },
+                    OverflowPolicy::Block => {
+                        if !blocked {
+                            blocked = true;
+                            self.counters.blocked.fetch_add(1, Ordering::Relaxed);
+                        }
+                        drop(state);
+                        // 書き込みが進んでいない場合でもフラッシュを促す
+                        self.notify.notify_one();
+                        space.await;
+                    },
+                },
+            }
+        };
+
+        if len >= self.flush_threshold.load(Ordering::Relaxed) {
+            self.notify.notify_one();
+        }
     }
 
     pub async fn drain(&self) -> Vec<PacketData> {
-        let mut buffer = PACKET_BUFFER.lock().await;
-        if buffer.is_empty() {
-            return Vec::new();
+        self.drain_up_to(usize::MAX).await
+    }
+
+    /// 古い順に最大max個のパケットを取り出す
+    pub async fn drain_up_to(&self, max: usize) -> Vec<PacketData> {
+        let packets: Vec<PacketData> = {
+            let mut state = self.inner.lock().await;
+            let count = state.queue.len().min(max);
+            let packets: Vec<PacketData> = state.queue.drain(..count).collect();
+            state.bytes -= packets.iter().map(Self::packet_size).sum::<usize>();
+            packets
+        };
+
+        if !packets.is_empty() {
+            self.space.notify_waiters();
+        }
+        packets
+    }
+
+    /// パケット数がthreshold以上になるまで待機する
+    pub async fn wait_for_len(&self, threshold: usize) {
+        self.flush_threshold.store(threshold, Ordering::Relaxed);
+
+        // 確認とpushの間に通知が失われないよう、確認の前に通知を受け取れる状態にする
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+
+        if self.len().await >= threshold {
+            return;
+        }
+        notified.await;
+    }
+
+    pub async fn stats(&self) -> BufferStats {
+        let state = self.inner.lock().await;
+        BufferStats {
+            len: state.queue.len(),
+            bytes: state.bytes,
+            dropped_packet_limit: self.counters.dropped_packet_limit.load(Ordering::Relaxed),
+            dropped_byte_limit: self.counters.dropped_byte_limit.load(Ordering::Relaxed),
+            dropped_oversized: self.counters.dropped_oversized.load(Ordering::Relaxed),
+            blocked: self.counters.blocked.load(Ordering::Relaxed),
         }
-        buffer.drain(..).collect()
     }
 
     pub async fn len(&self) -> usize {
-        PACKET_BUFFER.lock().await.len()
+        self.inner.lock().await.queue.len()
     }
 
     pub async fn is_empty(&self) -> bool {
-        PACKET_BUFFER.lock().await.is_empty()
+        self.inner.lock().await.queue.is_empty()
+    }
+
+    fn overflow_reason(&self, state: &BufferState, incoming_size: usize) -> Option<DropReason> {
+        if state.queue.len() >= self.config.max_packets {
+            Some(DropReason::PacketLimit)
+        } else if state.bytes + incoming_size > self.config.max_bytes {
+            Some(DropReason::ByteLimit)
+        } else {
+            None
+        }
+    }
+
+    fn record_drop(&self, state: &mut BufferState, reason: DropReason, count: u64) {
+        let counter = match reason {
+            DropReason::PacketLimit => &self.counters.dropped_packet_limit,
+            DropReason::ByteLimit => &self.counters.dropped_byte_limit,
+            DropReason::Oversized => &self.counters.dropped_oversized,
+        };
+        counter.fetch_add(count, Ordering::Relaxed);
+
+        // データベースが停止している間に大量に出力しないよう間隔を空ける
+        let now = Instant::now();
+        if state.last_drop_log.is_none_or(|last| now.duration_since(last) >= DROP_LOG_INTERVAL) {
+            state.last_drop_log = Some(now);
+            warn!(
+                "パケットバッファが上限に達したためパケットを破棄しています (累計: パケット数上限={}, バイト数上限={}, サイズ超過={}, バッファ: {}パケット/{}バイト)",
+                self.counters.dropped_packet_limit.load(Ordering::Relaxed),
+                self.counters.dropped_byte_limit.load(Ordering::Relaxed),
+                self.counters.dropped_oversized.load(Ordering::Relaxed),
+                state.queue.len(),
+                state.bytes
+            );
+        }
     }
-}
 
-impl Default for PacketBuffer {
-    fn default() -> Self {
-        PacketBuffer
+    /// バッファ上のパケット1つあたりのおおよそのメモリ使用量
+    fn packet_size(packet: &PacketData) -> usize {
+        std::mem::size_of::<PacketData>() + packet.raw_packet.len()
     }
 }
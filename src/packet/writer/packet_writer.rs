@@ -1,59 +1,240 @@
-use crate::config::AppConfig;
+use crate::context::AppContext;
 use crate::packet::analysis::{AnalyzeResult, PacketAnalyzer};
-use crate::packet::repository::PacketRepository;
+use crate::packet::writer::batch_size::AdaptiveBatchSize;
 use crate::packet::writer::error::WriterError;
-use crate::packet::writer::PacketBuffer;
-use log::{error, info, trace};
-use tokio::time::{interval, Duration};
-
-const FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+use crate::packet::PacketData;
+use log::{error, info, trace, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{interval, MissedTickBehavior};
 
 pub struct PacketWriter {
-    buffer: PacketBuffer,
+    context: Arc<AppContext>,
+    /// 同時に実行するフラッシュ・再送の数 (permitはフラッシュの完了まで保持する)
+    in_flight: Arc<Semaphore>,
+    /// 停止処理中は新しい再送を開始せず、実行中の再送はバッチの区切りで終了する
+    stopping: Arc<AtomicBool>,
 }
 
-impl Default for PacketWriter {
-    fn default() -> Self {
-        Self { buffer: PacketBuffer::default() }
+impl PacketWriter {
+    pub fn new(context: Arc<AppContext>) -> Self {
+        let in_flight = Arc::new(Semaphore::new(context.config.batching.max_in_flight));
+        Self {
+            context,
+            in_flight,
+            stopping: Arc::new(AtomicBool::new(false)),
+        }
     }
-}
 
-impl PacketWriter {
     pub async fn start(&self) -> Result<(), WriterError> {
-        info!("パケットライターを開始します");
-        let mut interval_timer = interval(FLUSH_INTERVAL);
+        let batching = &self.context.config.batching;
+        info!(
+            "パケットライターを開始します (フラッシュ間隔: {}ms, バッチサイズ: {}~{}, 同時フラッシュ数: {})",
+            batching.flush_interval.as_millis(),
+            batching.min_batch_size,
+            batching.max_batch_size,
+            batching.max_in_flight
+        );
 
-        let config: AppConfig = AppConfig::new().map_err(|e| WriterError::ConfigurationError(e.to_string()))?;
+        let mut batch_size = AdaptiveBatchSize::new(batching.min_batch_size, batching.max_batch_size);
+        let mut interval_timer = interval(batching.flush_interval);
+        interval_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         loop {
-            interval_timer.tick().await;
-            if let Err(e) = self.flush_buffer(config.node_id).await {
-                error!("バッファのフラッシュに失敗しました: {}", e);
+            // 一定時間が経過するか、目標のバッチサイズ分のパケットが溜まったらフラッシュする
+            tokio::select! {
+                _ = interval_timer.tick() => {},
+                _ = self.context.buffer.wait_for_len(batch_size.current()) => {},
+            }
+
+            // スプールがない場合、データベースへのアクセスが停止している間はバッファに溜めたまま待機する
+            if self.context.spool.is_none() {
+                self.context.carrier.health().wait_until_available().await;
+            }
+
+            self.try_spawn_replay();
+
+            // 目標サイズ分のパケットが残っている間は、空いている接続で続けてフラッシュする
+            let mut first = true;
+            loop {
+                let permit = Arc::clone(&self.in_flight).acquire_owned().await.map_err(|e| WriterError::PacketBufferFlushError(e.to_string()))?;
+
+                let target = batch_size.current();
+                let packets = self.context.buffer.drain_up_to(target).await;
+                if packets.is_empty() {
+                    // 空いている間はバッチサイズを小さくする
+                    if first {
+                        batch_size.record(0);
+                    }
+                    break;
+                }
+                first = false;
+
+                let count = packets.len();
+                batch_size.record(count);
+
+                self.spawn_flush(packets, permit);
+                if count < target {
+                    break;
+                }
             }
         }
     }
 
-    async fn flush_buffer(&self, node_id: i16) -> Result<(), WriterError> {
-        let packets = self.buffer.drain().await;
-        if packets.is_empty() {
-            return Ok(());
+    /// 停止時に、バッファに残っているパケットをフラッシュし、実行中のフラッシュの完了を待つ
+    /// 書き込めなかったパケットは通常のフラッシュと同じく、スプールが有効な場合はスプールに退避する
+    pub async fn shutdown(&self) -> Result<(), WriterError> {
+        self.stopping.store(true, Ordering::Release);
+        let batching = &self.context.config.batching;
+
+        let mut remaining = 0;
+        loop {
+            let packets = self.context.buffer.drain_up_to(batching.max_batch_size).await;
+            if packets.is_empty() {
+                break;
+            }
+            remaining += packets.len();
+            let permit = Arc::clone(&self.in_flight).acquire_owned().await.map_err(|e| WriterError::PacketBufferFlushError(e.to_string()))?;
+            self.spawn_flush(packets, permit);
+        }
+
+        // 全てのpermitが返されるまで待つ (実行中の再送はバッチの区切りで終了する)
+        let _all = self.in_flight.acquire_many(batching.max_in_flight as u32).await.map_err(|e| WriterError::PacketBufferFlushError(e.to_string()))?;
+        info!("パケットライターを停止しました (停止時にフラッシュしたパケット: {})", remaining);
+        Ok(())
+    }
+
+    /// フラッシュを別タスクで実行する (permitはフラッシュの完了まで保持する)
+    fn spawn_flush(&self, packets: Vec<PacketData>, permit: OwnedSemaphorePermit) {
+        let context = Arc::clone(&self.context);
+        tokio::spawn(async move {
+            let _permit = permit;
+            if let Err(e) = Self::flush(&context, packets).await {
+                error!("バッファのフラッシュに失敗しました: {}", e);
+            }
+        });
+    }
+
+    async fn flush(context: &AppContext, packets: Vec<PacketData>) -> Result<(), WriterError> {
+        // 再送待ちのパケットがある間は、順番を保つため新しいパケットもスプールに追記する
+        // データベースへのアクセスが停止している間も、書き込みを試みずにスプールに追記する
+        if let Some(spool) = &context.spool {
+            let must_spool = || spool.has_pending() || !context.carrier.health().is_available();
+            if must_spool() {
+                // 確認から追記までの間に再送が完了し、後のフラッシュに追い越されないようにする (ロックはデータベースへの書き込み中は保持しない)
+                let _order = spool.lock_order().await;
+                if must_spool() {
+                    return spool.append(context.node_id(), &packets).await.map_err(|e| WriterError::SpoolError(e.to_string()));
+                }
+            }
         }
 
         let start = std::time::Instant::now();
-        match PacketRepository::bulk_insert(node_id, packets).await {
-            Ok(_) => {
+        match Self::publish(context, context.node_id(), &packets).await {
+            Ok(()) => {
                 let duration = start.elapsed();
-                info!("フラッシュ完了: 処理時間 {}ms", duration.as_millis());
+                info!("フラッシュ完了: {}パケット, 処理時間 {}ms", packets.len(), duration.as_millis());
                 Ok(())
             },
-            Err(e) => Err(WriterError::PacketBufferFlushError(e.to_string())),
+            Err(e) => match &context.spool {
+                Some(spool) => {
+                    warn!("データベースに書き込めなかったため、{}パケットをスプールに退避します: {}", packets.len(), e);
+                    let _order = spool.lock_order().await;
+                    spool.append(context.node_id(), &packets).await.map_err(|e| WriterError::SpoolError(e.to_string()))
+                },
+                None => Err(WriterError::PacketBufferFlushError(e.to_string())),
+            },
+        }
+    }
+
+    /// 媒体にパケットを書き込む (暗号化・署名が有効な場合は、raw_packetを暗号化してから署名する)
+    /// スプールには暗号化・署名する前のパケットを退避し、再送時に暗号化・署名する
+    async fn publish(context: &AppContext, node_id: i16, packets: &[PacketData]) -> Result<(), WriterError> {
+        if context.cipher.is_none() && context.signer.is_none() {
+            return context.carrier.publish(node_id, packets).await.map_err(|e| WriterError::PacketBufferFlushError(e.to_string()));
         }
+
+        let mut prepared = Vec::with_capacity(packets.len());
+        for packet in packets {
+            let packet = match &context.cipher {
+                Some(cipher) => cipher.seal(node_id, packet).map_err(|e| WriterError::EncryptionError(e.to_string()))?,
+                None => packet.clone(),
+            };
+            prepared.push(match &context.signer {
+                Some(signer) => signer.sign(node_id, packet).map_err(|e| WriterError::SigningError(e.to_string()))?,
+                None => packet,
+            });
+        }
+        context.carrier.publish(node_id, &prepared).await.map_err(|e| WriterError::PacketBufferFlushError(e.to_string()))
+    }
+
+    /// スプールに再送待ちのパケットがあり、空いている接続がある場合は再送を開始する
+    fn try_spawn_replay(&self) {
+        let Some(spool) = self.context.spool.clone() else { return };
+        if !self.context.carrier.health().is_available() {
+            return;
+        }
+        let Some(guard) = spool.try_begin_replay() else { return };
+        let Ok(permit) = Arc::clone(&self.in_flight).try_acquire_owned() else { return };
+
+        let context = Arc::clone(&self.context);
+        let stopping = Arc::clone(&self.stopping);
+        tokio::spawn(async move {
+            let _permit = permit;
+            let _guard = guard;
+            let mut replayed = 0;
+
+            loop {
+                // 停止処理中は残りを次回の起動時に再送する
+                if stopping.load(Ordering::Acquire) {
+                    info!("停止するため、スプールの再送を中断します: {}バッチ再送済み", replayed);
+                    break;
+                }
+                let batch = match spool.next_batch().await {
+                    Ok(Some(batch)) => batch,
+                    Ok(None) => {
+                        info!("スプールの再送が完了しました: {}バッチ", replayed);
+                        break;
+                    },
+                    Err(e) => {
+                        error!("スプールの読み出しに失敗しました: {}", e);
+                        spool.replay_failed();
+                        break;
+                    },
+                };
+
+                if let Err(e) = Self::publish(&context, batch.node_id, &batch.packets).await {
+                    warn!(
+                        "スプールの再送に失敗しました ({}ms後に再試行します): {}",
+                        context.config.spool.retry_interval.as_millis(),
+                        e
+                    );
+                    spool.replay_failed();
+                    break;
+                }
+                // 最後のバッチの再送済みの記録と、再送待ちを確認してからの追記が入れ違わないようにする
+                let order = spool.lock_order().await;
+                let acked = spool.ack(&batch).await;
+                drop(order);
+                if let Err(e) = acked {
+                    error!("スプールの再送位置の記録に失敗しました: {}", e);
+                    spool.replay_failed();
+                    break;
+                }
+                replayed += 1;
+            }
+        });
     }
 
     pub async fn process_packet(&self, ethernet_frame: &[u8]) -> Result<(), WriterError> {
-        match PacketAnalyzer::analyze_packet(ethernet_frame).await {
+        match PacketAnalyzer::analyze_packet(ethernet_frame, &self.context.firewall).await {
             AnalyzeResult::Accept(packet_data) => {
-                self.buffer.push(packet_data).await;
+                // 購読者がいない場合は複製しない
+                if self.context.captured.receiver_count() > 0 {
+                    let _ = self.context.captured.send(packet_data.clone());
+                }
+                self.context.buffer.push(packet_data).await;
                 Ok(())
             },
             AnalyzeResult::Reject => {
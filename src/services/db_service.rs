@@ -2,17 +2,82 @@ use crate::database::{Database, ExecuteQuery};
 use crate::packet::analysis::{Filter, IpFirewall, Policy};
 use crate::packet::MacAddr;
 use crate::services::error::ServiceError;
+use chrono::{DateTime, TimeDelta, Utc};
 use log::{error, info, warn};
 use pnet::datalink::NetworkInterface;
 use std::net::IpAddr;
 use std::str::FromStr;
+use std::time::Duration;
+
+/// node_list に登録されたノード
+#[derive(Debug, Clone)]
+pub struct NodeRecord {
+    pub id: i16,
+    pub name: String,
+    pub description: Option<String>,
+    /// node_activity に記録された最後の起動時刻
+    pub last_boot_time: Option<DateTime<Utc>>,
+    /// パケットの署名を検証する公開鍵 (Ed25519、未登録の場合はNone)
+    pub public_key: Option<Vec<u8>>,
+}
+
+/// firewall_settings の1行
+pub(crate) struct FirewallRuleRow {
+    pub filter_type: String,
+    pub filter_value: String,
+    pub priority: i16,
+    pub policy: String,
+}
 
 pub struct DbService;
 
 impl DbService {
-    pub async fn validate_and_record_node(node_id: i16, interface: &NetworkInterface) -> Result<String, ServiceError> {
-        let db = Database::get_database();
+    /// ノードを登録する (既に存在する場合は名前と説明を更新する)
+    pub async fn register_node(db: &Database, node_id: i16, name: &str, description: Option<&str>) -> Result<(), ServiceError> {
+        let query = "
+            INSERT INTO node_list (id, name, description) VALUES ($1, $2, $3)
+            ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, description = EXCLUDED.description
+        ";
+        db.execute(query, &[&node_id, &name, &description]).await?;
+
+        info!("ノード {} ({}) を登録しました", node_id, name);
+        Ok(())
+    }
+
+    /// パケットの署名を検証する公開鍵を登録する (Noneの場合は削除する)
+    pub async fn set_node_key(db: &Database, node_id: i16, public_key: Option<&[u8]>) -> Result<(), ServiceError> {
+        let updated = db.execute("UPDATE node_list SET public_key = $2 WHERE id = $1", &[&node_id, &public_key]).await?;
+        if updated == 0 {
+            return Err(ServiceError::NodeNotFound(node_id));
+        }
+
+        info!("ノード {} の公開鍵を{}しました", node_id, if public_key.is_some() { "登録" } else { "削除" });
+        Ok(())
+    }
+
+    pub async fn list_nodes(db: &Database) -> Result<Vec<NodeRecord>, ServiceError> {
+        let query = "
+            SELECT n.id, n.name, n.description, n.public_key, MAX(a.boot_time) AS last_boot_time
+            FROM node_list n
+            LEFT JOIN node_activity a ON a.node_id = n.id
+            GROUP BY n.id, n.name, n.description, n.public_key
+            ORDER BY n.id
+        ";
+        let rows = db.query(query, &[]).await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| NodeRecord {
+                id: row.get("id"),
+                name: row.get("name"),
+                description: row.get("description"),
+                last_boot_time: row.get("last_boot_time"),
+                public_key: row.get("public_key"),
+            })
+            .collect())
+    }
 
+    pub async fn validate_and_record_node(db: &Database, node_id: i16, interface: &NetworkInterface) -> Result<String, ServiceError> {
         // ノードの存在確認
         let validate_query = "SELECT name FROM node_list WHERE id = $1";
         let rows = db.query(validate_query, &[&node_id]).await?;
@@ -58,53 +123,83 @@ impl DbService {
         Ok(node_name)
     }
 
-    pub async fn load_firewall_settings(node_id: i16) -> Result<IpFirewall, ServiceError> {
-        let db = Database::get_database();
+    /// ノードの時計とデータベースの時計のずれを測定し、閾値を超えていれば警告する
+    /// 正の値はノードの時計が遅れていることを表す (往復時間の半分程度の誤差を含む)
+    pub async fn check_clock_skew(db: &Database, threshold: Duration) -> Result<TimeDelta, ServiceError> {
+        let sent_at = Utc::now();
+        let rows = db.query("SELECT clock_timestamp() AS now", &[]).await?;
+        let received_at = Utc::now();
+        Ok(Self::report_clock_skew(sent_at, rows[0].get("now"), received_at, threshold))
+    }
 
-        // ファイアウォールのポリシー決定（最も優先度の高いもの）
-        let policy_query = "
-            SELECT policy FROM firewall_settings
-            WHERE (node_id = $1 OR node_id IS NULL)
-            ORDER BY priority DESC LIMIT 1
-        ";
+    /// 問い合わせの送信・受信時刻とデータベースが返した時刻から時計のずれを求め、閾値を超えていれば警告する
+    pub(crate) fn report_clock_skew(sent_at: DateTime<Utc>, db_now: DateTime<Utc>, received_at: DateTime<Utc>, threshold: Duration) -> TimeDelta {
+        // 往復時間の中間にデータベースが時刻を取得したとみなす
+        let round_trip = received_at - sent_at;
+        let skew = db_now - (sent_at + round_trip / 2);
 
-        let policy_rows = db.query(policy_query, &[&node_id]).await?;
-        let default_policy = if !policy_rows.is_empty() {
-            let policy_str: String = policy_rows[0].get("policy");
-            match policy_str.to_lowercase().as_str() {
-                "whitelist" => Policy::Whitelist,
-                "blacklist" => Policy::Blacklist,
-                _ => {
-                    info!("未知のポリシー '{}' が指定されました。デフォルトのWhitelistを使用します", policy_str);
-                    Policy::Whitelist
-                },
-            }
+        if skew.abs().to_std().unwrap_or_default() > threshold {
+            warn!(
+                "ノードの時計がデータベースの時計と{}msずれています (閾値: {}ms, 往復時間: {}ms)。キャプチャ時刻 (captured_at) が不正確になります",
+                skew.num_milliseconds(),
+                threshold.as_millis(),
+                round_trip.num_milliseconds()
+            );
         } else {
-            info!("ファイアウォール設定が見つかりませんでした。デフォルトのWhitelistを使用します");
-            Policy::Whitelist
-        };
-
-        // 選択されたポリシーを表示
-        info!("ファイアウォールは {:?} ポリシーモードで動作します", default_policy);
+            info!(
+                "ノードとデータベースの時計のずれ: {}ms (往復時間: {}ms)",
+                skew.num_milliseconds(),
+                round_trip.num_milliseconds()
+            );
+        }
+        skew
+    }
 
-        // すべての設定を取得して、ポリシーの一貫性を確認
-        let all_policies_query = "
-            SELECT DISTINCT policy
+    pub async fn load_firewall_settings(db: &Database, node_id: i16, fallback_policy: Policy) -> Result<IpFirewall, ServiceError> {
+        // 優先度の高い順に取得する (最も優先度の高いルールのポリシーを使用する)
+        let rules_query = "
+            SELECT filter_type, filter_value, priority, policy
             FROM firewall_settings
             WHERE (node_id = $1 OR node_id IS NULL)
+            ORDER BY priority DESC
         ";
 
-        let all_policies = db.query(all_policies_query, &[&node_id]).await?;
+        let rows = db.query(rules_query, &[&node_id]).await?;
+        let rules: Vec<FirewallRuleRow> = rows
+            .iter()
+            .map(|row| FirewallRuleRow {
+                filter_type: row.get("filter_type"),
+                filter_value: row.get("filter_value"),
+                priority: row.get("priority"),
+                policy: row.get("policy"),
+            })
+            .collect();
 
-        // ポリシーの一貫性チェック
-        for row in &all_policies {
-            let policy_str: String = row.get("policy");
-            let current_policy = match policy_str.to_lowercase().as_str() {
-                "whitelist" => Policy::Whitelist,
-                "blacklist" => Policy::Blacklist,
-                _ => continue, // 不明なポリシーはスキップ
-            };
+        Self::build_firewall(node_id, fallback_policy, &rules)
+    }
+
+    /// firewall_settingsの行 (優先度の高い順) からファイアウォールを構築する (PostgreSQL以外の媒体と共通)
+    pub(crate) fn build_firewall(node_id: i16, fallback_policy: Policy, rules: &[FirewallRuleRow]) -> Result<IpFirewall, ServiceError> {
+        // ファイアウォールのポリシー決定（最も優先度の高いもの）
+        let default_policy = match rules.first() {
+            Some(rule) => match Self::parse_policy(&rule.policy) {
+                Some(policy) => policy,
+                None => {
+                    info!("未知のポリシー '{}' が指定されました。デフォルトの{:?}を使用します", rule.policy, fallback_policy);
+                    fallback_policy
+                },
+            },
+            None => {
+                info!("ファイアウォール設定が見つかりませんでした。デフォルトの{:?}を使用します", fallback_policy);
+                fallback_policy
+            },
+        };
 
+        // 選択されたポリシーを表示
+        info!("ファイアウォールは {:?} ポリシーモードで動作します", default_policy);
+
+        // ポリシーの一貫性チェック (不明なポリシーはスキップ)
+        for current_policy in rules.iter().filter_map(|rule| Self::parse_policy(&rule.policy)) {
             // メインポリシーと異なるポリシーが見つかった場合
             if std::mem::discriminant(&current_policy) != std::mem::discriminant(&default_policy) {
                 warn!(
@@ -120,34 +215,59 @@ impl DbService {
 
         let mut firewall = IpFirewall::new(default_policy);
 
-        // ファイアウォールルールの取得
-        let rules_query = "
-            SELECT filter_type, filter_value, priority
-            FROM firewall_settings
-            WHERE (node_id = $1 OR node_id IS NULL)
-            ORDER BY priority DESC
-        ";
-
-        let rule_rows = db.query(rules_query, &[&node_id]).await?;
-        let rules_count = rule_rows.len();
-
-        for row in &rule_rows {
-            let filter_type: String = row.get("filter_type");
-            let filter_value: String = row.get("filter_value");
-            let priority: i16 = row.get("priority");
-
-            if let Some(filter) = Self::parse_filter_rule(&filter_type, &filter_value) {
-                info!("ファイアウォールルールを追加: {:?}, 優先度: {}", filter, priority);
-                firewall.add_rule(filter, priority as u8);
+        for rule in rules {
+            if let Some(filter) = Self::parse_filter_rule(&rule.filter_type, &rule.filter_value) {
+                info!("ファイアウォールルールを追加: {:?}, 優先度: {}", filter, rule.priority);
+                firewall.add_rule(filter, rule.priority as u8);
             } else {
-                error!("ファイアウォールルールの解析に失敗しました: {} = {}", filter_type, filter_value);
+                error!("ファイアウォールルールの解析に失敗しました: {} = {}", rule.filter_type, rule.filter_value);
             }
         }
 
-        info!("ノード {} のファイアウォール設定を {} 個のルールでロードしました", node_id, rules_count);
+        info!("ノード {} のファイアウォール設定を {} 個のルールでロードしました", node_id, rules.len());
         Ok(firewall)
     }
 
+    /// ファイアウォールルールをデータベースに登録し、採番されたIDを返す
+    /// node_idがNoneの場合は全ノード共通のルールになる
+    pub async fn add_firewall_rule(db: &Database, node_id: Option<i16>, filter: &Filter, priority: i16, policy: Policy) -> Result<i32, ServiceError> {
+        let query = "
+            INSERT INTO firewall_settings (node_id, filter_type, filter_value, priority, policy)
+            VALUES ($1, $2, $3, $4, $5) RETURNING id
+        ";
+
+        let rows = db
+            .query(
+                query,
+                &[
+                    &node_id,
+                    &filter.filter_type(),
+                    &filter.filter_value(),
+                    &priority,
+                    &policy.as_str(),
+                ],
+            )
+            .await?;
+        let rule_id: i32 = rows[0].get("id");
+
+        info!("ファイアウォールルールを登録しました: id={}, {:?}, 優先度: {}", rule_id, filter, priority);
+        Ok(rule_id)
+    }
+
+    /// ファイアウォールルールをデータベースから削除する
+    pub async fn remove_firewall_rule(db: &Database, rule_id: i32) -> Result<bool, ServiceError> {
+        let deleted = db.execute("DELETE FROM firewall_settings WHERE id = $1", &[&rule_id]).await?;
+        Ok(deleted > 0)
+    }
+
+    fn parse_policy(policy: &str) -> Option<Policy> {
+        match policy.to_lowercase().as_str() {
+            "whitelist" => Some(Policy::Whitelist),
+            "blacklist" => Some(Policy::Blacklist),
+            _ => None,
+        }
+    }
+
     fn parse_filter_rule(filter_type: &str, filter_value: &str) -> Option<Filter> {
         match filter_type {
             "SrcIpAddress" => IpAddr::from_str(filter_value).ok().map(Filter::SrcIpAddress),
@@ -156,8 +276,8 @@ impl DbService {
             "DstPort" => filter_value.parse::<u16>().ok().map(Filter::DstPort),
             "EtherType" => {
                 // 16進数の場合の処理
-                if filter_value.starts_with("0x") {
-                    u16::from_str_radix(&filter_value[2..], 16).ok().map(Filter::EtherType)
+                if let Some(hex) = filter_value.strip_prefix("0x") {
+                    u16::from_str_radix(hex, 16).ok().map(Filter::EtherType)
                 } else {
                     // 10進数の場合
                     filter_value.parse::<u16>().ok().map(Filter::EtherType)
@@ -17,4 +17,10 @@ pub enum ServiceError {
 
     #[error("ファイアウォールポリシーの不一致: {0}")]
     InconsistentPolicyError(String),
+
+    #[error("ファイアウォールが初期化されていません")]
+    FirewallNotInitialized,
+
+    #[error("保持ポリシーの設定に失敗しました: {0}")]
+    RetentionPolicyError(String),
 }
@@ -1,26 +1,40 @@
-use crate::packet::analysis::{FirewallPacket, IpFirewall};
-use crate::services::db_service::DbService;
+use crate::carrier::{Carrier, CarrierError};
+use crate::config::FirewallConfig;
+use crate::packet::analysis::{Filter, FirewallPacket, IpFirewall};
 use crate::services::error::ServiceError;
 use log::{error, info, warn};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-lazy_static::lazy_static! {
-    static ref DYNAMIC_FIREWALL: Arc<RwLock<Option<IpFirewall>>> = Arc::new(RwLock::new(None));
+#[derive(Clone, Default)]
+pub struct FirewallService {
+    firewall: Arc<RwLock<Option<IpFirewall>>>,
 }
 
-pub struct FirewallService;
-
 impl FirewallService {
-    pub async fn initialize(node_id: i16) -> Result<(), ServiceError> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// データベースを経由せずにルールを指定して作成する
+    pub fn with_firewall(firewall: IpFirewall) -> Self {
+        Self {
+            firewall: Arc::new(RwLock::new(Some(firewall))),
+        }
+    }
+
+    pub async fn initialize(&self, carrier: &dyn Carrier, node_id: i16, config: &FirewallConfig) -> Result<(), CarrierError> {
         info!("ノード {} のファイアウォール設定を初期化しています...", node_id);
 
-        // データベースからファイアウォール設定を取得
-        match DbService::load_firewall_settings(node_id).await {
+        // 媒体からファイアウォール設定を取得
+        match carrier.load_firewall(node_id, config.default_policy).await {
             Ok(firewall) => {
-                // グローバルファイアウォールインスタンスを更新
-                info!("ファイアウォール設定を読み込みました: {:?}", firewall);
-                let mut fw = DYNAMIC_FIREWALL.write().await;
+                info!(
+                    "ファイアウォール設定を読み込みました: ポリシー={:?}, ルール数={}",
+                    firewall.get_policy(),
+                    firewall.rules_count()
+                );
+                let mut fw = self.firewall.write().await;
                 *fw = Some(firewall);
 
                 info!("ファイアウォールの初期化が完了しました");
@@ -33,8 +47,8 @@ impl FirewallService {
         }
     }
 
-    pub async fn check_packet(packet: &FirewallPacket) -> bool {
-        let fw = DYNAMIC_FIREWALL.read().await;
+    pub async fn check_packet(&self, packet: &FirewallPacket) -> bool {
+        let fw = self.firewall.read().await;
 
         match &*fw {
             Some(firewall) => firewall.check(packet),
@@ -44,4 +58,37 @@ impl FirewallService {
             },
         }
     }
+
+    /// 現在のファイアウォール設定のコピーを取得する
+    pub async fn current(&self) -> Option<IpFirewall> {
+        self.firewall.read().await.clone()
+    }
+
+    /// ファイアウォール設定を丸ごと置き換える
+    pub async fn replace(&self, firewall: IpFirewall) {
+        *self.firewall.write().await = Some(firewall);
+    }
+
+    /// 実行中のファイアウォールにルールを追加する (データベースには保存しない)
+    pub async fn add_rule(&self, filter: Filter, priority: u8) -> Result<(), ServiceError> {
+        match &mut *self.firewall.write().await {
+            Some(firewall) => {
+                info!("ファイアウォールルールを追加: {:?}, 優先度: {}", filter, priority);
+                firewall.add_rule(filter, priority);
+                Ok(())
+            },
+            None => Err(ServiceError::FirewallNotInitialized),
+        }
+    }
+
+    /// 実行中のファイアウォールからルールを削除する (データベースには反映しない)
+    pub async fn remove_rule(&self, filter: &Filter) -> Result<Option<u8>, ServiceError> {
+        match &mut *self.firewall.write().await {
+            Some(firewall) => {
+                info!("ファイアウォールルールを削除: {:?}", filter);
+                Ok(firewall.remove_rule(filter))
+            },
+            None => Err(ServiceError::FirewallNotInitialized),
+        }
+    }
 }
@@ -1,6 +1,10 @@
 mod db_service;
 mod error;
 mod firewall_service;
+mod retention_service;
 
-pub use db_service::DbService;
+pub(crate) use db_service::FirewallRuleRow;
+pub use db_service::{DbService, NodeRecord};
+pub use error::ServiceError;
 pub use firewall_service::FirewallService;
+pub use retention_service::{PurgeReport, RetentionService};
@@ -0,0 +1,205 @@
+use crate::config::RetentionConfig;
+use crate::database::{Database, ExecuteQuery, PacketPartitions, Schema};
+use crate::services::error::ServiceError;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{debug, info, warn};
+
+/// 1回の削除の結果
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PurgeReport {
+    /// 削除したpacketsのチャンク・パーティションの数
+    pub dropped_chunks: u64,
+    /// 行単位で削除したpacketsの数 (チャンク単位で削除した分は含まない)
+    pub deleted_packets: u64,
+    pub deleted_activities: u64,
+}
+
+// 1回の削除でサイズの上限のために削除するチャンクの最大数 (残りは次回の削除で続ける)
+const MAX_DROPPED_CHUNKS_PER_PURGE: u64 = 100;
+
+pub struct RetentionService;
+
+impl RetentionService {
+    /// packetsの保持期間をTimescaleDBの保持ポリシーとして登録し、設定と一致しているか確認する
+    /// TimescaleDBがない場合は、ノードの定期削除 (purge) とパーティションの削除で保持期間を適用する
+    pub async fn install(db: &Database, config: &RetentionConfig) -> Result<(), ServiceError> {
+        if !Self::is_hypertable(db).await? {
+            if PacketPartitions::is_partitioned(db).await? {
+                info!("packetsはパーティションテーブルのため、保持期間はパーティションの削除で適用します");
+            } else {
+                info!("packetsがハイパーテーブルではないため、保持期間はノードの定期削除で適用します");
+                if config.packets_max_size.is_some() {
+                    warn!("retention.packets_max_size_mb はハイパーテーブルかパーティションテーブルでのみ有効です");
+                }
+            }
+            return Ok(());
+        }
+
+        let current = Self::retention_policy(db).await?;
+        match (config.packets_max_age, current) {
+            (None, None) => {},
+            (None, Some(_)) => {
+                db.execute("SELECT remove_retention_policy('packets', if_exists => true)", &[]).await?;
+                info!("packetsの保持ポリシーを削除しました (保持期間: 無期限)");
+            },
+            (Some(max_age), current) => {
+                let max_age_secs = max_age.as_secs_f64();
+                if current.is_none_or(|secs| secs != max_age_secs) {
+                    db.execute("SELECT remove_retention_policy('packets', if_exists => true)", &[]).await?;
+                    db.execute("SELECT add_retention_policy('packets', drop_after => make_interval(secs => $1))", &[&max_age_secs]).await?;
+                    info!("packetsの保持ポリシーを登録しました (保持期間: {}時間)", max_age.as_secs() / 3600);
+                }
+            },
+        }
+
+        // 登録した内容を読み直して確認する
+        let installed = Self::retention_policy(db).await?;
+        if installed != config.packets_max_age.map(|max_age| max_age.as_secs_f64()) {
+            return Err(ServiceError::RetentionPolicyError(format!(
+                "packetsの保持ポリシーが設定と一致しません (設定: {:?}, 登録済み: {:?}秒)",
+                config.packets_max_age, installed
+            )));
+        }
+        debug!("packetsの保持ポリシーを確認しました: {:?}秒", installed);
+        Ok(())
+    }
+
+    /// 保持期間・サイズを超えたデータを削除する
+    pub async fn purge(db: &Database, config: &RetentionConfig) -> Result<PurgeReport, ServiceError> {
+        let mut report = PurgeReport::default();
+        let hypertable = Self::is_hypertable(db).await?;
+        let partitioned = !hypertable && PacketPartitions::is_partitioned(db).await?;
+
+        if let Some(max_age) = config.packets_max_age {
+            let max_age_secs = max_age.as_secs_f64();
+            if hypertable {
+                let rows = db
+                    .query(
+                        "SELECT COUNT(*) AS dropped FROM drop_chunks('packets', older_than => make_interval(secs => $1))",
+                        &[&max_age_secs],
+                    )
+                    .await?;
+                report.dropped_chunks += rows[0].get::<_, i64>("dropped") as u64;
+            } else if partitioned {
+                // パーティション単位で削除し、どのパーティションにも入らなかった行だけを行単位で削除する
+                let max_age = TimeDelta::from_std(max_age).map_err(|e| ServiceError::RetentionPolicyError(e.to_string()))?;
+                report.dropped_chunks += PacketPartitions::drop_expired(db, max_age).await?.len() as u64;
+                let query = format!("DELETE FROM {} WHERE timestamp < NOW() - make_interval(secs => $1)", PacketPartitions::DEFAULT_PARTITION);
+                report.deleted_packets += db.execute(&query, &[&max_age_secs]).await?;
+            } else {
+                report.deleted_packets += db.execute("DELETE FROM packets WHERE timestamp < NOW() - make_interval(secs => $1)", &[&max_age_secs]).await?;
+            }
+        }
+
+        for (node_id, max_age) in &config.node_max_age {
+            // 全体の保持期間で削除される範囲は対象外
+            if config.packets_max_age.is_some_and(|global| global <= *max_age) {
+                continue;
+            }
+            let query = "DELETE FROM packets WHERE node_id = $1 AND timestamp < NOW() - make_interval(secs => $2)";
+            report.deleted_packets += db.execute(query, &[node_id, &max_age.as_secs_f64()]).await?;
+        }
+
+        if let Some(max_size) = config.packets_max_size {
+            if hypertable {
+                report.dropped_chunks += Self::drop_chunks_over(db, max_size).await?;
+            } else if partitioned {
+                report.dropped_chunks += PacketPartitions::drop_oldest_over(db, max_size).await?.len() as u64;
+            } else {
+                debug!("packetsがハイパーテーブル・パーティションテーブルではないため、サイズによる削除を行いません");
+            }
+        }
+
+        if let Some(max_age) = config.node_activity_max_age {
+            // 最終起動時刻が分からなくならないよう、ノードごとの最新の記録は残す
+            let query = "
+                DELETE FROM node_activity
+                WHERE boot_time < NOW() - make_interval(secs => $1)
+                    AND id NOT IN (SELECT MAX(id) FROM node_activity GROUP BY node_id)";
+            report.deleted_activities += db.execute(query, &[&max_age.as_secs_f64()]).await?;
+        }
+
+        if report.dropped_chunks > 0 || report.deleted_packets > 0 || report.deleted_activities > 0 {
+            info!(
+                "保持期間を超えたデータを削除しました (packets: {}チャンク/{}行, node_activity: {}行)",
+                report.dropped_chunks, report.deleted_packets, report.deleted_activities
+            );
+        }
+        Ok(report)
+    }
+
+    /// packetsのサイズが上限以下になるまで古いチャンクから削除する (書き込み中の最新のチャンクは残す)
+    /// 1回に削除するのは MAX_DROPPED_CHUNKS_PER_PURGE 個までで、チャンクを削除できなかった場合はそこで止める
+    async fn drop_chunks_over(db: &Database, max_size: u64) -> Result<u64, ServiceError> {
+        let mut dropped = 0;
+        loop {
+            if dropped >= MAX_DROPPED_CHUNKS_PER_PURGE {
+                warn!("packetsのサイズの上限のために{}個のチャンクを削除しました。残りは次回の削除で続けます", dropped);
+                return Ok(dropped);
+            }
+
+            let rows = db.query("SELECT hypertable_size('packets') AS size", &[]).await?;
+            let size = rows[0].get::<_, Option<i64>>("size").unwrap_or(0) as u64;
+            if size <= max_size {
+                return Ok(dropped);
+            }
+
+            let query = "
+                SELECT range_end
+                FROM timescaledb_information.chunks
+                WHERE hypertable_name = 'packets'
+                ORDER BY range_start
+                LIMIT 2";
+            let chunks = db.query(query, &[]).await?;
+            if chunks.len() < 2 {
+                warn!(
+                    "packetsのサイズ ({}バイト) が上限 ({}バイト) を超えていますが、最新のチャンクのみのため削除しません",
+                    size, max_size
+                );
+                return Ok(dropped);
+            }
+
+            let oldest_end: DateTime<Utc> = chunks[0].get("range_end");
+            let removed = db.query("SELECT drop_chunks('packets', older_than => $1::TIMESTAMPTZ)", &[&oldest_end]).await?;
+            if removed.is_empty() {
+                // 圧縮中などで削除されなかった場合に、同じチャンクの削除を繰り返さない
+                warn!(
+                    "packetsのサイズ ({}バイト) が上限 ({}バイト) を超えていますが、{}より前のチャンクを削除できませんでした",
+                    size, max_size, oldest_end
+                );
+                return Ok(dropped);
+            }
+            info!(
+                "packetsのサイズ ({}バイト) が上限 ({}バイト) を超えたため、{}より前のチャンクを削除しました",
+                size, max_size, oldest_end
+            );
+            dropped += removed.len() as u64;
+        }
+    }
+
+    /// packetsがTimescaleDBのハイパーテーブルか
+    async fn is_hypertable(db: &Database) -> Result<bool, ServiceError> {
+        // 拡張機能がない場合はtimescaledb_informationを参照できないため、先に確認する
+        if !Schema::timescale_installed(db).await? {
+            return Ok(false);
+        }
+
+        let rows = db
+            .query(
+                "SELECT EXISTS (SELECT 1 FROM timescaledb_information.hypertables WHERE hypertable_name = 'packets') AS hypertable",
+                &[],
+            )
+            .await?;
+        Ok(rows[0].get("hypertable"))
+    }
+
+    /// 登録されているpacketsの保持期間 (秒)
+    async fn retention_policy(db: &Database) -> Result<Option<f64>, ServiceError> {
+        let query = "
+            SELECT EXTRACT(EPOCH FROM (config->>'drop_after')::INTERVAL)::FLOAT8 AS drop_after
+            FROM timescaledb_information.jobs
+            WHERE proc_name = 'policy_retention' AND hypertable_name = 'packets'";
+        let rows = db.query(query, &[]).await?;
+        Ok(rows.first().and_then(|row| row.get("drop_after")))
+    }
+}
@@ -1,5 +1,6 @@
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names, reason = "エラー型のバリアントは `~Error` で統一している")]
 #[derive(Error, Debug)]
 pub enum TaskError {
     #[error("タスク実行エラー: {0}")]
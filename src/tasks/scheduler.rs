@@ -1,18 +1,21 @@
 use super::TaskState;
+use crate::context::AppContext;
+use crate::database::{PacketPartitions, StatementCacheStats};
 use crate::packet::monitor::NetworkMonitor;
 use crate::packet::reader::PacketReader;
 use crate::packet::writer::PacketWriter;
 use crate::tasks::error::TaskError;
 use crate::tasks::task_monitor::TaskMonitor;
-use log::info;
+use chrono::TimeDelta;
+use log::{info, warn};
 use pnet::datalink::NetworkInterface;
 use std::sync::Arc;
 use tokio::sync::{broadcast, Mutex, Semaphore};
 use tokio::task::JoinHandle;
 use tokio::time::Duration;
 
-// タイムアウトを延長
-const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+// 停止時にバッファに残っているパケットのフラッシュを待つため、接続の取得のタイムアウトより長くする
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 // 同時実行数の制限
 const MAX_CONCURRENT_TASKS: usize = 3;
 
@@ -25,28 +28,139 @@ struct TaskHandles {
 pub struct TaskScheduler {
     task_state: Arc<Mutex<TaskState>>,
     shutdown_tx: broadcast::Sender<()>,
+    context: Arc<AppContext>,
     interface: NetworkInterface,
     semaphore: Arc<Semaphore>,
 }
 
 impl TaskScheduler {
-    pub fn new(interface: NetworkInterface) -> Self {
+    pub fn new(context: Arc<AppContext>, interface: NetworkInterface) -> Self {
         let (shutdown_tx, _) = broadcast::channel(1);
         Self {
             task_state: Arc::new(Mutex::new(TaskState::new())),
             shutdown_tx,
+            context,
             interface,
             semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_TASKS)),
         }
     }
 
+    /// 実行中のタスクを停止させるための送信側を取得する
+    pub fn shutdown_sender(&self) -> broadcast::Sender<()> {
+        self.shutdown_tx.clone()
+    }
+
     pub async fn run(&self) -> Result<(), TaskError> {
         info!("タスクスケジューラを起動しています");
         let monitor = TaskMonitor::new(self.task_state.clone(), SHUTDOWN_TIMEOUT);
 
         let handles = self.spawn_all_tasks().await;
+        let stats_task = self.spawn_pool_stats_task();
+        let retention_task = self.spawn_retention_task();
+        let partition_task = self.spawn_partition_task();
 
-        monitor.monitor_tasks(handles.reader, handles.writer, handles.analysis, self.shutdown_tx.subscribe()).await
+        let result = monitor.monitor_tasks(handles.reader, handles.writer, handles.analysis, self.shutdown_tx.subscribe()).await;
+
+        if let Some(handle) = stats_task {
+            handle.abort();
+        }
+        retention_task.abort();
+        partition_task.abort();
+        result
+    }
+
+    /// データベースの接続状態・接続プール・パケットバッファの統計情報を定期的にログに出力する (監視対象外の補助タスク)
+    fn spawn_pool_stats_task(&self) -> Option<JoinHandle<()>> {
+        let interval = self.context.config.database.pool.stats_interval?;
+        let context = Arc::clone(&self.context);
+
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            // 最初のtickは即座に完了するため読み飛ばす
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                // データベースを使わない媒体の場合は接続プール・キャッシュの統計情報がない
+                let (pool, cache) = match context.carrier.database() {
+                    Some(db) => (db.pool_stats().to_string(), db.statement_cache_stats()),
+                    None => ("-".to_string(), StatementCacheStats::default()),
+                };
+                let buffer = context.buffer.stats().await;
+                let health = context.carrier.health().stats();
+                let delivery = context.delivery.stats();
+                info!(
+                    "データベース: {} (連続失敗={}, 遮断={}), 接続プール: {}, ステートメントキャッシュ: hit={}, miss={}, バッファ: {}パケット/{}バイト (破棄={}, 停止={}), 送信: {}/{}パケット (損失={})",
+                    health.state,
+                    health.consecutive_failures,
+                    health.trips,
+                    pool,
+                    cache.hits,
+                    cache.misses,
+                    buffer.len,
+                    buffer.bytes,
+                    buffer.dropped(),
+                    buffer.blocked,
+                    delivery.sent,
+                    delivery.fetched,
+                    delivery.lost()
+                );
+            }
+        }))
+    }
+
+    /// 保持期間・サイズを超えたデータを起動時とpurge_intervalごとに削除する (監視対象外の補助タスク)
+    fn spawn_retention_task(&self) -> JoinHandle<()> {
+        let context = Arc::clone(&self.context);
+
+        tokio::spawn(async move {
+            let retention = &context.config.retention;
+            loop {
+                context.carrier.health().wait_until_available().await;
+                if let Err(e) = context.carrier.purge(retention).await {
+                    warn!("保持期間を超えたデータの削除に失敗しました: {}", e);
+                }
+
+                match retention.purge_interval {
+                    Some(interval) => tokio::time::sleep(interval).await,
+                    None => break,
+                }
+            }
+        })
+    }
+
+    /// TimescaleDBがない場合に、先のパーティションの作成と保持期間を過ぎたパーティションの削除を定期的に行う (監視対象外の補助タスク)
+    fn spawn_partition_task(&self) -> JoinHandle<()> {
+        let context = Arc::clone(&self.context);
+
+        tokio::spawn(async move {
+            let partition = &context.config.partition;
+            let max_age = context.config.retention.packets_max_age.and_then(|max_age| TimeDelta::from_std(max_age).ok());
+            // パーティションはPostgreSQLの媒体にのみある
+            let Some(db) = context.carrier.database() else { return };
+            loop {
+                tokio::time::sleep(partition.maintenance_interval).await;
+                db.health().wait_until_available().await;
+
+                match PacketPartitions::is_partitioned(db).await {
+                    Ok(true) => {},
+                    // TimescaleDBのハイパーテーブルなどパーティションテーブルでない場合は何もしない
+                    Ok(false) => break,
+                    Err(e) => {
+                        warn!("パーティションの確認に失敗しました: {}", e);
+                        continue;
+                    },
+                }
+
+                if let Err(e) = PacketPartitions::create_upcoming(db, partition).await {
+                    warn!("パーティションの作成に失敗しました: {}", e);
+                }
+                if let Some(max_age) = max_age {
+                    if let Err(e) = PacketPartitions::drop_expired(db, max_age).await {
+                        warn!("保持期間を過ぎたパーティションの削除に失敗しました: {}", e);
+                    }
+                }
+            }
+        })
     }
 
     async fn spawn_all_tasks(&self) -> TaskHandles {
@@ -58,6 +172,7 @@ impl TaskScheduler {
     }
 
     async fn spawn_reader_task(&self) -> JoinHandle<Result<(), String>> {
+        let context = Arc::clone(&self.context);
         let interface = self.interface.clone();
         let mut shutdown_rx = self.shutdown_tx.subscribe();
         let semaphore = Arc::clone(&self.semaphore);
@@ -71,7 +186,7 @@ impl TaskScheduler {
             tokio::select! {
                 result = async move {
                     info!("パケットのデータベース読み取りタスクを起動しました");
-                    PacketReader::start(interface).await
+                    PacketReader::start(context, interface).await
                 } => {
                     result.map_err(|e| e.to_string())
                 }
@@ -85,7 +200,7 @@ impl TaskScheduler {
 
     async fn spawn_writer_task(&self) -> JoinHandle<Result<(), String>> {
         let mut shutdown_rx = self.shutdown_tx.subscribe();
-        let writer = PacketWriter::default();
+        let writer = PacketWriter::new(Arc::clone(&self.context));
         let semaphore = Arc::clone(&self.semaphore);
 
         tokio::spawn(async move {
@@ -102,14 +217,16 @@ impl TaskScheduler {
                     result.map_err(|e| e.to_string())
                 }
                 _ = shutdown_rx.recv() => {
-                    info!("パケットのデータベース書き込みタスクを停止させました");
-                    Ok(())
+                    // バッファに残っているパケットと実行中のフラッシュを失わないよう、書き込みを終えてから停止する
+                    info!("パケットのデータベース書き込みタスクを停止しています");
+                    writer.shutdown().await.map_err(|e| e.to_string())
                 }
             }
         })
     }
 
     async fn spawn_analysis_task(&self) -> JoinHandle<Result<(), String>> {
+        let context = Arc::clone(&self.context);
         let interface = self.interface.clone();
         let mut shutdown_rx = self.shutdown_tx.subscribe();
         let semaphore = Arc::clone(&self.semaphore);
@@ -123,7 +240,7 @@ impl TaskScheduler {
             tokio::select! {
                 result = async {
                     info!("パケットの収集・解析タスクを起動しました");
-                    NetworkMonitor::start(interface).await
+                    NetworkMonitor::start(context, interface).await
                 } => {
                     result.map_err(|e| e.to_string())
                 }
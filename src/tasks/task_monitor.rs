@@ -4,9 +4,7 @@ use log::{debug, error, info};
 use std::sync::Arc;
 use tokio::sync::{broadcast, Mutex};
 use tokio::task::JoinHandle;
-use tokio::time::{sleep, Duration};
-
-const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+use tokio::time::{timeout_at, Duration, Instant};
 
 pub struct TaskMonitor {
     task_state: Arc<Mutex<TaskState>>,
@@ -20,9 +18,9 @@ impl TaskMonitor {
 
     pub async fn monitor_tasks(
         &self,
-        reader: JoinHandle<Result<(), String>>,
-        writer: JoinHandle<Result<(), String>>,
-        analysis: JoinHandle<Result<(), String>>,
+        mut reader: JoinHandle<Result<(), String>>,
+        mut writer: JoinHandle<Result<(), String>>,
+        mut analysis: JoinHandle<Result<(), String>>,
         mut shutdown_rx: broadcast::Receiver<()>,
     ) -> Result<(), TaskError> {
         // 初期状態の設定
@@ -30,36 +28,38 @@ impl TaskMonitor {
         self.update_task_state("writer", true).await.map_err(|e| TaskError::TaskExecutionError(e.to_string()))?;
         self.update_task_state("analysis", true).await.map_err(|e| TaskError::TaskExecutionError(e.to_string()))?;
 
-        let result = loop {
-            tokio::select! {
-                result = reader => {
-                    if let Err(e) = self.handle_task_result(result, "reader").await {
-                        break Err(TaskError::TaskExecutionError(e.to_string()));
-                    }
-                    break Err(TaskError::TaskExecutionError("Reader task unexpectedly terminated".into()));
-                }
-                result = writer => {
-                    if let Err(e) = self.handle_task_result(result, "writer").await {
-                        break Err(TaskError::TaskExecutionError(e.to_string()));
-                    }
-                    break Err(TaskError::TaskExecutionError("Writer task unexpectedly terminated".into()));
+        let result = tokio::select! {
+            result = &mut reader => {
+                match self.handle_task_result(result, "reader").await {
+                    Err(e) => Err(TaskError::TaskExecutionError(e.to_string())),
+                    Ok(_) => Err(TaskError::TaskExecutionError("Reader task unexpectedly terminated".into())),
                 }
-                result = analysis => {
-                    if let Err(e) = self.handle_task_result(result, "analysis").await {
-                        break Err(TaskError::TaskExecutionError(e.to_string()));
-                    }
-                    break Err(TaskError::TaskExecutionError("Analysis task unexpectedly terminated".into()));
+            }
+            result = &mut writer => {
+                match self.handle_task_result(result, "writer").await {
+                    Err(e) => Err(TaskError::TaskExecutionError(e.to_string())),
+                    Ok(_) => Err(TaskError::TaskExecutionError("Writer task unexpectedly terminated".into())),
                 }
-                _ = shutdown_rx.recv() => {
-                    info!("Received shutdown signal");
-                    match self.wait_for_shutdown().await {
-                        Ok(_) => break Ok(()),
-                        Err(e) => break Err(TaskError::TaskExecutionError(e.to_string())),
-                    }
+            }
+            result = &mut analysis => {
+                match self.handle_task_result(result, "analysis").await {
+                    Err(e) => Err(TaskError::TaskExecutionError(e.to_string())),
+                    Ok(_) => Err(TaskError::TaskExecutionError("Analysis task unexpectedly terminated".into())),
                 }
             }
+            _ = shutdown_rx.recv() => {
+                info!("Received shutdown signal");
+                self.wait_for_shutdown([("reader", &mut reader), ("writer", &mut writer), ("analysis", &mut analysis)]).await
+            }
         };
 
+        // 残っているタスクを停止
+        for handle in [&reader, &writer, &analysis] {
+            if !handle.is_finished() {
+                handle.abort();
+            }
+        }
+
         // タスクの状態をクリーンアップ
         self.update_task_state("reader", false).await.map_err(|e| TaskError::TaskExecutionError(e.to_string()))?;
         self.update_task_state("writer", false).await.map_err(|e| TaskError::TaskExecutionError(e.to_string()))?;
@@ -87,20 +87,29 @@ impl TaskMonitor {
         }
     }
 
-    pub async fn wait_for_shutdown(&self) -> Result<(), TaskError> {
-        let start_time = std::time::Instant::now();
-        while start_time.elapsed() < self.shutdown_timeout {
-            let state = self.task_state.lock().await;
-            if state.is_all_inactive() {
-                info!("全てのタスクが正常にシャットダウンしました");
-                return Ok(());
+    /// 各タスクがシャットダウン通知を受けて終了するのを待つ
+    pub async fn wait_for_shutdown(&self, handles: [(&str, &mut JoinHandle<Result<(), String>>); 3]) -> Result<(), TaskError> {
+        let deadline = Instant::now() + self.shutdown_timeout;
+
+        for (task_name, handle) in handles {
+            match timeout_at(deadline, handle).await {
+                Ok(result) => {
+                    if let Err(e) = self.handle_task_result(result, task_name).await {
+                        error!("{} タスクのシャットダウン中にエラーが発生しました: {}", task_name, e);
+                    }
+                },
+                Err(_) => {
+                    error!("タスクのシャットダウンがタイムアウトしました: {}", task_name);
+                    return Err(TaskError::TimeoutError("シャットダウンタイムアウト".to_string()));
+                },
             }
-            drop(state);
-            sleep(SHUTDOWN_CHECK_INTERVAL).await;
         }
 
-        error!("タスクのシャットダウンがタイムアウトしました");
-        Err(TaskError::TimeoutError("シャットダウンタイムアウト".to_string()))
+        let state = self.task_state.lock().await;
+        if state.is_all_inactive() {
+            info!("全てのタスクが正常にシャットダウンしました");
+        }
+        Ok(())
     }
 
     async fn update_task_state(&self, task_name: &str, active: bool) -> Result<(), TaskError> {
@@ -1 +0,0 @@
-pub mod measure_time;
@@ -0,0 +1,52 @@
+//! フラッシュのバッチサイズの調整の結合テスト
+
+use stegrdb::packet::writer::AdaptiveBatchSize;
+
+fn record_all(batch_size: &mut AdaptiveBatchSize, flushed: &[usize]) -> Vec<usize> {
+    flushed
+        .iter()
+        .map(|flushed| {
+            batch_size.record(*flushed);
+            batch_size.current()
+        })
+        .collect()
+}
+
+#[test]
+fn grows_up_to_max() {
+    let mut batch_size = AdaptiveBatchSize::new(10, 25);
+    assert_eq!(batch_size.current(), 10);
+
+    // 目標値いっぱいまで溜まっていた場合は倍にし、上限で止める
+    assert_eq!(record_all(&mut batch_size, &[10, 20, 25, 25]), vec![20, 25, 25, 25]);
+    // 目標値を超えて溜まっていても上限を超えない
+    assert_eq!(record_all(&mut batch_size, &[1000]), vec![25]);
+}
+
+#[test]
+fn shrinks_down_to_min() {
+    let mut batch_size = AdaptiveBatchSize::new(10, 100);
+    record_all(&mut batch_size, &[10, 20, 40, 80]);
+    assert_eq!(batch_size.current(), 100);
+
+    // 目標値の1/4未満しか溜まっていなかった場合は半分にし、下限で止める
+    assert_eq!(record_all(&mut batch_size, &[24, 0, 0, 0]), vec![50, 25, 12, 10]);
+    assert_eq!(record_all(&mut batch_size, &[0, 1]), vec![10, 10]);
+}
+
+#[test]
+fn keeps_size_between_quarter_and_target() {
+    let mut batch_size = AdaptiveBatchSize::new(10, 100);
+    record_all(&mut batch_size, &[10, 20]);
+    assert_eq!(batch_size.current(), 40);
+
+    // 目標値の1/4以上・目標値未満の場合は変えない
+    assert_eq!(record_all(&mut batch_size, &[10, 39, 25]), vec![40, 40, 40]);
+    assert_eq!(record_all(&mut batch_size, &[9]), vec![20]);
+}
+
+#[test]
+fn fixed_size_when_min_equals_max() {
+    let mut batch_size = AdaptiveBatchSize::new(50, 50);
+    assert_eq!(record_all(&mut batch_size, &[50, 1000, 0, 1]), vec![50, 50, 50, 50]);
+}
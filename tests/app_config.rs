@@ -0,0 +1,85 @@
+//! 設定の読み込みの結合テスト
+
+use stegrdb::config::{ConfigOverrides, MetadataMode, RetentionConfig, SslMode};
+use stegrdb::AppConfig;
+
+fn overrides(values: &[(&str, &str)]) -> ConfigOverrides {
+    let mut overrides = ConfigOverrides::default();
+    overrides.set("node_id", 1);
+    for (key, value) in values {
+        overrides.set(key, value);
+    }
+    overrides
+}
+
+#[test]
+fn hours_that_overflow_are_rejected() {
+    for key in ["retention.packets_max_age_hours", "retention.node_activity_max_age_hours"] {
+        assert!(RetentionConfig::load(&overrides(&[(key, "18446744073709551615")])).is_err(), "{}", key);
+        assert!(RetentionConfig::load(&overrides(&[(key, "8760000")])).is_ok(), "{}", key);
+    }
+    assert!(RetentionConfig::load(&overrides(&[("retention.node_max_age_hours", "150=5124095576030431")])).is_err());
+    assert!(AppConfig::load(&overrides(&[("carrier.kind", "sqlite"), ("partition.interval_hours", "24")])).is_ok());
+    assert!(AppConfig::load(&overrides(&[("carrier.kind", "sqlite"), ("partition.interval_hours", "5124095576030431")])).is_err());
+}
+
+#[test]
+fn max_size_that_overflows_is_rejected() {
+    let max_size = |mb: &str| RetentionConfig::load(&overrides(&[("retention.packets_max_size_mb", mb)])).map(|config| config.packets_max_size);
+    assert_eq!(max_size("0").unwrap(), None);
+    assert_eq!(max_size("1024").unwrap(), Some(1024 * 1024 * 1024));
+    assert_eq!(max_size("8796093022207").unwrap(), Some(8796093022207 * 1024 * 1024));
+    assert!(max_size("8796093022208").is_err());
+    assert!(max_size("18446744073709551615").is_err());
+}
+
+#[test]
+fn pool_max_size_that_overflows_is_rejected() {
+    let load = |max_size: &str| AppConfig::load(&overrides(&[("carrier.kind", "sqlite"), ("database.pool.max_size", max_size)]));
+    assert_eq!(load("4294967295").unwrap().database.pool.max_size, u32::MAX);
+    // u32に切り詰めると0になり、接続プールの作成でpanicする値
+    let error = load("4294967296").unwrap_err().to_string();
+    assert!(error.contains("database.pool.max_size"), "{}", error);
+}
+
+#[test]
+fn failure_threshold_that_overflows_is_rejected() {
+    let load = |threshold: &str| {
+        AppConfig::load(&overrides(&[
+            ("carrier.kind", "sqlite"),
+            ("database.circuit_breaker.failure_threshold", threshold),
+        ]))
+    };
+    assert_eq!(load("4294967295").unwrap().database.circuit_breaker.failure_threshold, u32::MAX);
+    // u32に切り詰めると0になり、最初の失敗で遮断する値
+    let error = load("4294967296").unwrap_err().to_string();
+    assert!(error.contains("database.circuit_breaker.failure_threshold"), "{}", error);
+}
+
+#[test]
+fn metadata_mode_accepts_previous_name() {
+    let metadata = |mode: &str| AppConfig::load(&overrides(&[("carrier.kind", "sqlite"), ("encryption.metadata", mode)])).map(|config| config.encryption.metadata);
+    assert_eq!(
+        AppConfig::load(&overrides(&[("carrier.kind", "sqlite")])).unwrap().encryption.metadata,
+        MetadataMode::Redact
+    );
+    assert_eq!(metadata("redact").unwrap(), MetadataMode::Redact);
+    assert_eq!(metadata("encrypt").unwrap(), MetadataMode::Redact);
+    assert_eq!(metadata("plain").unwrap(), MetadataMode::Plain);
+    assert!(metadata("hidden").is_err());
+}
+
+#[test]
+fn sslmode_defaults_to_verify_full_with_root_cert() {
+    let dir = tempfile::tempdir().unwrap();
+    let root_cert = dir.path().join("ca.pem");
+    std::fs::write(&root_cert, "").unwrap();
+    let root_cert = root_cert.to_str().unwrap();
+
+    let mode = |values: &[(&str, &str)]| AppConfig::load(&overrides(&[&[("carrier.kind", "sqlite")], values].concat())).unwrap().database.tls.mode;
+    assert_eq!(mode(&[]), SslMode::Prefer);
+    assert_eq!(mode(&[("database.sslrootcert", root_cert)]), SslMode::VerifyFull);
+    // 明示的に指定した場合はCA証明書があっても指定に従う
+    assert_eq!(mode(&[("database.sslrootcert", root_cert), ("database.sslmode", "require")]), SslMode::Require);
+    assert_eq!(mode(&[("database.sslmode", "verify-full")]), SslMode::VerifyFull);
+}
@@ -0,0 +1,122 @@
+//! データベースのサーキットブレーカーの状態遷移のテスト
+
+use std::time::Duration;
+use stegrdb::config::CircuitBreakerConfig;
+use stegrdb::database::{CircuitState, DatabaseHealth};
+
+const BACKOFF: Duration = Duration::from_millis(100);
+
+fn health(failure_threshold: u32) -> DatabaseHealth {
+    DatabaseHealth::new(&CircuitBreakerConfig {
+        failure_threshold,
+        initial_backoff: BACKOFF,
+        max_backoff: BACKOFF * 3,
+    })
+}
+
+fn fail(health: &DatabaseHealth) {
+    health.try_acquire().expect("要求が遮断されています").failure("connection refused");
+}
+
+/// 遮断した状態にし、再試行の時刻まで待つ
+async fn open_and_wait(health: &DatabaseHealth) {
+    for _ in 0..3 {
+        fail(health);
+    }
+    assert_eq!(health.stats().state, CircuitState::Open);
+    tokio::time::sleep(health.stats().retry_in.unwrap() + Duration::from_millis(5)).await;
+}
+
+#[tokio::test]
+async fn opens_after_failure_threshold() {
+    let health = health(3);
+    fail(&health);
+    fail(&health);
+    // 閾値に達するまでは要求を通す
+    assert_eq!(health.stats().state, CircuitState::Closed);
+    assert_eq!(health.stats().consecutive_failures, 2);
+    assert!(health.is_available());
+    assert!(!health.is_healthy());
+
+    fail(&health);
+    let stats = health.stats();
+    assert_eq!(stats.state, CircuitState::Open);
+    assert_eq!(stats.trips, 1);
+    assert_eq!(stats.last_error.as_deref(), Some("connection refused"));
+    assert!(stats.retry_in.is_some_and(|retry_in| retry_in <= BACKOFF));
+    assert!(health.try_acquire().is_none());
+    assert!(!health.is_available());
+}
+
+#[tokio::test]
+async fn success_resets_consecutive_failures() {
+    let health = health(3);
+    fail(&health);
+    fail(&health);
+    health.try_acquire().unwrap().success();
+    assert!(health.is_healthy());
+
+    // 連続していない失敗は数えない
+    fail(&health);
+    fail(&health);
+    assert_eq!(health.stats().state, CircuitState::Closed);
+    assert_eq!(health.stats().trips, 0);
+}
+
+#[tokio::test]
+async fn half_open_lets_one_probe_through_and_closes_on_success() {
+    let health = health(3);
+    open_and_wait(&health).await;
+    assert!(health.is_available());
+
+    let probe = health.try_acquire().expect("再試行の時刻を過ぎても要求を通しません");
+    assert_eq!(health.stats().state, CircuitState::HalfOpen);
+    // 試行中は他の要求を通さない
+    assert!(health.try_acquire().is_none());
+    assert!(!health.is_available());
+
+    probe.success();
+    let stats = health.stats();
+    assert_eq!(stats.state, CircuitState::Closed);
+    assert_eq!(stats.consecutive_failures, 0);
+    assert!(stats.last_error.is_none());
+    assert!(health.try_acquire().is_some());
+}
+
+#[tokio::test]
+async fn failed_probe_reopens_with_doubled_backoff_up_to_max() {
+    let health = health(3);
+    open_and_wait(&health).await;
+
+    health.try_acquire().unwrap().failure("timeout");
+    let stats = health.stats();
+    assert_eq!(stats.state, CircuitState::Open);
+    assert!(stats.retry_in.is_some_and(|retry_in| retry_in > BACKOFF && retry_in <= BACKOFF * 2));
+    // 再試行の失敗は遮断の回数に数えない
+    assert_eq!(stats.trips, 1);
+
+    tokio::time::sleep(stats.retry_in.unwrap() + Duration::from_millis(5)).await;
+    health.try_acquire().unwrap().failure("timeout");
+    assert!(health.stats().retry_in.is_some_and(|retry_in| retry_in > BACKOFF * 2 && retry_in <= BACKOFF * 3));
+}
+
+#[tokio::test]
+async fn dropped_probe_allows_next_probe() {
+    let health = health(3);
+    open_and_wait(&health).await;
+
+    // 結果を記録せずに破棄された試行は、成功・失敗のどちらとしても扱わない
+    drop(health.try_acquire().unwrap());
+    assert_eq!(health.stats().state, CircuitState::HalfOpen);
+    assert!(health.try_acquire().is_some());
+}
+
+#[tokio::test]
+async fn waiters_resume_when_retry_is_due() {
+    let health = health(1);
+    fail(&health);
+    assert!(!health.is_available());
+
+    tokio::time::timeout(BACKOFF * 10, health.wait_until_available()).await.expect("再試行の時刻を過ぎても待機が終わりません");
+    assert!(health.is_available());
+}
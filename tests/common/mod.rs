@@ -0,0 +1,28 @@
+//! 結合テストの共通処理
+
+use chrono::Utc;
+use std::net::{IpAddr, Ipv4Addr};
+use stegrdb::packet::types::{EtherType, IpProtocol};
+use stegrdb::packet::{InetAddr, MacAddr, PacketData};
+
+/// src_portで区別できるテスト用のUDPパケット
+pub fn packet(src_port: i32) -> PacketData {
+    let mut raw_packet = vec![0u8; 42];
+    raw_packet[12..14].copy_from_slice(&[0x08, 0x00]);
+    raw_packet[34..36].copy_from_slice(&(src_port as u16).to_be_bytes());
+
+    PacketData {
+        src_mac: MacAddr([0x02, 0, 0, 0, 0, 1]),
+        dst_mac: MacAddr([0x02, 0, 0, 0, 0, 2]),
+        ether_type: EtherType::IP_V4,
+        src_ip: InetAddr(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))),
+        dst_ip: InetAddr(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))),
+        src_port,
+        dst_port: 9,
+        ip_protocol: IpProtocol::UDP,
+        captured_at: Utc::now(),
+        raw_packet,
+        key_id: None,
+        signature: None,
+    }
+}
@@ -0,0 +1,106 @@
+//! 設定ファイル・環境変数 (.env)・コマンドライン引数の優先順位と、不明なキーの結合テスト
+//! 環境変数と作業ディレクトリを変更するため、他のテストとは別のバイナリで実行する
+
+use std::path::{Path, PathBuf};
+use stegrdb::config::{ConfigError, ConfigOverrides, DatabaseConfig};
+
+const DATABASE_ENV: &[&str] = &[
+    "TIMESCALE_DB_HOST",
+    "TIMESCALE_DB_PORT",
+    "TIMESCALE_DB_USER",
+    "TIMESCALE_DB_PASSWORD",
+    "TIMESCALE_DB_DATABASE",
+];
+
+fn write_config(dir: &Path, content: &str) -> PathBuf {
+    let path = dir.join("stegrdb.toml");
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn with_file(path: PathBuf, values: &[(&str, &str)]) -> ConfigOverrides {
+    let mut overrides = ConfigOverrides {
+        config_path: Some(path),
+        ..ConfigOverrides::default()
+    };
+    for (key, value) in values {
+        overrides.set(key, value);
+    }
+    overrides
+}
+
+#[test]
+fn later_layers_override_earlier_ones() {
+    for name in DATABASE_ENV.iter().chain(&["STEGRDB_CONFIG"]) {
+        std::env::remove_var(name);
+    }
+
+    // 作業ディレクトリの stegrdb.toml と .env を読み込む
+    let dir = tempfile::tempdir().unwrap();
+    write_config(
+        dir.path(),
+        r#"
+        [database]
+        host = "file-host"
+        port = 1111
+        user = "file-user"
+        password = "file-password"
+        database = "file-db"
+        "#,
+    );
+    std::fs::write(dir.path().join(".env"), "TIMESCALE_DB_PASSWORD=dotenv-password\nTIMESCALE_DB_DATABASE=dotenv-db\n").unwrap();
+    std::env::set_current_dir(dir.path()).unwrap();
+
+    // .envは既に設定されている環境変数を上書きしない
+    std::env::set_var("TIMESCALE_DB_PORT", "2222");
+    std::env::set_var("TIMESCALE_DB_USER", "env-user");
+    std::env::set_var("TIMESCALE_DB_PASSWORD", "env-password");
+
+    let mut overrides = ConfigOverrides::default();
+    overrides.set("database.user", "set-user");
+    let config = DatabaseConfig::load(&overrides).expect("設定の読み込みに失敗しました");
+
+    assert_eq!(config.host, "file-host");
+    assert_eq!(config.port, 2222);
+    assert_eq!(config.user, "set-user");
+    assert_eq!(config.password, "env-password");
+    assert_eq!(config.database, "dotenv-db");
+
+    // 不正な値のエラーには値の出所を含める
+    std::env::set_var("TIMESCALE_DB_PORT", "not-a-port");
+    let error = DatabaseConfig::load(&ConfigOverrides::default()).unwrap_err().to_string();
+    assert!(error.contains("環境変数 TIMESCALE_DB_PORT"), "{error}");
+
+    let mut overrides = ConfigOverrides::default();
+    overrides.set("database.port", "70000");
+    let error = DatabaseConfig::load(&overrides).unwrap_err().to_string();
+    assert!(error.contains("コマンドライン引数"), "{error}");
+
+    std::env::remove_var("TIMESCALE_DB_PORT");
+    write_config(dir.path(), "[database]\nhost = \"file-host\"\nport = \"x\"\nuser = \"file-user\"\n");
+    let error = DatabaseConfig::load(&ConfigOverrides::default()).unwrap_err().to_string();
+    assert!(error.contains("設定ファイル"), "{error}");
+}
+
+#[test]
+fn unknown_key_in_file_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let path = write_config(dir.path(), "[database]\nhots = \"localhost\"\n");
+    assert!(matches!(DatabaseConfig::load(&with_file(path, &[])), Err(ConfigError::UnknownKeyError(key)) if key == "database.hots"));
+
+    let path = write_config(dir.path(), "node_id = 1\n[unknown]\nkey = 1\n");
+    assert!(matches!(DatabaseConfig::load(&with_file(path, &[])), Err(ConfigError::UnknownKeyError(key)) if key == "unknown.key"));
+
+    // 配列などの展開できない値も受け付けない
+    let path = write_config(dir.path(), "[database]\nhost = [\"a\", \"b\"]\n");
+    assert!(matches!(DatabaseConfig::load(&with_file(path, &[])), Err(ConfigError::InvalidValueError(key, _)) if key == "database.host"));
+}
+
+#[test]
+fn unknown_key_in_overrides_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_config(dir.path(), "");
+    let overrides = with_file(path, &[("database.hots", "localhost")]);
+    assert!(matches!(DatabaseConfig::load(&overrides), Err(ConfigError::UnknownKeyError(key)) if key == "database.hots"));
+}
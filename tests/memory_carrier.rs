@@ -0,0 +1,165 @@
+//! メモリ上の媒体で2つのノードの間でパケットを受け渡す結合テスト (外部のデータベースは不要)
+
+mod common;
+
+use pnet::datalink::NetworkInterface;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use stegrdb::carrier::Carrier;
+use stegrdb::config::{ConfigOverrides, RetentionConfig};
+use stegrdb::packet::repository::DeliveryCursor;
+use stegrdb::{AppConfig, MemoryCarrier, PacketQuery, TunnelNode};
+
+const NODE_A: i16 = 1;
+const NODE_B: i16 = 2;
+
+fn interface(name: &str) -> NetworkInterface {
+    NetworkInterface {
+        name: name.to_string(),
+        description: String::new(),
+        index: 0,
+        mac: None,
+        ips: Vec::new(),
+        flags: 0,
+    }
+}
+
+async fn node(carrier: &MemoryCarrier, node_id: i16) -> TunnelNode {
+    let mut overrides = ConfigOverrides::default();
+    overrides.set("node_id", node_id);
+    // with_carrier は carrier.kind を参照しないため、データベースの接続設定が不要な sqlite を指定して読み込む
+    overrides.set("carrier.kind", "sqlite");
+    let config = AppConfig::load(&overrides).expect("設定の読み込みに失敗しました");
+
+    carrier.register_node(node_id, &format!("node-{node_id}"), None).await.unwrap();
+    TunnelNode::with_carrier(config, Arc::new(carrier.clone()), interface(&format!("test{node_id}"))).await.expect("ノードの作成に失敗しました")
+}
+
+async fn publish(node: &TunnelNode, ports: impl IntoIterator<Item = i32>) {
+    let packets: Vec<_> = ports.into_iter().map(common::packet).collect();
+    node.context().carrier.publish(node.node_id(), &packets).await.unwrap();
+}
+
+async fn fetch(node: &TunnelNode, cursor: DeliveryCursor) -> (Vec<(i16, usize)>, DeliveryCursor) {
+    let (packets, next) = node.context().carrier.fetch(node.node_id(), cursor).await.unwrap();
+    (packets.iter().map(|p| (p.node_id, p.raw_packet.len())).collect(), next)
+}
+
+fn ports(packets: &[stegrdb::StoredPacket]) -> Vec<i32> {
+    packets.iter().map(|p| p.data.src_port).collect()
+}
+
+#[tokio::test]
+async fn nodes_on_shared_carrier_exchange_packets() {
+    let carrier = MemoryCarrier::new();
+    let node_a = node(&carrier, NODE_A).await;
+    let node_b = node(&carrier, NODE_B).await;
+
+    // 初期化で両方のノードの起動が記録されている
+    let nodes = carrier.list_nodes().await.unwrap();
+    assert_eq!(nodes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![NODE_A, NODE_B]);
+    assert!(nodes.iter().all(|n| n.last_boot_time.is_some()));
+
+    publish(&node_a, 1..=3).await;
+    publish(&node_b, [10]).await;
+    assert_eq!(carrier.len(), 4);
+
+    // 自ノードが書き込んだパケットは受け取らない
+    let (received_by_b, cursor_b) = fetch(&node_b, DeliveryCursor::default()).await;
+    assert_eq!(received_by_b.iter().map(|(node_id, _)| *node_id).collect::<Vec<_>>(), vec![NODE_A; 3]);
+    let (received_by_a, cursor_a) = fetch(&node_a, DeliveryCursor::default()).await;
+    assert_eq!(received_by_a.iter().map(|(node_id, _)| *node_id).collect::<Vec<_>>(), vec![NODE_B]);
+
+    // 検索では全ノードのパケットを新しい順に返す
+    assert_eq!(ports(&node_a.query_packets(&PacketQuery::new()).await.unwrap()), vec![10, 3, 2, 1]);
+    let query = PacketQuery {
+        node_id: Some(NODE_A),
+        ..PacketQuery::new()
+    };
+    assert_eq!(ports(&node_b.query_packets(&query).await.unwrap()), vec![3, 2, 1]);
+
+    // 返された読み取り位置から読み直すと、既に受け取ったパケットは返らない
+    assert!(cursor_b > DeliveryCursor::default());
+    assert_eq!(fetch(&node_b, cursor_b).await, (Vec::new(), cursor_b));
+
+    // 保存した読み取り位置はノードごとに読み出せる
+    assert_eq!(carrier.saved_cursor(NODE_B).await.unwrap(), None);
+    carrier.acknowledge(NODE_B, cursor_b).await.unwrap();
+    assert_eq!(carrier.saved_cursor(NODE_B).await.unwrap(), Some(cursor_b));
+    assert_eq!(carrier.saved_cursor(NODE_A).await.unwrap(), None);
+
+    // 新しく書き込まれたパケットだけが返り、読み取り位置が進む
+    publish(&node_a, 4..=5).await;
+    let (received, next) = fetch(&node_b, cursor_b).await;
+    assert_eq!(received.len(), 2);
+    assert!(next > cursor_b);
+    assert_eq!(next, carrier.live_cursor().await.unwrap());
+    assert_eq!(fetch(&node_a, cursor_a).await.0, Vec::new());
+}
+
+#[tokio::test]
+async fn cursor_before_latest_skips_only_other_nodes_packets() {
+    let carrier = MemoryCarrier::new();
+    let node_a = node(&carrier, NODE_A).await;
+    let node_b = node(&carrier, NODE_B).await;
+
+    publish(&node_a, 1..=2).await;
+    publish(&node_b, [10]).await;
+    publish(&node_a, 3..=4).await;
+    publish(&node_b, [11]).await;
+
+    // 自ノードのパケットを数えずに、他ノードの直近2件の手前から読み取る
+    let cursor = carrier.cursor_before_latest(NODE_B, 2).await.unwrap();
+    let (packets, _) = node_b.context().carrier.fetch(NODE_B, cursor).await.unwrap();
+    assert_eq!(packets.len(), 2);
+    assert!(packets.iter().all(|p| p.node_id == NODE_A));
+
+    let cursor = carrier.cursor_before_latest(NODE_A, 1).await.unwrap();
+    let (packets, _) = node_a.context().carrier.fetch(NODE_A, cursor).await.unwrap();
+    assert_eq!(packets.iter().map(|p| p.node_id).collect::<Vec<_>>(), vec![NODE_B]);
+
+    // 保持している件数より多い場合は先頭から読み取る
+    assert_eq!(carrier.cursor_before_latest(NODE_B, 100).await.unwrap(), DeliveryCursor::default());
+    // 0件の場合は未読のパケットがない位置から読み取る
+    let cursor = carrier.cursor_before_latest(NODE_B, 0).await.unwrap();
+    assert!(node_b.context().carrier.fetch(NODE_B, cursor).await.unwrap().0.is_empty());
+}
+
+#[tokio::test]
+async fn purge_removes_expired_packets_and_keeps_latest_boot() {
+    let carrier = MemoryCarrier::new();
+    let node_a = node(&carrier, NODE_A).await;
+    let node_b = node(&carrier, NODE_B).await;
+
+    publish(&node_a, 1..=3).await;
+    publish(&node_b, [10, 11]).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let retention = |packets_max_age: Option<Duration>, node_max_age: BTreeMap<i16, Duration>| RetentionConfig {
+        packets_max_age,
+        packets_max_size: None,
+        node_max_age,
+        node_activity_max_age: Some(Duration::ZERO),
+        purge_interval: None,
+    };
+
+    // ノードごとの保持期間を過ぎたノードAのパケットだけが削除される
+    let report = node_a.context().carrier.purge(&retention(Some(Duration::from_secs(3600)), BTreeMap::from([(NODE_A, Duration::from_millis(1))]))).await.unwrap();
+    assert_eq!(report.deleted_packets, 3);
+    assert_eq!(ports(&node_b.query_packets(&PacketQuery::new()).await.unwrap()), vec![11, 10]);
+
+    // 起動記録は各ノードの最新のものを残す
+    assert_eq!(report.deleted_activities, 0);
+    assert!(carrier.list_nodes().await.unwrap().iter().all(|n| n.last_boot_time.is_some()));
+
+    // 保持期間内に書き込まれたパケットは残り、全体の保持期間を過ぎたものは削除される
+    publish(&node_a, [4]).await;
+    let report = node_b.context().carrier.purge(&retention(Some(Duration::from_millis(100)), BTreeMap::new())).await.unwrap();
+    assert_eq!(report.deleted_packets, 2);
+    assert_eq!(ports(&node_a.query_packets(&PacketQuery::new()).await.unwrap()), vec![4]);
+
+    // 読み取り位置は削除の影響を受けない
+    let (packets, _) = node_b.context().carrier.fetch(NODE_B, DeliveryCursor::default()).await.unwrap();
+    assert_eq!(packets.len(), 1);
+}
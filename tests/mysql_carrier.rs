@@ -0,0 +1,113 @@
+//! MySQL/MariaDBの媒体の結合テスト
+//! STEGRDB_TEST_MYSQL=1 の場合のみ実行する (接続先は通常の設定と同じく CARRIER_MYSQL_* 等で指定する)
+
+mod common;
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use stegrdb::carrier::Carrier;
+use stegrdb::config::{ConfigOverrides, RetentionConfig};
+use stegrdb::crypto::ReceivedSeqs;
+use stegrdb::{AppConfig, MysqlCarrier, PacketQuery};
+
+const NODE_A: i16 = 32001;
+const NODE_B: i16 = 32002;
+
+fn mysql_config() -> Option<AppConfig> {
+    if std::env::var("STEGRDB_TEST_MYSQL").as_deref() != Ok("1") {
+        eprintln!("STEGRDB_TEST_MYSQL=1 が指定されていないため、MySQLのテストを省略します");
+        return None;
+    }
+
+    let mut overrides = ConfigOverrides::default();
+    overrides.set("node_id", NODE_A);
+    overrides.set("carrier.kind", "mysql");
+    Some(AppConfig::load(&overrides).expect("設定の読み込みに失敗しました"))
+}
+
+/// ノードAが書き込んだパケットの受信済みのシーケンス番号
+fn received_from_a(highest: i64, ranges: &[(i64, i64)]) -> ReceivedSeqs {
+    ReceivedSeqs {
+        node_id: NODE_A,
+        highest,
+        ranges: ranges.to_vec(),
+    }
+}
+
+fn node_retention(node_ids: &[i16]) -> RetentionConfig {
+    RetentionConfig {
+        packets_max_age: None,
+        packets_max_size: None,
+        node_max_age: node_ids.iter().map(|node_id| (*node_id, Duration::ZERO)).collect::<BTreeMap<_, _>>(),
+        node_activity_max_age: None,
+        purge_interval: None,
+    }
+}
+
+#[tokio::test]
+async fn packets_are_delivered_and_purged() {
+    let Some(config) = mysql_config() else {
+        return;
+    };
+
+    let carrier = MysqlCarrier::connect(&config.carrier, &config.database.circuit_breaker).await.unwrap();
+    carrier.migrate().await.unwrap();
+    carrier.verify().await.unwrap();
+    assert_eq!(carrier.schema_version().await.unwrap().map(|(version, _)| version), Some(MysqlCarrier::SCHEMA_VERSION));
+
+    carrier.register_node(NODE_A, "stegrdb-test-a", None).await.unwrap();
+    carrier.register_node(NODE_B, "stegrdb-test-b", None).await.unwrap();
+    // 前回のテストの残りを削除する
+    carrier.purge(&node_retention(&[NODE_A, NODE_B])).await.unwrap();
+
+    // 他のノードの既存の行を読まないよう、現在の末尾から読み取る
+    let start = carrier.live_cursor().await.unwrap();
+    carrier.publish(NODE_A, &[common::packet(1), common::packet(2)]).await.unwrap();
+    carrier.publish(NODE_B, &[common::packet(10)]).await.unwrap();
+
+    // 自ノードの書き込みを除いて受け取る
+    let (packets, cursor) = carrier.fetch(NODE_B, start).await.unwrap();
+    assert!(packets.iter().all(|p| p.node_id != NODE_B));
+    let received: Vec<_> = packets.iter().filter(|p| p.node_id == NODE_A).collect();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0].raw_packet, common::packet(1).raw_packet);
+    assert!(cursor > start);
+    let (packets, _) = carrier.fetch(NODE_A, start).await.unwrap();
+    assert!(packets.iter().any(|p| p.node_id == NODE_B));
+    assert!(packets.iter().all(|p| p.node_id != NODE_A));
+
+    // 保存した読み取り位置から読み直すと、既に受け取ったパケットは返らない
+    carrier.acknowledge(NODE_B, cursor).await.unwrap();
+    let saved = carrier.saved_cursor(NODE_B).await.unwrap().expect("読み取り位置が保存されていません");
+    assert_eq!(saved, cursor);
+    let (packets, _) = carrier.fetch(NODE_B, saved).await.unwrap();
+    assert!(packets.iter().all(|p| p.node_id != NODE_A));
+
+    carrier.publish(NODE_A, &[common::packet(3)]).await.unwrap();
+    let (packets, next) = carrier.fetch(NODE_B, saved).await.unwrap();
+    assert_eq!(packets.iter().filter(|p| p.node_id == NODE_A).count(), 1);
+    assert!(next > saved);
+
+    // 受信済みのシーケンス番号は区間ごと置き換えて記録する
+    carrier.acknowledge_seqs(NODE_B, &[received_from_a(10, &[(1, 3), (8, 10)])]).await.unwrap();
+    let replaced = received_from_a(12, &[(1, 3), (8, 12)]);
+    carrier.acknowledge_seqs(NODE_B, std::slice::from_ref(&replaced)).await.unwrap();
+    assert_eq!(carrier.saved_seqs(NODE_B).await.unwrap(), vec![replaced]);
+
+    // ノードごとの保持期間を過ぎたノードAのパケットだけが削除される
+    let query = |node_id| PacketQuery {
+        node_id: Some(node_id),
+        ..PacketQuery::new()
+    };
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let report = carrier.purge(&node_retention(&[NODE_A])).await.unwrap();
+    assert_eq!(report.deleted_packets, 3);
+    assert!(carrier.query_packets(&query(NODE_A)).await.unwrap().is_empty());
+    assert_eq!(
+        carrier.query_packets(&query(NODE_B)).await.unwrap().iter().map(|p| p.data.src_port).collect::<Vec<_>>(),
+        vec![10]
+    );
+
+    carrier.purge(&node_retention(&[NODE_B])).await.unwrap();
+    assert!(carrier.query_packets(&query(NODE_B)).await.unwrap().is_empty());
+}
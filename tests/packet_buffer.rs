@@ -0,0 +1,152 @@
+//! パケットバッファの上限と破棄・待機の結合テスト
+
+mod common;
+
+use std::time::Duration;
+use stegrdb::config::{BufferConfig, OverflowPolicy};
+use stegrdb::packet::writer::PacketBuffer;
+use stegrdb::PacketData;
+
+/// バッファ上のパケット1つあたりの使用量 (raw_packetとパケット1つあたりの管理領域の合計)
+fn packet_size() -> usize {
+    std::mem::size_of::<PacketData>() + common::packet(0).raw_packet.len()
+}
+
+fn buffer(max_packets: usize, max_bytes: usize, overflow_policy: OverflowPolicy) -> PacketBuffer {
+    PacketBuffer::new(&BufferConfig {
+        max_packets,
+        max_bytes,
+        overflow_policy,
+    })
+}
+
+async fn push(buffer: &PacketBuffer, ports: impl IntoIterator<Item = i32>) {
+    for port in ports {
+        buffer.push(common::packet(port)).await;
+    }
+}
+
+fn ports(packets: &[PacketData]) -> Vec<i32> {
+    packets.iter().map(|p| p.src_port).collect()
+}
+
+#[tokio::test]
+async fn drop_newest_discards_incoming_packets() {
+    let buffer = buffer(2, usize::MAX, OverflowPolicy::DropNewest);
+    push(&buffer, 1..=4).await;
+
+    let stats = buffer.stats().await;
+    assert_eq!((stats.len, stats.bytes), (2, 2 * packet_size()));
+    assert_eq!(stats.dropped_packet_limit, 2);
+    assert_eq!(stats.dropped(), 2);
+    assert_eq!(ports(&buffer.drain().await), vec![1, 2]);
+
+    // 取り出して空きができれば再び追加できる
+    push(&buffer, [5]).await;
+    assert_eq!(ports(&buffer.drain().await), vec![5]);
+    assert_eq!(buffer.stats().await.dropped(), 2);
+}
+
+#[tokio::test]
+async fn drop_oldest_discards_buffered_packets() {
+    let buffer = buffer(2, usize::MAX, OverflowPolicy::DropOldest);
+    push(&buffer, 1..=4).await;
+
+    let stats = buffer.stats().await;
+    assert_eq!((stats.len, stats.bytes), (2, 2 * packet_size()));
+    assert_eq!(stats.dropped_packet_limit, 2);
+    assert_eq!(ports(&buffer.drain().await), vec![3, 4]);
+}
+
+#[tokio::test]
+async fn byte_limit_is_applied_by_policy() {
+    // パケット数の上限には達しない
+    let newest = buffer(100, 2 * packet_size(), OverflowPolicy::DropNewest);
+    push(&newest, 1..=3).await;
+    assert_eq!(ports(&newest.drain().await), vec![1, 2]);
+
+    let oldest = buffer(100, 2 * packet_size(), OverflowPolicy::DropOldest);
+    push(&oldest, 1..=3).await;
+    assert_eq!(ports(&oldest.drain().await), vec![2, 3]);
+
+    for buffer in [newest, oldest] {
+        let stats = buffer.stats().await;
+        assert_eq!((stats.dropped_byte_limit, stats.dropped_packet_limit), (1, 0));
+        assert_eq!((stats.len, stats.bytes), (0, 0));
+    }
+}
+
+#[tokio::test]
+async fn oversized_packet_is_discarded_by_every_policy() {
+    for policy in [OverflowPolicy::DropNewest, OverflowPolicy::DropOldest, OverflowPolicy::Block] {
+        let buffer = buffer(100, packet_size() - 1, policy);
+        // 1つで上限を超えるパケットは、待機しても追加できないため待たずに破棄する
+        tokio::time::timeout(Duration::from_secs(1), buffer.push(common::packet(1))).await.expect("破棄されずに待機しています");
+
+        let stats = buffer.stats().await;
+        assert_eq!(stats.dropped_oversized, 1, "{policy:?}");
+        assert_eq!(stats.dropped(), 1, "{policy:?}");
+        assert_eq!((stats.len, stats.blocked), (0, 0), "{policy:?}");
+    }
+}
+
+#[tokio::test]
+async fn block_waits_until_packets_are_drained() {
+    let buffer = buffer(2, usize::MAX, OverflowPolicy::Block);
+    push(&buffer, 1..=2).await;
+
+    let pushing = tokio::spawn({
+        let buffer = buffer.clone();
+        async move { push(&buffer, 3..=4).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!pushing.is_finished(), "上限に達したバッファに追加されています");
+    assert_eq!(buffer.stats().await.blocked, 1);
+
+    // 1つ取り出すと1つだけ追加され、残りは再び待機する
+    assert_eq!(ports(&buffer.drain_up_to(1).await), vec![1]);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!pushing.is_finished());
+    assert_eq!(buffer.len().await, 2);
+    assert_eq!(buffer.stats().await.blocked, 2);
+
+    assert_eq!(ports(&buffer.drain().await), vec![2, 3]);
+    tokio::time::timeout(Duration::from_secs(1), pushing).await.expect("取り出した後も待機しています").unwrap();
+    assert_eq!(ports(&buffer.drain().await), vec![4]);
+
+    // 待機したパケットは破棄しない
+    assert_eq!(buffer.stats().await.dropped(), 0);
+}
+
+#[tokio::test]
+async fn drain_up_to_takes_oldest_packets() {
+    let buffer = buffer(100, usize::MAX, OverflowPolicy::DropNewest);
+    push(&buffer, 1..=5).await;
+
+    assert_eq!(ports(&buffer.drain_up_to(2).await), vec![1, 2]);
+    let stats = buffer.stats().await;
+    assert_eq!((stats.len, stats.bytes), (3, 3 * packet_size()));
+
+    assert_eq!(ports(&buffer.drain_up_to(10).await), vec![3, 4, 5]);
+    assert!(buffer.is_empty().await);
+    assert_eq!(buffer.stats().await.bytes, 0);
+    assert!(buffer.drain_up_to(10).await.is_empty());
+}
+
+#[tokio::test]
+async fn wait_for_len_wakes_at_threshold() {
+    let buffer = buffer(100, usize::MAX, OverflowPolicy::DropNewest);
+    push(&buffer, [1]).await;
+
+    let waiting = tokio::spawn({
+        let buffer = buffer.clone();
+        async move { buffer.wait_for_len(3).await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    push(&buffer, [2]).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!waiting.is_finished(), "パケット数がしきい値に達する前に起きています");
+
+    push(&buffer, [3]).await;
+    tokio::time::timeout(Duration::from_secs(1), waiting).await.expect("しきい値に達しても起きません").unwrap();
+}
@@ -0,0 +1,150 @@
+//! raw_packetの暗号化と鍵ファイルの結合テスト
+
+mod common;
+
+use stegrdb::config::MetadataMode;
+use stegrdb::crypto::{CryptoError, KeyRing, PacketCipher};
+use stegrdb::packet::types::{EtherType, IpProtocol};
+
+const WRITER: i16 = 1;
+const OTHER: i16 = 2;
+const KEY_1: &str = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+const KEY_2: &str = "ffeeddccbbaa99887766554433221100ffeeddccbbaa99887766554433221100";
+
+fn key_ring(active: i16, keys: &[(i16, &str)]) -> KeyRing {
+    let keys: String = keys.iter().map(|(id, key)| format!("{id} = \"{key}\"\n")).collect();
+    KeyRing::parse(&format!("active = {active}\n\n[keys]\n{keys}")).expect("鍵ファイルの読み込みに失敗しました")
+}
+
+fn cipher(active: i16, metadata: MetadataMode, accept_plaintext: bool) -> PacketCipher {
+    PacketCipher::new(&key_ring(active, &[(1, KEY_1), (2, KEY_2)]), metadata, accept_plaintext)
+}
+
+#[test]
+fn sealed_packet_is_opened() {
+    let cipher = cipher(1, MetadataMode::Plain, false);
+    let packet = common::packet(7);
+    let sealed = cipher.seal(WRITER, &packet).unwrap();
+
+    assert_eq!(sealed.key_id, Some(1));
+    assert_eq!(sealed.raw_packet.len(), packet.raw_packet.len() + PacketCipher::NONCE_LENGTH + PacketCipher::TAG_LENGTH);
+    assert_ne!(
+        &sealed.raw_packet[PacketCipher::NONCE_LENGTH..PacketCipher::NONCE_LENGTH + packet.raw_packet.len()],
+        packet.raw_packet.as_slice()
+    );
+    assert_eq!(cipher.open(WRITER, sealed.key_id, &sealed.raw_packet).unwrap(), packet.raw_packet);
+
+    // 同じパケットでもnonceが異なるため暗号文は一致しない
+    assert_ne!(cipher.seal(WRITER, &packet).unwrap().raw_packet, sealed.raw_packet);
+}
+
+#[test]
+fn tampered_packet_is_rejected() {
+    let cipher = cipher(1, MetadataMode::Plain, false);
+    let sealed = cipher.seal(WRITER, &common::packet(7)).unwrap();
+
+    // nonce・暗号文・タグのどのバイトを書き換えても復号に失敗する
+    for index in [0, PacketCipher::NONCE_LENGTH, sealed.raw_packet.len() - 1] {
+        let mut tampered = sealed.raw_packet.clone();
+        tampered[index] ^= 0x01;
+        assert!(matches!(cipher.open(WRITER, sealed.key_id, &tampered), Err(CryptoError::DecryptionError(1))), "{index}");
+    }
+
+    // nonceとタグにも満たない長さ
+    let truncated = &sealed.raw_packet[..PacketCipher::NONCE_LENGTH + PacketCipher::TAG_LENGTH - 1];
+    assert!(matches!(cipher.open(WRITER, sealed.key_id, truncated), Err(CryptoError::DecryptionError(1))));
+}
+
+#[test]
+fn replaced_node_id_or_key_id_is_rejected() {
+    let cipher = cipher(1, MetadataMode::Plain, false);
+    let sealed = cipher.seal(WRITER, &common::packet(7)).unwrap();
+
+    // 行のnode_idを他のノードに書き換えた場合
+    assert!(matches!(cipher.open(OTHER, sealed.key_id, &sealed.raw_packet), Err(CryptoError::DecryptionError(1))));
+    // 行のkey_idを別の既知の鍵に書き換えた場合
+    assert!(matches!(cipher.open(WRITER, Some(2), &sealed.raw_packet), Err(CryptoError::DecryptionError(2))));
+    // 鍵ファイルにない鍵ID
+    assert!(matches!(cipher.open(WRITER, Some(3), &sealed.raw_packet), Err(CryptoError::UnknownKeyError(3))));
+}
+
+#[test]
+fn packet_sealed_before_rotation_is_opened() {
+    let before = PacketCipher::new(&key_ring(1, &[(1, KEY_1)]), MetadataMode::Plain, false);
+    let packet = common::packet(7);
+    let old = before.seal(WRITER, &packet).unwrap();
+
+    // 鍵2を追加して書き込みに使う鍵を切り替えても、鍵1で書き込まれたパケットを読める
+    let after = cipher(2, MetadataMode::Plain, false);
+    assert_eq!(after.active_id(), 2);
+    assert_eq!(after.open(WRITER, old.key_id, &old.raw_packet).unwrap(), packet.raw_packet);
+    let new = after.seal(WRITER, &packet).unwrap();
+    assert_eq!(new.key_id, Some(2));
+    assert_eq!(after.open(WRITER, new.key_id, &new.raw_packet).unwrap(), packet.raw_packet);
+
+    // 切り替え前の鍵ファイルのノードは新しい鍵のパケットを読めない
+    assert!(matches!(before.open(WRITER, new.key_id, &new.raw_packet), Err(CryptoError::UnknownKeyError(2))));
+
+    // 鍵1を削除すると、鍵1で書き込まれたパケットは読めない
+    let removed = PacketCipher::new(&key_ring(2, &[(2, KEY_2)]), MetadataMode::Plain, false);
+    assert!(matches!(removed.open(WRITER, old.key_id, &old.raw_packet), Err(CryptoError::UnknownKeyError(1))));
+}
+
+#[test]
+fn plaintext_packet_is_accepted_only_when_enabled() {
+    let packet = common::packet(7);
+
+    let rejecting = cipher(1, MetadataMode::Plain, false);
+    assert!(matches!(rejecting.open(WRITER, None, &packet.raw_packet), Err(CryptoError::PlaintextRejectedError)));
+
+    let accepting = cipher(1, MetadataMode::Plain, true);
+    assert_eq!(accepting.open(WRITER, None, &packet.raw_packet).unwrap(), packet.raw_packet);
+    // 暗号化されたパケットは平文を受け付ける場合も検証する
+    let sealed = accepting.seal(WRITER, &packet).unwrap();
+    assert!(matches!(accepting.open(OTHER, sealed.key_id, &sealed.raw_packet), Err(CryptoError::DecryptionError(1))));
+}
+
+#[test]
+fn redact_mode_zeroes_header_columns() {
+    let packet = common::packet(7);
+
+    let redacted = cipher(1, MetadataMode::Redact, false).seal(WRITER, &packet).unwrap();
+    assert_eq!(redacted.src_mac.0, [0; 6]);
+    assert_eq!(redacted.dst_mac.0, [0; 6]);
+    assert_eq!(redacted.ether_type, EtherType::from(0u16));
+    assert_eq!(redacted.ip_protocol, IpProtocol::from(0u8));
+    assert!(redacted.src_ip.0.is_unspecified() && redacted.dst_ip.0.is_unspecified());
+    assert_eq!((redacted.src_port, redacted.dst_port), (0, 0));
+    assert_eq!(redacted.captured_at, packet.captured_at);
+    // ヘッダーは暗号化したフレームにのみ残る
+    assert_eq!(
+        cipher(1, MetadataMode::Redact, false).open(WRITER, redacted.key_id, &redacted.raw_packet).unwrap(),
+        packet.raw_packet
+    );
+
+    let plain = cipher(1, MetadataMode::Plain, false).seal(WRITER, &packet).unwrap();
+    assert_eq!(plain.src_mac, packet.src_mac);
+    assert_eq!(plain.src_ip.0, packet.src_ip.0);
+    assert_eq!((plain.src_port, plain.dst_port), (packet.src_port, packet.dst_port));
+}
+
+#[test]
+fn invalid_key_file_is_rejected() {
+    let parse = |content: &str| KeyRing::parse(content).map(|_| ());
+    assert!(matches!(parse(&format!("[keys]\n1 = \"{KEY_1}\"")), Err(CryptoError::KeyFormatError(_))));
+    assert!(matches!(parse("active = 1"), Err(CryptoError::KeyFormatError(_))));
+    assert!(matches!(parse(&format!("active = 2\n[keys]\n1 = \"{KEY_1}\"")), Err(CryptoError::UnknownKeyError(2))));
+    // 鍵の長さが32バイトでない場合
+    assert!(matches!(parse("active = 1\n[keys]\n1 = \"0011\""), Err(CryptoError::KeyFormatError(_))));
+    assert!(matches!(
+        parse(&format!("active = 1\n[keys]\n1 = \"{}\"", &KEY_1.replace('0', "g"))),
+        Err(CryptoError::KeyFormatError(_))
+    ));
+    // 鍵IDの範囲外
+    assert!(matches!(parse(&format!("active = -1\n[keys]\n-1 = \"{KEY_1}\"")), Err(CryptoError::KeyFormatError(_))));
+
+    let key_ring = key_ring(2, &[(1, KEY_1), (2, KEY_2)]);
+    assert_eq!(key_ring.active_id(), 2);
+    assert_eq!(key_ring.ids().collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(hex::encode(key_ring.get(1).unwrap()), KEY_1);
+}
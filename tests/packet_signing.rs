@@ -0,0 +1,246 @@
+//! パケットの署名とリプレイの検出の結合テスト (メモリ上の媒体を使用する)
+
+mod common;
+
+use ed25519_dalek::SigningKey;
+use std::path::Path;
+use stegrdb::carrier::Carrier;
+use stegrdb::config::SigningConfig;
+use stegrdb::crypto::{CryptoError, PacketSigner, PacketVerifier, ReceivedSeqs};
+use stegrdb::packet::repository::DeliveryCursor;
+use stegrdb::{MemoryCarrier, PacketData};
+
+const WRITER: i16 = 1;
+const READER: i16 = 2;
+
+fn signing_config(dir: &Path) -> SigningConfig {
+    let key_file = dir.join("signing.key");
+    if !key_file.exists() {
+        PacketSigner::generate(&key_file).unwrap();
+    }
+    SigningConfig {
+        enabled: true,
+        key_file,
+        seq_file: dir.join("signing.seq"),
+        accept_unsigned: false,
+    }
+}
+
+fn seq_of(packet: &PacketData) -> i64 {
+    packet.signature.as_ref().expect("署名されていません").seq
+}
+
+fn received(node_id: i16, highest: i64, ranges: &[(i64, i64)]) -> ReceivedSeqs {
+    ReceivedSeqs {
+        node_id,
+        highest,
+        ranges: ranges.to_vec(),
+    }
+}
+
+fn saved_limit(config: &SigningConfig) -> i64 {
+    std::fs::read_to_string(&config.seq_file).unwrap().trim().parse().unwrap()
+}
+
+async fn signed_carrier(signer: &PacketSigner) -> MemoryCarrier {
+    let carrier = MemoryCarrier::new();
+    carrier.register_node(WRITER, "writer", None).await.unwrap();
+    carrier.register_node(READER, "reader", None).await.unwrap();
+    carrier.set_node_key(WRITER, Some(&signer.public_key())).await.unwrap();
+    carrier
+}
+
+async fn verifier(carrier: &MemoryCarrier) -> PacketVerifier {
+    let mut verifier = PacketVerifier::new(false);
+    assert!(verifier.update_keys(&carrier.list_nodes().await.unwrap()).is_empty());
+    verifier
+}
+
+/// 読み取り側と同じく、カーソルより後のパケットを読み取って検証する
+async fn fetch_and_verify(carrier: &MemoryCarrier, verifier: &mut PacketVerifier, cursor: DeliveryCursor) -> (Vec<Result<(), CryptoError>>, DeliveryCursor) {
+    let (packets, next) = carrier.fetch(READER, cursor).await.unwrap();
+    (packets.iter().map(|packet| verifier.verify(packet)).collect(), next)
+}
+
+/// 読み取り側の再起動を再現する (記録した受信済みのシーケンス番号から検証を始める)
+async fn restarted_verifier(carrier: &MemoryCarrier) -> PacketVerifier {
+    let mut verifier = verifier(carrier).await;
+    verifier.restore_seqs(&carrier.saved_seqs(READER).await.unwrap());
+    verifier
+}
+
+#[tokio::test]
+async fn replay_is_detected_after_restart() {
+    let signer = PacketSigner::new(SigningKey::from_bytes(&[7; 32]));
+    let carrier = signed_carrier(&signer).await;
+
+    let signed: Vec<PacketData> = (1..=3).map(|port| signer.sign(WRITER, common::packet(port)).unwrap()).collect();
+    carrier.publish(WRITER, &signed).await.unwrap();
+
+    let mut before_restart = verifier(&carrier).await;
+    let (results, cursor) = fetch_and_verify(&carrier, &mut before_restart, DeliveryCursor::default()).await;
+    assert!(results.iter().all(Result::is_ok));
+    let received = before_restart.received_seqs();
+    assert_eq!(
+        received,
+        vec![ReceivedSeqs {
+            node_id: WRITER,
+            highest: seq_of(&signed[2]),
+            ranges: vec![(seq_of(&signed[0]), seq_of(&signed[2]))],
+        }]
+    );
+    carrier.acknowledge_seqs(READER, &received).await.unwrap();
+
+    // 署名付きの行を複製して書き込む
+    carrier.publish(WRITER, &signed).await.unwrap();
+
+    // 記録がなければ、再起動後の読み取り側は複製を受け付けてしまう
+    let mut without_restore = verifier(&carrier).await;
+    let (results, _) = fetch_and_verify(&carrier, &mut without_restore, cursor).await;
+    assert!(results.iter().all(Result::is_ok));
+
+    let mut after_restart = restarted_verifier(&carrier).await;
+    let (results, cursor) = fetch_and_verify(&carrier, &mut after_restart, cursor).await;
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|result| matches!(result, Err(CryptoError::ReplayError(WRITER, _)))));
+
+    // 新しく署名したパケットは受け付ける
+    carrier.publish(WRITER, &[signer.sign(WRITER, common::packet(4)).unwrap()]).await.unwrap();
+    let (results, _) = fetch_and_verify(&carrier, &mut after_restart, cursor).await;
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_ok());
+}
+
+#[tokio::test]
+async fn out_of_order_seq_is_accepted_after_restart() {
+    let signer = PacketSigner::new(SigningKey::from_bytes(&[7; 32]));
+    let carrier = signed_carrier(&signer).await;
+    let signed: Vec<PacketData> = (1..=4).map(|port| signer.sign(WRITER, common::packet(port)).unwrap()).collect();
+
+    // 3番目のパケットのバッチは、後の番号のバッチより後にコミットされる
+    carrier.publish(WRITER, &[signed[0].clone(), signed[1].clone()]).await.unwrap();
+    carrier.publish(WRITER, &[signed[3].clone()]).await.unwrap();
+    let mut before_restart = verifier(&carrier).await;
+    let (results, cursor) = fetch_and_verify(&carrier, &mut before_restart, DeliveryCursor::default()).await;
+    assert!(results.iter().all(Result::is_ok));
+    carrier.acknowledge_seqs(READER, &before_restart.received_seqs()).await.unwrap();
+
+    // 再起動後に届いた、最大値より小さい未受信の番号は受け付け、受信済みの番号の複製は破棄する
+    carrier.publish(WRITER, &[signed[2].clone(), signed[1].clone(), signed[3].clone()]).await.unwrap();
+    let mut after_restart = restarted_verifier(&carrier).await;
+    let (results, _) = fetch_and_verify(&carrier, &mut after_restart, cursor).await;
+    assert_eq!(results.len(), 3);
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(CryptoError::ReplayError(WRITER, seq)) if seq == seq_of(&signed[1])));
+    assert!(matches!(results[2], Err(CryptoError::ReplayError(WRITER, seq)) if seq == seq_of(&signed[3])));
+    assert_eq!(after_restart.received_seqs()[0].ranges, vec![(seq_of(&signed[0]), seq_of(&signed[3]))]);
+
+    // 最大値から範囲外の古い番号は、未受信でも破棄する
+    let stale = PacketSigner::new(SigningKey::from_bytes(&[7; 32]));
+    let old = stale.sign(WRITER, common::packet(5)).unwrap();
+    let mut far_ahead = after_restart.received_seqs();
+    far_ahead[0].highest = seq_of(&old) + PacketVerifier::REPLAY_WINDOW;
+    carrier.acknowledge_seqs(READER, &far_ahead).await.unwrap();
+    let mut after_restart = restarted_verifier(&carrier).await;
+    let start = carrier.live_cursor().await.unwrap();
+    carrier.publish(WRITER, &[old]).await.unwrap();
+    let (results, _) = fetch_and_verify(&carrier, &mut after_restart, start).await;
+    assert!(matches!(results[..], [Err(CryptoError::ReplayError(WRITER, _))]));
+}
+
+#[tokio::test]
+async fn forged_signature_is_rejected() {
+    let signer = PacketSigner::new(SigningKey::from_bytes(&[7; 32]));
+    let forger = PacketSigner::new(SigningKey::from_bytes(&[8; 32]));
+    let carrier = signed_carrier(&signer).await;
+
+    // 登録されていない鍵で、書き込んだノードを偽って署名する
+    let forged = forger.sign(WRITER, common::packet(1)).unwrap();
+    carrier.publish(WRITER, &[forged]).await.unwrap();
+    let mut verifier = verifier(&carrier).await;
+    let (results, cursor) = fetch_and_verify(&carrier, &mut verifier, DeliveryCursor::default()).await;
+    assert!(matches!(results[..], [Err(CryptoError::SignatureError(WRITER))]));
+    // 検証に失敗したパケットは受信済みとして記録しない
+    assert!(verifier.received_seqs().is_empty());
+
+    // 署名のない行と、公開鍵が登録されていないノードの行も破棄する
+    carrier.publish(WRITER, &[common::packet(2)]).await.unwrap();
+    carrier.publish(3, &[signer.sign(3, common::packet(3)).unwrap()]).await.unwrap();
+    let (results, _) = fetch_and_verify(&carrier, &mut verifier, cursor).await;
+    assert!(matches!(results[..], [Err(CryptoError::UnsignedPacketError), Err(CryptoError::UnknownNodeKeyError(3))]));
+}
+
+#[tokio::test]
+async fn tampered_column_is_rejected() {
+    let signer = PacketSigner::new(SigningKey::from_bytes(&[7; 32]));
+    let carrier = signed_carrier(&signer).await;
+    carrier.publish(WRITER, &[signer.sign(WRITER, common::packet(1)).unwrap()]).await.unwrap();
+    let (packets, _) = carrier.fetch(READER, DeliveryCursor::default()).await.unwrap();
+    let original = packets.into_iter().next().unwrap();
+
+    // 署名の対象の列をそれぞれ書き換える
+    let mut tampered = Vec::new();
+    let mut packet = original.clone();
+    packet.raw_packet[0] ^= 1;
+    tampered.push(packet);
+    let mut packet = original.clone();
+    packet.captured_at += chrono::Duration::microseconds(1);
+    tampered.push(packet);
+    let mut packet = original.clone();
+    packet.key_id = Some(1);
+    tampered.push(packet);
+    let mut packet = original.clone();
+    packet.node_id = READER;
+    tampered.push(packet);
+    let mut packet = original.clone();
+    packet.signature.as_mut().unwrap().seq += 1;
+    tampered.push(packet);
+
+    let mut verifier = verifier(&carrier).await;
+    for packet in &tampered {
+        assert!(matches!(verifier.verify(packet), Err(CryptoError::SignatureError(_) | CryptoError::UnknownNodeKeyError(_))));
+    }
+    // 書き換えていない行は受け付ける
+    assert!(verifier.verify(&original).is_ok());
+}
+
+#[tokio::test]
+async fn saved_seqs_replace_recorded_window() {
+    let carrier = MemoryCarrier::new();
+    let other = received(3, 5, &[(5, 5)]);
+    carrier.acknowledge_seqs(READER, &[received(WRITER, 10, &[(1, 3), (8, 10)]), other.clone()]).await.unwrap();
+    // 記録済みの書き込んだノードは区間ごと置き換え、含まれないノードの記録は残す
+    let replaced = received(WRITER, 12, &[(1, 3), (8, 12)]);
+    carrier.acknowledge_seqs(READER, std::slice::from_ref(&replaced)).await.unwrap();
+
+    assert_eq!(carrier.saved_seqs(READER).await.unwrap(), vec![replaced, other]);
+    assert!(carrier.saved_seqs(WRITER).await.unwrap().is_empty());
+}
+
+#[test]
+fn seq_continues_from_saved_limit_even_if_clock_is_behind() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = signing_config(dir.path());
+
+    let signer = PacketSigner::load(&config).unwrap().unwrap();
+    let first = seq_of(&signer.sign(WRITER, common::packet(1)).unwrap());
+    // 使用する前に、使用した番号より大きい上限が保存されている
+    assert!(saved_limit(&config) > first);
+    drop(signer);
+
+    // 時計より先の上限が保存されている場合 (時計を戻した場合) も、上限から始める
+    let ahead = first + 3_600_000_000;
+    std::fs::write(&config.seq_file, format!("{ahead}\n")).unwrap();
+    let signer = PacketSigner::load(&config).unwrap().unwrap();
+    let seqs: Vec<i64> = (1..=3).map(|port| seq_of(&signer.sign(WRITER, common::packet(port)).unwrap())).collect();
+    assert_eq!(seqs, vec![ahead, ahead + 1, ahead + 2]);
+    assert!(saved_limit(&config) > ahead + 2);
+}
+
+#[test]
+fn invalid_seq_file_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = signing_config(dir.path());
+    std::fs::write(&config.seq_file, "not a number\n").unwrap();
+    assert!(matches!(PacketSigner::load(&config), Err(CryptoError::SeqFileError(_))));
+}
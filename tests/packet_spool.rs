@@ -0,0 +1,173 @@
+//! スプールの結合テスト
+
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use stegrdb::config::SpoolConfig;
+use stegrdb::packet::spool::PacketSpool;
+use stegrdb::PacketData;
+
+fn spool_config(directory: &Path) -> SpoolConfig {
+    SpoolConfig {
+        enabled: true,
+        directory: directory.to_path_buf(),
+        max_bytes: 64 * 1024 * 1024,
+        retry_interval: Duration::from_millis(10),
+    }
+}
+
+fn segments(directory: &Path) -> Vec<PathBuf> {
+    let mut segments: Vec<PathBuf> =
+        fs::read_dir(directory).unwrap().map(|entry| entry.unwrap().path()).filter(|path| path.extension().is_some_and(|ext| ext == "spool")).collect();
+    segments.sort();
+    segments
+}
+
+fn large_packet(src_port: i32) -> PacketData {
+    let mut packet = common::packet(src_port);
+    packet.raw_packet = vec![src_port as u8; 65536];
+    packet
+}
+
+/// 再送待ちのバッチを全て読み出し、バッチごとのsrc_portを返す
+async fn replay_all(spool: &PacketSpool) -> Vec<Vec<i32>> {
+    let mut batches = Vec::new();
+    while let Some(batch) = spool.next_batch().await.unwrap() {
+        assert_eq!(batch.node_id, 1);
+        batches.push(batch.packets.iter().map(|p| p.src_port).collect());
+        spool.ack(&batch).await.unwrap();
+    }
+    assert!(!spool.has_pending());
+    batches
+}
+
+#[tokio::test]
+async fn oversized_frame_header_is_truncated_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = spool_config(dir.path());
+
+    let spool = PacketSpool::open(&config).unwrap();
+    spool.append(1, &[common::packet(1), common::packet(2)]).await.unwrap();
+    drop(spool);
+
+    // 書き込み途中で壊れた、4GiB近い長さを示すヘッダーを末尾に追加する
+    let segment = segments(dir.path()).remove(0);
+    let valid_len = fs::metadata(&segment).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+    file.write_all(&[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 1, 2, 3]).unwrap();
+    drop(file);
+
+    let spool = PacketSpool::open(&config).unwrap();
+    assert_eq!(fs::metadata(&segment).unwrap().len(), valid_len);
+    assert_eq!(replay_all(&spool).await, vec![vec![1, 2]]);
+}
+
+#[tokio::test]
+async fn partially_written_frame_is_truncated_on_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = spool_config(dir.path());
+
+    let spool = PacketSpool::open(&config).unwrap();
+    spool.append(1, &[common::packet(1)]).await.unwrap();
+    let segment = segments(dir.path()).remove(0);
+    let valid_len = fs::metadata(&segment).unwrap().len();
+    spool.append(1, &[common::packet(2)]).await.unwrap();
+    drop(spool);
+
+    // 2つ目のフレームの書き込み途中で終了した状態にする
+    let file = OpenOptions::new().write(true).open(&segment).unwrap();
+    file.set_len(fs::metadata(&segment).unwrap().len() - 3).unwrap();
+    drop(file);
+
+    let spool = PacketSpool::open(&config).unwrap();
+    assert_eq!(fs::metadata(&segment).unwrap().len(), valid_len);
+    assert_eq!(replay_all(&spool).await, vec![vec![1]]);
+
+    // 切り詰めた位置から追記を続けられる
+    spool.append(1, &[common::packet(3)]).await.unwrap();
+    assert_eq!(replay_all(&spool).await, vec![vec![3]]);
+}
+
+#[tokio::test]
+async fn frame_with_crc_mismatch_is_skipped_without_losing_later_frames() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = spool_config(dir.path());
+
+    let spool = PacketSpool::open(&config).unwrap();
+    spool.append(1, &[common::packet(1)]).await.unwrap();
+    let segment = segments(dir.path()).remove(0);
+    let second_offset = fs::metadata(&segment).unwrap().len();
+    spool.append(1, &[common::packet(2), common::packet(3)]).await.unwrap();
+    spool.append(1, &[common::packet(4)]).await.unwrap();
+    let size = fs::metadata(&segment).unwrap().len();
+    drop(spool);
+
+    // 2つ目のフレームのペイロードの1バイトを書き換える
+    let mut file = OpenOptions::new().write(true).open(&segment).unwrap();
+    file.seek(SeekFrom::Start(second_offset + 8 + 10)).unwrap();
+    file.write_all(&[0xaa]).unwrap();
+    drop(file);
+
+    let spool = PacketSpool::open(&config).unwrap();
+    assert_eq!(fs::metadata(&segment).unwrap().len(), size);
+    assert_eq!(replay_all(&spool).await, vec![vec![1], vec![4]]);
+}
+
+#[tokio::test]
+async fn replay_continues_from_acknowledged_position_after_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = spool_config(dir.path());
+
+    let spool = PacketSpool::open(&config).unwrap();
+    for port in 1..=3 {
+        spool.append(1, &[common::packet(port)]).await.unwrap();
+    }
+    let batch = spool.next_batch().await.unwrap().unwrap();
+    spool.ack(&batch).await.unwrap();
+    // 再送したが記録する前に終了したバッチは、再起動後にもう一度再送する
+    spool.next_batch().await.unwrap().unwrap();
+    drop(spool);
+
+    let spool = PacketSpool::open(&config).unwrap();
+    assert!(spool.has_pending());
+    assert_eq!(replay_all(&spool).await, vec![vec![2], vec![3]]);
+    // 再送済みのセグメントは削除される
+    assert!(segments(dir.path()).is_empty());
+}
+
+#[tokio::test]
+async fn batch_larger_than_frame_limit_is_split() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = spool_config(dir.path());
+
+    // 1フレームの上限 (16MiB) を超える、65536バイトのパケット300個のバッチ
+    let spool = PacketSpool::open(&config).unwrap();
+    let packets: Vec<PacketData> = (1..=300).map(large_packet).collect();
+    spool.append(1, &packets).await.unwrap();
+    assert_eq!(spool.stats().spooled_batches, 2);
+    drop(spool);
+
+    // 再起動後もフレームは切り詰められず、全てのパケットを順番に再送する
+    let spool = PacketSpool::open(&config).unwrap();
+    let batches = replay_all(&spool).await;
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches.concat(), (1..=300).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn batch_over_capacity_is_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = SpoolConfig {
+        max_bytes: 200 * 1024,
+        ..spool_config(dir.path())
+    };
+
+    let spool = PacketSpool::open(&config).unwrap();
+    spool.append(1, &[large_packet(1), large_packet(2)]).await.unwrap();
+    assert!(spool.append(1, &[large_packet(3), large_packet(4)]).await.is_err());
+    assert_eq!(spool.stats().dropped_batches, 1);
+    assert_eq!(replay_all(&spool).await, vec![vec![1, 2]]);
+}
@@ -0,0 +1,136 @@
+//! パケットライターの結合テスト (メモリ上の媒体を使用する)
+
+mod common;
+
+use pnet::datalink::NetworkInterface;
+use std::sync::Arc;
+use stegrdb::carrier::Carrier;
+use stegrdb::config::ConfigOverrides;
+use stegrdb::packet::spool::PacketSpool;
+use stegrdb::packet::writer::PacketWriter;
+use stegrdb::{AppConfig, MemoryCarrier, PacketQuery, TunnelNode};
+
+const NODE_ID: i16 = 1;
+
+async fn node(carrier: &MemoryCarrier, values: &[(&str, &str)]) -> TunnelNode {
+    let mut overrides = ConfigOverrides::default();
+    overrides.set("node_id", NODE_ID);
+    overrides.set("carrier.kind", "sqlite");
+    for (key, value) in values {
+        overrides.set(key, *value);
+    }
+    let config = AppConfig::load(&overrides).expect("設定の読み込みに失敗しました");
+
+    carrier.register_node(NODE_ID, "writer", None).await.unwrap();
+    let interface = NetworkInterface {
+        name: "test0".to_string(),
+        description: String::new(),
+        index: 0,
+        mac: None,
+        ips: Vec::new(),
+        flags: 0,
+    };
+    TunnelNode::with_carrier(config, Arc::new(carrier.clone()), interface).await.expect("ノードの作成に失敗しました")
+}
+
+#[tokio::test]
+async fn shutdown_flushes_buffered_packets() {
+    let carrier = MemoryCarrier::new();
+    // フラッシュ間隔を長くし、停止するまでバッファに残す
+    let node = node(
+        &carrier,
+        &[
+            ("batching.flush_interval_ms", "3600000"),
+            ("batching.min_batch_size", "100"),
+            ("batching.max_batch_size", "100"),
+        ],
+    )
+    .await;
+    for port in 1..=250 {
+        node.context().buffer.push(common::packet(port)).await;
+    }
+
+    let writer = PacketWriter::new(Arc::clone(node.context()));
+    writer.shutdown().await.unwrap();
+
+    assert!(node.context().buffer.is_empty().await);
+    let mut ports: Vec<i32> = carrier.query_packets(&PacketQuery::new()).await.unwrap().iter().map(|p| p.data.src_port).collect();
+    ports.sort_unstable();
+    assert_eq!(ports, (1..=250).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn shutdown_spools_buffered_packets_behind_pending_batches() {
+    let dir = tempfile::tempdir().unwrap();
+    let carrier = MemoryCarrier::new();
+    let directory = dir.path().to_str().unwrap();
+    let node = node(&carrier, &[("spool.enabled", "true"), ("spool.directory", directory)]).await;
+    let spool = node.context().spool.clone().expect("スプールが有効になっていません");
+
+    // 再送待ちのバッチがある間は、停止時のフラッシュも順番を保つためスプールに追記する
+    spool.append(NODE_ID, &[common::packet(1)]).await.unwrap();
+    node.context().buffer.push(common::packet(2)).await;
+    PacketWriter::new(Arc::clone(node.context())).shutdown().await.unwrap();
+    assert!(carrier.query_packets(&PacketQuery::new()).await.unwrap().is_empty());
+    let config = node.context().config.spool.clone();
+    drop((node, spool));
+
+    // 再起動後に退避した順番で再送する
+    let spool = PacketSpool::open(&config).unwrap();
+    let mut ports = Vec::new();
+    while let Some(batch) = spool.next_batch().await.unwrap() {
+        ports.extend(batch.packets.iter().map(|p| p.src_port));
+        spool.ack(&batch).await.unwrap();
+    }
+    assert_eq!(ports, vec![1, 2]);
+}
+
+#[tokio::test]
+async fn concurrent_flushes_keep_spooled_packets_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let carrier = MemoryCarrier::new();
+    let directory = dir.path().to_str().unwrap();
+    let node = node(
+        &carrier,
+        &[
+            ("spool.enabled", "true"),
+            ("spool.directory", directory),
+            ("spool.retry_interval_ms", "10"),
+            ("batching.flush_interval_ms", "5"),
+            ("batching.min_batch_size", "5"),
+            ("batching.max_batch_size", "40"),
+            ("batching.max_in_flight", "4"),
+        ],
+    )
+    .await;
+    let spool = node.context().spool.clone().expect("スプールが有効になっていません");
+
+    // 再送待ちのバッチがある状態で、複数のフラッシュを同時に実行させる
+    spool.append(NODE_ID, &(1..=5).map(common::packet).collect::<Vec<_>>()).await.unwrap();
+    let writer = tokio::spawn({
+        let context = Arc::clone(node.context());
+        async move { PacketWriter::new(context).start().await }
+    });
+    for port in 6..=505 {
+        node.context().buffer.push(common::packet(port)).await;
+        if port % 50 == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while carrier.len() < 505 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("全てのパケットが書き込まれていません");
+    writer.abort();
+
+    // 書き込まれた順 (query_packetsは新しい順) で、再送待ちだったパケットが先頭にあり、重複や欠落がない
+    let mut ports: Vec<i32> = carrier.query_packets(&PacketQuery::new()).await.unwrap().iter().rev().map(|p| p.data.src_port).collect();
+    assert_eq!(ports[..5], [1, 2, 3, 4, 5]);
+    ports.sort_unstable();
+    assert_eq!(ports, (1..=505).collect::<Vec<_>>());
+    assert!(!spool.has_pending());
+}
@@ -0,0 +1,205 @@
+//! PostgreSQLの媒体の結合テスト
+//! STEGRDB_TEST_POSTGRES=1 の場合のみ実行する (接続先は通常の設定と同じく TIMESCALE_DB_* 等で指定する)
+
+mod common;
+
+use stegrdb::carrier::{Carrier, CarrierError};
+use stegrdb::config::ConfigOverrides;
+use stegrdb::crypto::ReceivedSeqs;
+use stegrdb::database::{Database, DatabaseError, ExecuteQuery, Schema};
+use stegrdb::packet::repository::PacketRepository;
+use stegrdb::packet::spool::PacketSpool;
+use stegrdb::{AppConfig, PostgresCarrier};
+
+fn postgres_config(values: &[(&str, &str)]) -> Option<AppConfig> {
+    if std::env::var("STEGRDB_TEST_POSTGRES").as_deref() != Ok("1") {
+        eprintln!("STEGRDB_TEST_POSTGRES=1 が指定されていないため、PostgreSQLのテストを省略します");
+        return None;
+    }
+
+    let mut overrides = ConfigOverrides::default();
+    overrides.set("node_id", 32000);
+    for (key, value) in values {
+        overrides.set(key, value);
+    }
+    Some(AppConfig::load(&overrides).expect("設定の読み込みに失敗しました"))
+}
+
+fn received(node_id: i16, highest: i64, ranges: &[(i64, i64)]) -> ReceivedSeqs {
+    ReceivedSeqs {
+        node_id,
+        highest,
+        ranges: ranges.to_vec(),
+    }
+}
+
+async fn count_packets(db: &Database, node_id: i16) -> i64 {
+    let rows = db.query("SELECT COUNT(*) AS count FROM packets WHERE node_id = $1", &[&node_id]).await.unwrap();
+    rows[0].get("count")
+}
+
+#[tokio::test]
+async fn failed_chunk_is_not_duplicated_by_spool_replay() {
+    const NODE_ID: i16 = 32001;
+    let spool_dir = tempfile::tempdir().unwrap();
+    let Some(config) = postgres_config(&[
+        ("batching.insert_method", "unnest"),
+        ("batching.chunk_size", "2"),
+        ("batching.max_retries", "0"),
+        ("spool.directory", spool_dir.path().to_str().unwrap()),
+    ]) else {
+        return;
+    };
+
+    let db = Database::connect(&config.database).await.unwrap();
+    Schema::migrate(&db).await.unwrap();
+
+    // stegrdb_test_fail に登録したノードの src_port = 3 のパケット (2つ目のチャンク) の挿入を失敗させる
+    db.batch_execute(&format!(
+        "
+        DELETE FROM packets WHERE node_id = {NODE_ID};
+        CREATE TABLE IF NOT EXISTS stegrdb_test_fail (node_id SMALLINT PRIMARY KEY);
+        INSERT INTO stegrdb_test_fail VALUES ({NODE_ID}) ON CONFLICT DO NOTHING;
+        CREATE OR REPLACE FUNCTION stegrdb_test_fail_chunk() RETURNS trigger AS $$
+        BEGIN
+            IF NEW.src_port = 3 AND EXISTS (SELECT 1 FROM stegrdb_test_fail WHERE node_id = NEW.node_id) THEN
+                RAISE EXCEPTION 'stegrdb test: chunk failure';
+            END IF;
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql;
+        CREATE OR REPLACE TRIGGER stegrdb_test_fail_chunk BEFORE INSERT ON packets FOR EACH ROW EXECUTE FUNCTION stegrdb_test_fail_chunk();
+        "
+    ))
+    .await
+    .unwrap();
+
+    let packets: Vec<_> = (1..=5).map(common::packet).collect();
+    let result = PacketRepository::bulk_insert(&db, NODE_ID, &packets, &config.batching).await;
+    assert!(result.is_err(), "2つ目のチャンクの挿入が失敗していません");
+    assert_eq!(count_packets(&db, NODE_ID).await, 0, "失敗したバッチの前のチャンクがコミットされています");
+
+    // ライターと同じく、失敗したバッチ全体をスプールに退避してから再送する
+    let spool = PacketSpool::open(&config.spool).unwrap();
+    spool.append(NODE_ID, &packets).await.unwrap();
+    db.batch_execute(&format!("DELETE FROM stegrdb_test_fail WHERE node_id = {NODE_ID}")).await.unwrap();
+
+    let batch = spool.next_batch().await.unwrap().expect("スプールにバッチがありません");
+    PacketRepository::bulk_insert(&db, batch.node_id, &batch.packets, &config.batching).await.unwrap();
+    spool.ack(&batch).await.unwrap();
+    assert!(spool.next_batch().await.unwrap().is_none());
+
+    let rows = db.query("SELECT src_port FROM packets WHERE node_id = $1 ORDER BY src_port", &[&NODE_ID]).await.unwrap();
+    let ports: Vec<i32> = rows.iter().map(|row| row.get("src_port")).collect();
+    assert_eq!(ports, vec![1, 2, 3, 4, 5]);
+
+    db.batch_execute(&format!(
+        "DROP TRIGGER stegrdb_test_fail_chunk ON packets; DROP FUNCTION stegrdb_test_fail_chunk(); DROP TABLE stegrdb_test_fail; DELETE FROM packets WHERE node_id = {NODE_ID};"
+    ))
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn saved_seqs_replace_recorded_window() {
+    const READER: i16 = 32002;
+    const WRITER: i16 = 32003;
+    let Some(config) = postgres_config(&[]) else {
+        return;
+    };
+
+    let db = Database::connect(&config.database).await.unwrap();
+    Schema::migrate(&db).await.unwrap();
+    db.batch_execute(&format!(
+        "INSERT INTO node_list (id, name) VALUES ({READER}, 'stegrdb-test-reader') ON CONFLICT DO NOTHING; DELETE FROM delivery_seq_ranges WHERE node_id = {READER}; DELETE FROM delivery_seqs WHERE node_id = {READER};"
+    ))
+    .await
+    .unwrap();
+
+    let other = received(32004, 5, &[(5, 5)]);
+    PacketRepository::save_seqs(&db, READER, &[received(WRITER, 10, &[(1, 3), (8, 10)]), other.clone()]).await.unwrap();
+    // 記録済みの書き込んだノードは区間ごと置き換え、含まれないノードの記録は残す
+    let replaced = received(WRITER, 12, &[(1, 3), (8, 12)]);
+    PacketRepository::save_seqs(&db, READER, std::slice::from_ref(&replaced)).await.unwrap();
+    assert_eq!(PacketRepository::load_seqs(&db, READER).await.unwrap(), vec![replaced, other]);
+
+    db.batch_execute(&format!(
+        "DELETE FROM delivery_seq_ranges WHERE node_id = {READER}; DELETE FROM delivery_seqs WHERE node_id = {READER}; DELETE FROM node_list WHERE id = {READER};"
+    ))
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn packets_committed_after_running_transaction_are_held_back() {
+    const WRITER: i16 = 32005;
+    const READER: i16 = 32006;
+    let Some(config) = postgres_config(&[]) else {
+        return;
+    };
+
+    let db = Database::connect(&config.database).await.unwrap();
+    Schema::migrate(&db).await.unwrap();
+    db.batch_execute(&format!(
+        "INSERT INTO node_list (id, name) VALUES ({WRITER}, 'stegrdb-test-writer') ON CONFLICT DO NOTHING; DELETE FROM packets WHERE node_id = {WRITER};"
+    ))
+    .await
+    .unwrap();
+    let cursor = PacketRepository::live_cursor(&db).await.unwrap();
+
+    // トランザクションIDを割り当てたまま終了しないトランザクション (packetsには書き込まない)
+    let (started_tx, started) = tokio::sync::oneshot::channel();
+    let (release, released) = tokio::sync::oneshot::channel::<()>();
+    let blocker = Database::connect(&config.database).await.unwrap();
+    let blocking = tokio::spawn(async move {
+        blocker
+            .transaction(|tx| {
+                Box::pin(async move {
+                    tx.query("SELECT pg_current_xact_id()", &[]).await.map_err(|e| DatabaseError::QueryExecutionError(e.to_string()))?;
+                    started_tx.send(()).unwrap();
+                    released.await.ok();
+                    Ok(())
+                })
+            })
+            .await
+    });
+    started.await.unwrap();
+
+    let packets: Vec<_> = (1..=3).map(common::packet).collect();
+    PacketRepository::bulk_insert(&db, WRITER, &packets, &config.batching).await.unwrap();
+
+    // コミット済みでも、実行中のトランザクションが終わるまでは読み取らずに待つ
+    let (fetched, next) = PacketRepository::fetch_after(&db, READER, cursor).await.unwrap();
+    assert!(fetched.iter().all(|p| p.node_id != WRITER));
+    let held_back = PacketRepository::held_back(&db, READER, next).await.unwrap().expect("読み取れないパケットが検出されていません");
+    assert!(held_back.count >= 3);
+
+    release.send(()).unwrap();
+    blocking.await.unwrap().unwrap();
+
+    let (fetched, next) = PacketRepository::fetch_after(&db, READER, next).await.unwrap();
+    assert_eq!(fetched.iter().filter(|p| p.node_id == WRITER).count(), 3);
+    assert_eq!(PacketRepository::held_back(&db, READER, next).await.unwrap(), None);
+
+    db.batch_execute(&format!("DELETE FROM packets WHERE node_id = {WRITER}; DELETE FROM node_list WHERE id = {WRITER};")).await.unwrap();
+}
+
+#[tokio::test]
+async fn management_carrier_does_not_publish() {
+    let Some(config) = postgres_config(&[]) else {
+        return;
+    };
+
+    let db = Database::connect(&config.database).await.unwrap();
+    Schema::migrate(&db).await.unwrap();
+    let carrier = PostgresCarrier::for_management(db);
+
+    // 管理コマンドの操作はノードの設定なしで使用できる
+    carrier.register_node(32007, "stegrdb-test-management", None).await.unwrap();
+    assert!(carrier.list_nodes().await.unwrap().iter().any(|node| node.id == 32007));
+
+    assert!(matches!(carrier.publish(32007, &[common::packet(1)]).await, Err(CarrierError::ManagementOnlyError)));
+    assert!(matches!(carrier.subscribe().await, Err(CarrierError::ManagementOnlyError)));
+
+    carrier.database().unwrap().batch_execute("DELETE FROM node_list WHERE id = 32007;").await.unwrap();
+}
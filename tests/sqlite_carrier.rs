@@ -0,0 +1,189 @@
+//! SQLiteの媒体の結合テスト (一時ディレクトリのファイルを使用する)
+
+mod common;
+
+use rusqlite::Connection;
+use std::path::Path;
+use std::time::Duration;
+use stegrdb::carrier::{Carrier, CarrierError, CarrierSubscription};
+use stegrdb::config::{CarrierConfig, ConfigOverrides};
+use stegrdb::crypto::ReceivedSeqs;
+use stegrdb::packet::repository::DeliveryCursor;
+use stegrdb::{AppConfig, PacketQuery, SqliteCarrier};
+
+const NODE_A: i16 = 1;
+const NODE_B: i16 = 2;
+
+fn carrier_config(path: &Path) -> CarrierConfig {
+    let mut overrides = ConfigOverrides::default();
+    overrides.set("node_id", NODE_A);
+    overrides.set("carrier.kind", "sqlite");
+    overrides.set("carrier.sqlite_path", path.to_str().unwrap());
+    AppConfig::load(&overrides).expect("設定の読み込みに失敗しました").carrier
+}
+
+/// ノードAが書き込んだパケットの受信済みのシーケンス番号
+fn received_from_a(highest: i64, ranges: &[(i64, i64)]) -> ReceivedSeqs {
+    ReceivedSeqs {
+        node_id: NODE_A,
+        highest,
+        ranges: ranges.to_vec(),
+    }
+}
+
+fn user_version(path: &Path) -> i32 {
+    Connection::open(path).unwrap().query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap()
+}
+
+fn columns(path: &Path, table: &str) -> Vec<String> {
+    let connection = Connection::open(path).unwrap();
+    let mut statement = connection.prepare(&format!("SELECT name FROM pragma_table_info('{table}')")).unwrap();
+    statement.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap()
+}
+
+/// 以前のバージョンのバイナリが作成したファイルを再現する (バージョン2でpackets.key_idが追加された)
+fn create_old_schema(path: &Path, version: i32) {
+    let key_id = if version >= 2 { ", key_id INTEGER" } else { "" };
+    let connection = Connection::open(path).unwrap();
+    connection
+        .execute_batch(&format!(
+            "
+            CREATE TABLE node_list (id INTEGER PRIMARY KEY, name TEXT NOT NULL, description TEXT);
+            CREATE TABLE packets
+            (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp   INTEGER NOT NULL,
+                captured_at INTEGER NOT NULL,
+                node_id     INTEGER NOT NULL,
+                src_mac     BLOB    NOT NULL,
+                dst_mac     BLOB    NOT NULL,
+                ether_type  INTEGER NOT NULL,
+                ip_protocol INTEGER NOT NULL,
+                src_ip      TEXT    NOT NULL,
+                dst_ip      TEXT    NOT NULL,
+                src_port    INTEGER NOT NULL,
+                dst_port    INTEGER NOT NULL,
+                raw_packet  BLOB    NOT NULL{key_id}
+            );
+            INSERT INTO node_list (id, name) VALUES ({NODE_A}, 'old');
+            INSERT INTO packets (timestamp, captured_at, node_id, src_mac, dst_mac, ether_type, ip_protocol, src_ip, dst_ip, src_port, dst_port, raw_packet)
+                VALUES (1, 1, {NODE_A}, x'020000000001', x'020000000002', 2048, 17, '10.0.0.1', '10.0.0.2', 7, 9, x'00');
+            PRAGMA user_version = {version};
+            "
+        ))
+        .unwrap();
+}
+
+async fn recv(subscription: &mut dyn CarrierSubscription) -> Option<i16> {
+    tokio::time::timeout(Duration::from_secs(5), subscription.recv()).await.expect("書き込みが通知されません")
+}
+
+async fn assert_upgraded(path: &Path) {
+    let carrier = SqliteCarrier::open(&carrier_config(path)).expect("既存のファイルを開けませんでした");
+    assert_eq!(user_version(path), SqliteCarrier::SCHEMA_VERSION);
+    for column in ["key_id", "seq", "signature"] {
+        assert!(columns(path, "packets").iter().any(|name| name == column), "packets.{column} がありません");
+    }
+    assert!(columns(path, "node_list").iter().any(|name| name == "public_key"));
+
+    // 既存の行はそのまま読み出せる
+    let packets = carrier.query_packets(&PacketQuery::new()).await.unwrap();
+    assert_eq!(packets.len(), 1);
+    assert_eq!(packets[0].data.src_port, 7);
+    assert_eq!(packets[0].data.key_id, None);
+    assert!(packets[0].data.signature.is_none());
+}
+
+#[tokio::test]
+async fn fresh_file_is_created_with_current_schema() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("carrier.sqlite3");
+
+    let carrier = SqliteCarrier::open(&carrier_config(&path)).unwrap();
+    assert_eq!(user_version(&path), SqliteCarrier::SCHEMA_VERSION);
+    assert!(carrier.query_packets(&PacketQuery::new()).await.unwrap().is_empty());
+    assert_eq!(carrier.live_cursor().await.unwrap(), DeliveryCursor::default());
+    drop(carrier);
+
+    // 開き直してもスキーマは変わらない
+    SqliteCarrier::open(&carrier_config(&path)).unwrap();
+    assert_eq!(user_version(&path), SqliteCarrier::SCHEMA_VERSION);
+}
+
+#[tokio::test]
+async fn version_1_file_is_upgraded() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("carrier.sqlite3");
+    create_old_schema(&path, 1);
+    assert_upgraded(&path).await;
+}
+
+#[tokio::test]
+async fn version_2_file_applies_only_later_upgrades() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("carrier.sqlite3");
+    // バージョン2の変更 (key_idの追加) を再度適用すると列の重複で失敗する
+    create_old_schema(&path, 2);
+    assert_upgraded(&path).await;
+}
+
+#[tokio::test]
+async fn newer_schema_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("carrier.sqlite3");
+    Connection::open(&path).unwrap().pragma_update(None, "user_version", SqliteCarrier::SCHEMA_VERSION + 1).unwrap();
+
+    let result = SqliteCarrier::open(&carrier_config(&path));
+    assert!(matches!(result, Err(CarrierError::SchemaVersionError(found, supported)) if found == SqliteCarrier::SCHEMA_VERSION + 1 && supported == SqliteCarrier::SCHEMA_VERSION));
+    // 新しいバージョンのファイルは変更しない
+    assert_eq!(user_version(&path), SqliteCarrier::SCHEMA_VERSION + 1);
+}
+
+#[tokio::test]
+async fn packets_are_exchanged_between_connections() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("carrier.sqlite3");
+    let carrier_a = SqliteCarrier::open(&carrier_config(&path)).unwrap();
+    let carrier_b = SqliteCarrier::open(&carrier_config(&path)).unwrap();
+    carrier_a.register_node(NODE_A, "a", None).await.unwrap();
+    carrier_b.register_node(NODE_B, "b", None).await.unwrap();
+    assert_eq!(carrier_a.list_nodes().await.unwrap().len(), 2);
+
+    let mut subscription = carrier_b.subscribe().await.unwrap();
+    assert_eq!(subscription.try_recv(), None);
+
+    carrier_a.publish(NODE_A, &[common::packet(1), common::packet(2)]).await.unwrap();
+    carrier_b.publish(NODE_B, &[common::packet(10)]).await.unwrap();
+
+    // 他の接続の書き込みを確認し、書き込んだノードごとに通知する
+    let mut writers = vec![recv(subscription.as_mut()).await, subscription.try_recv()];
+    writers.sort();
+    assert_eq!(writers, vec![Some(NODE_A), Some(NODE_B)]);
+    assert_eq!(subscription.try_recv(), None);
+
+    // 自ノードの書き込みを除いて受け取る
+    let (packets, cursor) = carrier_b.fetch(NODE_B, DeliveryCursor::default()).await.unwrap();
+    assert_eq!(packets.iter().map(|p| p.node_id).collect::<Vec<_>>(), vec![NODE_A, NODE_A]);
+    assert_eq!(packets[0].raw_packet, common::packet(1).raw_packet);
+    let (packets, _) = carrier_a.fetch(NODE_A, DeliveryCursor::default()).await.unwrap();
+    assert_eq!(packets.iter().map(|p| p.node_id).collect::<Vec<_>>(), vec![NODE_B]);
+
+    // 保存した読み取り位置は他の接続からも読み出せる
+    carrier_b.acknowledge(NODE_B, cursor).await.unwrap();
+    assert_eq!(carrier_a.saved_cursor(NODE_B).await.unwrap(), Some(cursor));
+    assert_eq!(carrier_b.fetch(NODE_B, cursor).await.unwrap().0.len(), 0);
+
+    carrier_a.publish(NODE_A, &[common::packet(3)]).await.unwrap();
+    assert_eq!(recv(subscription.as_mut()).await, Some(NODE_A));
+    let (packets, next) = carrier_b.fetch(NODE_B, cursor).await.unwrap();
+    assert_eq!(packets.len(), 1);
+    assert!(next > cursor);
+    assert_eq!(next, carrier_b.live_cursor().await.unwrap());
+
+    // 受信済みのシーケンス番号は区間ごと置き換えて記録し、他の接続からも読み出せる
+    carrier_b.acknowledge_seqs(NODE_B, &[received_from_a(10, &[(1, 3), (8, 10)])]).await.unwrap();
+    let replaced = received_from_a(12, &[(1, 3), (8, 12)]);
+    carrier_b.acknowledge_seqs(NODE_B, std::slice::from_ref(&replaced)).await.unwrap();
+    assert_eq!(carrier_a.saved_seqs(NODE_B).await.unwrap(), vec![replaced]);
+    assert!(carrier_a.saved_seqs(NODE_A).await.unwrap().is_empty());
+}